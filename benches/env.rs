@@ -0,0 +1,114 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use poly::{app, lam};
+use poly::{
+    env::Env,
+    eval::{eval_, EvalState, TermEnv},
+    infer::infer_expr,
+    syntax::{Expr, Lit, Name, PrimOp},
+    vm::{compile, run},
+};
+
+const LENGTHS: [usize; 3] = [10, 100, 1000];
+
+fn var(i: usize) -> Name {
+    Name(format!("x{}", i))
+}
+
+// `(let [x0 0] (let [x1 (+ x0 1)] ... xn))`: each binder extends an
+// environment which already holds all of the previous ones.
+fn let_chain(n: usize) -> Expr {
+    let succ = |i| app!(app!(Expr::Prim(PrimOp::Add), Expr::Var(var(i))), int(1));
+    let body = (1..=n).rev().fold(Expr::Var(var(n)), |bd, i| {
+        Expr::Let(var(i), Box::new(succ(i - 1)), Box::new(bd))
+    });
+    Expr::Let(var(0), Box::new(int(0)), Box::new(body))
+}
+
+// as `let_chain`, but each binding refers to the outermost binder, ever
+// farther away: `(let [x0 0] (let [x1 (+ x0 1)] ... (let [xn (+ x0 1)] xn)))`.
+fn far_chain(n: usize) -> Expr {
+    let succ = || app!(app!(Expr::Prim(PrimOp::Add), Expr::Var(var(0))), int(1));
+    let body = (1..=n).rev().fold(Expr::Var(var(n)), |bd, i| {
+        Expr::Let(var(i), Box::new(succ()), Box::new(bd))
+    });
+    Expr::Let(var(0), Box::new(int(0)), Box::new(body))
+}
+
+// as `let_chain`, but each binding is a closure, which captures the
+// environment of all of the previous ones.
+fn closure_chain(n: usize) -> Expr {
+    let arg = var(n + 1);
+    let call = |i| lam!(arg.clone(), app!(Expr::Var(var(i)), Expr::Var(arg.clone())));
+    let body = (1..=n)
+        .rev()
+        .fold(app!(Expr::Var(var(n)), int(0)), |bd, i| {
+            Expr::Let(var(i), Box::new(call(i - 1)), Box::new(bd))
+        });
+    let id = lam!(arg.clone(), Expr::Var(arg.clone()));
+    Expr::Let(var(0), Box::new(id), Box::new(body))
+}
+
+fn int(n: i64) -> Expr {
+    Expr::Lit(Lit::LInt(n))
+}
+
+fn bench_eval(c: &mut Criterion) {
+    let mut group = c.benchmark_group("eval");
+    for &n in LENGTHS.iter() {
+        let lets = let_chain(n);
+        group.bench_with_input(BenchmarkId::new("let_chain", n), &lets, |b, expr| {
+            b.iter(|| eval_(&TermEnv::new(), &mut EvalState::new(), black_box(expr)).ok())
+        });
+        let fars = far_chain(n);
+        group.bench_with_input(BenchmarkId::new("far_chain", n), &fars, |b, expr| {
+            b.iter(|| eval_(&TermEnv::new(), &mut EvalState::new(), black_box(expr)).ok())
+        });
+        let closures = closure_chain(n);
+        group.bench_with_input(
+            BenchmarkId::new("closure_chain", n),
+            &closures,
+            |b, expr| {
+                b.iter(|| eval_(&TermEnv::new(), &mut EvalState::new(), black_box(expr)).ok())
+            },
+        );
+    }
+    group.finish();
+}
+
+// as `bench_eval`, but running code compiled (beforehand) for the vm.
+fn bench_vm(c: &mut Criterion) {
+    let mut group = c.benchmark_group("vm");
+    for &n in LENGTHS.iter() {
+        let lets = compile(&TermEnv::new(), &let_chain(n)).unwrap();
+        group.bench_with_input(BenchmarkId::new("let_chain", n), &lets, |b, chunk| {
+            b.iter(|| run(&mut EvalState::new(), black_box(chunk)).ok())
+        });
+        let closures = compile(&TermEnv::new(), &closure_chain(n)).unwrap();
+        group.bench_with_input(
+            BenchmarkId::new("closure_chain", n),
+            &closures,
+            |b, chunk| b.iter(|| run(&mut EvalState::new(), black_box(chunk)).ok()),
+        );
+    }
+    group.finish();
+}
+
+fn bench_infer(c: &mut Criterion) {
+    let mut group = c.benchmark_group("infer");
+    // generalizing at each `let` only consults the environment's count of
+    // its free type variables, but solving still applies each substitution to
+    // all of the remaining constraints, so inference of a chain remains
+    // quadratic in its length.
+    group.sample_size(10);
+    for &n in LENGTHS.iter() {
+        let lets = let_chain(n);
+        group.bench_with_input(BenchmarkId::new("let_chain", n), &lets, |b, expr| {
+            b.iter(|| infer_expr(&Env::new(), black_box(expr)).ok())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_eval, bench_vm, bench_infer);
+criterion_main!(benches);
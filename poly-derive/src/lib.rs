@@ -0,0 +1,146 @@
+//! derive macros for `poly::convert::{PolyType, IntoValue, FromValue}`.
+//!
+//! a struct is represented by its fields as pairs nested to the right, so that
+//! `struct S { a: A, b: B, c: C }` corresponds to the poly type `(A, (B, C))`.
+//! a struct with a single field is represented by that field alone.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, parse_quote, Data, DeriveInput, Error, Fields, GenericParam, Generics,
+    Index, Member, Type,
+};
+
+#[proc_macro_derive(PolyType)]
+pub fn derive_poly_type(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input, "PolyType", |repr, _fields| {
+        quote! {
+            fn poly_type() -> ::poly::types::Type {
+                <#repr as ::poly::convert::PolyType>::poly_type()
+            }
+        }
+    })
+}
+
+#[proc_macro_derive(IntoValue)]
+pub fn derive_into_value(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input, "IntoValue", |repr, fields| {
+        let members: Vec<&Member> = fields.iter().map(|(m, _ty)| m).collect();
+        let nested = nest(&members.iter().map(|m| quote!(self.#m)).collect::<Vec<_>>());
+        quote! {
+            fn into_value(self) -> ::poly::eval::Value {
+                let repr: #repr = #nested;
+                ::poly::convert::IntoValue::into_value(repr)
+            }
+        }
+    })
+}
+
+#[proc_macro_derive(FromValue)]
+pub fn derive_from_value(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(&input, "FromValue", |repr, fields| {
+        let vars: Vec<_> = (0..fields.len()).map(|i| format_ident!("f{}", i)).collect();
+        let pattern = nest(&vars.iter().map(|v| quote!(#v)).collect::<Vec<_>>());
+        let inits = fields.iter().zip(&vars).map(|((m, _ty), v)| quote!(#m: #v));
+        quote! {
+            fn from_value(
+                val: ::poly::eval::Value,
+            ) -> ::std::result::Result<Self, ::poly::convert::FromValueError> {
+                let #pattern = <#repr as ::poly::convert::FromValue>::from_value(val)?;
+                ::std::result::Result::Ok(Self { #( #inits ),* })
+            }
+        }
+    })
+}
+
+// generate `impl ::poly::convert::<trait_name> for <struct>`, whose body is
+// produced by `body` from the struct's representation type & fields.
+fn expand(
+    input: &DeriveInput,
+    trait_name: &str,
+    body: impl Fn(&TokenStream2, &[(Member, Type)]) -> TokenStream2,
+) -> TokenStream {
+    let fields = match struct_fields(input) {
+        Ok(fields) => fields,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let trait_ident = format_ident!("{}", trait_name);
+    let repr = nest(
+        &fields
+            .iter()
+            .map(|(_m, ty)| quote!(#ty))
+            .collect::<Vec<_>>(),
+    );
+    let generics = add_trait_bounds(input.generics.clone(), &trait_ident);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let name = &input.ident;
+    let body = body(&repr, &fields);
+    let expanded = quote! {
+        impl #impl_generics ::poly::convert::#trait_ident for #name #ty_generics #where_clause {
+            #body
+        }
+    };
+    expanded.into()
+}
+
+fn struct_fields(input: &DeriveInput) -> Result<Vec<(Member, Type)>, Error> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "poly conversions can only be derived for structs",
+            ))
+        }
+    };
+    let fields: Vec<(Member, Type)> = match fields {
+        Fields::Named(named) => named
+            .named
+            .iter()
+            .map(|f| (Member::Named(f.ident.clone().unwrap()), f.ty.clone()))
+            .collect(),
+        Fields::Unnamed(unnamed) => unnamed
+            .unnamed
+            .iter()
+            .enumerate()
+            .map(|(i, f)| (Member::Unnamed(Index::from(i)), f.ty.clone()))
+            .collect(),
+        Fields::Unit => Vec::new(),
+    };
+    if fields.is_empty() {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "poly conversions cannot be derived for structs without fields",
+        ));
+    }
+    Ok(fields)
+}
+
+// nest `items` as pairs to the right: `a`, `(a, b)`, `(a, (b, c))`, ...
+fn nest(items: &[TokenStream2]) -> TokenStream2 {
+    match items {
+        [] => quote!(()),
+        [item] => item.clone(),
+        [item, rest @ ..] => {
+            let rest = nest(rest);
+            quote!((#item, #rest))
+        }
+    }
+}
+
+fn add_trait_bounds(mut generics: Generics, trait_ident: &syn::Ident) -> Generics {
+    for param in &mut generics.params {
+        if let GenericParam::Type(ty_param) = param {
+            ty_param
+                .bounds
+                .push(parse_quote!(::poly::convert::#trait_ident));
+        }
+    }
+    generics
+}
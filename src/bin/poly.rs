@@ -1,47 +1,76 @@
-use combine::parser::Parser;
-use combine::stream::easy;
 use std::env;
 use std::fs::File;
 use std::io::prelude::*;
+use std::path::Path;
 
 use poly::{
-    env::Env, eval::eval_program, infer::infer_program, parse::program, util::pretty::to_pretty,
+    engine::Engine,
+    lower::{anf_program, closure_convert},
+    module::FileResolver,
+    parse::parse_program,
+    toplevel::CalculationOptions,
+    util::pretty::to_pretty,
 };
 
 fn main() -> std::io::Result<()> {
     let width = 80;
-    let fp = get_fp()?;
-    let mut file = File::open(fp)?;
+    let (fp, prelude, dump_ir) = get_args()?;
+    let mut file = File::open(&fp)?;
     let mut contents = String::new();
     file.read_to_string(&mut contents)?;
 
-    match program().parse(easy::Stream(&contents[..])) {
+    match parse_program(&contents) {
         Err(err) => panic!("parse error: {}", err),
-        Ok((prog, extra_input)) => {
-            if extra_input != easy::Stream("") {
-                panic!("error: unconsumed input: {:?}", extra_input);
-            } else {
-                // println!("{}", to_pretty(prog.ppr(), width));
-                match infer_program(Env::new(), &prog) {
-                    Ok((sc, env)) => {
-                        println!("{:?}\n\n{:?}\n", sc, env);
-                        let ty = to_pretty(sc.ppr(), width);
-                        let (val, _env) = eval_program(&prog);
-                        let val_str = to_pretty(val.ppr(), width);
-                        println!("(: {}\n   {}\n)", val_str, ty);
-                        Ok(())
-                    }
-                    Err(err) => panic!("type error: {:?}", err),
+        Ok(prog) => {
+            if dump_ir {
+                // each lowering pass, as the program it produces.
+                let anf = anf_program(&prog);
+                let closures = closure_convert(&anf);
+                println!("; a-normal form\n\n{}\n", to_pretty(anf.ppr(), width));
+                println!(
+                    "; closure converted\n\n{}\n",
+                    to_pretty(closures.ppr(), width)
+                );
+            }
+            // imports are resolved relative to the directory of the program file.
+            let dir = Path::new(&fp).parent().unwrap_or_else(|| Path::new(""));
+            let options = CalculationOptions {
+                resolver: Box::new(FileResolver::new(vec![dir.to_path_buf()])),
+                prelude,
+                ..CalculationOptions::default()
+            };
+            let mut engine = Engine::with_options(options);
+            // println!("{}", to_pretty(prog.ppr(), width));
+            match engine.eval_program(&prog) {
+                Ok((val, sc)) => {
+                    println!("{:?}\n\n{:?}\n", sc, engine.type_env());
+                    let ty = to_pretty(sc.ppr(), width);
+                    let val_str = to_pretty(val.ppr(), width);
+                    println!("(: {}\n   {}\n)", val_str, ty);
+                    Ok(())
                 }
+                Err(err) => panic!("error: {:?}", err),
             }
         }
     }
 }
 
-fn get_fp() -> std::io::Result<String> {
+/// returns the filepath, whether the prelude should be loaded, & whether the
+/// lowered program should be dumped.
+fn get_args() -> std::io::Result<(String, bool, bool)> {
     let args: Vec<String> = env::args().collect();
     match args.as_slice() {
-        [_, fp] => Ok(fp.clone()),
-        _ => panic!("wanted one filepath, got {:?}", &args[1..]),
+        [_, flags @ .., fp]
+            if flags
+                .iter()
+                .all(|f| f == "--no-prelude" || f == "--dump-ir") =>
+        {
+            let has = |flag: &str| flags.iter().any(|f| f == flag);
+            Ok((fp.clone(), !has("--no-prelude"), has("--dump-ir")))
+        }
+        _ => panic!(
+            "wanted one filepath (optionally preceded by --no-prelude & --dump-ir), got {:?}",
+            &args[1..]
+        ),
     }
 }
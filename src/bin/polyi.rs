@@ -1,18 +1,17 @@
-use combine::parser::Parser;
-use combine::stream::easy;
 use rustyline::{error::ReadlineError, Editor};
-use std::collections::HashMap;
+use std::env;
 
 use poly::{
-    env::*,
-    eval::{eval_, EvalState},
-    infer::*,
-    parse::defn_or_it_expr,
-    syntax::Defn,
+    engine::{Engine, EngineError},
+    eval::{Strategy, Value},
+    parse::{parse_defn_or_it_expr, parse_expr},
+    syntax::{Defn, Name},
+    toplevel::CalculationOptions,
+    trace::Trace,
     util::pretty::to_pretty,
 };
 
-const BANNER: &'static str = r#"
+const BANNER: &str = r#"
                  __
     ____  ____  / /_  __      __________
    / __ \/ __ \/ / / / /_____/ ___/ ___/
@@ -29,35 +28,39 @@ fn main() {
         None => panic!("output is not a tty"),
         Some(dims) => dims,
     };
-    let mut type_env = Env::new();
-    let mut term_env = HashMap::new();
-    let mut es = EvalState::new();
+    // the prelude is loaded unless `--no-prelude` is passed, & evaluation is
+    // lazy if `--lazy` is.
+    let mut engine = Engine::with_options(CalculationOptions {
+        prelude: !env::args().any(|arg| arg == "--no-prelude"),
+        strategy: if env::args().any(|arg| arg == "--lazy") {
+            Strategy::Lazy
+        } else {
+            Strategy::Strict
+        },
+        ..CalculationOptions::default()
+    });
+    // the names of `defn`s at which `:step` stops when continuing.
+    let mut breakpoints: Vec<Name> = Vec::new();
     loop {
         let readline = rl.readline("> ");
         match readline {
             Ok(line) => {
                 rl.add_history_entry(line.as_str());
-                match defn_or_it_expr().parse(easy::Stream(&line[..])) {
-                    Err(err) => println!("parse error: {}", err),
-                    Ok((Defn(nm, e), extra_input)) => {
-                        if extra_input != easy::Stream("") {
-                            println!("error: unconsumed input: {:?}", extra_input);
-                        } else {
-                            println!("ast: {:?}\n", e);
-                            match infer_expr(&type_env, &e) {
-                                Err(err) => println!("type error: {:?}", err),
-                                Ok(sc) => {
-                                    let ty = to_pretty(sc.ppr(), width);
-                                    type_env.extend(nm.clone(), sc);
-                                    let val = eval_(&term_env, &mut es, &e);
-                                    let val_str = to_pretty(val.ppr(), width);
-                                    term_env.insert(nm, val);
-                                    println!("(: {}\n   {}\n)", val_str, ty);
-                                }
-                            }
-                        }
-                    }
+                let (cmd, arg) = match line.trim().split_once(' ') {
+                    Some((cmd, arg)) => (cmd, arg.trim()),
+                    None => (line.trim(), ""),
                 };
+                match cmd {
+                    ":trace" => trace(&mut engine, arg, width),
+                    ":step" => step(&mut rl, &mut engine, arg, &breakpoints, width),
+                    ":break" if arg.is_empty() => {
+                        let names: Vec<&str> = breakpoints.iter().map(|nm| &nm.0[..]).collect();
+                        println!("breakpoints: {}", names.join(" "));
+                    }
+                    ":break" => breakpoints.push(Name(arg.to_string())),
+                    ":delete" => breakpoints.retain(|nm| nm.0 != arg),
+                    _ => define(&mut engine, &line, width),
+                }
             }
             Err(ReadlineError::Interrupted) => continue,
             Err(ReadlineError::Eof) => {
@@ -71,3 +74,86 @@ fn main() {
         }
     }
 }
+
+fn define(engine: &mut Engine, line: &str, width: usize) {
+    match parse_defn_or_it_expr(line) {
+        Err(err) => println!("parse error: {}", err),
+        Ok(defn) => {
+            let Defn(_nm, e) = &defn;
+            println!("ast: {:?}\n", e);
+            match engine.define(&defn) {
+                Err(EngineError::Type(err)) => println!("type error: {:?}", err),
+                Err(err) => println!("error: {:?}", err),
+                Ok((val, sc)) => {
+                    let ty = to_pretty(sc.ppr(), width);
+                    let val_str = to_pretty(val.ppr(), width);
+                    println!("(: {}\n   {}\n)", val_str, ty);
+                }
+            }
+        }
+    };
+}
+
+// evaluate `src`, tracing its evaluation, or print why it cannot be.
+fn eval_traced(engine: &mut Engine, src: &str) -> Option<(Trace, Option<Value>)> {
+    let expr = match parse_expr(src) {
+        Ok(expr) => expr,
+        Err(err) => {
+            println!("parse error: {}", err);
+            return None;
+        }
+    };
+    match engine.eval_traced(&expr) {
+        (_trace, Err(EngineError::Type(err))) => {
+            println!("type error: {:?}", err);
+            None
+        }
+        (trace, Err(err)) => {
+            println!("error: {:?}", err);
+            Some((trace, None))
+        }
+        (trace, Ok((val, _sc))) => Some((trace, Some(val))),
+    }
+}
+
+/// `:trace <expr>`: print every step of the evaluation of the expression.
+fn trace(engine: &mut Engine, src: &str, width: usize) {
+    if let Some((trace, val)) = eval_traced(engine, src) {
+        println!("{}", to_pretty(trace.ppr(), width));
+        if let Some(val) = val {
+            println!("\n{}", to_pretty(val.ppr(), width));
+        }
+    }
+}
+
+/// `:step <expr>`: walk through the steps of the evaluation of the expression.
+/// the expression is evaluated first, & its trace replayed.
+fn step(rl: &mut Editor<()>, engine: &mut Engine, src: &str, breakpoints: &[Name], width: usize) {
+    let (trace, val) = match eval_traced(engine, src) {
+        Some(traced) => traced,
+        None => return,
+    };
+    let stops = trace.breakpoints(breakpoints);
+    println!(
+        "{} steps. <enter> steps, c continues to the next breakpoint, q quits.",
+        trace.len()
+    );
+    let mut ix = 0;
+    while ix < trace.len() {
+        let step = &trace.steps()[ix];
+        println!("[{}/{}] depth {}", ix + 1, trace.len(), step.depth);
+        println!("{}", to_pretty(step.ppr(), width));
+        match rl.readline("step> ").as_ref().map(|line| line.trim()) {
+            Ok("") | Ok("s") => ix += 1,
+            Ok("c") => match stops.iter().find(|&&stop| stop > ix) {
+                Some(&stop) => ix = stop,
+                None => break,
+            },
+            Ok("q") | Err(_) => return,
+            Ok(other) => println!("unknown step command: {}", other),
+        }
+    }
+    if let Some(val) = val {
+        println!("{}", to_pretty(val.ppr(), width));
+    }
+}
@@ -0,0 +1,319 @@
+//! an evaluator based on an abstract (CEK) machine.
+//!
+//! the machine state is a control (an expression to evaluate under an
+//! environment, a function to apply, or a value to return), along with an
+//! explicit stack of continuations. evaluation therefore runs in constant
+//! native stack, however deep the recursion of the program: the
+//! continuations live on the heap. calls in tail position push no
+//! continuation, & neither do `map` & `foldl`, which iterate through
+//! continuations rather than recursing.
+//!
+//! a `Machine` may be stepped, or run for a bounded number of steps, and so
+//! paused & resumed. the results agree with those of `eval_`.
+
+use std::{mem, vec};
+
+use super::{
+    eval::{
+        apply, apply_primop, lift_primop, named_closure, primop_apply_case, EvalError, EvalState,
+        Limits, PrimOpApplyCase, TermEnv, Value,
+    },
+    syntax::{Expr, Lit, Name, PrimOp},
+};
+use crate::{app, lam};
+
+#[derive(Clone)]
+enum Control {
+    Eval(Expr, TermEnv),
+    Apply(Value, Value),
+    Return(Value),
+    /// a step failed: the machine cannot be resumed.
+    Failed(EvalError),
+}
+
+#[derive(Clone)]
+enum Kont {
+    /// evaluate the argument of an application, whose function is being
+    /// evaluated.
+    Arg(Expr, TermEnv),
+    /// apply the function to the argument being evaluated.
+    Call(Value),
+    /// apply the function being evaluated (or returned) to the argument.
+    ApplyTo(Value),
+    /// evaluate the remaining arguments of a fully applied `PrimOp`. the
+    /// remaining arguments are in reverse order.
+    PrimArgs(PrimOp, Vec<Value>, Vec<Expr>, TermEnv),
+    Branch(Expr, Expr, TermEnv),
+    Let(Name, Expr, TermEnv),
+    /// collect the results of mapping the function over the remaining values.
+    Map(Value, Vec<Value>, vec::IntoIter<Value>),
+    /// fold the function over the remaining values, given the accumulator.
+    Foldl(Value, vec::IntoIter<Value>),
+}
+
+/// a CEK machine, evaluating an expression. it owns its state, & so may be
+/// cloned (e.g. to snapshot evaluation part way through) or sent between
+/// threads.
+#[derive(Clone)]
+pub struct Machine {
+    control: Control,
+    kont: Vec<Kont>,
+    es: EvalState,
+}
+
+impl Machine {
+    /// a machine evaluating `expr` (assumed to be well typed) under `env`.
+    pub fn new(env: TermEnv, expr: Expr) -> Machine {
+        Machine::with_state(env, expr, EvalState::new())
+    }
+
+    /// as `new`, but stepping under the limits of `es`, in which each step of
+    /// the machine counts as a step, & each pending continuation as a level
+    /// of depth.
+    pub fn with_state(env: TermEnv, expr: Expr, es: EvalState) -> Machine {
+        Machine {
+            control: Control::Eval(expr, env),
+            kont: Vec::new(),
+            es,
+        }
+    }
+
+    /// the value of the expression, once evaluation is complete.
+    pub fn result(&self) -> Option<&Value> {
+        match (&self.control, self.kont.is_empty()) {
+            (Control::Return(val), true) => Some(val),
+            _ => None,
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.result().is_some()
+    }
+
+    /// the number of pending continuations.
+    pub fn depth(&self) -> usize {
+        self.kont.len()
+    }
+
+    pub fn state(&self) -> &EvalState {
+        &self.es
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.es.set_limits(limits);
+    }
+
+    pub fn into_state(self) -> EvalState {
+        self.es
+    }
+
+    /// run to completion.
+    pub fn run(&mut self) -> Result<Value, EvalError> {
+        loop {
+            if let Some(val) = self.result() {
+                return Ok(val.clone());
+            }
+            self.step()?;
+        }
+    }
+
+    /// run for at most `steps` steps. returns the value of the expression if
+    /// evaluation completes, & otherwise pauses, to be resumed by a later
+    /// call.
+    pub fn run_for(&mut self, steps: u64) -> Result<Option<Value>, EvalError> {
+        for _ in 0..steps {
+            if self.is_done() {
+                break;
+            }
+            self.step()?;
+        }
+        Ok(self.result().cloned())
+    }
+
+    /// take a single step. a machine which is done stays put, & one which
+    /// has failed fails again.
+    pub fn step(&mut self) -> Result<(), EvalError> {
+        if self.is_done() {
+            return Ok(());
+        }
+        if let Control::Failed(err) = &self.control {
+            return Err(err.clone());
+        }
+        // exceeding a limit leaves the machine as it was, so that it may be
+        // resumed under raised limits.
+        self.es.tick(self.kont.len())?;
+        let control = match mem::replace(&mut self.control, Control::Return(Value::VInt(0))) {
+            Control::Eval(expr, env) => Ok(self.eval(expr, env)),
+            Control::Apply(fun, arg) => self.apply(fun, arg),
+            Control::Return(val) => self.ret(val),
+            Control::Failed(err) => Err(err),
+        };
+        match control {
+            Ok(control) => {
+                self.control = control;
+                Ok(())
+            }
+            Err(err) => {
+                self.control = Control::Failed(err.clone());
+                Err(err)
+            }
+        }
+    }
+
+    fn eval(&mut self, expr: Expr, env: TermEnv) -> Control {
+        match primop_apply_case(&mut self.es, &expr) {
+            PrimOpApplyCase::FullyApplied(op, mut args) => {
+                args.reverse();
+                match args.pop() {
+                    Some(arg) => {
+                        let kont = Kont::PrimArgs(op, Vec::new(), args, env.clone());
+                        self.kont.push(kont);
+                        Control::Eval(arg, env)
+                    }
+                    None => panic!("impossible: {:?} has arity 0", op),
+                }
+            }
+            PrimOpApplyCase::PartiallyApplied(lam) => Control::Eval(lam, env),
+            PrimOpApplyCase::Other => match expr {
+                Expr::Lit(Lit::LInt(x)) => Control::Return(Value::VInt(x)),
+                Expr::Lit(Lit::LBool(x)) => Control::Return(Value::VBool(x)),
+                Expr::Var(x) => match env.get(&x) {
+                    None => panic!("impossible: free variable: {:?}", x),
+                    Some(v) => Control::Return(v.clone()),
+                },
+                Expr::Lam(nm, bd) => {
+                    self.es.charge(self.es.costs().allocation);
+                    Control::Return(Value::VClosure(nm, bd, env))
+                }
+                Expr::Let(x, e, bd) => {
+                    self.kont.push(Kont::Let(x, *bd, env.clone()));
+                    Control::Eval(*e, env)
+                }
+                Expr::If(tst, thn, els) => {
+                    self.kont.push(Kont::Branch(*thn, *els, env.clone()));
+                    Control::Eval(*tst, env)
+                }
+                Expr::Prim(PrimOp::Nil) => Control::Return(Value::VList(Vec::new())),
+                Expr::Prim(op) => Control::Return(lift_primop(&mut self.es, &op)),
+                Expr::App(fun, arg) => {
+                    self.kont.push(Kont::Arg(*arg, env.clone()));
+                    Control::Eval(*fun, env)
+                }
+                // as for `eval_`, `(fix e)` unfolds to `(e (lam [x] ((fix e) x)))`.
+                Expr::Fix(e) => {
+                    let x = self.es.fresh();
+                    let delayed = lam!(x.clone(), app!(Expr::Fix(e.clone()), Expr::Var(x)));
+                    Control::Eval(app!(*e, delayed), env)
+                }
+            },
+        }
+    }
+
+    fn apply(&mut self, fun: Value, arg: Value) -> Result<Control, EvalError> {
+        match fun {
+            Value::VClosure(nm, bd, clo) => {
+                self.es.charge(self.es.costs().application);
+                Ok(Control::Eval(*bd, clo.update(nm, arg)))
+            }
+            // a core closure (e.g. from a strictly evaluated `defn`) is
+            // applied as written, so that its body is stepped by the machine.
+            Value::VCore(nm, bd, locals, globals) => {
+                self.es.charge(self.es.costs().application);
+                let (nm, bd, clo) = named_closure(&nm, &bd, &locals, &globals);
+                Ok(Control::Eval(*bd, clo.update(nm, arg)))
+            }
+            // natives & compiled closures run to completion in a single step.
+            fun => Ok(Control::Return(apply(&mut self.es, fun, arg)?)),
+        }
+    }
+
+    fn ret(&mut self, val: Value) -> Result<Control, EvalError> {
+        let kont = match self.kont.pop() {
+            Some(kont) => kont,
+            None => panic!("impossible: cek: return with no continuation"),
+        };
+        let control = match kont {
+            Kont::Arg(arg, env) => {
+                self.kont.push(Kont::Call(val));
+                Control::Eval(arg, env)
+            }
+            Kont::Call(fun) => Control::Apply(fun, val),
+            Kont::ApplyTo(arg) => Control::Apply(val, arg),
+            Kont::PrimArgs(op, mut vals, mut args, env) => {
+                vals.push(val);
+                match args.pop() {
+                    Some(arg) => {
+                        self.kont.push(Kont::PrimArgs(op, vals, args, env.clone()));
+                        Control::Eval(arg, env)
+                    }
+                    None => self.prim(op, vals)?,
+                }
+            }
+            Kont::Branch(thn, els, env) => match val {
+                Value::VBool(true) => Control::Eval(thn, env),
+                Value::VBool(false) => Control::Eval(els, env),
+                _ => panic!("impossible: non-bool in test position of if"),
+            },
+            Kont::Let(x, bd, env) => Control::Eval(bd, env.update(x, val)),
+            Kont::Map(fun, mut done, rest) => {
+                done.push(val);
+                self.map(fun, done, rest)
+            }
+            Kont::Foldl(fun, rest) => self.foldl(fun, val, rest),
+        };
+        Ok(control)
+    }
+
+    // apply a fully applied PrimOp. the higher order ones apply their function
+    // argument through continuations.
+    fn prim(&mut self, op: PrimOp, mut vals: Vec<Value>) -> Result<Control, EvalError> {
+        if op == PrimOp::Map || op == PrimOp::Foldl {
+            self.es.charge_primop(&op, &vals);
+        }
+        match op {
+            PrimOp::Map => match (vals.pop(), vals.pop()) {
+                (Some(Value::VList(xs)), Some(fun)) => {
+                    Ok(self.map(fun, Vec::new(), xs.into_iter()))
+                }
+                _ => panic!("map: bad types"),
+            },
+            PrimOp::Foldl => match (vals.pop(), vals.pop(), vals.pop()) {
+                (Some(Value::VList(xs)), Some(acc), Some(fun)) => {
+                    Ok(self.foldl(fun, acc, xs.into_iter()))
+                }
+                _ => panic!("foldl: bad types"),
+            },
+            op => Ok(Control::Return(apply_primop(&mut self.es, op, vals)?)),
+        }
+    }
+
+    fn map(&mut self, fun: Value, done: Vec<Value>, mut rest: vec::IntoIter<Value>) -> Control {
+        match rest.next() {
+            None => Control::Return(Value::VList(done)),
+            Some(x) => {
+                self.kont.push(Kont::Map(fun.clone(), done, rest));
+                Control::Apply(fun, x)
+            }
+        }
+    }
+
+    fn foldl(&mut self, fun: Value, acc: Value, mut rest: vec::IntoIter<Value>) -> Control {
+        match rest.next() {
+            None => Control::Return(acc),
+            Some(x) => {
+                self.kont.push(Kont::Foldl(fun.clone(), rest));
+                self.kont.push(Kont::ApplyTo(x));
+                Control::Apply(fun, acc)
+            }
+        }
+    }
+}
+
+/// evaluate `expr` under `env` with a CEK machine. as for `eval_`, evaluation
+/// only fails if it exceeds the limits of `es`.
+pub fn eval_cek(env: &TermEnv, es: &mut EvalState, expr: &Expr) -> Result<Value, EvalError> {
+    let mut machine = Machine::with_state(env.clone(), expr.clone(), mem::take(es));
+    let res = machine.run();
+    *es = machine.into_state();
+    res
+}
@@ -0,0 +1,239 @@
+//! conversions between Rust values & `Value`s.
+//!
+//! | Rust                     | poly                        |
+//! |--------------------------|-----------------------------|
+//! | `i64`                    | `Int`                       |
+//! | `bool`                   | `Bool`                      |
+//! | `Vec<T>`                 | `(List T)`                  |
+//! | `Option<T>`              | `(List T)`, of length 0 or 1 |
+//! | `(A, B)`                 | `(A, B)`                    |
+//! | `(A, B, C)`, ...         | `(A, (B, C))`, ...          |
+//! | struct with fields `a, b, c` | `(A, (B, C))`           |
+//!
+//! structs (with at least one field) get these conversions via
+//! `#[derive(PolyType, IntoValue, FromValue)]`, with the `derive` feature.
+//! a struct with a single field is represented by that field alone.
+
+use std::fmt;
+
+use super::{
+    eval::{
+        EvalError,
+        Value::{self, *},
+    },
+    types::{type_bool, type_int, type_list, type_pair, Type},
+    util::pretty::to_pretty,
+};
+
+#[cfg(feature = "derive")]
+pub use poly_derive::{FromValue, IntoValue, PolyType};
+
+/// a Rust type which corresponds to a poly type.
+pub trait PolyType {
+    fn poly_type() -> Type;
+}
+
+pub trait IntoValue: PolyType {
+    fn into_value(self) -> Value;
+}
+
+pub trait FromValue: PolyType + Sized {
+    fn from_value(val: Value) -> Result<Self, FromValueError>;
+}
+
+/// a `Value` was not of the shape expected for the Rust type, or (under the
+/// lazy strategy) could not be forced.
+#[derive(Debug)]
+pub struct FromValueError {
+    pub expected: Type,
+    /// the offending value, pretty printed.
+    pub found: String,
+    /// the failure of forcing the value, if it could not be forced.
+    pub error: Option<EvalError>,
+}
+
+impl FromValueError {
+    pub fn new(expected: Type, found: &Value) -> FromValueError {
+        FromValueError {
+            expected,
+            found: to_pretty(found.ppr(), 80),
+            error: None,
+        }
+    }
+
+    pub fn failed(expected: Type, err: EvalError) -> FromValueError {
+        FromValueError {
+            expected,
+            found: format!("<<error: {:?}>>", err),
+            error: Some(err),
+        }
+    }
+}
+
+impl fmt::Display for FromValueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.error {
+            None => write!(
+                f,
+                "expected a value of type {}, found {}",
+                to_pretty(self.expected.ppr(), 80),
+                self.found
+            ),
+            Some(err) => write!(
+                f,
+                "expected a value of type {}, but forcing it failed: {:?}",
+                to_pretty(self.expected.ppr(), 80),
+                err
+            ),
+        }
+    }
+}
+
+// `val`, with any thunk at its head forced, to be converted to a `T`.
+fn forced<T: PolyType>(val: Value) -> Result<Value, FromValueError> {
+    val.forced()
+        .map_err(|err| FromValueError::failed(T::poly_type(), err))
+}
+
+impl PolyType for i64 {
+    fn poly_type() -> Type {
+        type_int()
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self) -> Value {
+        VInt(self)
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(val: Value) -> Result<Self, FromValueError> {
+        let val = forced::<Self>(val)?;
+        match val {
+            VInt(n) => Ok(n),
+            _ => Err(FromValueError::new(Self::poly_type(), &val)),
+        }
+    }
+}
+
+impl PolyType for bool {
+    fn poly_type() -> Type {
+        type_bool()
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        VBool(self)
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(val: Value) -> Result<Self, FromValueError> {
+        let val = forced::<Self>(val)?;
+        match val {
+            VBool(b) => Ok(b),
+            _ => Err(FromValueError::new(Self::poly_type(), &val)),
+        }
+    }
+}
+
+impl<T: PolyType> PolyType for Vec<T> {
+    fn poly_type() -> Type {
+        type_list(T::poly_type())
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        VList(self.into_iter().map(IntoValue::into_value).collect())
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(val: Value) -> Result<Self, FromValueError> {
+        let val = forced::<Self>(val)?;
+        match val {
+            VList(vals) => vals.into_iter().map(T::from_value).collect(),
+            _ => Err(FromValueError::new(Self::poly_type(), &val)),
+        }
+    }
+}
+
+impl<T: PolyType> PolyType for Option<T> {
+    fn poly_type() -> Type {
+        type_list(T::poly_type())
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        VList(self.into_iter().map(IntoValue::into_value).collect())
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(val: Value) -> Result<Self, FromValueError> {
+        let val = forced::<Self>(val)?;
+        match val {
+            VList(vals) if vals.len() <= 1 => {
+                vals.into_iter().next().map(T::from_value).transpose()
+            }
+            _ => Err(FromValueError::new(Self::poly_type(), &val)),
+        }
+    }
+}
+
+impl<A: PolyType, B: PolyType> PolyType for (A, B) {
+    fn poly_type() -> Type {
+        type_pair(A::poly_type(), B::poly_type())
+    }
+}
+
+impl<A: IntoValue, B: IntoValue> IntoValue for (A, B) {
+    fn into_value(self) -> Value {
+        VPair(Box::new(self.0.into_value()), Box::new(self.1.into_value()))
+    }
+}
+
+impl<A: FromValue, B: FromValue> FromValue for (A, B) {
+    fn from_value(val: Value) -> Result<Self, FromValueError> {
+        let val = forced::<Self>(val)?;
+        match val {
+            VPair(a, b) => Ok((A::from_value(*a)?, B::from_value(*b)?)),
+            _ => Err(FromValueError::new(Self::poly_type(), &val)),
+        }
+    }
+}
+
+// larger tuples are represented as pairs nested to the right.
+macro_rules! nested_tuple {
+    ( $a: ident, $( $rest: ident ),+ ) => {
+        impl<$a: PolyType, $( $rest: PolyType ),+> PolyType for ($a, $( $rest ),+) {
+            fn poly_type() -> Type {
+                <($a, ($( $rest ),+))>::poly_type()
+            }
+        }
+
+        impl<$a: IntoValue, $( $rest: IntoValue ),+> IntoValue for ($a, $( $rest ),+) {
+            #[allow(non_snake_case)]
+            fn into_value(self) -> Value {
+                let ($a, $( $rest ),+) = self;
+                ($a, ($( $rest ),+)).into_value()
+            }
+        }
+
+        impl<$a: FromValue, $( $rest: FromValue ),+> FromValue for ($a, $( $rest ),+) {
+            #[allow(non_snake_case)]
+            fn from_value(val: Value) -> Result<Self, FromValueError> {
+                let ($a, ($( $rest ),+)) = <($a, ($( $rest ),+))>::from_value(val)?;
+                Ok(($a, $( $rest ),+))
+            }
+        }
+    };
+}
+
+nested_tuple!(A, B, C);
+nested_tuple!(A, B, C, D);
+nested_tuple!(A, B, C, D, E);
@@ -0,0 +1,308 @@
+//! a resolved core IR, in which the variables bound by `lam`s & `let`s are
+//! de Bruijn indices, & only the free variables (e.g. `defn`s, the prelude &
+//! the inputs of a calculation) are names.
+//!
+//! a bound variable cannot be captured, so substitution needs no renaming, &
+//! the evaluator finds locals by their position rather than by name (see
+//! `eval::eval_core`). each binder keeps the name it was written with, which
+//! `Core::to_expr` reuses unless it would capture another variable.
+
+use std::{cmp::Ordering, collections::HashSet};
+
+use super::syntax::{Expr, Lit, Name, PrimOp};
+use crate::{app, lam};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Core {
+    /// a bound variable, by the number of binders between it & its own: 0 is
+    /// the innermost.
+    Local(usize),
+    /// a free variable.
+    Global(Name),
+    App(Box<Core>, Box<Core>),
+    Lam(Name, Box<Core>),
+    Let(Name, Box<Core>, Box<Core>),
+    Lit(Lit),
+    If(Box<Core>, Box<Core>, Box<Core>),
+    Fix(Box<Core>),
+    Prim(PrimOp),
+}
+
+pub(crate) fn capp(fun: Core, arg: Core) -> Core {
+    Core::App(Box::new(fun), Box::new(arg))
+}
+
+/// resolve the bound variables of `expr`.
+pub fn resolve(expr: &Expr) -> Core {
+    resolve_in(&mut Vec::new(), expr)
+}
+
+/// resolve `expr` under binders of the names `bound`, the innermost last.
+pub fn resolve_under(bound: &[Name], expr: &Expr) -> Core {
+    resolve_in(&mut bound.to_vec(), expr)
+}
+
+fn resolve_in(scope: &mut Vec<Name>, expr: &Expr) -> Core {
+    let under = |scope: &mut Vec<Name>, x: &Name, bd: &Expr| {
+        scope.push(x.clone());
+        let bd = resolve_in(scope, bd);
+        scope.pop();
+        Box::new(bd)
+    };
+    match expr {
+        Expr::Var(x) => match scope.iter().rposition(|nm| nm == x) {
+            Some(ix) => Core::Local(scope.len() - 1 - ix),
+            None => Core::Global(x.clone()),
+        },
+        Expr::App(fun, arg) => capp(resolve_in(scope, fun), resolve_in(scope, arg)),
+        Expr::Lam(x, bd) => Core::Lam(x.clone(), under(scope, x, bd)),
+        Expr::Let(x, e, bd) => {
+            let e = Box::new(resolve_in(scope, e));
+            Core::Let(x.clone(), e, under(scope, x, bd))
+        }
+        Expr::Lit(lit) => Core::Lit(lit.clone()),
+        Expr::If(tst, thn, els) => Core::If(
+            Box::new(resolve_in(scope, tst)),
+            Box::new(resolve_in(scope, thn)),
+            Box::new(resolve_in(scope, els)),
+        ),
+        Expr::Fix(e) => Core::Fix(Box::new(resolve_in(scope, e))),
+        Expr::Prim(op) => Core::Prim(op.clone()),
+    }
+}
+
+impl Core {
+    /// the expression, naming each binder by the name it was written with, or
+    /// by that name & a number, where that name would capture a variable.
+    pub fn to_expr(&self) -> Expr {
+        self.named(&mut Vec::new())
+    }
+
+    /// as `to_expr`, for a term under binders of the names `hints`, the
+    /// innermost last. the names chosen for those binders are returned along
+    /// with the expression.
+    pub fn to_expr_under(&self, hints: &[Name]) -> (Vec<Name>, Expr) {
+        // the binders are named as `let`s would be.
+        let wrapped = hints.iter().rev().fold(self.clone(), |bd, hint| {
+            Core::Let(
+                hint.clone(),
+                Box::new(Core::Lit(Lit::LInt(0))),
+                Box::new(bd),
+            )
+        });
+        let mut names = Vec::new();
+        let mut expr = wrapped.to_expr();
+        for _hint in hints {
+            match expr {
+                Expr::Let(x, _e, bd) => {
+                    names.push(x);
+                    expr = *bd;
+                }
+                _ => panic!("impossible: to_expr_under: lost a binder"),
+            }
+        }
+        (names, expr)
+    }
+
+    fn named(&self, scope: &mut Vec<Name>) -> Expr {
+        match self {
+            Core::Local(ix) => Expr::Var(scope[scope.len() - 1 - ix].clone()),
+            Core::Global(x) => Expr::Var(x.clone()),
+            Core::App(fun, arg) => app!(fun.named(scope), arg.named(scope)),
+            Core::Lam(hint, bd) => {
+                let x = binder_name(hint, bd, scope);
+                lam!(x.clone(), bd.named_under(scope, x))
+            }
+            Core::Let(hint, e, bd) => {
+                let e = e.named(scope);
+                let x = binder_name(hint, bd, scope);
+                Expr::Let(x.clone(), Box::new(e), Box::new(bd.named_under(scope, x)))
+            }
+            Core::Lit(lit) => Expr::Lit(lit.clone()),
+            Core::If(tst, thn, els) => Expr::If(
+                Box::new(tst.named(scope)),
+                Box::new(thn.named(scope)),
+                Box::new(els.named(scope)),
+            ),
+            Core::Fix(e) => Expr::Fix(Box::new(e.named(scope))),
+            Core::Prim(op) => Expr::Prim(op.clone()),
+        }
+    }
+
+    fn named_under(&self, scope: &mut Vec<Name>, x: Name) -> Expr {
+        scope.push(x);
+        let expr = self.named(scope);
+        scope.pop();
+        expr
+    }
+
+    /// the term, under `by` more binders.
+    pub fn shift(&self, by: usize) -> Core {
+        self.map_locals(0, &|ix, depth| {
+            if ix >= depth {
+                Core::Local(ix + by)
+            } else {
+                Core::Local(ix)
+            }
+        })
+    }
+
+    /// the body of a binder, with `val` substituted for the variable it binds.
+    /// `val` is not under the binder.
+    pub fn instantiate(&self, val: &Core) -> Core {
+        self.map_locals(0, &|ix, depth| match ix.cmp(&depth) {
+            Ordering::Equal => val.shift(depth),
+            Ordering::Greater => Core::Local(ix - 1),
+            Ordering::Less => Core::Local(ix),
+        })
+    }
+
+    /// the body of a binder whose variable it does not use, without the
+    /// binder.
+    pub fn strengthen(&self) -> Core {
+        self.map_locals(0, &|ix, depth| match ix.cmp(&depth) {
+            Ordering::Equal => panic!("impossible: strengthen: the variable is used"),
+            Ordering::Greater => Core::Local(ix - 1),
+            Ordering::Less => Core::Local(ix),
+        })
+    }
+
+    // rebuild the term, replacing each `Local(ix)`, under `depth` binders, by
+    // `f(ix, depth)`.
+    fn map_locals(&self, depth: usize, f: &dyn Fn(usize, usize) -> Core) -> Core {
+        match self {
+            Core::Local(ix) => f(*ix, depth),
+            Core::Global(_) | Core::Lit(_) | Core::Prim(_) => self.clone(),
+            Core::App(fun, arg) => capp(fun.map_locals(depth, f), arg.map_locals(depth, f)),
+            Core::Lam(x, bd) => Core::Lam(x.clone(), Box::new(bd.map_locals(depth + 1, f))),
+            Core::Let(x, e, bd) => Core::Let(
+                x.clone(),
+                Box::new(e.map_locals(depth, f)),
+                Box::new(bd.map_locals(depth + 1, f)),
+            ),
+            Core::If(tst, thn, els) => Core::If(
+                Box::new(tst.map_locals(depth, f)),
+                Box::new(thn.map_locals(depth, f)),
+                Box::new(els.map_locals(depth, f)),
+            ),
+            Core::Fix(e) => Core::Fix(Box::new(e.map_locals(depth, f))),
+        }
+    }
+
+    /// the number of occurrences of the local `ix`.
+    pub fn occurrences(&self, ix: usize) -> usize {
+        let mut count = 0;
+        self.each_local(0, &mut |local, depth| {
+            if local == ix + depth {
+                count += 1;
+            }
+        });
+        count
+    }
+
+    /// the locals which are free in the term.
+    pub fn free_locals(&self) -> HashSet<usize> {
+        let mut free = HashSet::new();
+        self.each_local(0, &mut |local, depth| {
+            if local >= depth {
+                free.insert(local - depth);
+            }
+        });
+        free
+    }
+
+    // call `f(ix, depth)` on each `Local(ix)`, under `depth` binders.
+    fn each_local(&self, depth: usize, f: &mut dyn FnMut(usize, usize)) {
+        match self {
+            Core::Local(ix) => f(*ix, depth),
+            Core::Global(_) | Core::Lit(_) | Core::Prim(_) => (),
+            Core::App(fun, arg) => {
+                fun.each_local(depth, f);
+                arg.each_local(depth, f);
+            }
+            Core::Lam(_x, bd) => bd.each_local(depth + 1, f),
+            Core::Let(_x, e, bd) => {
+                e.each_local(depth, f);
+                bd.each_local(depth + 1, f);
+            }
+            Core::If(tst, thn, els) => {
+                tst.each_local(depth, f);
+                thn.each_local(depth, f);
+                els.each_local(depth, f);
+            }
+            Core::Fix(e) => e.each_local(depth, f),
+        }
+    }
+
+    /// the free variables of the term, which are its globals.
+    pub fn globals(&self) -> HashSet<Name> {
+        let mut globals = HashSet::new();
+        self.each_global(&mut |x| {
+            globals.insert(x.clone());
+        });
+        globals
+    }
+
+    fn each_global(&self, f: &mut dyn FnMut(&Name)) {
+        match self {
+            Core::Global(x) => f(x),
+            Core::Local(_) | Core::Lit(_) | Core::Prim(_) => (),
+            Core::App(fun, arg) => {
+                fun.each_global(f);
+                arg.each_global(f);
+            }
+            Core::Lam(_x, bd) => bd.each_global(f),
+            Core::Let(_x, e, bd) => {
+                e.each_global(f);
+                bd.each_global(f);
+            }
+            Core::If(tst, thn, els) => {
+                tst.each_global(f);
+                thn.each_global(f);
+                els.each_global(f);
+            }
+            Core::Fix(e) => e.each_global(f),
+        }
+    }
+
+    /// the term, with `val` substituted for the global `x`. `val` must not
+    /// have free locals.
+    pub fn subst_global(&self, x: &Name, val: &Core) -> Core {
+        match self {
+            Core::Global(y) if y == x => val.clone(),
+            Core::Global(_) | Core::Local(_) | Core::Lit(_) | Core::Prim(_) => self.clone(),
+            Core::App(fun, arg) => capp(fun.subst_global(x, val), arg.subst_global(x, val)),
+            Core::Lam(y, bd) => Core::Lam(y.clone(), Box::new(bd.subst_global(x, val))),
+            Core::Let(y, e, bd) => Core::Let(
+                y.clone(),
+                Box::new(e.subst_global(x, val)),
+                Box::new(bd.subst_global(x, val)),
+            ),
+            Core::If(tst, thn, els) => Core::If(
+                Box::new(tst.subst_global(x, val)),
+                Box::new(thn.subst_global(x, val)),
+                Box::new(els.subst_global(x, val)),
+            ),
+            Core::Fix(e) => Core::Fix(Box::new(e.subst_global(x, val))),
+        }
+    }
+}
+
+// the name for a binder written as `hint`, whose body is `bd`, under binders
+// named `scope`: the name must not capture a global of the body, nor an
+// enclosing binder the body refers to.
+fn binder_name(hint: &Name, bd: &Core, scope: &[Name]) -> Name {
+    let mut avoid = bd.globals();
+    for ix in bd.free_locals() {
+        if ix > 0 {
+            avoid.insert(scope[scope.len() - ix].clone());
+        }
+    }
+    let mut x = hint.clone();
+    let mut suffix = 0;
+    while avoid.contains(&x) {
+        suffix += 1;
+        x = Name::lettered(&hint.0, suffix);
+    }
+    x
+}
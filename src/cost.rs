@@ -0,0 +1,550 @@
+//! deterministic accounting of the cost of evaluation, in units of gas.
+//!
+//! the gas used by an evaluation depends only on the program & its inputs
+//! (& on the evaluator), never on the speed of the host. it is the sum of the
+//! costs, from a `CostTable`, of:
+//!
+//! - each fully applied `PrimOp`;
+//! - each application of a function (a closure, a native, or a lifted
+//!   `PrimOp`) to an argument;
+//! - each allocation of a closure, a pair, a list cell, or (under the lazy
+//!   strategy) a thunk;
+//! - each list element processed by `map` or `foldl`.
+//!
+//! `gas_bound` estimates an upper bound on the gas used by the strict
+//! evaluation of a program with no `fix`, given a bound on the lengths of its
+//! input lists.
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use std::sync::Arc;
+
+use super::{
+    eval::{
+        named_closure, primop_apply_case, EvalError, EvalState, PrimOpApplyCase, TermEnv, Value,
+    },
+    native::Native,
+    syntax::{primop_arity, Expr, Name, PrimOp},
+    types::Type,
+};
+
+/// the cost of each operation, in gas.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CostTable {
+    pub add: u64,
+    pub sub: u64,
+    pub mul: u64,
+    pub eql: u64,
+    pub null: u64,
+    pub map: u64,
+    pub foldl: u64,
+    pub pair: u64,
+    pub fst: u64,
+    pub snd: u64,
+    pub cons: u64,
+    pub application: u64,
+    pub allocation: u64,
+    pub element: u64,
+}
+
+impl Default for CostTable {
+    fn default() -> Self {
+        CostTable {
+            add: 1,
+            sub: 1,
+            mul: 2,
+            eql: 1,
+            null: 1,
+            map: 2,
+            foldl: 2,
+            pair: 1,
+            fst: 1,
+            snd: 1,
+            cons: 1,
+            application: 3,
+            allocation: 2,
+            element: 1,
+        }
+    }
+}
+
+impl CostTable {
+    /// the cost of a fully applied `PrimOp`, not counting the elements it
+    /// processes or the values it allocates.
+    pub fn primop(&self, op: &PrimOp) -> u64 {
+        match op {
+            PrimOp::Add => self.add,
+            PrimOp::Sub => self.sub,
+            PrimOp::Mul => self.mul,
+            PrimOp::Eql => self.eql,
+            PrimOp::Null => self.null,
+            PrimOp::Map => self.map,
+            PrimOp::Foldl => self.foldl,
+            PrimOp::Pair => self.pair,
+            PrimOp::Fst => self.fst,
+            PrimOp::Snd => self.snd,
+            PrimOp::Cons => self.cons,
+            // `nil` is a value, which is never applied.
+            PrimOp::Nil => 0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BoundError {
+    /// the program uses `fix`, so that its cost may be unbounded.
+    Recursive,
+    /// the program calls a compiled closure, whose body is not available.
+    Compiled,
+    /// an input, or the result of a native, is a function, whose cost is
+    /// unknown.
+    FunctionValued,
+    /// forcing a thunk of the lazy strategy, in an input, failed.
+    Eval(EvalError),
+}
+
+/// an upper bound on the gas used by the strict evaluation of `expr` under
+/// `env`, with the costs of `costs`, where each of `inputs` is bound to any
+/// value of its type in which no list is longer than `max_len`. lists returned
+/// by natives are assumed to be no longer than `max_len` either.
+pub fn gas_bound(
+    costs: &CostTable,
+    env: &TermEnv,
+    expr: &Expr,
+    inputs: &[(Name, Type)],
+    max_len: u64,
+) -> Result<u64, BoundError> {
+    let mut abs_env = AbsEnv::new(env.clone());
+    for (nm, ty) in inputs {
+        abs_env = abs_env.update(nm.clone(), abstract_type(ty, max_len)?);
+    }
+    let mut bound = Bound {
+        costs,
+        es: EvalState::new(),
+        max_len,
+    };
+    bound.eval(&abs_env, expr).map(|(gas, _val)| gas)
+}
+
+// an abstraction of the values an expression may evaluate to.
+#[derive(Clone)]
+enum Abs {
+    // no value, e.g. the elements of an empty list.
+    Never,
+    Scalar,
+    // lists of at most the given length.
+    List(u64, Box<Abs>),
+    Pair(Box<Abs>, Box<Abs>),
+    // any one of the functions.
+    Funs(Vec<Fun>),
+}
+
+#[derive(Clone)]
+enum Fun {
+    Closure(Name, Expr, AbsEnv),
+    // a lifted PrimOp, applied to some of its arguments.
+    Prim(PrimOp, Vec<Abs>),
+    // a native, applied to the given number of arguments.
+    Native(Arc<Native>, usize),
+}
+
+// the values of a program are abstracted lazily, as they are looked up.
+#[derive(Clone)]
+struct AbsEnv {
+    abs: im::HashMap<Name, Abs>,
+    concrete: TermEnv,
+}
+
+impl AbsEnv {
+    fn new(concrete: TermEnv) -> AbsEnv {
+        AbsEnv {
+            abs: im::HashMap::new(),
+            concrete,
+        }
+    }
+
+    fn update(&self, nm: Name, val: Abs) -> AbsEnv {
+        AbsEnv {
+            abs: self.abs.update(nm, val),
+            concrete: self.concrete.clone(),
+        }
+    }
+
+    fn lookup(&self, nm: &Name) -> Result<Abs, BoundError> {
+        match (self.abs.get(nm), self.concrete.get(nm)) {
+            (Some(val), _) => Ok(val.clone()),
+            (None, Some(val)) => abstract_value(val),
+            (None, None) => panic!("impossible: free variable: {:?}", nm),
+        }
+    }
+}
+
+fn abstract_value(val: &Value) -> Result<Abs, BoundError> {
+    match val {
+        Value::VInt(_) | Value::VBool(_) => Ok(Abs::Scalar),
+        Value::VList(vals) => {
+            let elem = vals.iter().try_fold(Abs::Never, |elem, val| {
+                abstract_value(val).map(|val| join(elem, val))
+            })?;
+            Ok(Abs::List(vals.len() as u64, Box::new(elem)))
+        }
+        Value::VPair(a, b) => Ok(Abs::Pair(
+            Box::new(abstract_value(a)?),
+            Box::new(abstract_value(b)?),
+        )),
+        Value::VClosure(nm, bd, env) => Ok(Abs::Funs(vec![Fun::Closure(
+            nm.clone(),
+            *bd.clone(),
+            AbsEnv::new(env.clone()),
+        )])),
+        Value::VCore(nm, bd, locals, globals) => {
+            let (nm, bd, env) = named_closure(nm, bd, locals, globals);
+            abstract_value(&Value::VClosure(nm, bd, env))
+        }
+        Value::VNative(native, args) => {
+            Ok(Abs::Funs(vec![Fun::Native(native.clone(), args.len())]))
+        }
+        Value::VCode(_, _) => Err(BoundError::Compiled),
+        Value::VThunk(_) | Value::VCons(_, _) => {
+            abstract_value(&val.clone().forced().map_err(BoundError::Eval)?)
+        }
+    }
+}
+
+// type variables are abstracted as scalars: a polymorphic value can only be
+// passed around, never inspected.
+fn abstract_type(ty: &Type, max_len: u64) -> Result<Abs, BoundError> {
+    match ty {
+        Type::TVar(_) | Type::TCon(_) => Ok(Abs::Scalar),
+        Type::TList(elem) => Ok(Abs::List(max_len, Box::new(abstract_type(elem, max_len)?))),
+        Type::TPair(a, b) => Ok(Abs::Pair(
+            Box::new(abstract_type(a, max_len)?),
+            Box::new(abstract_type(b, max_len)?),
+        )),
+        Type::TArr(_, _) => Err(BoundError::FunctionValued),
+    }
+}
+
+// the least abstraction of both `a` & `b`.
+fn join(a: Abs, b: Abs) -> Abs {
+    match (a, b) {
+        (Abs::Never, x) | (x, Abs::Never) => x,
+        (Abs::Scalar, Abs::Scalar) => Abs::Scalar,
+        (Abs::List(n, a), Abs::List(m, b)) => Abs::List(n.max(m), Box::new(join(*a, *b))),
+        (Abs::Pair(a1, b1), Abs::Pair(a2, b2)) => {
+            Abs::Pair(Box::new(join(*a1, *a2)), Box::new(join(*b1, *b2)))
+        }
+        (Abs::Funs(mut fs), Abs::Funs(gs)) => {
+            fs.extend(gs);
+            Abs::Funs(fs)
+        }
+        _ => panic!("impossible: join of values of different types"),
+    }
+}
+
+// `new`, with each list as much longer again, `times` over, as it is longer
+// than in `old`.
+fn extrapolate(old: &Abs, new: &Abs, times: u64) -> Abs {
+    match (old, new) {
+        (Abs::List(n, a), Abs::List(m, b)) => {
+            let len = m.saturating_add(m.saturating_sub(*n).saturating_mul(times));
+            Abs::List(len, Box::new(extrapolate(a, b, times)))
+        }
+        (Abs::Pair(a1, b1), Abs::Pair(a2, b2)) => Abs::Pair(
+            Box::new(extrapolate(a1, a2, times)),
+            Box::new(extrapolate(b1, b2, times)),
+        ),
+        (_, new) => new.clone(),
+    }
+}
+
+// whether each value described by `a` is also described by `b`. functions are
+// never known to be.
+fn within(a: &Abs, b: &Abs) -> bool {
+    match (a, b) {
+        (Abs::Never, _) | (Abs::Scalar, Abs::Scalar) => true,
+        (Abs::List(n, a), Abs::List(m, b)) => n <= m && within(a, b),
+        (Abs::Pair(a1, b1), Abs::Pair(a2, b2)) => within(a1, a2) && within(b1, b2),
+        _ => false,
+    }
+}
+
+// `new`, with the lists which are longer than in `old` of any length.
+fn widen(old: &Abs, new: Abs) -> Abs {
+    match (old, new) {
+        (Abs::List(n, a), Abs::List(m, b)) => {
+            let len = if m > *n { u64::MAX } else { m };
+            Abs::List(len, Box::new(widen(a, *b)))
+        }
+        (Abs::Pair(a1, b1), Abs::Pair(a2, b2)) => {
+            Abs::Pair(Box::new(widen(a1, *a2)), Box::new(widen(b1, *b2)))
+        }
+        (_, new) => new,
+    }
+}
+
+// whether `a` & `b` are the same abstraction of data. functions are never
+// the same, as they cannot be compared.
+fn same(a: &Abs, b: &Abs) -> bool {
+    match (a, b) {
+        (Abs::Never, Abs::Never) | (Abs::Scalar, Abs::Scalar) => true,
+        (Abs::List(n, a), Abs::List(m, b)) => n == m && same(a, b),
+        (Abs::Pair(a1, b1), Abs::Pair(a2, b2)) => same(a1, a2) && same(b1, b2),
+        _ => false,
+    }
+}
+
+// the type of the result of a native, once fully applied.
+fn result_type(native: &Native) -> &Type {
+    let mut ty = &native.scheme.1;
+    for _ in 0..native.arity {
+        if let Type::TArr(_arg, ret) = ty {
+            ty = ret;
+        }
+    }
+    ty
+}
+
+// the number of elements of a `foldl` after which its accumulator is widened.
+const WIDEN_AFTER: u64 = 16;
+
+// an abstract interpreter, which charges at least as much as `eval_` would
+// for any values described by the abstractions.
+struct Bound<'a> {
+    costs: &'a CostTable,
+    // conjures up the names of partially applied PrimOps.
+    es: EvalState,
+    max_len: u64,
+}
+
+impl<'a> Bound<'a> {
+    fn eval(&mut self, env: &AbsEnv, expr: &Expr) -> Result<(u64, Abs), BoundError> {
+        match primop_apply_case(&mut self.es, expr) {
+            PrimOpApplyCase::FullyApplied(op, args) => {
+                let mut gas = 0u64;
+                let mut args_v = Vec::new();
+                for arg in args.iter() {
+                    let (arg_gas, arg_v) = self.eval(env, arg)?;
+                    gas = gas.saturating_add(arg_gas);
+                    args_v.push(arg_v);
+                }
+                let (op_gas, val) = self.primop(op, args_v)?;
+                Ok((gas.saturating_add(op_gas), val))
+            }
+            PrimOpApplyCase::PartiallyApplied(lam) => self.eval(env, &lam),
+            PrimOpApplyCase::Other => match expr {
+                Expr::Lit(_) => Ok((0, Abs::Scalar)),
+                Expr::Var(x) => Ok((0, env.lookup(x)?)),
+                Expr::Lam(nm, bd) => Ok((
+                    self.costs.allocation,
+                    Abs::Funs(vec![Fun::Closure(nm.clone(), *bd.clone(), env.clone())]),
+                )),
+                Expr::Let(x, e, bd) => {
+                    let (e_gas, e_v) = self.eval(env, e)?;
+                    let (bd_gas, bd_v) = self.eval(&env.update(x.clone(), e_v), bd)?;
+                    Ok((e_gas.saturating_add(bd_gas), bd_v))
+                }
+                // only one of the branches is taken.
+                Expr::If(tst, thn, els) => {
+                    let (tst_gas, _tst_v) = self.eval(env, tst)?;
+                    let (thn_gas, thn_v) = self.eval(env, thn)?;
+                    let (els_gas, els_v) = self.eval(env, els)?;
+                    Ok((
+                        tst_gas.saturating_add(thn_gas.max(els_gas)),
+                        join(thn_v, els_v),
+                    ))
+                }
+                Expr::Prim(PrimOp::Nil) => Ok((0, Abs::List(0, Box::new(Abs::Never)))),
+                Expr::Prim(op) => Ok((
+                    self.costs.allocation,
+                    Abs::Funs(vec![Fun::Prim(op.clone(), Vec::new())]),
+                )),
+                Expr::App(fun, arg) => {
+                    let (fun_gas, fun_v) = self.eval(env, fun)?;
+                    let (arg_gas, arg_v) = self.eval(env, arg)?;
+                    let (app_gas, val) = self.apply(fun_v, arg_v)?;
+                    Ok((fun_gas.saturating_add(arg_gas).saturating_add(app_gas), val))
+                }
+                Expr::Fix(_) => Err(BoundError::Recursive),
+            },
+        }
+    }
+
+    fn apply(&mut self, fun: Abs, arg: Abs) -> Result<(u64, Abs), BoundError> {
+        match fun {
+            // the most expensive of the functions it may be.
+            Abs::Funs(funs) => {
+                let mut gas = 0;
+                let mut val = Abs::Never;
+                for fun in funs {
+                    let (fun_gas, fun_v) = self.apply_fun(fun, arg.clone())?;
+                    gas = gas.max(fun_gas);
+                    val = join(val, fun_v);
+                }
+                Ok((gas, val))
+            }
+            Abs::Never => Ok((0, Abs::Never)),
+            _ => panic!("impossible: non-function in function position of app"),
+        }
+    }
+
+    fn apply_fun(&mut self, fun: Fun, arg: Abs) -> Result<(u64, Abs), BoundError> {
+        let application = self.costs.application;
+        match fun {
+            Fun::Closure(nm, bd, env) => {
+                let (gas, val) = self.eval(&env.update(nm, arg), &bd)?;
+                Ok((application.saturating_add(gas), val))
+            }
+            // a lifted PrimOp is a closure of one lambda per argument.
+            Fun::Prim(op, mut args) => {
+                args.push(arg);
+                if args.len() == primop_arity(&op) {
+                    let (gas, val) = self.primop(op, args)?;
+                    Ok((application.saturating_add(gas), val))
+                } else {
+                    Ok((
+                        application.saturating_add(self.costs.allocation),
+                        Abs::Funs(vec![Fun::Prim(op, args)]),
+                    ))
+                }
+            }
+            Fun::Native(native, args) => {
+                if args + 1 == native.arity {
+                    Ok((
+                        application,
+                        abstract_type(result_type(&native), self.max_len)?,
+                    ))
+                } else {
+                    Ok((application, Abs::Funs(vec![Fun::Native(native, args + 1)])))
+                }
+            }
+        }
+    }
+
+    // as `apply_primop`.
+    fn primop(&mut self, op: PrimOp, mut args: Vec<Abs>) -> Result<(u64, Abs), BoundError> {
+        let costs = self.costs;
+        let gas = costs.primop(&op);
+        match op {
+            PrimOp::Add | PrimOp::Sub | PrimOp::Mul | PrimOp::Eql | PrimOp::Null => {
+                Ok((gas, Abs::Scalar))
+            }
+            PrimOp::Pair => {
+                let b = args.pop().unwrap_or(Abs::Never);
+                let a = args.pop().unwrap_or(Abs::Never);
+                Ok((
+                    gas.saturating_add(costs.allocation),
+                    Abs::Pair(Box::new(a), Box::new(b)),
+                ))
+            }
+            PrimOp::Fst | PrimOp::Snd => match args.pop() {
+                Some(Abs::Pair(a, b)) => Ok((gas, if op == PrimOp::Fst { *a } else { *b })),
+                Some(Abs::Never) => Ok((gas, Abs::Never)),
+                _ => panic!("fst/snd: bad types"),
+            },
+            PrimOp::Cons => {
+                let list = args.pop();
+                let x = args.pop().unwrap_or(Abs::Never);
+                let val = match list {
+                    Some(Abs::List(n, elem)) => {
+                        Abs::List(n.saturating_add(1), Box::new(join(x, *elem)))
+                    }
+                    Some(Abs::Never) => Abs::List(1, Box::new(x)),
+                    _ => panic!("cons: bad types"),
+                };
+                Ok((gas.saturating_add(costs.allocation), val))
+            }
+            PrimOp::Map => match (args.pop(), args.pop()) {
+                (Some(Abs::List(n, elem)), Some(fun)) => {
+                    let gas = gas.saturating_add(costs.element.saturating_mul(n));
+                    if n == 0 {
+                        return Ok((gas, Abs::List(0, Box::new(Abs::Never))));
+                    }
+                    let (app_gas, val) = self.apply(fun, *elem)?;
+                    Ok((
+                        gas.saturating_add(app_gas.saturating_mul(n)),
+                        Abs::List(n, Box::new(val)),
+                    ))
+                }
+                (Some(Abs::Never), _) => Ok((gas, Abs::Never)),
+                _ => panic!("map: bad types"),
+            },
+            // the result may be the accumulator after any number of elements,
+            // up to the bound on the length of the list. past `WIDEN_AFTER`
+            // elements, an accumulator which still grows is extrapolated to
+            // the end of the list, if its growth is no faster there. failing
+            // that, its lists are widened to any length, so that it soon
+            // stops changing.
+            PrimOp::Foldl => match (args.pop(), args.pop(), args.pop()) {
+                (Some(Abs::List(n, elem)), Some(acc), Some(fun)) => {
+                    let mut gas = gas.saturating_add(costs.element.saturating_mul(n));
+                    let mut acc = acc;
+                    let mut val = acc.clone();
+                    for ix in 0..n {
+                        let (step_gas, mut next) = self.fold_step(&fun, &acc, &elem)?;
+                        gas = gas.saturating_add(step_gas);
+                        if ix >= WIDEN_AFTER {
+                            next = join(acc.clone(), next);
+                            if ix == WIDEN_AFTER && !same(&acc, &next) {
+                                let rest = n - ix - 1;
+                                if let Some((rest_gas, last)) =
+                                    self.fold_rest(&fun, &elem, step_gas, &acc, &next, rest)?
+                                {
+                                    return Ok((gas.saturating_add(rest_gas), join(val, last)));
+                                }
+                            }
+                            next = widen(&acc, next);
+                        }
+                        // once the accumulator is unchanged, so is each step.
+                        if same(&acc, &next) {
+                            gas = gas.saturating_add(step_gas.saturating_mul(n - ix - 1));
+                            break;
+                        }
+                        val = join(val, next.clone());
+                        acc = next;
+                    }
+                    Ok((gas, val))
+                }
+                (Some(Abs::Never), _, _) => Ok((gas, Abs::Never)),
+                _ => panic!("foldl: bad types"),
+            },
+            PrimOp::Nil => panic!("impossible: nil is not applied"),
+        }
+    }
+
+    // the gas of the `rest` steps of a `foldl` after the step from `acc` to
+    // `next` (which used `step_gas`), & the accumulator after them, if its
+    // growth is no faster at the end. the steps are monotone, & convex in
+    // the lengths of lists, so that if the last step grows the accumulator
+    // no more than the first, neither does any step in between, & the gas of
+    // each is at most that on the line between the first & the last.
+    fn fold_rest(
+        &mut self,
+        fun: &Abs,
+        elem: &Abs,
+        step_gas: u64,
+        acc: &Abs,
+        next: &Abs,
+        rest: u64,
+    ) -> Result<Option<(u64, Abs)>, BoundError> {
+        let last = extrapolate(acc, next, rest);
+        let (last_gas, after) = self.fold_step(fun, &last, elem)?;
+        if !within(&after, &extrapolate(acc, next, rest.saturating_add(1))) {
+            return Ok(None);
+        }
+        let rise = last_gas.saturating_sub(step_gas);
+        let gas = step_gas
+            .saturating_mul(rest)
+            .saturating_add(rise.saturating_mul(rest).div_ceil(2));
+        Ok(Some((gas, last)))
+    }
+
+    // a step of a `foldl` of `fun` from `acc`, on an element `elem`.
+    fn fold_step(&mut self, fun: &Abs, acc: &Abs, elem: &Abs) -> Result<(u64, Abs), BoundError> {
+        let (gas1, fun_acc) = self.apply(fun.clone(), acc.clone())?;
+        let (gas2, next) = self.apply(fun_acc, elem.clone())?;
+        Ok((gas1.saturating_add(gas2), next))
+    }
+}
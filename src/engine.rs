@@ -0,0 +1,229 @@
+use super::{
+    env::Env,
+    eval::{eval_, EvalError, EvalState, Limits, Strategy, TermEnv, Value},
+    infer::{infer_expr, infer_program, TypeError},
+    lazy,
+    module::ModuleError,
+    syntax::{Defn, Expr, Import, Program},
+    toplevel::{
+        compile_calculation_in, CalculationOptions, CompiledCalculation,
+        ReputationCalculationError, ReputationCalculationOutput,
+    },
+    trace::{eval_traced, Trace},
+    types::Scheme,
+};
+
+#[derive(Debug)]
+pub enum EngineError {
+    Type(TypeError),
+    Eval(EvalError),
+    Module(ModuleError),
+}
+
+/// the global environments of an `Engine`, as captured by `Engine::snapshot`.
+#[derive(Clone)]
+pub struct Snapshot {
+    type_env: Env,
+    term_env: TermEnv,
+    es: EvalState,
+}
+
+/// an embeddable interpreter. it owns the global type & term environments
+/// (seeded with the prelude & natives, per its `CalculationOptions`), to
+/// which `define` adds, and under which expressions are checked & evaluated.
+pub struct Engine {
+    options: CalculationOptions,
+    type_env: Env,
+    term_env: TermEnv,
+    es: EvalState,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    /// an engine with the default `CalculationOptions`.
+    pub fn new() -> Engine {
+        Engine::with_options(CalculationOptions::default())
+    }
+
+    pub fn with_options(options: CalculationOptions) -> Engine {
+        let (type_env, term_env) = match options.loader().load_scope(&[]) {
+            Ok(envs) => envs,
+            Err(err) => panic!("impossible: engine: failed without imports: {:?}", err),
+        };
+        let es = options.eval_state();
+        Engine {
+            options,
+            type_env,
+            term_env,
+            es,
+        }
+    }
+
+    pub fn options(&self) -> &CalculationOptions {
+        &self.options
+    }
+
+    pub fn type_env(&self) -> &Env {
+        &self.type_env
+    }
+
+    pub fn term_env(&self) -> &TermEnv {
+        &self.term_env
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.options.limits
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.options.limits = limits;
+        self.es.set_limits(limits);
+    }
+
+    /// bring the exports of `imports` into scope, under qualified names.
+    pub fn import(&mut self, imports: &[Import]) -> Result<(), EngineError> {
+        let (type_env, term_env) = self
+            .options
+            .loader()
+            .load_imports(None, imports)
+            .map_err(EngineError::Module)?;
+        self.type_env.merge(&type_env);
+        self.term_env.merge(&term_env);
+        Ok(())
+    }
+
+    /// infer the type of `expr` in the global environment.
+    pub fn check(&self, expr: &Expr) -> Result<Scheme, EngineError> {
+        infer_expr(&self.type_env, expr).map_err(EngineError::Type)
+    }
+
+    /// check & evaluate `expr` in the global environment. under the lazy
+    /// strategy, the value is forced in full, as for `reduce`.
+    pub fn eval(&mut self, expr: &Expr) -> Result<(Value, Scheme), EngineError> {
+        let sc = self.check(expr)?;
+        let val = self.eval_checked(expr)?;
+        let val = self.force(val)?;
+        Ok((val, sc))
+    }
+
+    /// as `eval`, tracing the evaluation. the trace is returned even if
+    /// evaluation fails, & is empty if checking does.
+    pub fn eval_traced(&mut self, expr: &Expr) -> (Trace, Result<(Value, Scheme), EngineError>) {
+        let sc = match self.check(expr) {
+            Ok(sc) => sc,
+            Err(err) => return (Trace::new(), Err(err)),
+        };
+        self.es.reset_steps();
+        let (res, trace) = eval_traced(&self.term_env, &mut self.es, expr);
+        let res = res
+            .map_err(EngineError::Eval)
+            .and_then(|val| self.force(val));
+        (trace, res.map(|val| (val, sc)))
+    }
+
+    /// check & evaluate a `defn`, adding it to the global environment. its
+    /// value is not forced.
+    pub fn define(&mut self, defn: &Defn) -> Result<(Value, Scheme), EngineError> {
+        let Defn(nm, expr) = defn;
+        let sc = self.check(expr)?;
+        let val = self.eval_checked(expr)?;
+        self.type_env.extend(nm.clone(), sc.clone());
+        self.term_env.insert(nm.clone(), val.clone());
+        Ok((val, sc))
+    }
+
+    /// check & evaluate a whole program: its imports & `defn`s are added to the
+    /// global environment, and the value of its body is returned.
+    pub fn eval_program(&mut self, prog: &Program) -> Result<(Value, Scheme), EngineError> {
+        let snapshot = self.snapshot();
+        let res = self.eval_program_(prog);
+        // a program which fails leaves no trace.
+        if res.is_err() {
+            self.restore(snapshot);
+        }
+        res
+    }
+
+    fn eval_program_(&mut self, prog: &Program) -> Result<(Value, Scheme), EngineError> {
+        self.import(&prog.p_imports)?;
+        let (sc, type_env) =
+            infer_program(self.type_env.clone(), prog).map_err(EngineError::Type)?;
+        for Defn(nm, bd) in prog.p_defns.iter() {
+            let val = self.eval_checked(bd)?;
+            self.term_env.insert(nm.clone(), val);
+        }
+        self.type_env = type_env;
+        let val = self.eval_checked(&prog.body_expr())?;
+        let val = self.force(val)?;
+        Ok((val, sc))
+    }
+
+    /// compile a calculation in the global environment (along with its own
+    /// imports), so that it may be reduced against many inputs.
+    pub fn compile(
+        &self,
+        prog: Program,
+    ) -> Result<CompiledCalculation, ReputationCalculationError> {
+        let (import_env, import_term_env) = self
+            .options
+            .loader()
+            .load_imports(None, &prog.p_imports)
+            .map_err(ReputationCalculationError::ModuleError)?;
+        let mut type_env = self.type_env.clone();
+        type_env.merge(&import_env);
+        let mut term_env = self.term_env.clone();
+        term_env.merge(&import_term_env);
+        compile_calculation_in(prog, type_env, term_env, self.options.eval_state())
+    }
+
+    /// reduce a calculation against `input_data` in the global environment.
+    pub fn reduce(
+        &self,
+        prog: Program,
+        input_data: &mut dyn Iterator<Item = Value>,
+    ) -> Result<ReputationCalculationOutput, ReputationCalculationError> {
+        self.compile(prog)?.reduce(input_data)
+    }
+
+    /// capture the global environments, so that they may later be restored.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            type_env: self.type_env.clone(),
+            term_env: self.term_env.clone(),
+            es: self.es.clone(),
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        let Snapshot {
+            type_env,
+            term_env,
+            mut es,
+        } = snapshot;
+        es.set_limits(self.options.limits);
+        self.type_env = type_env;
+        self.term_env = term_env;
+        self.es = es;
+    }
+
+    // evaluate an expression which has already been checked. the limits apply
+    // to each such evaluation separately.
+    fn eval_checked(&mut self, expr: &Expr) -> Result<Value, EngineError> {
+        self.es.reset_steps();
+        eval_(&self.term_env, &mut self.es, expr).map_err(EngineError::Eval)
+    }
+
+    // force `val` in full under the lazy strategy, within the limits of the
+    // evaluation which gave it.
+    fn force(&mut self, val: Value) -> Result<Value, EngineError> {
+        match self.es.strategy() {
+            Strategy::Strict => Ok(val),
+            Strategy::Lazy => lazy::force_deep(&mut self.es, val).map_err(EngineError::Eval),
+        }
+    }
+}
@@ -1,48 +1,67 @@
 use core::iter::FromIterator;
-use std::{collections::HashMap, ops::Deref};
+use std::ops::Deref;
 
+use im::HashMap;
+
+use super::infer::free_type_vars;
 use super::syntax::*;
 use super::types::*;
 
+/// a type environment. it is a persistent map, so that extending a clone of
+/// it (as when checking under a binder) shares structure with the original.
+///
+/// it also counts the occurrences of the free type variables of its schemes,
+/// so that generalizing (at each `let`) needn't traverse the whole map.
 #[derive(Clone, Debug)]
-pub struct Env(HashMap<Name, Scheme>);
+pub struct Env(HashMap<Name, Scheme>, HashMap<TV, usize>);
+
+impl Default for Env {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Env {
     pub fn new() -> Env {
-        Env(HashMap::new())
+        Env(HashMap::new(), HashMap::new())
     }
 
     pub fn extend(&mut self, nm: Name, sc: Scheme) -> Option<Scheme> {
-        match self {
-            Env(hm) => hm.insert(nm, sc),
+        let Env(hm, ftv) = self;
+        count_ftv(ftv, &sc, |n| n + 1);
+        let old = hm.insert(nm, sc);
+        if let Some(old_sc) = &old {
+            count_ftv(ftv, old_sc, |n| n - 1);
         }
+        old
     }
 
     pub fn remove(&mut self, nm: Name) -> Option<Scheme> {
-        match self {
-            Env(hm) => hm.remove(&nm),
+        let Env(hm, ftv) = self;
+        let old = hm.remove(&nm);
+        if let Some(old_sc) = &old {
+            count_ftv(ftv, old_sc, |n| n - 1);
         }
+        old
     }
 
     pub fn extends<T>(&mut self, xs: T)
     where
         T: IntoIterator<Item = (Name, Scheme)>,
     {
-        let Env(ref mut hm) = self;
-        hm.extend(xs)
+        for (nm, sc) in xs {
+            self.extend(nm, sc);
+        }
     }
 
     pub fn get(&self, nm: &Name) -> Option<&Scheme> {
-        match self {
-            Env(hm) => hm.get(nm),
-        }
+        let Env(hm, _ftv) = self;
+        hm.get(nm)
     }
 
     pub fn merge(&mut self, other: &Env) {
-        let Env(ref mut hm) = self;
-        let Env(other_hm) = other;
-        // TODO is this avoidable waste?
-        hm.extend(other_hm.clone())
+        let Env(other_hm, _ftv) = other;
+        self.extends(other_hm.clone())
     }
 
     pub fn merge_envs(&mut self, others: Vec<Env>) {
@@ -58,21 +77,50 @@ impl Env {
     }
 
     pub fn keys<T>(&self) -> Vec<Name> {
-        let Env(hm) = self;
+        let Env(hm, _ftv) = self;
         // TODO is this avoidable waste?
-        hm.keys().map(|x| x.clone()).collect()
+        hm.keys().cloned().collect()
     }
 
     pub fn replace(&mut self, nm: &Name, sc: Scheme) {
         self.remove(nm.clone());
         self.extend(nm.clone(), sc);
     }
+
+    /// the free type variables of the schemes in the environment.
+    pub fn free_type_vars(&self) -> impl Iterator<Item = &TV> {
+        let Env(_hm, ftv) = self;
+        ftv.keys()
+    }
+
+    pub fn has_free_type_var(&self, tv: &TV) -> bool {
+        let Env(_hm, ftv) = self;
+        ftv.contains_key(tv)
+    }
+}
+
+// adjusts the count of each free type variable of `sc`, dropping those which
+// no longer occur.
+fn count_ftv(ftv: &mut HashMap<TV, usize>, sc: &Scheme, f: impl Fn(usize) -> usize) {
+    let Scheme(xs, ty) = sc;
+    for tv in free_type_vars(ty.clone()) {
+        if xs.contains(&tv) {
+            continue;
+        }
+        let n = f(ftv.get(&tv).copied().unwrap_or(0));
+        if n == 0 {
+            ftv.remove(&tv);
+        } else {
+            ftv.insert(tv, n);
+        }
+    }
 }
 
 impl FromIterator<(Name, Scheme)> for Env {
     fn from_iter<T: IntoIterator<Item = (Name, Scheme)>>(iter: T) -> Env {
-        let hm: HashMap<Name, Scheme> = HashMap::from_iter(iter);
-        Env(hm)
+        let mut env = Env::new();
+        env.extends(iter);
+        env
     }
 }
 
@@ -80,7 +128,7 @@ impl Deref for Env {
     type Target = HashMap<Name, Scheme>;
 
     fn deref(&self) -> &Self::Target {
-        let Env(hm) = self;
-        &hm
+        let Env(hm, _ftv) = self;
+        hm
     }
 }
@@ -1,8 +1,15 @@
 use pretty::RcDoc;
-use std::{cmp::Ordering, collections::HashMap, iter};
+use std::{cmp::Ordering, iter, ops::Index, sync::Arc};
 
+use super::core::{self, capp, Core};
+use super::cost::CostTable;
+use super::lazy::{self, Thunk};
+use super::native::Native;
+use super::prelude::prelude;
 use super::syntax::{primop_arity, Defn, Expr, Lit, Name, PrimOp, Program};
+use super::trace::Trace;
 use super::util::pretty::parens;
+use super::vm::{self, Proto};
 use crate::{app, lam, sp};
 
 #[derive(Clone)]
@@ -10,19 +17,39 @@ pub enum Value {
     VInt(i64),
     VBool(bool),
     VClosure(Name, Box<Expr>, TermEnv),
+    /// a closure over a core expression (see `core`), as made by the strict
+    /// evaluator. its body finds the values of its locals by position, & those
+    /// of its free variables by name.
+    VCore(Name, Box<Core>, Locals, TermEnv),
+    /// a native function, along with the arguments it has been applied to so
+    /// far (fewer than its arity).
+    VNative(Arc<Native>, Vec<Value>),
+    /// a closure compiled to bytecode, along with the (shared) values it
+    /// captures.
+    VCode(Arc<Proto>, Arc<[Value]>),
     VList(Vec<Value>),
     VPair(Box<Value>, Box<Value>),
+    /// a delayed evaluation, under the lazy strategy.
+    VThunk(Thunk),
+    /// a list cell, whose head & tail may be delayed, under the lazy strategy.
+    VCons(Box<Value>, Box<Value>),
 }
 
-type TermEnv = HashMap<Name, Value>;
+/// a term environment. it is a persistent map: capturing it in a closure, or
+/// extending it under a binder, shares structure rather than copying it. the
+/// values are shared too, as updating the map copies the entries alongside
+/// the updated one.
+#[derive(Clone, Default)]
+pub struct TermEnv(im::HashMap<Name, Arc<Value>>);
 
 impl Value {
-    pub fn ppr(&self) -> RcDoc<()> {
+    pub fn ppr(&self) -> RcDoc<'static, ()> {
         match self {
             VInt(n) => RcDoc::as_string(n),
             VBool(true) => RcDoc::text("true"),
             VBool(false) => RcDoc::text("false"),
-            VClosure(_, _, _) => RcDoc::text("<<closure>>"),
+            VClosure(_, _, _) | VCore(_, _, _, _) | VCode(_, _) => RcDoc::text("<<closure>>"),
+            VNative(native, _) => RcDoc::text(format!("<<native {}>>", native.name.0)),
             VList(vec) => {
                 let header = iter::once(RcDoc::text("(list"));
                 let footer = RcDoc::text(")");
@@ -30,157 +57,573 @@ impl Value {
                 RcDoc::intersperse(header.chain(middle), sp!()).append(footer)
             }
             VPair(a, b) => parens(a.ppr().append(RcDoc::text(", ")).append(b.ppr())),
+            VThunk(_) | VCons(_, _) => match self.clone().forced() {
+                Ok(val) => val.ppr(),
+                Err(err) => RcDoc::text(format!("<<error: {:?}>>", err)),
+            },
+        }
+    }
+}
+
+impl TermEnv {
+    pub fn new() -> TermEnv {
+        TermEnv(im::HashMap::new())
+    }
+
+    pub fn get(&self, nm: &Name) -> Option<&Value> {
+        let TermEnv(hm) = self;
+        hm.get(nm).map(|val| &**val)
+    }
+
+    pub fn contains_key(&self, nm: &Name) -> bool {
+        let TermEnv(hm) = self;
+        hm.contains_key(nm)
+    }
+
+    pub fn insert(&mut self, nm: Name, val: Value) {
+        let TermEnv(hm) = self;
+        hm.insert(nm, Arc::new(val));
+    }
+
+    /// a copy of the environment, extended with `nm` bound to `val`.
+    pub fn update(&self, nm: Name, val: Value) -> TermEnv {
+        let TermEnv(hm) = self;
+        TermEnv(hm.update(nm, Arc::new(val)))
+    }
+
+    /// bind all of the names of `other`, shadowing those already bound.
+    pub fn merge(&mut self, other: &TermEnv) {
+        let TermEnv(hm) = self;
+        let TermEnv(other_hm) = other;
+        hm.extend(other_hm.clone())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Name, &Value)> {
+        let TermEnv(hm) = self;
+        hm.iter().map(|(nm, val)| (nm, &**val))
+    }
+}
+
+impl Index<&Name> for TermEnv {
+    type Output = Value;
+
+    fn index(&self, nm: &Name) -> &Value {
+        match self.get(nm) {
+            Some(val) => val,
+            None => panic!("unbound name: {:?}", nm),
+        }
+    }
+}
+
+impl iter::FromIterator<(Name, Value)> for TermEnv {
+    fn from_iter<T: IntoIterator<Item = (Name, Value)>>(iter: T) -> TermEnv {
+        TermEnv(
+            iter.into_iter()
+                .map(|(nm, val)| (nm, Arc::new(val)))
+                .collect(),
+        )
+    }
+}
+
+/// the values of the locals of a core expression, the innermost first, along
+/// with the names they were written with. it is a persistent (skew binary)
+/// random access list, shared by the closures which capture it: a list of
+/// complete binary trees, of increasing sizes, so that a local is bound in
+/// constant time & found in logarithmic time, however deep the scope.
+#[derive(Clone, Default)]
+pub struct Locals(Option<Arc<(usize, LocalTree, Locals)>>);
+
+// a complete binary tree of locals, the innermost at its root, & the rest in
+// its left, then its right subtree.
+#[derive(Clone)]
+struct LocalTree(Arc<LocalNode>);
+
+type LocalNode = (Name, Value, Option<(LocalTree, LocalTree)>);
+
+impl Locals {
+    pub fn new() -> Locals {
+        Locals(None)
+    }
+
+    /// the locals, under one more binder, of `nm` to `val`.
+    pub fn push(&self, nm: Name, val: Value) -> Locals {
+        // the first two trees are joined under the new local, if they are of
+        // the same size.
+        if let Locals(Some(first)) = self {
+            let (size, left, second) = &**first;
+            if let Locals(Some(second)) = second {
+                let (second_size, right, rest) = &**second;
+                if size == second_size {
+                    let tree = LocalTree(Arc::new((nm, val, Some((left.clone(), right.clone())))));
+                    return Locals(Some(Arc::new((1 + size + second_size, tree, rest.clone()))));
+                }
+            }
+        }
+        let tree = LocalTree(Arc::new((nm, val, None)));
+        Locals(Some(Arc::new((1, tree, self.clone()))))
+    }
+
+    /// the value of the local `ix`, as a de Bruijn index.
+    pub fn get(&self, mut ix: usize) -> Option<&Value> {
+        let mut locals = self;
+        while let Locals(Some(frame)) = locals {
+            let (size, tree, rest) = &**frame;
+            if ix < *size {
+                return Some(tree.get(*size, ix));
+            }
+            ix -= size;
+            locals = rest;
+        }
+        None
+    }
+
+    /// the names & values of the locals, the innermost first.
+    pub fn iter(&self) -> impl Iterator<Item = (&Name, &Value)> {
+        let mut locals = self;
+        let mut trees: Vec<&LocalTree> = Vec::new();
+        iter::from_fn(move || {
+            if trees.is_empty() {
+                let Locals(frame) = locals;
+                let (_size, tree, rest) = &**frame.as_ref()?;
+                trees.push(tree);
+                locals = rest;
+            }
+            let LocalTree(node) = trees.pop()?;
+            let (nm, val, children) = &**node;
+            if let Some((left, right)) = children {
+                trees.push(right);
+                trees.push(left);
+            }
+            Some((nm, val))
+        })
+    }
+}
+
+impl LocalTree {
+    // the local `ix` of a tree of `size` locals.
+    fn get(&self, mut size: usize, mut ix: usize) -> &Value {
+        let mut tree = self;
+        loop {
+            let LocalTree(node) = tree;
+            let (_nm, val, children) = &**node;
+            if ix == 0 {
+                return val;
+            }
+            let half = size / 2;
+            match children {
+                Some((left, _right)) if ix <= half => {
+                    tree = left;
+                    ix -= 1;
+                }
+                Some((_left, right)) => {
+                    tree = right;
+                    ix -= 1 + half;
+                }
+                None => panic!("impossible: Locals: index out of a leaf"),
+            }
+            size = half;
         }
     }
 }
 
-pub struct EvalState(u64);
+/// the closure `VCore(nm, bd, locals, globals)`, as one over the body as
+/// written, whose locals are bound by name (e.g. to be applied by an evaluator
+/// of `Expr`s). the names of the locals are chosen so as not to capture one
+/// another, nor the free variables of the body.
+pub fn named_closure(
+    nm: &Name,
+    bd: &Core,
+    locals: &Locals,
+    globals: &TermEnv,
+) -> (Name, Box<Expr>, TermEnv) {
+    let mut hints: Vec<Name> = locals.iter().map(|(nm, _val)| nm.clone()).collect();
+    hints.reverse();
+    hints.push(nm.clone());
+    let (mut names, bd) = bd.to_expr_under(&hints);
+    let param = match names.pop() {
+        Some(param) => param,
+        None => panic!("impossible: named_closure: no parameter"),
+    };
+    let mut env = globals.clone();
+    let vals: Vec<&Value> = locals.iter().map(|(_nm, val)| val).collect();
+    for (nm, val) in names.into_iter().zip(vals.into_iter().rev()) {
+        env.insert(nm, val.clone());
+    }
+    (param, Box::new(bd), env)
+}
+
+/// bounds on the resources used by evaluation. `None` means unbounded.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Limits {
+    /// the maximum number of evaluation steps (one per expression evaluated).
+    pub max_steps: Option<u64>,
+    /// the maximum nesting depth of evaluation, which bounds the native stack
+    /// used by the evaluator.
+    pub max_depth: Option<usize>,
+}
+
+/// the order of evaluation used by `eval_`. the bytecode vm & the CEK machine
+/// are always strict.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Strategy {
+    /// call-by-value.
+    #[default]
+    Strict,
+    /// call-by-need (see `lazy`).
+    Lazy,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum EvalError {
+    StepLimitExceeded(u64),
+    DepthLimitExceeded(usize),
+    /// a native function failed, with a message.
+    Native(Name, String),
+}
+
+#[derive(Clone)]
+pub struct EvalState {
+    fresh: u64,
+    steps: u64,
+    depth: usize,
+    limits: Limits,
+    strategy: Strategy,
+    trace: Option<Trace>,
+    gas: u64,
+    costs: Arc<CostTable>,
+}
+
+impl Default for EvalState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl EvalState {
     pub fn new() -> EvalState {
-        EvalState(0)
+        EvalState::with_limits(Limits::default())
+    }
+
+    pub fn with_limits(limits: Limits) -> EvalState {
+        EvalState {
+            fresh: 0,
+            steps: 0,
+            depth: 0,
+            limits,
+            strategy: Strategy::Strict,
+            trace: None,
+            gas: 0,
+            costs: Arc::new(CostTable::default()),
+        }
     }
 
     pub fn fresh(&mut self) -> Name {
-        let cnt = match self {
-            EvalState(c) => {
-                *c += 1;
-                *c
-            }
-        };
-        let s = format!("_{}", cnt);
+        self.fresh += 1;
+        let s = format!("_{}", self.fresh);
         Name(s)
     }
+
+    /// the number of steps taken since the state was created, or since the
+    /// last `reset_steps`.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// the gas used since the state was created, or since the last
+    /// `reset_steps` (see `cost`).
+    pub fn gas(&self) -> u64 {
+        self.gas
+    }
+
+    /// reset the counts of steps taken & of gas used.
+    pub fn reset_steps(&mut self) {
+        self.steps = 0;
+        self.gas = 0;
+    }
+
+    pub fn costs(&self) -> &CostTable {
+        &self.costs
+    }
+
+    pub fn set_costs(&mut self, costs: CostTable) {
+        self.costs = Arc::new(costs);
+    }
+
+    pub(crate) fn charge(&mut self, gas: u64) {
+        self.gas = self.gas.saturating_add(gas);
+    }
+
+    // charge for a fully applied PrimOp, given its (forced) arguments.
+    pub(crate) fn charge_primop(&mut self, op: &PrimOp, args_v: &[Value]) {
+        let costs = &self.costs;
+        let gas = costs.primop(op).saturating_add(match (op, args_v.last()) {
+            (PrimOp::Pair, _) | (PrimOp::Cons, _) => costs.allocation,
+            (PrimOp::Map, Some(VList(vec))) | (PrimOp::Foldl, Some(VList(vec))) => {
+                costs.element.saturating_mul(vec.len() as u64)
+            }
+            _ => 0,
+        });
+        self.charge(gas);
+    }
+
+    pub fn limits(&self) -> Limits {
+        self.limits
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    pub fn set_strategy(&mut self, strategy: Strategy) {
+        self.strategy = strategy;
+    }
+
+    /// record the steps of subsequent evaluation by `eval_` (see `trace`).
+    pub fn start_trace(&mut self) {
+        self.trace = Some(Trace::new());
+    }
+
+    /// stop tracing, returning the steps recorded so far.
+    pub fn take_trace(&mut self) -> Option<Trace> {
+        self.trace.take()
+    }
+
+    // whether `eval_` evaluates the core IR, rather than expressions as
+    // written.
+    fn evaluates_core(&self) -> bool {
+        self.strategy == Strategy::Strict && self.trace.is_none()
+    }
+
+    // account for a step of evaluation, one level deeper than the current one.
+    fn enter(&mut self) -> Result<(), EvalError> {
+        self.depth += 1;
+        self.tick(0)
+    }
+
+    // account for a step of evaluation, `extra` levels deeper than the current
+    // one (e.g. within the call frames of the bytecode vm).
+    pub(crate) fn tick(&mut self, extra: usize) -> Result<(), EvalError> {
+        self.steps += 1;
+        match self.limits {
+            Limits {
+                max_steps: Some(max),
+                ..
+            } if self.steps > max => Err(EvalError::StepLimitExceeded(max)),
+            Limits {
+                max_depth: Some(max),
+                ..
+            } if self.depth + extra > max => Err(EvalError::DepthLimitExceeded(max)),
+            _ => Ok(()),
+        }
+    }
 }
 
-pub fn eval_program(prog: &Program) -> (Value, TermEnv) {
-    let mut env = HashMap::new();
+/// evaluate a program with the prelude in scope.
+pub fn eval_program(prog: &Program) -> Result<(Value, TermEnv), EvalError> {
+    eval_program_in(prelude().term_env.clone(), prog)
+}
+
+/// evaluate a program whose free variables (e.g. qualified names from its
+/// imports) are bound in `env`.
+pub fn eval_program_in(mut env: TermEnv, prog: &Program) -> Result<(Value, TermEnv), EvalError> {
     let mut es = EvalState::new();
     for Defn(nm, bd) in prog.p_defns.iter() {
-        let val = eval_(&env, &mut es, bd);
+        let val = eval_(&env, &mut es, bd)?;
         env.insert(nm.clone(), val);
     }
-    (eval_(&env, &mut es, &prog.p_body), env)
+    Ok((eval_(&env, &mut es, &prog.body_expr())?, env))
 }
 
-pub fn eval(expr: &Expr) -> Value {
-    let env = HashMap::new();
+pub fn eval(expr: &Expr) -> Result<Value, EvalError> {
+    let env = TermEnv::new();
     let mut es = EvalState::new();
     eval_(&env, &mut es, expr)
 }
 
 use Value::*;
-pub fn eval_(env: &TermEnv, es: &mut EvalState, expr: &Expr) -> Value {
+/// evaluate `expr` under `env`. evaluation only fails if it exceeds the limits
+/// of `es`: the expression is assumed to be well typed.
+///
+/// strict evaluation resolves the expression, & evaluates the core IR (see
+/// `eval_core`), unless it is traced: the steps of a trace are of the
+/// expression as written.
+pub fn eval_(env: &TermEnv, es: &mut EvalState, expr: &Expr) -> Result<Value, EvalError> {
+    if es.evaluates_core() {
+        return eval_core(env, &Locals::new(), es, &core::resolve(expr));
+    }
+    let res = es.enter().and_then(|()| traced_step(env, es, expr));
+    es.depth -= 1;
+    res
+}
+
+/// evaluate the core expression `core`, whose locals are bound in `locals` &
+/// whose globals are bound in `env`, strictly. it takes the same steps & uses
+/// the same gas as the evaluation of the expression as written.
+pub fn eval_core(
+    env: &TermEnv,
+    locals: &Locals,
+    es: &mut EvalState,
+    core: &Core,
+) -> Result<Value, EvalError> {
+    let res = es.enter().and_then(|()| core_step(env, locals, es, core));
+    es.depth -= 1;
+    res
+}
+
+fn core_step(
+    env: &TermEnv,
+    locals: &Locals,
+    es: &mut EvalState,
+    core: &Core,
+) -> Result<Value, EvalError> {
+    // as in `eval_step`, PrimOps in application position are interpreted
+    // directly, & their partial applications are eta expanded.
+    if let Some((op, args)) = find_core_prim_app(core, false) {
+        let delta = primop_arity(&op) - args.len();
+        if delta == 0 {
+            let args_v = args
+                .iter()
+                .map(|arg| eval_core(env, locals, es, arg))
+                .collect::<Result<Vec<Value>, EvalError>>()?;
+            return apply_primop(es, op, args_v);
+        }
+        let app = args
+            .iter()
+            .map(|arg| arg.shift(delta))
+            .chain((0..delta).rev().map(Core::Local))
+            .fold(Core::Prim(op), capp);
+        let lam = (0..delta).fold(app, |bd, _| Core::Lam(Name("x".to_string()), Box::new(bd)));
+        return eval_core(env, locals, es, &lam);
+    }
+    match core {
+        Core::Lit(Lit::LInt(x)) => Ok(VInt(*x)),
+        Core::Lit(Lit::LBool(x)) => Ok(VBool(*x)),
+
+        Core::Local(ix) => match locals.get(*ix) {
+            None => panic!("impossible: unbound local: {}", ix),
+            Some(v) => Ok(v.clone()),
+        },
+
+        Core::Global(x) => match env.get(x) {
+            None => panic!("impossible: free variable: {:?}", x),
+            Some(v) => Ok(v.clone()),
+        },
+
+        Core::Lam(nm, bd) => {
+            es.charge(es.costs.allocation);
+            Ok(VCore(nm.clone(), bd.clone(), locals.clone(), env.clone()))
+        }
+
+        Core::Let(x, e, bd) => {
+            let e_v = eval_core(env, locals, es, e)?;
+            eval_core(env, &locals.push(x.clone(), e_v), es, bd)
+        }
+
+        Core::If(tst, thn, els) => match eval_core(env, locals, es, tst)? {
+            VBool(true) => eval_core(env, locals, es, thn),
+            VBool(false) => eval_core(env, locals, es, els),
+            _ => panic!("impossible: non-bool in test position of if"),
+        },
+
+        Core::Prim(PrimOp::Nil) => Ok(VList(Vec::new())),
+
+        Core::Prim(op) => Ok(lift_core_primop(es, op)),
+
+        Core::App(fun, arg) => {
+            let fun_v = eval_core(env, locals, es, fun)?;
+            let arg_v = eval_core(env, locals, es, arg)?;
+            apply(es, fun_v, arg_v)
+        }
+
+        // `(fix e)` unfolds to `(e (lam [x] ((fix e) x)))`, as in `eval_step`.
+        // the bound variable needs no fresh name.
+        Core::Fix(e) => {
+            let delayed = Core::Lam(
+                Name("x".to_string()),
+                Box::new(capp(Core::Fix(Box::new(e.shift(1))), Core::Local(0))),
+            );
+            eval_core(env, locals, es, &capp(*e.clone(), delayed))
+        }
+    }
+}
+
+// as `find_prim_app`, for core expressions.
+fn find_core_prim_app(core: &Core, in_app: bool) -> Option<(PrimOp, Vec<&Core>)> {
+    match core {
+        Core::App(fun, arg) => {
+            let (op, mut args) = find_core_prim_app(fun, true)?;
+            args.push(arg);
+            Some((op, args))
+        }
+        Core::Prim(op) if in_app => Some((op.clone(), Vec::new())),
+        _ => None,
+    }
+}
+
+// as `lift_primop`, for the strict evaluation of core expressions.
+fn lift_core_primop(es: &mut EvalState, op: &PrimOp) -> Value {
+    es.charge(es.costs.allocation);
+    let arity = primop_arity(op);
+    let bd = (0..arity)
+        .rev()
+        .map(Core::Local)
+        .fold(Core::Prim(op.clone()), capp);
+    let x = Name("x".to_string());
+    let inner = (1..arity).fold(bd, |bd, _| Core::Lam(x.clone(), Box::new(bd)));
+    VCore(x, Box::new(inner), Locals::new(), TermEnv::new())
+}
+
+fn traced_step(env: &TermEnv, es: &mut EvalState, expr: &Expr) -> Result<Value, EvalError> {
+    let depth = es.depth - 1;
+    let step = es.trace.as_mut().map(|trace| trace.enter(depth, env, expr));
+    let res = match es.strategy {
+        Strategy::Strict => eval_step(env, es, expr),
+        Strategy::Lazy => lazy::eval_step(env, es, expr),
+    };
+    if let (Some(ix), Some(trace)) = (step, es.trace.as_mut()) {
+        trace.exit(ix, &res);
+    }
+    res
+}
+
+fn eval_step(env: &TermEnv, es: &mut EvalState, expr: &Expr) -> Result<Value, EvalError> {
     match primop_apply_case(es, expr) {
         // in this case we directly interpret the PrimOp.
         PrimOpApplyCase::FullyApplied(op, args) => {
-            let args_v: Vec<Value> = args
+            let args_v = args
                 .iter()
-                .map(|arg| eval_(env, es, &arg.clone()))
-                .collect();
-            match op {
-                PrimOp::Add => match (&args_v[0], &args_v[1]) {
-                    (VInt(a_), VInt(b_)) => VInt(a_ + b_),
-                    _ => panic!("+: bad types"),
-                },
-                PrimOp::Sub => match (&args_v[0], &args_v[1]) {
-                    (VInt(a_), VInt(b_)) => VInt(a_ - b_),
-                    _ => panic!("-: bad types"),
-                },
-                PrimOp::Mul => match (&args_v[0], &args_v[1]) {
-                    (VInt(a_), VInt(b_)) => VInt(a_ * b_),
-                    _ => panic!("*: bad types"),
-                },
-                PrimOp::Eql => match (&args_v[0], &args_v[1]) {
-                    (VInt(a_), VInt(b_)) => VBool(a_ == b_),
-                    _ => panic!("==: bad types"),
-                },
-                PrimOp::Null => match &args_v[0] {
-                    VList(vec) => VBool(vec.is_empty()),
-                    _ => panic!("null: bad types"),
-                },
-                PrimOp::Map => match (&args_v[0], &args_v[1]) {
-                    (VClosure(nm, bd, clo), VList(vec)) => {
-                        let mut results = Vec::new();
-                        for arg_v in vec {
-                            let mut new_env = clo.clone();
-                            // TODO
-                            // why is this clone necessary? \|/
-                            // don't we have ownership?      |
-                            new_env.insert(nm.clone(), arg_v.clone());
-                            results.push(eval_(&new_env, es, &bd));
-                        }
-                        Value::VList(results)
-                    }
-                    _ => panic!("map: bad types"),
-                },
-                PrimOp::Foldl => match (&args_v[0], &args_v[1], &args_v[2]) {
-                    (VClosure(nm, bd, clo), init, VList(vec)) => {
-                        let applicator = |acc: Value, arg_v: &Value| {
-                            let mut new_env = clo.clone();
-                            new_env.insert(nm.clone(), acc);
-                            match eval_(&new_env, es, &bd) {
-                                VClosure(nm2, bd2, clo2) => {
-                                    let mut new_env2 = clo2.clone();
-                                    new_env2.insert(nm2, arg_v.clone());
-                                    eval_(&new_env2, es, &bd2)
-                                }
-                                _ => panic!("foldl: bad types"),
-                            }
-                        };
-                        // TODO: why is this clone necessary?
-                        vec.into_iter().fold(init.clone(), applicator)
-                    }
-                    _ => panic!("foldl: bad types"),
-                },
-                PrimOp::Pair => {
-                    let a = args_v[0].clone();
-                    let b = args_v[1].clone();
-                    VPair(Box::new(a), Box::new(b))
-                }
-                PrimOp::Fst => match &args_v[0] {
-                    VPair(a, _) => *a.clone(),
-                    _ => panic!("fst: bad types"),
-                },
-                PrimOp::Snd => match &args_v[0] {
-                    VPair(_, b) => *b.clone(),
-                    _ => panic!("snd: bad types"),
-                },
-                PrimOp::Cons => match &args_v[1] {
-                    VList(vec) => VList(
-                        iter::once(&args_v[0])
-                            .chain(vec.into_iter())
-                            .map(|x| x.clone())
-                            .collect(),
-                    ),
-                    _ => panic!("cons: bad types"),
-                },
-                PrimOp::Nil => panic!("nil: application of non-function"),
-            }
+                .map(|arg| eval_(env, es, arg))
+                .collect::<Result<Vec<Value>, EvalError>>()?;
+            apply_primop(es, op, args_v)
         }
 
         PrimOpApplyCase::PartiallyApplied(lam) => eval_(env, es, &lam),
 
         // we do not find a direct PrimOp application, so we interpret normally.
         PrimOpApplyCase::Other => match expr {
-            Expr::Lit(Lit::LInt(x)) => VInt(*x),
-            Expr::Lit(Lit::LBool(x)) => VBool(*x),
+            Expr::Lit(Lit::LInt(x)) => Ok(VInt(*x)),
+            Expr::Lit(Lit::LBool(x)) => Ok(VBool(*x)),
 
-            Expr::Var(x) => match env.get(&x) {
+            Expr::Var(x) => match env.get(x) {
                 None => panic!("impossible: free variable: {:?}", x),
-                Some(v) => v.clone(),
+                Some(v) => Ok(v.clone()),
             },
 
-            Expr::Lam(nm, bd) => VClosure(nm.clone(), bd.clone(), env.clone()),
+            Expr::Lam(nm, bd) => {
+                es.charge(es.costs.allocation);
+                Ok(VClosure(nm.clone(), bd.clone(), env.clone()))
+            }
 
             Expr::Let(x, e, bd) => {
-                let e_v = eval_(env, es, e);
-                let mut new_env = env.clone();
-                new_env.insert(x.clone(), e_v);
-                eval_(&new_env, es, bd)
+                let e_v = eval_(env, es, e)?;
+                eval_(&env.update(x.clone(), e_v), es, bd)
             }
 
-            Expr::If(tst, thn, els) => match eval_(env, es, tst) {
+            Expr::If(tst, thn, els) => match eval_(env, es, tst)? {
                 VBool(true) => eval_(env, es, thn),
                 VBool(false) => eval_(env, es, els),
                 _ => panic!("impossible: non-bool in test position of if"),
@@ -189,45 +632,180 @@ pub fn eval_(env: &TermEnv, es: &mut EvalState, expr: &Expr) -> Value {
             // we treat `Nil` here differently from the other `PrimOp`s,
             // interpreting it directly as a value (since it is not a function,
             // like all the other `PrimOp`s.
-            Expr::Prim(PrimOp::Nil) => VList(Vec::new()),
+            Expr::Prim(PrimOp::Nil) => Ok(VList(Vec::new())),
 
             // this represents a PrimOp that is not in application position.
             // since it is then being used as an argument (or being bound), we
             // must package it into a closure so it can be used "lifted".
-            //
-            // note that we assume these are arity-2 PrimOps - an assumption
-            // which likely will not hold in the future.
-            Expr::Prim(op) => {
-                let nm1 = es.fresh();
-                let nm2 = es.fresh();
-                let bd = app!(
-                    app!(Expr::Prim(op.clone()), Expr::Var(nm1.clone())),
-                    Expr::Var(nm2.clone())
-                );
-                let inner = lam!(nm2, bd);
-                VClosure(nm1, Box::new(inner), HashMap::new())
+            Expr::Prim(op) => Ok(lift_primop(es, op)),
+
+            Expr::App(fun, arg) => {
+                let fun_v = eval_(env, es, fun)?;
+                let arg_v = eval_(env, es, arg)?;
+                apply(es, fun_v, arg_v)
             }
 
-            Expr::App(fun, arg) => match eval_(env, es, fun) {
-                VClosure(nm, bd, clo) => {
-                    let arg_v = eval_(env, es, arg);
-                    let mut new_env = clo.clone();
-                    new_env.insert(nm, arg_v);
-                    eval_(&new_env, es, &bd)
-                }
-                _ => panic!("impossible: non-closure in function position of app"),
-            },
+            // `(fix e)` unfolds to `(e (lam [x] ((fix e) x)))`: under strict
+            // evaluation, unfolding to `(e (fix e))` would unfold forever. the
+            // fresh name cannot clash with a name in the program.
+            Expr::Fix(e) => {
+                let x = es.fresh();
+                let delayed = lam!(x.clone(), app!(Expr::Fix(e.clone()), Expr::Var(x)));
+                eval_(env, es, &app!(*e.clone(), delayed))
+            }
+        },
+    }
+}
 
-            Expr::Fix(e) => eval_(
-                env,
-                es,
-                &Expr::App(e.clone(), Box::new(Expr::Fix(e.clone()))),
-            ),
+/// the closure for a PrimOp which is not in application position.
+pub(crate) fn lift_primop(es: &mut EvalState, op: &PrimOp) -> Value {
+    es.charge(es.costs.allocation);
+    let names: Vec<Name> = iter::repeat_with(|| es.fresh())
+        .take(primop_arity(op))
+        .collect();
+    let app_f = |f, nm: &Name| app!(f, Expr::Var(nm.clone()));
+    let bd = names.iter().fold(Expr::Prim(op.clone()), app_f);
+    match names.split_first() {
+        Some((nm1, rest)) => {
+            let lam_f = |bd, nm: &Name| lam!(nm.clone(), bd);
+            let inner = rest.iter().rev().fold(bd, lam_f);
+            VClosure(nm1.clone(), Box::new(inner), TermEnv::new())
+        }
+        None => panic!("impossible: {:?} has arity 0", op),
+    }
+}
+
+/// apply a fully applied PrimOp to the values of its arguments.
+pub(crate) fn apply_primop(
+    es: &mut EvalState,
+    op: PrimOp,
+    args_v: Vec<Value>,
+) -> Result<Value, EvalError> {
+    // values from lazy evaluation may be delayed.
+    let args_v = args_v
+        .into_iter()
+        .map(|arg_v| lazy::spine(es, arg_v))
+        .collect::<Result<Vec<Value>, EvalError>>()?;
+    es.charge_primop(&op, &args_v);
+    let val = match op {
+        PrimOp::Add => match (&args_v[0], &args_v[1]) {
+            (VInt(a_), VInt(b_)) => VInt(a_ + b_),
+            _ => panic!("+: bad types"),
+        },
+        PrimOp::Sub => match (&args_v[0], &args_v[1]) {
+            (VInt(a_), VInt(b_)) => VInt(a_ - b_),
+            _ => panic!("-: bad types"),
+        },
+        PrimOp::Mul => match (&args_v[0], &args_v[1]) {
+            (VInt(a_), VInt(b_)) => VInt(a_ * b_),
+            _ => panic!("*: bad types"),
         },
+        PrimOp::Eql => match (&args_v[0], &args_v[1]) {
+            (VInt(a_), VInt(b_)) => VBool(a_ == b_),
+            _ => panic!("==: bad types"),
+        },
+        PrimOp::Null => match &args_v[0] {
+            VList(vec) => VBool(vec.is_empty()),
+            _ => panic!("null: bad types"),
+        },
+        PrimOp::Map => match &args_v[1] {
+            VList(vec) => {
+                let f = &args_v[0];
+                let results = vec
+                    .iter()
+                    .map(|arg_v| apply(es, f.clone(), arg_v.clone()))
+                    .collect::<Result<Vec<Value>, EvalError>>()?;
+                Value::VList(results)
+            }
+            _ => panic!("map: bad types"),
+        },
+        PrimOp::Foldl => match &args_v[2] {
+            VList(vec) => {
+                let f = &args_v[0];
+                let applicator = |acc: Value, arg_v: &Value| {
+                    let f_acc = apply(es, f.clone(), acc)?;
+                    apply(es, f_acc, arg_v.clone())
+                };
+                // TODO: why is this clone necessary?
+                vec.iter().try_fold(args_v[1].clone(), applicator)?
+            }
+            _ => panic!("foldl: bad types"),
+        },
+        PrimOp::Pair => {
+            let a = args_v[0].clone();
+            let b = args_v[1].clone();
+            VPair(Box::new(a), Box::new(b))
+        }
+        PrimOp::Fst => match &args_v[0] {
+            VPair(a, _) => *a.clone(),
+            _ => panic!("fst: bad types"),
+        },
+        PrimOp::Snd => match &args_v[0] {
+            VPair(_, b) => *b.clone(),
+            _ => panic!("snd: bad types"),
+        },
+        PrimOp::Cons => match &args_v[1] {
+            VList(vec) => VList(iter::once(&args_v[0]).chain(vec).cloned().collect()),
+            _ => panic!("cons: bad types"),
+        },
+        PrimOp::Nil => panic!("nil: application of non-function"),
+    };
+    Ok(val)
+}
+
+/// apply a function value (a closure or a native) to an argument.
+pub fn apply(es: &mut EvalState, fun: Value, arg: Value) -> Result<Value, EvalError> {
+    match fun {
+        VClosure(nm, bd, clo) => {
+            es.charge(es.costs.application);
+            eval_(&clo.update(nm, arg), es, &bd)
+        }
+        // a core closure is applied as written, if the evaluation is of
+        // expressions as written.
+        VCore(nm, bd, locals, globals) => {
+            es.charge(es.costs.application);
+            if es.evaluates_core() {
+                eval_core(&globals, &locals.push(nm, arg), es, &bd)
+            } else {
+                let (nm, bd, clo) = named_closure(&nm, &bd, &locals, &globals);
+                eval_(&clo.update(nm, arg), es, &bd)
+            }
+        }
+        // natives & compiled closures are strict, so their arguments are
+        // forced in full under the lazy strategy.
+        VCode(proto, captures) => {
+            let arg = match es.strategy {
+                Strategy::Strict => arg,
+                Strategy::Lazy => lazy::force_deep(es, arg)?,
+            };
+            es.charge(es.costs.application);
+            vm::call(es, &proto, captures, arg)
+        }
+        // natives accumulate their arguments until they are fully applied.
+        VNative(native, mut args) => {
+            es.charge(es.costs.application);
+            args.push(arg);
+            if args.len() == native.arity {
+                if es.strategy == Strategy::Lazy {
+                    args = args
+                        .into_iter()
+                        .map(|arg| lazy::force_deep(es, arg))
+                        .collect::<Result<Vec<Value>, EvalError>>()?;
+                }
+                native.call(&args)
+            } else {
+                Ok(VNative(native, args))
+            }
+        }
+        VThunk(thunk) => {
+            let fun = thunk.force(es)?;
+            apply(es, fun, arg)
+        }
+        _ => panic!("impossible: non-function in function position of app"),
     }
 }
 
-enum PrimOpApplyCase {
+pub(crate) enum PrimOpApplyCase {
     FullyApplied(PrimOp, Vec<Expr>),
     PartiallyApplied(Expr),
     Other,
@@ -252,7 +830,7 @@ fn find_prim_app(expr: &Expr, in_app: bool) -> Option<(PrimOp, Vec<Expr>)> {
     }
 }
 
-fn primop_apply_case(es: &mut EvalState, expr: &Expr) -> PrimOpApplyCase {
+pub(crate) fn primop_apply_case(es: &mut EvalState, expr: &Expr) -> PrimOpApplyCase {
     match find_prim_app(expr, false) {
         None => PrimOpApplyCase::Other,
         Some((op, args)) => {
@@ -271,7 +849,7 @@ fn primop_apply_case(es: &mut EvalState, expr: &Expr) -> PrimOpApplyCase {
                     // generate fresh names for the args which have not been applied
                     let names: Vec<Name> = iter::repeat_with(|| es.fresh()).take(delta).collect();
                     // wrap said fresh names into `Expr`s
-                    let name_vars = names.clone().into_iter().map(|nm| Expr::Var(nm));
+                    let name_vars = names.clone().into_iter().map(Expr::Var);
                     // iterator which runs through the provided arguments, adding the fresh names
                     // onto the end to fill out to a full application
                     let all_args = args.into_iter().chain(name_vars);
@@ -8,8 +8,15 @@ pub struct Constraint(pub Type, pub Type);
 
 pub type Subst = HashMap<TV, Type>;
 
+#[derive(Clone)]
 pub struct InferState(u64);
 
+impl Default for InferState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl InferState {
     pub fn new() -> InferState {
         InferState(0)
@@ -40,7 +47,7 @@ impl Type {
     pub fn apply(self, subst: &Subst) -> Type {
         match self {
             Type::TCon(a) => Type::TCon(a),
-            Type::TVar(ref a) => match subst.get(&a) {
+            Type::TVar(ref a) => match subst.get(a) {
                 None => self,
                 Some(x) => x.clone(),
             },
@@ -74,12 +81,12 @@ impl Type {
                 // TODO figure out if this is performing unnecessary copying
                 // I think we could just iterate through hs2 and insert values
                 // into `t1.ftv()`
-                t1.ftv().union(&hs2).map(|x| x.clone()).collect()
+                t1.ftv().union(&hs2).cloned().collect()
             }
             Type::TList(ty) => ty.ftv(),
             Type::TPair(t1, t2) => {
                 let hs2 = t2.ftv();
-                t1.ftv().union(&hs2).map(|x| x.clone()).collect()
+                t1.ftv().union(&hs2).cloned().collect()
             }
         }
     }
@@ -92,7 +99,7 @@ impl Scheme {
                 let subst2 = {
                     let mut subst_ = subst.clone();
                     for x in &xs {
-                        subst_.remove(&x);
+                        subst_.remove(x);
                     }
                     subst_
                 };
@@ -100,6 +107,7 @@ impl Scheme {
             }
         }
     }
+    #[allow(dead_code)]
     fn ftv(self) -> HashSet<TV> {
         match self {
             Scheme(xs, ty) => {
@@ -129,7 +137,7 @@ impl Constraint {
             Constraint(t1, t2) => {
                 let hs2 = t2.ftv();
                 // TODO see note on Type::ftv about excess copying
-                t1.ftv().union(&hs2).map(|x| x.clone()).collect()
+                t1.ftv().union(&hs2).cloned().collect()
             }
         }
     }
@@ -142,17 +150,17 @@ impl Constraint {
 // `Env`.
 impl Env {
     fn apply(self, subst: &Subst) -> Env {
+        // a substitution which binds none of the environment's free type
+        // variables leaves it unchanged, as is usual of one from a `let`.
+        if !subst.keys().any(|tv| self.has_free_type_var(tv)) {
+            return self;
+        }
         self.iter()
             .map(|(nm, sc)| (nm.clone(), sc.clone().apply(subst)))
             .collect()
     }
     fn ftv(self) -> HashSet<TV> {
-        let mut hs = HashSet::new();
-        for sc in self.values() {
-            let sc_ftvs = sc.clone().ftv();
-            hs = hs.union(&sc_ftvs).map(|x| x.clone()).collect();
-        }
-        hs
+        self.free_type_vars().cloned().collect()
     }
 }
 
@@ -163,6 +171,8 @@ pub enum TypeError {
     UnboundVariable(Name),
     Ambigious(Vec<Constraint>),
     UnificationMismatch(Vec<Type>, Vec<Type>),
+    /// a name declared twice in a program's `params`.
+    DuplicateParameter(Name),
 }
 
 fn infer(
@@ -173,7 +183,7 @@ fn infer(
     match expr {
         Expr::Lit(lit) => Ok((infer_lit(lit), Vec::new())),
         Expr::Var(nm) => {
-            let ty = lookup_env(&env, is, &nm)?;
+            let ty = lookup_env(env, is, nm)?;
             Ok((ty, Vec::new()))
         }
         Expr::Lam(nm, bd) => {
@@ -243,25 +253,42 @@ pub fn infer_program_with_is(
     prog: &Program,
 ) -> Result<(Scheme, Env, InferState), TypeError> {
     for Defn(name, expr) in prog.p_defns.iter() {
-        let sc = infer_expr(&env, &expr)?;
+        let sc = infer_expr(&env, expr)?;
         env.extend(name.clone(), sc);
     }
-    let (sc, is) = infer_expr_with_is(&env, &prog.p_body)?;
+    let (sc, is) = infer_program_body(&env, prog)?;
     Ok((sc, env, is))
 }
 
 pub fn infer_program(mut env: Env, prog: &Program) -> Result<(Scheme, Env), TypeError> {
     for Defn(name, expr) in prog.p_defns.iter() {
-        let sc = infer_expr(&env, &expr)?;
+        let sc = infer_expr(&env, expr)?;
         env.extend(name.clone(), sc);
     }
-    let sc = infer_expr(&env, &prog.p_body)?;
+    let (sc, _is) = infer_program_body(&env, prog)?;
     Ok((sc, env))
 }
 
-fn infer_expr_with_is(env: &Env, expr: &Expr) -> Result<(Scheme, InferState), TypeError> {
+/// infer the type of the body of `prog`, abstracted over its parameters. the
+/// type must agree with the parameters' declared types. type variables in the
+/// declared types are instantiated afresh, so they may be specialised by
+/// inference.
+fn infer_program_body(env: &Env, prog: &Program) -> Result<(Scheme, InferState), TypeError> {
     let mut is = InferState::new();
-    let (ty, csts) = infer(env, &mut is, expr)?;
+    let (ty, mut csts) = infer(env, &mut is, &prog.body_expr())?;
+    if let Some(params) = &prog.p_params {
+        for (ix, Param(nm, _ty)) in params.iter().enumerate() {
+            if params[..ix].iter().any(|Param(prev, _ty)| prev == nm) {
+                return Err(TypeError::DuplicateParameter(nm.clone()));
+            }
+        }
+        let declared_tys: Vec<Type> = params.iter().map(|Param(_, ty)| ty.clone()).collect();
+        let declared = type_arr_multi(declared_tys, is.fresh());
+        let mut tvs: Vec<TV> = free_type_vars(declared.clone()).collect();
+        tvs.dedup();
+        let declared = instantiate(&mut is, &Scheme(tvs, declared))?;
+        csts.push(Constraint(ty.clone(), declared));
+    }
     let subst = run_solve(csts)?;
     Ok((close_over(ty.apply(&subst)), is))
 }
@@ -301,7 +328,7 @@ fn normalize(sc: Scheme) -> Scheme {
         }
         hm
     };
-    let foralls = hm.values().map(|x| x.clone()).collect();
+    let foralls = hm.values().cloned().collect();
     let ty = norm_type(&hm, body);
     Scheme(foralls, ty)
 }
@@ -330,7 +357,7 @@ fn norm_type(hm: &HashMap<TV, TV>, ty: Type) -> Type {
     }
 }
 
-fn free_type_vars(ty: Type) -> Box<dyn Iterator<Item = TV>> {
+pub(crate) fn free_type_vars(ty: Type) -> Box<dyn Iterator<Item = TV>> {
     match ty {
         Type::TVar(a) => Box::new(iter::once(a)),
         Type::TArr(a, b) => Box::new(free_type_vars(*a).chain(free_type_vars(*b))),
@@ -403,7 +430,7 @@ fn bind(a: TV, t: Type) -> Result<Subst, TypeError> {
 }
 
 fn occurs_check(a: &TV, t: Type) -> bool {
-    t.ftv().contains(&a)
+    t.ftv().contains(a)
 }
 
 fn compose(mut s1: Subst, mut s2: Subst) -> Subst {
@@ -412,7 +439,7 @@ fn compose(mut s1: Subst, mut s2: Subst) -> Subst {
     }
     // INFO we want a union which is biased to `s2`. `extend` will overwrite
     // entries in `s1`.
-    s1.extend(s2.into_iter());
+    s1.extend(s2);
     s1
 }
 
@@ -423,7 +450,7 @@ fn lookup_env(env: &Env, is: &mut InferState, nm: &Name) -> Result<Type, TypeErr
     }
 }
 
-fn instantiate(is: &mut InferState, sc: &Scheme) -> Result<Type, TypeError> {
+pub fn instantiate(is: &mut InferState, sc: &Scheme) -> Result<Type, TypeError> {
     match sc {
         Scheme(xs, ty) => {
             let subst: Subst = xs
@@ -440,7 +467,7 @@ fn generalize(env: Env, ty: Type) -> Scheme {
     let ty_ = ty.clone();
     let ty_ftv = ty.ftv();
     let env_ftv = env.ftv();
-    let free_vars = ty_ftv.difference(&env_ftv).map(|x| x.clone());
+    let free_vars = ty_ftv.difference(&env_ftv).cloned();
     Scheme(free_vars.collect(), ty_)
 }
 
@@ -0,0 +1,403 @@
+//! JSON (or any other serde format) support, enabled by the `serde` feature.
+//!
+//! the serialized forms below are stable: they are part of the crate's public
+//! interface, and will only change in a breaking release.
+//!
+//! # values
+//!
+//! | value         | JSON                     |
+//! |---------------|--------------------------|
+//! | `VInt(n)`     | `n`                      |
+//! | `VBool(b)`    | `true` / `false`         |
+//! | `VList(xs)`   | `[x, ...]`               |
+//! | `VPair(a, b)` | `{"pair": [a, b]}`       |
+//! | `VClosure`    | (not serializable)       |
+//! | `VCore`       | (not serializable)       |
+//! | `VCode`       | (not serializable)       |
+//! | `VNative`     | (not serializable)       |
+//!
+//! serializing a closure (or native function) is an error. integers must fit in an `i64`.
+//! the thunks & list cells of lazy evaluation are forced, & serialized as the
+//! values they evaluate to.
+//!
+//! # types & schemes
+//!
+//! | type                  | JSON                       |
+//! |-----------------------|----------------------------|
+//! | type variable `a`     | `{"var": "a"}`             |
+//! | constructor `Int`     | `{"con": "Int"}`           |
+//! | `(a -> b)`            | `{"arr": [a, b]}`          |
+//! | `(List a)`            | `{"list": a}`              |
+//! | `(a, b)`              | `{"pair": [a, b]}`         |
+//!
+//! a scheme is `{"forall": ["t1", ...], "type": ty}`.
+//!
+//! # expressions
+//!
+//! each expression is an object with a single key naming its form:
+//!
+//! | expression            | JSON                                   |
+//! |-----------------------|----------------------------------------|
+//! | variable              | `{"var": "x"}`                         |
+//! | application           | `{"app": [f, x]}`                      |
+//! | `(lam [x] e)`         | `{"lam": ["x", e]}`                    |
+//! | `(let ([x e1]) e2)`   | `{"let": ["x", e1, e2]}`               |
+//! | literal               | `{"lit": {"int": 1}}`, `{"lit": {"bool": true}}` |
+//! | `(if c t e)`          | `{"if": [c, t, e]}`                    |
+//! | `(fix e)`             | `{"fix": e}`                           |
+//! | primitive             | `{"prim": "add"}`, ...                 |
+//!
+//! primitives are named `add`, `sub`, `mul`, `eql`, `null`, `map`, `foldl`,
+//! `pair`, `fst`, `snd`, `cons` and `nil`.
+//!
+//! # programs & modules
+//!
+//! a program is
+//! `{"imports": [...], "defns": [["name", e], ...], "params": [["name", ty], ...] | null, "body": e}`,
+//! where each import is `{"path": "lib/util.poly"}` or `{"named": "util"}`.
+//! a module is `{"exports": ["name", ...] | null, "imports": [...], "defns": [...]}`.
+//!
+//! # derivations
+//!
+//! a `provenance::Derivation` is
+//! `{"kind": k, "expr": "...", "value": "...", "bindings": [b, ...], "children": [d, ...]}`,
+//! where the kind is `"calculation"`, `"input"`, `"defn"`, `"primitive"` or
+//! `{"element": i}`, & each binding is
+//! `{"name": "x", "source": "input" | "defn" | "local", "value": "..."}`.
+//! expressions & values are pretty printed.
+//!
+//! # type-directed decoding
+//!
+//! the value schema above can be decoded without knowing the expected type.
+//! when the type is known (e.g. from a calculation's signature), `decode_value`
+//! also accepts a pair as a two-element array `[a, b]`, and reports mismatches
+//! along with the path (e.g. `$[1][0]`) at which they occurred.
+
+use std::{collections::HashSet, fmt};
+
+use serde::{
+    de::{self, MapAccess, SeqAccess, Visitor},
+    ser::{self, SerializeMap, SerializeSeq},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use super::{
+    eval::Value::{self, *},
+    syntax::Name,
+    toplevel::CalculationSignature,
+    types::Type,
+    util::pretty::to_pretty,
+};
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            VInt(n) => serializer.serialize_i64(*n),
+            VBool(b) => serializer.serialize_bool(*b),
+            VClosure(_, _, _) | VCore(_, _, _, _) | VCode(_, _) => {
+                Err(ser::Error::custom("closures cannot be serialized"))
+            }
+            VNative(native, _) => Err(ser::Error::custom(format!(
+                "native functions cannot be serialized: {}",
+                native.name.0
+            ))),
+            VList(vs) => {
+                let mut seq = serializer.serialize_seq(Some(vs.len()))?;
+                for v in vs {
+                    seq.serialize_element(v)?;
+                }
+                seq.end()
+            }
+            VPair(a, b) => {
+                let mut map = serializer.serialize_map(Some(1))?;
+                map.serialize_entry("pair", &(a, b))?;
+                map.end()
+            }
+            VThunk(_) | VCons(_, _) => match self.clone().forced() {
+                Ok(val) => val.serialize(serializer),
+                Err(err) => Err(ser::Error::custom(format!("evaluation failed: {:?}", err))),
+            },
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Value, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "an integer, boolean, list, or pair")
+    }
+
+    fn visit_i64<E: de::Error>(self, n: i64) -> Result<Value, E> {
+        Ok(VInt(n))
+    }
+
+    fn visit_u64<E: de::Error>(self, n: u64) -> Result<Value, E> {
+        if n <= i64::MAX as u64 {
+            Ok(VInt(n as i64))
+        } else {
+            Err(E::custom(format!("integer out of range: {}", n)))
+        }
+    }
+
+    fn visit_bool<E: de::Error>(self, b: bool) -> Result<Value, E> {
+        Ok(VBool(b))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut vs = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(v) = seq.next_element()? {
+            vs.push(v);
+        }
+        Ok(VList(vs))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let val = match map.next_key::<String>()? {
+            Some(key) if key == "pair" => {
+                let (a, b): (Value, Value) = map.next_value()?;
+                VPair(Box::new(a), Box::new(b))
+            }
+            Some(key) => return Err(de::Error::unknown_field(&key, &["pair"])),
+            None => return Err(de::Error::missing_field("pair")),
+        };
+        match map.next_key::<String>()? {
+            None => Ok(val),
+            Some(key) => Err(de::Error::unknown_field(&key, &["pair"])),
+        }
+    }
+}
+
+/// a step from a JSON value into one of its components.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PathSegment {
+    /// an element of an array (a list element, a pair component, or a
+    /// positional argument).
+    Index(usize),
+    /// a field of an object (a named argument, or the `pair` field).
+    Field(String),
+}
+
+#[derive(Debug)]
+pub enum DecodeErrorKind {
+    /// the JSON value does not have the expected type.
+    Mismatch(Type, serde_json::Value),
+    IntOutOfRange(serde_json::Value),
+    /// functions cannot be passed as input data.
+    FunctionType(Type),
+    UnknownType(Type),
+    /// the JSON value at a polymorphic position is not a valid value (see the
+    /// value schema).
+    Untyped(String),
+    /// the calculation's arity, and the number of arguments given.
+    ArityMismatch(usize, usize),
+    /// arguments were given by name, but the calculation's parameters are not
+    /// (all) named.
+    UnnamedParameters,
+    MissingParameter(Name),
+    UnexpectedParameter(String),
+    /// the arguments were neither an array nor an object.
+    NotArguments(serde_json::Value),
+}
+
+#[derive(Debug)]
+pub struct DecodeError {
+    /// the path from the root of the JSON input to the offending value.
+    pub path: Vec<PathSegment>,
+    pub kind: DecodeErrorKind,
+}
+
+impl DecodeError {
+    fn new(path: &[PathSegment], kind: DecodeErrorKind) -> DecodeError {
+        DecodeError {
+            path: path.to_vec(),
+            kind,
+        }
+    }
+
+    pub fn path_string(&self) -> String {
+        let mut s = "$".to_string();
+        for seg in &self.path {
+            match seg {
+                PathSegment::Index(i) => s.push_str(&format!("[{}]", i)),
+                PathSegment::Field(f) => s.push_str(&format!(".{}", f)),
+            }
+        }
+        s
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ty = |ty: &Type| to_pretty(ty.ppr(), 80);
+        write!(f, "at {}: ", self.path_string())?;
+        match &self.kind {
+            DecodeErrorKind::Mismatch(t, json) => write!(f, "expected {}, found {}", ty(t), json),
+            DecodeErrorKind::IntOutOfRange(json) => write!(f, "integer out of range: {}", json),
+            DecodeErrorKind::FunctionType(t) => write!(f, "cannot decode a function: {}", ty(t)),
+            DecodeErrorKind::UnknownType(t) => write!(f, "unknown type: {}", ty(t)),
+            DecodeErrorKind::Untyped(msg) => write!(f, "{}", msg),
+            DecodeErrorKind::ArityMismatch(expected, found) => {
+                write!(f, "expected {} arguments, found {}", expected, found)
+            }
+            DecodeErrorKind::UnnamedParameters => {
+                write!(f, "arguments given by name, but parameters are not named")
+            }
+            DecodeErrorKind::MissingParameter(Name(nm)) => write!(f, "missing parameter: {}", nm),
+            DecodeErrorKind::UnexpectedParameter(nm) => {
+                write!(f, "unexpected parameter: {}", nm)
+            }
+            DecodeErrorKind::NotArguments(json) => {
+                write!(
+                    f,
+                    "expected an array or object of arguments, found {}",
+                    json
+                )
+            }
+        }
+    }
+}
+
+/// decode `json` as a value of type `ty`.
+pub fn decode_value(ty: &Type, json: &serde_json::Value) -> Result<Value, DecodeError> {
+    decode_at(&mut Vec::new(), ty, json)
+}
+
+/// decode the arguments to a calculation, given either positionally as an
+/// array, or by name as an object (if the calculation's parameters are named).
+/// the result is in parameter order, ready to be passed to e.g.
+/// `CompiledCalculation::reduce`.
+pub fn decode_arguments(
+    sig: &CalculationSignature,
+    json: &serde_json::Value,
+) -> Result<Vec<Value>, DecodeError> {
+    let mut path = Vec::new();
+    match json {
+        serde_json::Value::Array(args) => {
+            if args.len() != sig.parameters.len() {
+                let kind = DecodeErrorKind::ArityMismatch(sig.parameters.len(), args.len());
+                return Err(DecodeError::new(&path, kind));
+            }
+            let mut vals = Vec::new();
+            for (i, (param, arg)) in sig.parameters.iter().zip(args).enumerate() {
+                path.push(PathSegment::Index(i));
+                vals.push(decode_at(&mut path, &param.ty, arg)?);
+                path.pop();
+            }
+            Ok(vals)
+        }
+        serde_json::Value::Object(args) => {
+            let mut vals = Vec::new();
+            let mut seen = HashSet::new();
+            for param in &sig.parameters {
+                let nm = match &param.name {
+                    Some(nm) => nm,
+                    None => {
+                        return Err(DecodeError::new(&path, DecodeErrorKind::UnnamedParameters))
+                    }
+                };
+                let arg = args.get(&nm.0).ok_or_else(|| {
+                    DecodeError::new(&path, DecodeErrorKind::MissingParameter(nm.clone()))
+                })?;
+                path.push(PathSegment::Field(nm.0.clone()));
+                vals.push(decode_at(&mut path, &param.ty, arg)?);
+                path.pop();
+                seen.insert(&nm.0);
+            }
+            match args.keys().find(|k| !seen.contains(k)) {
+                Some(k) => Err(DecodeError::new(
+                    &path,
+                    DecodeErrorKind::UnexpectedParameter(k.clone()),
+                )),
+                None => Ok(vals),
+            }
+        }
+        _ => Err(DecodeError::new(
+            &path,
+            DecodeErrorKind::NotArguments(json.clone()),
+        )),
+    }
+}
+
+fn decode_at(
+    path: &mut Vec<PathSegment>,
+    ty: &Type,
+    json: &serde_json::Value,
+) -> Result<Value, DecodeError> {
+    use serde_json::Value as J;
+    let mismatch = |path: &[PathSegment]| {
+        DecodeError::new(path, DecodeErrorKind::Mismatch(ty.clone(), json.clone()))
+    };
+    match (ty, json) {
+        (Type::TCon(c), J::Number(n)) if c == "Int" => match n.as_i64() {
+            Some(n) => Ok(VInt(n)),
+            None if n.is_f64() => Err(mismatch(path)),
+            None => Err(DecodeError::new(
+                path,
+                DecodeErrorKind::IntOutOfRange(json.clone()),
+            )),
+        },
+        (Type::TCon(c), J::Bool(b)) if c == "Bool" => Ok(VBool(*b)),
+        (Type::TCon(c), _) if c == "Int" || c == "Bool" => Err(mismatch(path)),
+        (Type::TCon(_), _) => Err(DecodeError::new(
+            path,
+            DecodeErrorKind::UnknownType(ty.clone()),
+        )),
+        (Type::TList(elem_ty), J::Array(elems)) => {
+            let mut vals = Vec::with_capacity(elems.len());
+            for (i, elem) in elems.iter().enumerate() {
+                path.push(PathSegment::Index(i));
+                vals.push(decode_at(path, elem_ty, elem)?);
+                path.pop();
+            }
+            Ok(VList(vals))
+        }
+        (Type::TPair(a_ty, b_ty), J::Array(elems)) if elems.len() == 2 => {
+            decode_pair(path, a_ty, b_ty, &elems[0], &elems[1])
+        }
+        (Type::TPair(a_ty, b_ty), J::Object(fields)) if fields.len() == 1 => {
+            match fields.get("pair") {
+                Some(J::Array(elems)) if elems.len() == 2 => {
+                    path.push(PathSegment::Field("pair".to_string()));
+                    let val = decode_pair(path, a_ty, b_ty, &elems[0], &elems[1])?;
+                    path.pop();
+                    Ok(val)
+                }
+                _ => Err(mismatch(path)),
+            }
+        }
+        (Type::TList(_), _) | (Type::TPair(_, _), _) => Err(mismatch(path)),
+        (Type::TArr(_, _), _) => Err(DecodeError::new(
+            path,
+            DecodeErrorKind::FunctionType(ty.clone()),
+        )),
+        // nothing is known about the value at a polymorphic position, so it
+        // must be self-describing.
+        (Type::TVar(_), _) => serde_json::from_value(json.clone())
+            .map_err(|err| DecodeError::new(path, DecodeErrorKind::Untyped(err.to_string()))),
+    }
+}
+
+fn decode_pair(
+    path: &mut Vec<PathSegment>,
+    a_ty: &Type,
+    b_ty: &Type,
+    a: &serde_json::Value,
+    b: &serde_json::Value,
+) -> Result<Value, DecodeError> {
+    path.push(PathSegment::Index(0));
+    let a = decode_at(path, a_ty, a)?;
+    path.pop();
+    path.push(PathSegment::Index(1));
+    let b = decode_at(path, b_ty, b)?;
+    path.pop();
+    Ok(VPair(Box::new(a), Box::new(b)))
+}
@@ -0,0 +1,304 @@
+//! call-by-need evaluation, selected by `Strategy::Lazy`.
+//!
+//! under the lazy strategy, the arguments of applications, the expressions
+//! bound by `let`s, & the components of pairs & list cells are delayed as
+//! thunks. a thunk is evaluated when its value is needed, & at most once: its
+//! value is memoized, & shared by all of its copies. `eval_` still evaluates
+//! to weak head normal form, so the values it returns are not thunks, though
+//! they may contain thunks. `foldl` is strict in its accumulator.
+//!
+//! lists built by `cons` are `VCons` cells, whose tails may be thunks, so
+//! that infinite lists may be built with `fix`. pretty printing, conversions
+//! & serialization force values as they go (see `Value::forced`). forcing all
+//! of an infinite list does not terminate.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::{
+    eval::{
+        apply, apply_primop, eval_, lift_primop, primop_apply_case, EvalError, EvalState,
+        PrimOpApplyCase, Strategy, TermEnv, Value, Value::*,
+    },
+    syntax::{Expr, Lit, Name, PrimOp},
+};
+use crate::app;
+
+/// a delayed evaluation.
+#[derive(Clone)]
+pub struct Thunk(Arc<Mutex<ThunkState>>);
+
+enum ThunkState {
+    Delayed(Expr, TermEnv),
+    Forced(Value),
+}
+
+impl Thunk {
+    pub fn new(expr: Expr, env: TermEnv) -> Thunk {
+        Thunk(Arc::new(Mutex::new(ThunkState::Delayed(expr, env))))
+    }
+
+    /// the value of the thunk, in weak head normal form, evaluating it if it
+    /// has not been already.
+    pub fn force(&self, es: &mut EvalState) -> Result<Value, EvalError> {
+        // the lock is not held during evaluation: a thunk forced on several
+        // threads at once may be evaluated more than once, to the same value.
+        let (expr, env) = match &*self.lock() {
+            ThunkState::Forced(val) => return Ok(val.clone()),
+            ThunkState::Delayed(expr, env) => (expr.clone(), env.clone()),
+        };
+        let val = eval_(&env, es, &expr)?;
+        let val = whnf(es, val)?;
+        *self.lock() = ThunkState::Forced(val.clone());
+        Ok(val)
+    }
+
+    pub fn is_forced(&self) -> bool {
+        matches!(&*self.lock(), ThunkState::Forced(_))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ThunkState> {
+        let Thunk(state) = self;
+        // a panic while the lock is held cannot leave the state inconsistent.
+        match state.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+impl Value {
+    /// the value, with any thunk at its head forced, & any list cells forced
+    /// into a `VList` (whose elements may yet be thunks). forcing is unbounded
+    /// & lazy, but fails if a thunk calls a native which fails.
+    pub fn forced(self) -> Result<Value, EvalError> {
+        let mut es = EvalState::new();
+        es.set_strategy(Strategy::Lazy);
+        spine(&mut es, self)
+    }
+}
+
+/// force a thunk at the head of `val`.
+pub(crate) fn whnf(es: &mut EvalState, val: Value) -> Result<Value, EvalError> {
+    match val {
+        VThunk(thunk) => thunk.force(es),
+        val => Ok(val),
+    }
+}
+
+/// force a thunk at the head of `val`, & the spine of a list into a `VList`.
+pub(crate) fn spine(es: &mut EvalState, val: Value) -> Result<Value, EvalError> {
+    let mut elems = Vec::new();
+    let mut val = whnf(es, val)?;
+    loop {
+        match val {
+            VCons(head, tail) => {
+                elems.push(*head);
+                val = whnf(es, *tail)?;
+            }
+            VList(rest) if !elems.is_empty() => {
+                elems.extend(rest);
+                return Ok(VList(elems));
+            }
+            val => return Ok(val),
+        }
+    }
+}
+
+/// force `val` in full, leaving no thunks.
+pub(crate) fn force_deep(es: &mut EvalState, val: Value) -> Result<Value, EvalError> {
+    match spine(es, val)? {
+        VList(elems) => Ok(VList(
+            elems
+                .into_iter()
+                .map(|elem| force_deep(es, elem))
+                .collect::<Result<Vec<Value>, EvalError>>()?,
+        )),
+        VPair(a, b) => Ok(VPair(
+            Box::new(force_deep(es, *a)?),
+            Box::new(force_deep(es, *b)?),
+        )),
+        val => Ok(val),
+    }
+}
+
+pub(crate) fn eval_step(
+    env: &TermEnv,
+    es: &mut EvalState,
+    expr: &Expr,
+) -> Result<Value, EvalError> {
+    match primop_apply_case(es, expr) {
+        PrimOpApplyCase::FullyApplied(op, args) => eval_primop(env, es, op, args),
+        PrimOpApplyCase::PartiallyApplied(lam) => eval_(env, es, &lam),
+        PrimOpApplyCase::Other => match expr {
+            Expr::Lit(Lit::LInt(x)) => Ok(VInt(*x)),
+            Expr::Lit(Lit::LBool(x)) => Ok(VBool(*x)),
+            Expr::Var(x) => whnf(es, lookup(env, x)),
+            Expr::Lam(nm, bd) => {
+                es.charge(es.costs().allocation);
+                Ok(VClosure(nm.clone(), bd.clone(), env.clone()))
+            }
+            Expr::Let(x, e, bd) => eval_(&env.update(x.clone(), delay(es, env, e)), es, bd),
+            Expr::If(tst, thn, els) => match eval_(env, es, tst)? {
+                VBool(true) => eval_(env, es, thn),
+                VBool(false) => eval_(env, es, els),
+                _ => panic!("impossible: non-bool in test position of if"),
+            },
+            Expr::Prim(PrimOp::Nil) => Ok(VList(Vec::new())),
+            Expr::Prim(op) => Ok(lift_primop(es, op)),
+            Expr::App(fun, arg) => {
+                let fun_v = eval_(env, es, fun)?;
+                let arg_v = delay(es, env, arg);
+                apply(es, fun_v, arg_v)
+            }
+            // `(fix e)` unfolds to `(e (fix e))`: the argument is delayed, so
+            // no eta expansion is needed.
+            Expr::Fix(e) => {
+                let fun_v = eval_(env, es, e)?;
+                let arg_v = delay(es, env, expr);
+                apply(es, fun_v, arg_v)
+            }
+        },
+    }
+}
+
+fn eval_primop(
+    env: &TermEnv,
+    es: &mut EvalState,
+    op: PrimOp,
+    args: Vec<Expr>,
+) -> Result<Value, EvalError> {
+    // the strict PrimOps are charged by `apply_primop`, & the elements
+    // processed by `map` & `foldl` as they are.
+    match op {
+        PrimOp::Pair | PrimOp::Cons | PrimOp::Fst | PrimOp::Snd | PrimOp::Null => {
+            es.charge_primop(&op, &[])
+        }
+        PrimOp::Map | PrimOp::Foldl => es.charge(es.costs().primop(&op)),
+        _ => (),
+    }
+    match op {
+        // constructors do not evaluate their arguments.
+        PrimOp::Pair => Ok(VPair(
+            Box::new(delay(es, env, &args[0])),
+            Box::new(delay(es, env, &args[1])),
+        )),
+        PrimOp::Cons => Ok(VCons(
+            Box::new(delay(es, env, &args[0])),
+            Box::new(delay(es, env, &args[1])),
+        )),
+        PrimOp::Fst | PrimOp::Snd => match eval_(env, es, &args[0])? {
+            VPair(a, b) => whnf(es, if op == PrimOp::Fst { *a } else { *b }),
+            _ => panic!("fst/snd: bad types"),
+        },
+        PrimOp::Null => match eval_(env, es, &args[0])? {
+            VList(vec) => Ok(VBool(vec.is_empty())),
+            VCons(_, _) => Ok(VBool(false)),
+            _ => panic!("null: bad types"),
+        },
+        // the elements of the result are the (delayed) applications of the
+        // function, & the rest of an unforced list is mapped over lazily.
+        PrimOp::Map => {
+            let fun = eval_(env, es, &args[0])?;
+            match eval_(env, es, &args[1])? {
+                VList(vec) => {
+                    es.charge(es.costs().element.saturating_mul(vec.len() as u64));
+                    Ok(VList(
+                        vec.into_iter().map(|x| delay_apply(es, &fun, x)).collect(),
+                    ))
+                }
+                VCons(head, tail) => {
+                    es.charge(es.costs().element);
+                    let map = app!(app!(Expr::Prim(PrimOp::Map), var(FUN)), var(ARG));
+                    let rest = Thunk::new(map, bind(fun.clone(), *tail));
+                    Ok(VCons(
+                        Box::new(delay_apply(es, &fun, *head)),
+                        Box::new(VThunk(rest)),
+                    ))
+                }
+                _ => panic!("map: bad types"),
+            }
+        }
+        // the accumulator is forced at each step, so that a long list does not
+        // build up a long chain of thunks.
+        PrimOp::Foldl => {
+            let fun = eval_(env, es, &args[0])?;
+            let mut acc = eval_(env, es, &args[1])?;
+            let mut list = eval_(env, es, &args[2])?;
+            loop {
+                match list {
+                    VCons(head, tail) => {
+                        es.charge(es.costs().element);
+                        let f_acc = apply(es, fun.clone(), acc)?;
+                        let acc_v = apply(es, f_acc, *head)?;
+                        acc = whnf(es, acc_v)?;
+                        list = whnf(es, *tail)?;
+                    }
+                    VList(vec) => {
+                        es.charge(es.costs().element.saturating_mul(vec.len() as u64));
+                        for x in vec {
+                            let f_acc = apply(es, fun.clone(), acc)?;
+                            let acc_v = apply(es, f_acc, x)?;
+                            acc = whnf(es, acc_v)?;
+                        }
+                        return Ok(acc);
+                    }
+                    _ => panic!("foldl: bad types"),
+                }
+            }
+        }
+        // the remaining PrimOps are strict in all of their arguments.
+        op => {
+            let args_v = args
+                .iter()
+                .map(|arg| eval_(env, es, arg))
+                .collect::<Result<Vec<Value>, EvalError>>()?;
+            apply_primop(es, op, args_v)
+        }
+    }
+}
+
+// the names bound in the environments of thunks built by the evaluator,
+// rather than from the program.
+const FUN: &str = "_f";
+const ARG: &str = "_x";
+
+fn var(nm: &str) -> Expr {
+    Expr::Var(Name(nm.to_string()))
+}
+
+fn bind(fun: Value, arg: Value) -> TermEnv {
+    TermEnv::new()
+        .update(Name(FUN.to_string()), fun)
+        .update(Name(ARG.to_string()), arg)
+}
+
+fn lookup(env: &TermEnv, x: &Name) -> Value {
+    match env.get(x) {
+        None => panic!("impossible: free variable: {:?}", x),
+        Some(v) => v.clone(),
+    }
+}
+
+// the value of `expr` under `env`, delayed. expressions which are cheap to
+// evaluate are evaluated at once.
+fn delay(es: &mut EvalState, env: &TermEnv, expr: &Expr) -> Value {
+    match expr {
+        Expr::Lit(Lit::LInt(x)) => VInt(*x),
+        Expr::Lit(Lit::LBool(x)) => VBool(*x),
+        Expr::Var(x) => lookup(env, x),
+        Expr::Lam(nm, bd) => {
+            es.charge(es.costs().allocation);
+            VClosure(nm.clone(), bd.clone(), env.clone())
+        }
+        _ => {
+            es.charge(es.costs().allocation);
+            VThunk(Thunk::new(expr.clone(), env.clone()))
+        }
+    }
+}
+
+// the application of `fun` to `arg`, delayed.
+fn delay_apply(es: &mut EvalState, fun: &Value, arg: Value) -> Value {
+    es.charge(es.costs().allocation);
+    VThunk(Thunk::new(app!(var(FUN), var(ARG)), bind(fun.clone(), arg)))
+}
@@ -5,15 +5,37 @@ extern crate quickcheck;
 #[macro_use(quickcheck)]
 extern crate quickcheck_macros;
 
+// lets code generated by `poly-derive` refer to `::poly` within this crate, too.
+extern crate self as poly;
+
+pub mod cek;
+pub mod convert;
+pub mod core;
+pub mod cost;
+pub mod engine;
 pub mod env;
 pub mod eval;
 pub mod infer;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod lazy;
+pub mod lower;
+pub mod module;
+pub mod native;
+pub mod normalize;
+pub mod optimize;
 pub mod parse;
+pub mod prelude;
 pub mod pretty;
+pub mod provenance;
+pub mod specialize;
 pub mod syntax;
+pub mod termination;
 pub mod toplevel;
+pub mod trace;
 pub mod types;
 pub mod types_values;
 pub mod util;
+pub mod vm;
 
 pub mod test;
@@ -0,0 +1,472 @@
+//! lowering passes, towards the compilation of calculations to other targets.
+//!
+//! `anf_program` puts a program in A-normal form: the arguments of each
+//! application, the test of each `if` & the function of each `fix` are atoms
+//! (variables, literals or `PrimOp`s), & each intermediate result is bound by
+//! a `let`. the binders are renamed apart on the way, so no `let` shadows
+//! another, & nested `let`s are flattened.
+//!
+//! `closure_convert` then lifts each lambda of a program in A-normal form to
+//! a top-level `defn`, which takes the variables the lambda captured as an
+//! explicit environment: the variable itself, if it captured one, or nested
+//! pairs of them. a closure is the partial application of its `defn` to its
+//! environment, so closures may still be passed to `map`, `foldl` & the
+//! prelude. a lambda which refers to a `let`-bound closure captures the
+//! environment of that closure instead, & rebuilds it under the same `let`, so
+//! the closure stays polymorphic.
+//!
+//! each pass produces an ordinary program, which evaluates to the value of the
+//! program it was given (see `poly --dump-ir`).
+
+use std::collections::HashSet;
+
+use super::{
+    parse::reserved,
+    syntax::{primop_arity, Defn, Expr, Name, PrimOp, Program},
+};
+use crate::{app, lam};
+
+/// put a program in A-normal form.
+pub fn anf_program(prog: &Program) -> Program {
+    let mut anf = Anf::new(prog);
+    let defns = prog
+        .p_defns
+        .iter()
+        .map(|Defn(nm, bd)| Defn(nm.clone(), anf.expr(bd)))
+        .collect();
+    Program {
+        p_imports: prog.p_imports.clone(),
+        p_defns: defns,
+        p_params: prog.p_params.clone(),
+        p_body: anf.expr(&prog.p_body),
+    }
+}
+
+/// put an expression in A-normal form.
+pub fn anf(expr: &Expr) -> Expr {
+    let prog = Program {
+        p_imports: vec![],
+        p_defns: vec![],
+        p_params: None,
+        p_body: expr.clone(),
+    };
+    anf_program(&prog).p_body
+}
+
+/// whether an expression is in A-normal form.
+pub fn is_anf(expr: &Expr) -> bool {
+    match expr {
+        Expr::Let(_x, e, bd) => is_complex(e) && is_anf(bd),
+        _ => is_complex(expr),
+    }
+}
+
+fn is_atom(expr: &Expr) -> bool {
+    matches!(expr, Expr::Var(_) | Expr::Lit(_) | Expr::Prim(_))
+}
+
+fn is_complex(expr: &Expr) -> bool {
+    match expr {
+        Expr::App(_, _) => {
+            let (head, args) = spine(expr);
+            is_atom(head) && args.iter().all(|arg| is_atom(arg))
+        }
+        Expr::Lam(_x, bd) => is_anf(bd),
+        Expr::If(tst, thn, els) => is_atom(tst) && is_anf(thn) && is_anf(els),
+        Expr::Fix(e) => is_atom(e),
+        Expr::Let(_, _, _) => false,
+        _ => is_atom(expr),
+    }
+}
+
+// the function of a chain of applications, & its arguments, in order.
+fn spine(expr: &Expr) -> (&Expr, Vec<&Expr>) {
+    let mut args = Vec::new();
+    let mut head = expr;
+    while let Expr::App(fun, arg) = head {
+        args.push(&**arg);
+        head = fun;
+    }
+    args.reverse();
+    (head, args)
+}
+
+fn param_names(prog: &Program) -> Vec<Name> {
+    prog.p_params
+        .iter()
+        .flatten()
+        .map(|param| param.0.clone())
+        .collect()
+}
+
+fn lets(binds: Vec<(Name, Expr)>, bd: Expr) -> Expr {
+    binds
+        .into_iter()
+        .rev()
+        .fold(bd, |bd, (x, e)| Expr::Let(x, Box::new(e), Box::new(bd)))
+}
+
+// names which are not written anywhere in a program, nor reserved.
+pub(crate) struct Fresh {
+    used: HashSet<Name>,
+}
+
+impl Fresh {
+    pub(crate) fn new(prog: &Program) -> Fresh {
+        let mut used: HashSet<Name> = param_names(prog).into_iter().collect();
+        for Defn(nm, bd) in prog.p_defns.iter() {
+            used.insert(nm.clone());
+            names_in(bd, &mut used);
+        }
+        names_in(&prog.p_body, &mut used);
+        used.extend(reserved().into_iter().map(Name));
+        Fresh { used }
+    }
+
+    // avoid `names` too, e.g. the globals in scope of the program.
+    pub(crate) fn avoid(&mut self, names: impl IntoIterator<Item = Name>) {
+        self.used.extend(names);
+    }
+
+    // a name of letters alone, which may be written as a variable: `base`,
+    // then `basea`, `baseb`, ... `baseaa`, ...
+    pub(crate) fn var(&mut self, base: &str) -> Name {
+        let mut n = 0;
+        while self.used.contains(&Name::lettered(base, n)) {
+            n += 1;
+        }
+        let nm = Name::lettered(base, n);
+        self.used.insert(nm.clone());
+        nm
+    }
+}
+
+// the variables & binders of `expr`.
+fn names_in(expr: &Expr, names: &mut HashSet<Name>) {
+    match expr {
+        Expr::Var(x) => {
+            names.insert(x.clone());
+        }
+        Expr::Lit(_) | Expr::Prim(_) => (),
+        Expr::App(fun, arg) => {
+            names_in(fun, names);
+            names_in(arg, names);
+        }
+        Expr::Lam(x, bd) => {
+            names.insert(x.clone());
+            names_in(bd, names);
+        }
+        Expr::Let(x, e, bd) => {
+            names.insert(x.clone());
+            names_in(e, names);
+            names_in(bd, names);
+        }
+        Expr::If(tst, thn, els) => {
+            names_in(tst, names);
+            names_in(thn, names);
+            names_in(els, names);
+        }
+        Expr::Fix(e) => names_in(e, names),
+    }
+}
+
+struct Anf {
+    fresh: Fresh,
+    // the globals & the binders so far, which a binder is renamed apart from.
+    seen: HashSet<Name>,
+    // the binders in scope, innermost last, with their new names.
+    renamed: Vec<(Name, Name)>,
+}
+
+impl Anf {
+    fn new(prog: &Program) -> Anf {
+        // the parameters keep their names, as the inputs of the calculation.
+        let mut seen: HashSet<Name> = param_names(prog).into_iter().collect();
+        seen.extend(prog.p_defns.iter().map(|d| d.0.clone()));
+        for Defn(_nm, bd) in prog.p_defns.iter() {
+            seen.extend(bd.free_vars());
+        }
+        seen.extend(prog.p_body.free_vars());
+        Anf {
+            fresh: Fresh::new(prog),
+            seen,
+            renamed: Vec::new(),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Expr {
+        let mut binds = Vec::new();
+        let bd = self.complex(expr, &mut binds);
+        lets(binds, bd)
+    }
+
+    // `expr` as an atom, binding it to a new name if it is not one.
+    fn atom(&mut self, expr: &Expr, binds: &mut Vec<(Name, Expr)>) -> Expr {
+        let e = self.complex(expr, binds);
+        if is_atom(&e) {
+            e
+        } else {
+            self.bound(e, binds)
+        }
+    }
+
+    // `expr` as a complex expression, whose intermediate results are bound in
+    // `binds`.
+    fn complex(&mut self, expr: &Expr, binds: &mut Vec<(Name, Expr)>) -> Expr {
+        match expr {
+            Expr::Var(x) => match self.renamed.iter().rev().find(|(y, _)| y == x) {
+                Some((_x, renamed)) => Expr::Var(renamed.clone()),
+                None => expr.clone(),
+            },
+            Expr::Lit(_) | Expr::Prim(_) => expr.clone(),
+            Expr::App(_, _) => {
+                let (head, args) = spine(expr);
+                let (mut fun, arity) = match head {
+                    Expr::Prim(op) => (head.clone(), primop_arity(op)),
+                    _ => (self.atom(head, binds), usize::MAX),
+                };
+                for (applied, arg) in args.into_iter().enumerate() {
+                    // the result of a fully applied `PrimOp` is bound before
+                    // it is applied to more arguments.
+                    if applied == arity {
+                        fun = self.bound(fun, binds);
+                    }
+                    fun = app!(fun, self.atom(arg, binds));
+                }
+                fun
+            }
+            Expr::Lam(x, bd) => {
+                let x = self.bind(x);
+                let bd = self.expr(bd);
+                self.renamed.pop();
+                lam!(x, bd)
+            }
+            Expr::Let(x, e, bd) => {
+                let e = self.complex(e, binds);
+                let x = self.bind(x);
+                binds.push((x, e));
+                let bd = self.complex(bd, binds);
+                self.renamed.pop();
+                bd
+            }
+            Expr::If(tst, thn, els) => Expr::If(
+                Box::new(self.atom(tst, binds)),
+                Box::new(self.expr(thn)),
+                Box::new(self.expr(els)),
+            ),
+            Expr::Fix(e) => Expr::Fix(Box::new(self.atom(e, binds))),
+        }
+    }
+
+    // bind `e` to a new name.
+    fn bound(&mut self, e: Expr, binds: &mut Vec<(Name, Expr)>) -> Expr {
+        let t = self.fresh.var("t");
+        binds.push((t.clone(), e));
+        Expr::Var(t)
+    }
+
+    // bring the binder `x` into scope, renamed if its name is taken.
+    fn bind(&mut self, x: &Name) -> Name {
+        let renamed = if self.seen.contains(x) {
+            self.fresh.var(&x.0)
+        } else {
+            x.clone()
+        };
+        self.seen.insert(renamed.clone());
+        self.renamed.push((x.clone(), renamed.clone()));
+        renamed
+    }
+}
+
+/// lift the lambdas of a program in A-normal form to top-level `defn`s, which
+/// are placed before the `defn` (or body) they were lifted from. a `defn`
+/// whose body is a lambda is already at the top level, & is kept.
+pub fn closure_convert(prog: &Program) -> Program {
+    let mut cc = Convert {
+        fresh: Fresh::new(prog),
+        lifted: Vec::new(),
+        closures: Vec::new(),
+    };
+    let mut defns = Vec::new();
+    for Defn(nm, bd) in prog.p_defns.iter() {
+        let bd = match bd {
+            Expr::Lam(_, _) => {
+                let (params, bd) = lambdas(bd);
+                let bd = cc.expr(bd, &mut params.clone());
+                lams(params, bd)
+            }
+            _ => cc.expr(bd, &mut Vec::new()),
+        };
+        defns.append(&mut cc.lifted);
+        defns.push(Defn(nm.clone(), bd));
+    }
+    let body = cc.expr(&prog.p_body, &mut param_names(prog));
+    defns.append(&mut cc.lifted);
+    Program {
+        p_imports: prog.p_imports.clone(),
+        p_defns: defns,
+        p_params: prog.p_params.clone(),
+        p_body: body,
+    }
+}
+
+/// lower a program to A-normal form, & closure convert it.
+pub fn lower_program(prog: &Program) -> Program {
+    closure_convert(&anf_program(prog))
+}
+
+// the parameters of a chain of lambdas, & its body.
+fn lambdas(expr: &Expr) -> (Vec<Name>, &Expr) {
+    let mut params = Vec::new();
+    let mut bd = expr;
+    while let Expr::Lam(x, inner) = bd {
+        params.push(x.clone());
+        bd = inner;
+    }
+    (params, bd)
+}
+
+fn lams(params: Vec<Name>, bd: Expr) -> Expr {
+    params.into_iter().rev().fold(bd, |bd, x| lam!(x, bd))
+}
+
+struct Convert {
+    fresh: Fresh,
+    // the `defn`s lifted from the current `defn`, inner lambdas first.
+    lifted: Vec<Defn>,
+    // the `let`-bound closures in scope, innermost last.
+    closures: Vec<Closure>,
+}
+
+struct Closure {
+    name: Name,
+    captured: Vec<Name>,
+    // the environment of the closure, & the closure itself.
+    binds: Vec<(Name, Expr)>,
+}
+
+impl Convert {
+    // convert an expression, under the local variables `scope`.
+    fn expr(&mut self, expr: &Expr, scope: &mut Vec<Name>) -> Expr {
+        let mut binds = Vec::new();
+        let converted = match expr {
+            Expr::Let(x, e, bd) if matches!(**e, Expr::Lam(_, _)) => {
+                let (captured, _params, _bd) = self.captures(e, scope);
+                let mut env = Vec::new();
+                let clo = self.closure(e, scope, &mut env);
+                binds.extend(env.iter().cloned());
+                env.push((x.clone(), clo.clone()));
+                self.closures.push(Closure {
+                    name: x.clone(),
+                    captured,
+                    binds: env,
+                });
+                let bd = self.expr(bd, scope);
+                self.closures.pop();
+                Expr::Let(x.clone(), Box::new(clo), Box::new(bd))
+            }
+            Expr::Let(x, e, bd) => {
+                let e = self.complex(e, scope, &mut binds);
+                scope.push(x.clone());
+                let bd = self.expr(bd, scope);
+                scope.pop();
+                Expr::Let(x.clone(), Box::new(e), Box::new(bd))
+            }
+            _ => self.complex(expr, scope, &mut binds),
+        };
+        lets(binds, converted)
+    }
+
+    fn complex(
+        &mut self,
+        expr: &Expr,
+        scope: &mut Vec<Name>,
+        binds: &mut Vec<(Name, Expr)>,
+    ) -> Expr {
+        match expr {
+            Expr::Lam(_, _) => self.closure(expr, scope, binds),
+            Expr::If(tst, thn, els) => Expr::If(
+                tst.clone(),
+                Box::new(self.expr(thn, scope)),
+                Box::new(self.expr(els, scope)),
+            ),
+            _ => expr.clone(),
+        }
+    }
+
+    // lift a lambda to a `defn`, & return the closure which replaces it. its
+    // environment is built in `binds`.
+    fn closure(&mut self, expr: &Expr, scope: &[Name], binds: &mut Vec<(Name, Expr)>) -> Expr {
+        let (captured, params, bd) = self.captures(expr, scope);
+        let mut inner = captured.clone();
+        inner.extend(params.iter().cloned());
+        let mut bd = self.expr(bd, &mut inner);
+        // the `let`-bound closures it refers to are rebuilt in its body.
+        let free = bd.free_vars();
+        for clo in self.closures.iter().rev() {
+            if free.contains(&clo.name) {
+                bd = lets(clo.binds.clone(), bd);
+            }
+        }
+        let f = self.fresh.var("fun");
+        let (fun, clo) = match captured.as_slice() {
+            [] => (lams(params, bd), Expr::Var(f.clone())),
+            [x] => (
+                lams(vec![x.clone()].into_iter().chain(params).collect(), bd),
+                app!(Expr::Var(f.clone()), Expr::Var(x.clone())),
+            ),
+            [xs @ .., last] => {
+                // the body unpacks the environment `(pair x1 (pair x2 ...))`.
+                let env = self.fresh.var("env");
+                let mut unpack = Vec::new();
+                let mut rest = env.clone();
+                for x in xs {
+                    let next = self.fresh.var("env");
+                    unpack.push((x.clone(), prim1(PrimOp::Fst, &rest)));
+                    unpack.push((next.clone(), prim1(PrimOp::Snd, &rest)));
+                    rest = next;
+                }
+                unpack.push((last.clone(), Expr::Var(rest)));
+                let fun = lams(
+                    vec![env].into_iter().chain(params).collect(),
+                    lets(unpack, bd),
+                );
+                // & the closure packs it.
+                let mut record = Expr::Var(last.clone());
+                for x in xs.iter().rev() {
+                    let packed = self.fresh.var("env");
+                    let pair = app!(app!(Expr::Prim(PrimOp::Pair), Expr::Var(x.clone())), record);
+                    binds.push((packed.clone(), pair));
+                    record = Expr::Var(packed);
+                }
+                (fun, app!(Expr::Var(f.clone()), record))
+            }
+        };
+        self.lifted.push(Defn(f, fun));
+        clo
+    }
+
+    // the local variables a lambda captures, either itself or through the
+    // `let`-bound closures it refers to, & its parameters & body.
+    fn captures<'a>(&self, expr: &'a Expr, scope: &[Name]) -> (Vec<Name>, Vec<Name>, &'a Expr) {
+        let mut captured: Vec<Name> = Vec::new();
+        for x in expr.free_vars() {
+            let through = match self.closures.iter().rev().find(|clo| clo.name == x) {
+                Some(clo) => clo.captured.clone(),
+                None if scope.contains(&x) => vec![x],
+                None => vec![],
+            };
+            for x in through {
+                if !captured.contains(&x) {
+                    captured.push(x);
+                }
+            }
+        }
+        let (params, bd) = lambdas(expr);
+        (captured, params, bd)
+    }
+}
+
+fn prim1(op: PrimOp, x: &Name) -> Expr {
+    app!(Expr::Prim(op), Expr::Var(x.clone()))
+}
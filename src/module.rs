@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{
+    env::Env,
+    eval::{eval_, EvalError, EvalState, TermEnv, Value},
+    infer::{infer_expr, TypeError},
+    native::Natives,
+    parse::{parse_module, ParseError},
+    prelude::prelude,
+    syntax::{qualified_name, Defn, Import, Module, Name, Program},
+    types::Scheme,
+};
+
+/// uniquely identifies a module's source, so that a module imported along
+/// several paths is only loaded once, and so that import cycles can be found.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ModuleId(pub String);
+
+impl fmt::Display for ModuleId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// the source text of a module, as located by a `ModuleResolver`.
+pub struct ModuleSource {
+    pub id: ModuleId,
+    pub text: String,
+}
+
+#[derive(Debug)]
+pub enum ModuleError {
+    NotFound(Import),
+    Io(ModuleId, std::io::Error),
+    Parse(ModuleId, ParseError),
+    Type(ModuleId, TypeError),
+    Eval(ModuleId, EvalError),
+    /// the modules along the cycle, starting and ending with the same module.
+    Cycle(Vec<ModuleId>),
+    UnknownExport(ModuleId, Name),
+    /// two imports in the same program or module would be qualified by the same name.
+    DuplicateImport(Name),
+    /// the import does not give rise to a valid module name (e.g. a file stem
+    /// which contains non-letter characters).
+    BadModuleName(Import),
+    /// a constant native failed when it was registered.
+    Native(EvalError),
+}
+
+/// locates the source of imported modules. resolvers are shared between
+/// threads, along with the options & engines which hold them.
+pub trait ModuleResolver: Send + Sync {
+    /// find the source for `import`, which appears in the module `from` (or in
+    /// the root program, if `from` is `None`).
+    fn resolve(
+        &self,
+        import: &Import,
+        from: Option<&ModuleId>,
+    ) -> Result<ModuleSource, ModuleError>;
+
+    /// the id of the module which `resolve` would find, so that a module
+    /// which is already loaded needn't be read again. by default, this
+    /// resolves the module.
+    fn locate(&self, import: &Import, from: Option<&ModuleId>) -> Result<ModuleId, ModuleError> {
+        self.resolve(import, from).map(|source| source.id)
+    }
+}
+
+/// resolves modules on the filesystem. `(import "path.poly")` is resolved
+/// relative to the importing module's directory (or to the first search path,
+/// for the root program), and `(import name)` looks for `name.poly` in each
+/// search path in turn.
+pub struct FileResolver {
+    pub search_paths: Vec<PathBuf>,
+}
+
+impl FileResolver {
+    pub fn new(search_paths: Vec<PathBuf>) -> FileResolver {
+        FileResolver { search_paths }
+    }
+}
+
+impl ModuleResolver for FileResolver {
+    fn resolve(
+        &self,
+        import: &Import,
+        from: Option<&ModuleId>,
+    ) -> Result<ModuleSource, ModuleError> {
+        let id = self.locate(import, from)?;
+        let text = fs::read_to_string(&id.0).map_err(|err| ModuleError::Io(id.clone(), err))?;
+        Ok(ModuleSource { id, text })
+    }
+
+    fn locate(&self, import: &Import, from: Option<&ModuleId>) -> Result<ModuleId, ModuleError> {
+        let candidates: Vec<PathBuf> = match import {
+            Import::Path(path) => {
+                let base = match from {
+                    Some(ModuleId(id)) => Path::new(id).parent().map(|p| p.to_path_buf()),
+                    None => self.search_paths.first().cloned(),
+                };
+                vec![base.unwrap_or_default().join(path)]
+            }
+            Import::Named(Name(nm)) => self
+                .search_paths
+                .iter()
+                .map(|dir| dir.join(format!("{}.poly", nm)))
+                .collect(),
+        };
+        let path = candidates
+            .into_iter()
+            .find(|p| p.is_file())
+            .ok_or_else(|| ModuleError::NotFound(import.clone()))?;
+        Ok(ModuleId(
+            fs::canonicalize(&path)
+                .unwrap_or(path)
+                .to_string_lossy()
+                .into_owned(),
+        ))
+    }
+}
+
+/// resolves modules from sources held in memory, keyed by the path or name
+/// that appears in the `import`. useful for embedding & testing.
+#[derive(Default)]
+pub struct MemoryResolver(HashMap<String, String>);
+
+impl MemoryResolver {
+    pub fn new() -> MemoryResolver {
+        MemoryResolver(HashMap::new())
+    }
+
+    pub fn insert(&mut self, key: &str, text: &str) {
+        let MemoryResolver(hm) = self;
+        hm.insert(key.to_string(), text.to_string());
+    }
+}
+
+impl ModuleResolver for MemoryResolver {
+    fn resolve(
+        &self,
+        import: &Import,
+        _from: Option<&ModuleId>,
+    ) -> Result<ModuleSource, ModuleError> {
+        let MemoryResolver(hm) = self;
+        let key = match import {
+            Import::Path(path) => path,
+            Import::Named(Name(nm)) => nm,
+        };
+        match hm.get(key) {
+            None => Err(ModuleError::NotFound(import.clone())),
+            Some(text) => Ok(ModuleSource {
+                id: ModuleId(key.clone()),
+                text: text.clone(),
+            }),
+        }
+    }
+
+    fn locate(&self, import: &Import, _from: Option<&ModuleId>) -> Result<ModuleId, ModuleError> {
+        let MemoryResolver(hm) = self;
+        let key = match import {
+            Import::Path(path) => path,
+            Import::Named(Name(nm)) => nm,
+        };
+        match hm.contains_key(key) {
+            false => Err(ModuleError::NotFound(import.clone())),
+            true => Ok(ModuleId(key.clone())),
+        }
+    }
+}
+
+/// the name which qualifies the exports of an imported module: the file stem
+/// for path imports, and the name itself for named imports.
+pub fn import_name(import: &Import) -> Result<Name, ModuleError> {
+    let nm = match import {
+        Import::Path(path) => Path::new(path)
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("")
+            .to_string(),
+        Import::Named(Name(nm)) => nm.clone(),
+    };
+    if !nm.is_empty() && nm.chars().all(char::is_alphabetic) {
+        Ok(Name(nm))
+    } else {
+        Err(ModuleError::BadModuleName(import.clone()))
+    }
+}
+
+/// the (unqualified) exports of a loaded module.
+#[derive(Clone)]
+struct Exports {
+    types: Vec<(Name, Scheme)>,
+    terms: Vec<(Name, Value)>,
+}
+
+/// loads modules via a `ModuleResolver`, type checking & evaluating each
+/// module once, no matter how many times it is imported. unless disabled via
+/// `without_prelude`, each module is checked & evaluated with the prelude in
+/// scope, along with any natives given via `with_natives`.
+pub struct ModuleLoader<'a> {
+    resolver: &'a dyn ModuleResolver,
+    prelude: bool,
+    natives: Natives,
+    loaded: HashMap<ModuleId, Exports>,
+    // the chain of modules currently being loaded, used to detect cycles.
+    loading: Vec<ModuleId>,
+}
+
+impl<'a> ModuleLoader<'a> {
+    pub fn new(resolver: &'a dyn ModuleResolver) -> ModuleLoader<'a> {
+        ModuleLoader {
+            resolver,
+            prelude: true,
+            natives: Natives::new(),
+            loaded: HashMap::new(),
+            loading: Vec::new(),
+        }
+    }
+
+    pub fn without_prelude(mut self) -> ModuleLoader<'a> {
+        self.prelude = false;
+        self
+    }
+
+    pub fn with_natives(mut self, natives: &Natives) -> ModuleLoader<'a> {
+        self.natives = natives.clone();
+        self
+    }
+
+    /// resolve, type check & evaluate the imports of `prog`, returning the
+    /// environments under which `prog` itself should be checked & evaluated:
+    /// the prelude (if enabled), the natives & the qualified imports.
+    pub fn load_program(&mut self, prog: &Program) -> Result<(Env, TermEnv), ModuleError> {
+        self.load_scope(&prog.p_imports)
+    }
+
+    /// like `load_program`, for a root program with the given `imports`.
+    pub fn load_scope(&mut self, imports: &[Import]) -> Result<(Env, TermEnv), ModuleError> {
+        self.scope(None, imports)
+    }
+
+    /// check & evaluate an already-parsed module, returning the type & term
+    /// environments binding the (unqualified) names of its exports.
+    pub fn load_module(
+        &mut self,
+        id: ModuleId,
+        module: &Module,
+    ) -> Result<(Env, TermEnv), ModuleError> {
+        let exports = self.check_and_eval(&id, module)?;
+        Ok((
+            exports.types.into_iter().collect(),
+            exports.terms.into_iter().collect(),
+        ))
+    }
+
+    /// load `imports`, which appear in the module `from` (or in the root
+    /// program, if `from` is `None`). returns the type & term environments
+    /// binding the qualified names of all of their exports.
+    pub fn load_imports(
+        &mut self,
+        from: Option<&ModuleId>,
+        imports: &[Import],
+    ) -> Result<(Env, TermEnv), ModuleError> {
+        let mut type_env = Env::new();
+        let mut term_env = TermEnv::new();
+        let mut seen = Vec::new();
+        for import in imports {
+            let module_nm = import_name(import)?;
+            if seen.contains(&module_nm) {
+                return Err(ModuleError::DuplicateImport(module_nm));
+            }
+            let exports = self.load(from, import)?;
+            for (nm, sc) in exports.types {
+                type_env.extend(qualified_name(&module_nm, &nm), sc);
+            }
+            for (nm, val) in exports.terms {
+                term_env.insert(qualified_name(&module_nm, &nm), val);
+            }
+            seen.push(module_nm);
+        }
+        Ok((type_env, term_env))
+    }
+
+    fn load(&mut self, from: Option<&ModuleId>, import: &Import) -> Result<Exports, ModuleError> {
+        let id = self.resolver.locate(import, from)?;
+        if let Some(exports) = self.loaded.get(&id) {
+            return Ok(exports.clone());
+        }
+        if let Some(pos) = self.loading.iter().position(|x| *x == id) {
+            let mut cycle = self.loading[pos..].to_vec();
+            cycle.push(id);
+            return Err(ModuleError::Cycle(cycle));
+        }
+
+        let ModuleSource { id, text } = self.resolver.resolve(import, from)?;
+        let module = parse_module(&text).map_err(|err| ModuleError::Parse(id.clone(), err))?;
+        self.loading.push(id.clone());
+        let res = self.check_and_eval(&id, &module);
+        self.loading.pop();
+        let exports = res?;
+        self.loaded.insert(id, exports.clone());
+        Ok(exports)
+    }
+
+    fn check_and_eval(&mut self, id: &ModuleId, module: &Module) -> Result<Exports, ModuleError> {
+        let (mut type_env, mut term_env) = self.scope(Some(id), &module.m_imports)?;
+        let mut es = EvalState::new();
+        for Defn(nm, bd) in module.m_defns.iter() {
+            let sc = infer_expr(&type_env, bd).map_err(|err| ModuleError::Type(id.clone(), err))?;
+            type_env.extend(nm.clone(), sc);
+            let val =
+                eval_(&term_env, &mut es, bd).map_err(|err| ModuleError::Eval(id.clone(), err))?;
+            term_env.insert(nm.clone(), val);
+        }
+
+        let exported: Vec<Name> = match &module.m_exports {
+            Some(nms) => nms.clone(),
+            None => module
+                .m_defns
+                .iter()
+                .map(|Defn(nm, _)| nm.clone())
+                .collect(),
+        };
+        let mut exports = Exports {
+            types: Vec::new(),
+            terms: Vec::new(),
+        };
+        for nm in exported {
+            // only the module's own `defn`s may be exported, not those of its imports.
+            if !module.m_defns.iter().any(|Defn(d, _)| *d == nm) {
+                return Err(ModuleError::UnknownExport(id.clone(), nm));
+            }
+            exports.types.push((nm.clone(), type_env[&nm].clone()));
+            exports.terms.push((nm.clone(), term_env[&nm].clone()));
+        }
+        Ok(exports)
+    }
+
+    // the environments in which a module (or program) with `imports` is checked
+    // & evaluated: the prelude, if enabled, the natives, & the qualified imports.
+    fn scope(
+        &mut self,
+        from: Option<&ModuleId>,
+        imports: &[Import],
+    ) -> Result<(Env, TermEnv), ModuleError> {
+        let (import_env, import_term_env) = self.load_imports(from, imports)?;
+        let (mut type_env, mut term_env) = if self.prelude {
+            (prelude().type_env.clone(), prelude().term_env.clone())
+        } else {
+            (Env::new(), TermEnv::new())
+        };
+        type_env.merge(&self.natives.type_env());
+        term_env.merge(&self.natives.term_env().map_err(ModuleError::Native)?);
+        type_env.merge(&import_env);
+        term_env.merge(&import_term_env);
+        Ok((type_env, term_env))
+    }
+}
+
+/// resolve, type check & evaluate the imports of `prog`. the resulting
+/// environments are those under which `prog` itself should be checked (via
+/// `infer_program`) and evaluated (via `eval_program_in`).
+pub fn load_program_imports(
+    resolver: &dyn ModuleResolver,
+    prog: &Program,
+) -> Result<(Env, TermEnv), ModuleError> {
+    ModuleLoader::new(resolver).load_imports(None, &prog.p_imports)
+}
@@ -0,0 +1,149 @@
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use super::{
+    convert::{FromValue, IntoValue},
+    env::Env,
+    eval::{EvalError, TermEnv, Value},
+    syntax::Name,
+    types::{type_arity, type_variables, Scheme, Type},
+};
+
+/// the implementation of a native function. it is passed exactly as many
+/// arguments as its arity, whose types agree with its scheme, & may fail with
+/// a message (e.g. if a host lookup fails).
+pub type NativeFn = dyn Fn(&[Value]) -> Result<Value, String> + Send + Sync;
+
+/// a host (Rust) function, callable from poly under its name.
+pub struct Native {
+    pub name: Name,
+    pub scheme: Scheme,
+    /// the number of arguments the function takes, as given by its scheme.
+    pub arity: usize,
+    fun: Box<NativeFn>,
+    // the value of a native of arity 0, which is called once, when it is
+    // registered.
+    constant: Option<Result<Value, String>>,
+}
+
+impl Native {
+    pub fn call(&self, args: &[Value]) -> Result<Value, EvalError> {
+        (self.fun)(args).map_err(|msg| EvalError::Native(self.name.clone(), msg))
+    }
+}
+
+impl fmt::Debug for Native {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Native({:?}, {:?})", self.name, self.scheme)
+    }
+}
+
+/// a registry of native functions. these are placed into scope (like the
+/// prelude) when checking & evaluating programs, e.g. via
+/// `CalculationOptions::natives`.
+#[derive(Clone, Default)]
+pub struct Natives(HashMap<Name, Arc<Native>>);
+
+impl Natives {
+    pub fn new() -> Natives {
+        Natives(HashMap::new())
+    }
+
+    /// register `fun` under `name`, with the type given by `scheme`. any type
+    /// variables of the scheme's type are quantified, whether or not they are
+    /// listed in the scheme. a native of arity 0 is a constant: it is called
+    /// once, here, & a failure is reported when the natives are placed into
+    /// an environment (by `term_env`).
+    pub fn register<F>(&mut self, name: &str, scheme: Scheme, fun: F)
+    where
+        F: Fn(&[Value]) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        let Scheme(_tvs, ty) = scheme;
+        let scheme = Scheme(type_variables(&ty), ty.clone());
+        let name = Name(name.to_string());
+        let arity = type_arity(ty);
+        let constant = if arity == 0 { Some(fun(&[])) } else { None };
+        let native = Native {
+            name: name.clone(),
+            scheme,
+            arity,
+            fun: Box::new(fun),
+            constant,
+        };
+        let Natives(hm) = self;
+        hm.insert(name, Arc::new(native));
+    }
+
+    /// register a unary function, whose scheme is given by the `PolyType`s of
+    /// its argument & result.
+    pub fn register_fn1<A, R, F>(&mut self, name: &str, fun: F)
+    where
+        A: FromValue,
+        R: IntoValue,
+        F: Fn(A) -> R + Send + Sync + 'static,
+    {
+        let ty = Type::TArr(Box::new(A::poly_type()), Box::new(R::poly_type()));
+        let nm = name.to_string();
+        self.register(name, Scheme(Vec::new(), ty), move |args| {
+            Ok(fun(from_arg(&nm, &args[0])).into_value())
+        })
+    }
+
+    /// register a binary function, whose scheme is given by the `PolyType`s of
+    /// its arguments & result.
+    pub fn register_fn2<A, B, R, F>(&mut self, name: &str, fun: F)
+    where
+        A: FromValue,
+        B: FromValue,
+        R: IntoValue,
+        F: Fn(A, B) -> R + Send + Sync + 'static,
+    {
+        let ret = Type::TArr(Box::new(B::poly_type()), Box::new(R::poly_type()));
+        let ty = Type::TArr(Box::new(A::poly_type()), Box::new(ret));
+        let nm = name.to_string();
+        self.register(name, Scheme(Vec::new(), ty), move |args| {
+            let a = from_arg(&nm, &args[0]);
+            let b = from_arg(&nm, &args[1]);
+            Ok(fun(a, b).into_value())
+        })
+    }
+
+    pub fn get(&self, nm: &Name) -> Option<&Arc<Native>> {
+        let Natives(hm) = self;
+        hm.get(nm)
+    }
+
+    /// the schemes of the natives, under their names.
+    pub fn type_env(&self) -> Env {
+        let Natives(hm) = self;
+        hm.iter()
+            .map(|(nm, native)| (nm.clone(), native.scheme.clone()))
+            .collect()
+    }
+
+    /// the values of the natives, under their names. this fails if a
+    /// constant failed when it was registered.
+    pub fn term_env(&self) -> Result<TermEnv, EvalError> {
+        let Natives(hm) = self;
+        hm.iter()
+            .map(|(nm, native)| Ok((nm.clone(), native_value(native)?)))
+            .collect()
+    }
+}
+
+/// the value of a native which has not yet been applied to any arguments.
+pub fn native_value(native: &Arc<Native>) -> Result<Value, EvalError> {
+    match &native.constant {
+        Some(Ok(val)) => Ok(val.clone()),
+        Some(Err(msg)) => Err(EvalError::Native(native.name.clone(), msg.clone())),
+        None => Ok(Value::VNative(native.clone(), Vec::new())),
+    }
+}
+
+// the type checker guarantees that a native is only applied to arguments of
+// its declared types, so a failed conversion is a bug.
+fn from_arg<A: FromValue>(fun: &str, arg: &Value) -> A {
+    match A::from_value(arg.clone()) {
+        Ok(a) => a,
+        Err(err) => panic!("impossible: native {}: ill-typed argument: {}", fun, err),
+    }
+}
@@ -0,0 +1,498 @@
+//! alpha equivalence, & normalization by evaluation.
+//!
+//! `normalize` evaluates an expression into a semantic domain in which free
+//! variables (e.g. the inputs of a calculation) are neutral, & reads the
+//! result back as an expression in beta normal form: PrimOps are folded where
+//! their arguments are known, & are otherwise stuck, as are the tests of `if`s
+//! & the applications of variables. partial applications of PrimOps are read
+//! back eta expanded, so that `+` & `(lam [x y] (+ x y))` have the same normal
+//! form.
+//!
+//! evaluation is strict, as is that of `eval_`. a `fix` is unfolded only when
+//! applied to an argument which is known (& not neutral), & each unfolding
+//! (& each application) uses up some of the fuel given, so that normalization
+//! always terminates.
+//!
+//! a neutral computation which may not terminate (the application of a
+//! variable, a native, or a `fix`, or a `map` or `foldl` of one) is bound by
+//! a `let` where it is evaluated, so that it is kept in the normal form even if
+//! its value is not used.
+
+use std::sync::Arc;
+
+use super::{
+    eval::{named_closure, EvalError, TermEnv, Value},
+    native::Native,
+    syntax::{primop_arity, Expr, Lit, Name, PrimOp},
+};
+use crate::{app, lam};
+
+/// whether two programs are equivalent, i.e. give the same results for all
+/// inputs (see `toplevel::equivalent`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Equivalence {
+    /// the programs have the same normal form.
+    Equivalent,
+    /// the programs give different results for some inputs.
+    Inequivalent,
+    /// neither could be shown.
+    Unknown,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum NormalizeError {
+    /// normalization used up its fuel, e.g. unfolding a `fix` which does not
+    /// terminate.
+    OutOfFuel,
+    /// evaluation nested too deeply, e.g. unfolding a `fix` which does not
+    /// terminate, for the native stack.
+    TooDeep,
+    /// arithmetic on known integers overflowed.
+    Overflow,
+    /// the expression refers to a compiled closure, whose body is not
+    /// available.
+    Compiled,
+    /// forcing a thunk of the lazy strategy failed.
+    Eval(EvalError),
+}
+
+/// whether `a` & `b` are the same, up to the renaming of bound variables.
+pub fn alpha_eq(a: &Expr, b: &Expr) -> bool {
+    alpha_eq_in(a, b, &mut Vec::new(), &mut Vec::new())
+}
+
+fn alpha_eq_in(a: &Expr, b: &Expr, bound_a: &mut Vec<Name>, bound_b: &mut Vec<Name>) -> bool {
+    let under = |x: &Name, y: &Name, bound_a: &mut Vec<Name>, bound_b: &mut Vec<Name>, a, b| {
+        bound_a.push(x.clone());
+        bound_b.push(y.clone());
+        let eq = alpha_eq_in(a, b, bound_a, bound_b);
+        bound_a.pop();
+        bound_b.pop();
+        eq
+    };
+    match (a, b) {
+        // bound variables are compared by the binders they refer to, & free
+        // variables by name.
+        (Expr::Var(x), Expr::Var(y)) => {
+            match (
+                bound_a.iter().rposition(|nm| nm == x),
+                bound_b.iter().rposition(|nm| nm == y),
+            ) {
+                (Some(i), Some(j)) => bound_a.len() - i == bound_b.len() - j,
+                (None, None) => x == y,
+                _ => false,
+            }
+        }
+        (Expr::Lit(x), Expr::Lit(y)) => x == y,
+        (Expr::Prim(x), Expr::Prim(y)) => x == y,
+        (Expr::App(f, x), Expr::App(g, y)) => {
+            alpha_eq_in(f, g, bound_a, bound_b) && alpha_eq_in(x, y, bound_a, bound_b)
+        }
+        (Expr::Lam(x, a), Expr::Lam(y, b)) => under(x, y, bound_a, bound_b, a, b),
+        (Expr::Let(x, e1, a), Expr::Let(y, e2, b)) => {
+            alpha_eq_in(e1, e2, bound_a, bound_b) && under(x, y, bound_a, bound_b, a, b)
+        }
+        (Expr::If(t1, a1, b1), Expr::If(t2, a2, b2)) => {
+            alpha_eq_in(t1, t2, bound_a, bound_b)
+                && alpha_eq_in(a1, a2, bound_a, bound_b)
+                && alpha_eq_in(b1, b2, bound_a, bound_b)
+        }
+        (Expr::Fix(a), Expr::Fix(b)) => alpha_eq_in(a, b, bound_a, bound_b),
+        _ => false,
+    }
+}
+
+/// the maximum nesting of evaluation by `normalize`, which recurses on the
+/// native stack (by a few kilobytes per level, in debug builds).
+pub const MAX_DEPTH: usize = 500;
+
+/// the beta normal form of `expr`, whose free variables are bound in `env`
+/// (e.g. to the prelude) or are otherwise neutral, using at most `fuel`.
+pub fn normalize(env: &TermEnv, expr: &Expr, fuel: u64) -> Result<Expr, NormalizeError> {
+    let mut nbe = Nbe {
+        fuel,
+        depth: 0,
+        level: 0,
+        effects: Vec::new(),
+        bound: 0,
+    };
+    let sem = nbe.bind_effects(|nbe| nbe.eval(&SemEnv::new(env.clone()), expr))?;
+    nbe.quote(&sem)
+}
+
+// the semantic domain.
+#[derive(Clone)]
+enum Sem {
+    Int(i64),
+    Bool(bool),
+    Nil,
+    Cons(Box<Sem>, Box<Sem>),
+    Pair(Box<Sem>, Box<Sem>),
+    Closure(Name, Expr, SemEnv),
+    // a PrimOp, applied to fewer arguments than its arity.
+    Prim(PrimOp, Vec<Sem>),
+    // `(fix f)`, which is unfolded when applied to a known argument.
+    Fix(Box<Sem>),
+    Neutral(Neutral),
+    // a value, after the neutral computation bound to the name: only a branch
+    // of an `if`, or the body of a function, as it is read back.
+    Let(Name, Box<Neutral>, Box<Sem>),
+}
+
+// a computation which is stuck on a free variable.
+#[derive(Clone)]
+enum Neutral {
+    Var(Name),
+    App(Box<Neutral>, Box<Sem>),
+    // a full application of a PrimOp, to arguments of which some are neutral.
+    Prim(PrimOp, Vec<Sem>),
+    If(Box<Neutral>, Box<Sem>, Box<Sem>),
+    // `(fix f)`, applied to a neutral argument.
+    Fix(Box<Sem>),
+}
+
+// the values of the program are converted as they are looked up.
+#[derive(Clone)]
+struct SemEnv {
+    sem: im::HashMap<Name, Sem>,
+    concrete: TermEnv,
+}
+
+impl SemEnv {
+    fn new(concrete: TermEnv) -> SemEnv {
+        SemEnv {
+            sem: im::HashMap::new(),
+            concrete,
+        }
+    }
+
+    fn update(&self, nm: Name, val: Sem) -> SemEnv {
+        SemEnv {
+            sem: self.sem.update(nm, val),
+            concrete: self.concrete.clone(),
+        }
+    }
+
+    fn lookup(&self, nm: &Name) -> Result<Sem, NormalizeError> {
+        match (self.sem.get(nm), self.concrete.get(nm)) {
+            (Some(val), _) => Ok(val.clone()),
+            (None, Some(val)) => from_value(val),
+            (None, None) => Ok(Sem::Neutral(Neutral::Var(nm.clone()))),
+        }
+    }
+}
+
+fn from_value(val: &Value) -> Result<Sem, NormalizeError> {
+    match val {
+        Value::VInt(n) => Ok(Sem::Int(*n)),
+        Value::VBool(b) => Ok(Sem::Bool(*b)),
+        Value::VList(vals) => vals.iter().rev().try_fold(Sem::Nil, |tl, val| {
+            Ok(Sem::Cons(Box::new(from_value(val)?), Box::new(tl)))
+        }),
+        Value::VPair(a, b) => Ok(Sem::Pair(
+            Box::new(from_value(a)?),
+            Box::new(from_value(b)?),
+        )),
+        Value::VClosure(nm, bd, env) => Ok(Sem::Closure(
+            nm.clone(),
+            *bd.clone(),
+            SemEnv::new(env.clone()),
+        )),
+        Value::VCore(nm, bd, locals, globals) => {
+            let (nm, bd, env) = named_closure(nm, bd, locals, globals);
+            from_value(&Value::VClosure(nm, bd, env))
+        }
+        // a native is opaque: it is neutral, by its name.
+        Value::VNative(native, args) => args
+            .iter()
+            .try_fold(native_head(native), |fun, arg| {
+                Ok(Neutral::App(Box::new(fun), Box::new(from_value(arg)?)))
+            })
+            .map(Sem::Neutral),
+        Value::VCode(_, _) => Err(NormalizeError::Compiled),
+        Value::VThunk(_) | Value::VCons(_, _) => {
+            from_value(&val.clone().forced().map_err(NormalizeError::Eval)?)
+        }
+    }
+}
+
+fn native_head(native: &Arc<Native>) -> Neutral {
+    Neutral::Var(native.name.clone())
+}
+
+// whether `val` is known, & so a `fix` may be unfolded on it.
+fn is_known(val: &Sem) -> bool {
+    match val {
+        Sem::Neutral(_) => false,
+        Sem::Cons(hd, tl) | Sem::Pair(hd, tl) => is_known(hd) && is_known(tl),
+        _ => true,
+    }
+}
+
+struct Nbe {
+    fuel: u64,
+    depth: usize,
+    // the number of binders read back under, which names their variables.
+    level: usize,
+    // the neutral computations which may not terminate, bound to names, of
+    // each branch or body being evaluated.
+    effects: Vec<Vec<(Name, Neutral)>>,
+    // the number of such names, which names the next.
+    bound: usize,
+}
+
+impl Nbe {
+    fn tick(&mut self) -> Result<(), NormalizeError> {
+        match self.fuel.checked_sub(1) {
+            Some(fuel) => {
+                self.fuel = fuel;
+                Ok(())
+            }
+            None => Err(NormalizeError::OutOfFuel),
+        }
+    }
+
+    // bind a neutral computation which may not terminate, in the branch or
+    // body being evaluated.
+    fn effect(&mut self, neutral: Neutral) -> Sem {
+        let x = Name(format!("_e{}", self.bound));
+        self.bound += 1;
+        if let Some(effects) = self.effects.last_mut() {
+            effects.push((x.clone(), neutral));
+        }
+        Sem::Neutral(Neutral::Var(x))
+    }
+
+    // the value given by `eval`, a branch or body of its own, after the
+    // computations bound in it. the last, if it is the value, is not bound.
+    fn bind_effects(
+        &mut self,
+        eval: impl FnOnce(&mut Nbe) -> Result<Sem, NormalizeError>,
+    ) -> Result<Sem, NormalizeError> {
+        self.effects.push(Vec::new());
+        let val = eval(self);
+        let mut effects = self.effects.pop().unwrap_or_default();
+        let mut val = val?;
+        if let (Some((x, _)), Sem::Neutral(Neutral::Var(y))) = (effects.last(), &val) {
+            if x == y {
+                if let Some((_x, neutral)) = effects.pop() {
+                    val = Sem::Neutral(neutral);
+                }
+            }
+        }
+        Ok(effects.into_iter().rev().fold(val, |val, (x, neutral)| {
+            Sem::Let(x, Box::new(neutral), Box::new(val))
+        }))
+    }
+
+    fn eval(&mut self, env: &SemEnv, expr: &Expr) -> Result<Sem, NormalizeError> {
+        if self.depth == MAX_DEPTH {
+            return Err(NormalizeError::TooDeep);
+        }
+        self.depth += 1;
+        let val = self.eval_in(env, expr);
+        self.depth -= 1;
+        val
+    }
+
+    fn eval_in(&mut self, env: &SemEnv, expr: &Expr) -> Result<Sem, NormalizeError> {
+        match expr {
+            Expr::Lit(Lit::LInt(n)) => Ok(Sem::Int(*n)),
+            Expr::Lit(Lit::LBool(b)) => Ok(Sem::Bool(*b)),
+            Expr::Var(x) => env.lookup(x),
+            Expr::Lam(x, bd) => Ok(Sem::Closure(x.clone(), *bd.clone(), env.clone())),
+            Expr::App(fun, arg) => {
+                let fun = self.eval(env, fun)?;
+                let arg = self.eval(env, arg)?;
+                self.apply(fun, arg)
+            }
+            Expr::Let(x, e, bd) => {
+                let val = self.eval(env, e)?;
+                self.eval(&env.update(x.clone(), val), bd)
+            }
+            Expr::If(tst, thn, els) => self.eval_if(env, tst, thn, els),
+            Expr::Fix(e) => Ok(Sem::Fix(Box::new(self.eval(env, e)?))),
+            Expr::Prim(PrimOp::Nil) => Ok(Sem::Nil),
+            Expr::Prim(op) => Ok(Sem::Prim(op.clone(), Vec::new())),
+        }
+    }
+
+    fn eval_if(
+        &mut self,
+        env: &SemEnv,
+        tst: &Expr,
+        thn: &Expr,
+        els: &Expr,
+    ) -> Result<Sem, NormalizeError> {
+        match self.eval(env, tst)? {
+            Sem::Bool(true) => self.eval(env, thn),
+            Sem::Bool(false) => self.eval(env, els),
+            // an `if` is bound itself, if either branch binds a computation.
+            Sem::Neutral(tst) => {
+                let bound = self.bound;
+                let thn = self.bind_effects(|nbe| nbe.eval(env, thn))?;
+                let els = self.bind_effects(|nbe| nbe.eval(env, els))?;
+                let neutral = Neutral::If(Box::new(tst), Box::new(thn), Box::new(els));
+                if self.bound > bound {
+                    Ok(self.effect(neutral))
+                } else {
+                    Ok(Sem::Neutral(neutral))
+                }
+            }
+            _ => panic!("impossible: non-bool in test position of if"),
+        }
+    }
+
+    fn apply(&mut self, fun: Sem, arg: Sem) -> Result<Sem, NormalizeError> {
+        match fun {
+            Sem::Closure(x, bd, env) => {
+                self.tick()?;
+                self.eval(&env.update(x, arg), &bd)
+            }
+            Sem::Prim(op, mut args) => {
+                args.push(arg);
+                if args.len() == primop_arity(&op) {
+                    self.prim(op, args)
+                } else {
+                    Ok(Sem::Prim(op, args))
+                }
+            }
+            // `((fix f) x)` unfolds to `((f (fix f)) x)`.
+            Sem::Fix(f) => {
+                if is_known(&arg) {
+                    self.tick()?;
+                    let unfolded = self.apply(*f.clone(), Sem::Fix(f))?;
+                    self.apply(unfolded, arg)
+                } else {
+                    Ok(self.effect(Neutral::App(Box::new(Neutral::Fix(f)), Box::new(arg))))
+                }
+            }
+            Sem::Neutral(fun) => Ok(self.effect(Neutral::App(Box::new(fun), Box::new(arg)))),
+            _ => panic!("impossible: non-function in function position of app"),
+        }
+    }
+
+    fn prim(&mut self, op: PrimOp, mut args: Vec<Sem>) -> Result<Sem, NormalizeError> {
+        let stuck = |op, args| Ok(Sem::Neutral(Neutral::Prim(op, args)));
+        // the function of a `map` or `foldl` may not terminate.
+        let stuck_applying = |nbe: &mut Nbe, op, args| Ok(nbe.effect(Neutral::Prim(op, args)));
+        let arith = |n: Option<i64>| n.map(Sem::Int).ok_or(NormalizeError::Overflow);
+        match (&op, &args[..]) {
+            (PrimOp::Add, [Sem::Int(a), Sem::Int(b)]) => arith(a.checked_add(*b)),
+            (PrimOp::Sub, [Sem::Int(a), Sem::Int(b)]) => arith(a.checked_sub(*b)),
+            (PrimOp::Mul, [Sem::Int(a), Sem::Int(b)]) => arith(a.checked_mul(*b)),
+            (PrimOp::Eql, [Sem::Int(a), Sem::Int(b)]) => Ok(Sem::Bool(a == b)),
+            (PrimOp::Null, [Sem::Nil]) => Ok(Sem::Bool(true)),
+            (PrimOp::Null, [Sem::Cons(_, _)]) => Ok(Sem::Bool(false)),
+            (PrimOp::Fst, [Sem::Pair(a, _)]) => Ok((**a).clone()),
+            (PrimOp::Snd, [Sem::Pair(_, b)]) => Ok((**b).clone()),
+            (PrimOp::Pair, [_, _]) => {
+                let b = args.pop();
+                let a = args.pop();
+                match (a, b) {
+                    (Some(a), Some(b)) => Ok(Sem::Pair(Box::new(a), Box::new(b))),
+                    _ => panic!("impossible: pair: arity"),
+                }
+            }
+            (PrimOp::Cons, [_, _]) => {
+                let tl = args.pop();
+                let hd = args.pop();
+                match (hd, tl) {
+                    (Some(hd), Some(tl)) => Ok(Sem::Cons(Box::new(hd), Box::new(tl))),
+                    _ => panic!("impossible: cons: arity"),
+                }
+            }
+            (PrimOp::Map, [f, list]) => match elems(list) {
+                Some(elems) => {
+                    let f = f.clone();
+                    let mapped = elems
+                        .into_iter()
+                        .map(|elem| self.apply(f.clone(), elem))
+                        .collect::<Result<Vec<Sem>, NormalizeError>>()?;
+                    Ok(mapped
+                        .into_iter()
+                        .rev()
+                        .fold(Sem::Nil, |tl, hd| Sem::Cons(Box::new(hd), Box::new(tl))))
+                }
+                None => stuck_applying(self, op, args),
+            },
+            (PrimOp::Foldl, [f, z, list]) => match elems(list) {
+                Some(elems) => {
+                    let f = f.clone();
+                    elems.into_iter().try_fold(z.clone(), |acc, elem| {
+                        let f_acc = self.apply(f.clone(), acc)?;
+                        self.apply(f_acc, elem)
+                    })
+                }
+                None => stuck_applying(self, op, args),
+            },
+            _ => stuck(op, args),
+        }
+    }
+
+    // read a value back as an expression, in normal form.
+    fn quote(&mut self, val: &Sem) -> Result<Expr, NormalizeError> {
+        match val {
+            Sem::Int(n) => Ok(Expr::Lit(Lit::LInt(*n))),
+            Sem::Bool(b) => Ok(Expr::Lit(Lit::LBool(*b))),
+            Sem::Nil => Ok(Expr::Prim(PrimOp::Nil)),
+            Sem::Cons(hd, tl) => Ok(app!(
+                app!(Expr::Prim(PrimOp::Cons), self.quote(hd)?),
+                self.quote(tl)?
+            )),
+            Sem::Pair(a, b) => Ok(app!(
+                app!(Expr::Prim(PrimOp::Pair), self.quote(a)?),
+                self.quote(b)?
+            )),
+            // functions are read back by applying them to a fresh variable.
+            Sem::Closure(_, _, _) | Sem::Prim(_, _) => {
+                let x = Name(format!("_n{}", self.level));
+                let arg = Sem::Neutral(Neutral::Var(x.clone()));
+                let bd = self.bind_effects(|nbe| nbe.apply(val.clone(), arg))?;
+                self.level += 1;
+                let bd = self.quote(&bd);
+                self.level -= 1;
+                Ok(lam!(x, bd?))
+            }
+            Sem::Fix(f) => Ok(Expr::Fix(Box::new(self.quote(f)?))),
+            Sem::Neutral(neutral) => self.quote_neutral(neutral),
+            Sem::Let(x, neutral, bd) => Ok(Expr::Let(
+                x.clone(),
+                Box::new(self.quote_neutral(neutral)?),
+                Box::new(self.quote(bd)?),
+            )),
+        }
+    }
+
+    fn quote_neutral(&mut self, neutral: &Neutral) -> Result<Expr, NormalizeError> {
+        match neutral {
+            Neutral::Var(x) => Ok(Expr::Var(x.clone())),
+            Neutral::App(fun, arg) => Ok(app!(self.quote_neutral(fun)?, self.quote(arg)?)),
+            Neutral::Prim(op, args) => args.iter().try_fold(Expr::Prim(op.clone()), |fun, arg| {
+                Ok(app!(fun, self.quote(arg)?))
+            }),
+            Neutral::If(tst, thn, els) => Ok(Expr::If(
+                Box::new(self.quote_neutral(tst)?),
+                Box::new(self.quote(thn)?),
+                Box::new(self.quote(els)?),
+            )),
+            Neutral::Fix(f) => Ok(Expr::Fix(Box::new(self.quote(f)?))),
+        }
+    }
+}
+
+// the elements of a list, if its spine is known.
+fn elems(list: &Sem) -> Option<Vec<Sem>> {
+    let mut elems = Vec::new();
+    let mut list = list;
+    loop {
+        match list {
+            Sem::Nil => return Some(elems),
+            Sem::Cons(hd, tl) => {
+                elems.push((**hd).clone());
+                list = tl;
+            }
+            _ => return None,
+        }
+    }
+}
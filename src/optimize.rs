@@ -0,0 +1,229 @@
+//! a semantics preserving optimizer, for programs & expressions.
+//!
+//! the optimizer folds the `PrimOp`s applied to literals (& `if`s on them),
+//! turns each immediately applied lambda into a `let`, substitutes the `let`s
+//! of atomic values (& of lambdas used once) into their bodies, drops the
+//! `let`s whose values are unused, & inlines the small `defn`s of a program
+//! which contain no `fix`.
+//!
+//! under strict evaluation, dropping an expression which might not terminate
+//! (or which might overflow) would change the meaning of a program, so only
+//! expressions which are known to be total are ever dropped, & arithmetic is
+//! only folded if it does not overflow.
+//!
+//! the optimizer works on the resolved core IR (see `core`), in which
+//! substitution cannot capture a variable.
+
+use super::{
+    core::{self, capp, Core},
+    syntax::{primop_arity, Defn, Expr, Lit, Name, PrimOp, Program},
+};
+
+/// `defn`s whose bodies are at most this size (in nodes) are inlined.
+pub const INLINE_SIZE: usize = 24;
+
+// the optimizer stops after this many passes, even if it has not reached a
+// fixed point.
+const MAX_PASSES: usize = 16;
+
+/// optimize a closed expression, or one whose free variables are bound by the
+/// environment in which it is evaluated.
+pub fn optimize(expr: &Expr) -> Expr {
+    optimize_core(&core::resolve(expr)).to_expr()
+}
+
+/// optimize each of the program's `defn`s & its body, inlining the small
+/// `defn`s into those after them & into the body. the `defn`s themselves are
+/// kept, so that the program binds the same names.
+pub fn optimize_program(prog: &Program) -> Program {
+    let mut inline: Vec<(Name, Core)> = Vec::new();
+    let mut defns = Vec::new();
+    for (ix, Defn(nm, bd)) in prog.p_defns.iter().enumerate() {
+        let bd = optimize_core(&inlined(&inline, &core::resolve(bd)));
+        // a later `defn` shadows an earlier one.
+        inline.retain(|(defn, _bd)| defn != nm);
+        if size(&bd) <= INLINE_SIZE && is_total(&bd) && !is_captured(prog, ix, &bd) {
+            inline.push((nm.clone(), bd.clone()));
+        }
+        defns.push(Defn(nm.clone(), bd.to_expr()));
+    }
+    // the parameters of the body shadow the `defn`s.
+    if let Some(params) = &prog.p_params {
+        inline.retain(|(defn, _bd)| !params.iter().any(|param| param.0 == *defn));
+    }
+    let body = inlined(&inline, &core::resolve(&prog.p_body));
+    Program {
+        p_imports: prog.p_imports.clone(),
+        p_defns: defns,
+        p_params: prog.p_params.clone(),
+        p_body: optimize_core(&body).to_expr(),
+    }
+}
+
+/// optimize a core expression.
+pub fn optimize_core(core: &Core) -> Core {
+    let mut core = core.clone();
+    for _ in 0..MAX_PASSES {
+        let next = simplify(&core);
+        if next == core {
+            break;
+        }
+        core = next;
+    }
+    core
+}
+
+// whether a global of the body of the `ix`th `defn` is bound again, by that
+// `defn`, a later one or a parameter, so that, inlined after it, the global
+// would refer to another binding.
+fn is_captured(prog: &Program, ix: usize, bd: &Core) -> bool {
+    let globals = bd.globals();
+    let defns = prog.p_defns[ix..].iter().map(|Defn(nm, _bd)| nm);
+    let params = prog.p_params.iter().flatten().map(|param| &param.0);
+    defns.chain(params).any(|nm| globals.contains(nm))
+}
+
+// the `defn`s are closed, but for globals, & so may be substituted anywhere.
+fn inlined(inline: &[(Name, Core)], core: &Core) -> Core {
+    inline
+        .iter()
+        .fold(core.clone(), |core, (nm, bd)| core.subst_global(nm, bd))
+}
+
+// a single bottom up pass.
+fn simplify(core: &Core) -> Core {
+    match core {
+        Core::Local(_) | Core::Global(_) | Core::Lit(_) | Core::Prim(_) => core.clone(),
+        Core::Lam(x, bd) => Core::Lam(x.clone(), Box::new(simplify(bd))),
+        Core::Fix(e) => Core::Fix(Box::new(simplify(e))),
+        Core::If(tst, thn, els) => match simplify(tst) {
+            Core::Lit(Lit::LBool(true)) => simplify(thn),
+            Core::Lit(Lit::LBool(false)) => simplify(els),
+            tst => Core::If(
+                Box::new(tst),
+                Box::new(simplify(thn)),
+                Box::new(simplify(els)),
+            ),
+        },
+        Core::Let(x, e, bd) => simplify_let(x, simplify(e), simplify(bd)),
+        Core::App(fun, arg) => {
+            let fun = simplify(fun);
+            let arg = simplify(arg);
+            match fun {
+                // an immediately applied lambda binds its argument.
+                Core::Lam(x, bd) => Core::Let(x, Box::new(arg), bd),
+                // a `let` in function position is floated out, so that its
+                // lambda may be applied.
+                Core::Let(x, e, bd) => Core::Let(x, e, Box::new(capp(*bd, arg.shift(1)))),
+                fun => fold(capp(fun, arg)),
+            }
+        }
+    }
+}
+
+fn simplify_let(x: &Name, e: Core, bd: Core) -> Core {
+    let uses = bd.occurrences(0);
+    if uses == 0 && is_total(&e) {
+        return bd.strengthen();
+    }
+    let substitutable = match &e {
+        Core::Lit(_) | Core::Local(_) | Core::Global(_) | Core::Prim(_) => true,
+        // a lambda is a value, so that it may be moved anywhere.
+        Core::Lam(_, _) => uses == 1,
+        _ => false,
+    };
+    if substitutable {
+        bd.instantiate(&e)
+    } else {
+        Core::Let(x.clone(), Box::new(e), Box::new(bd))
+    }
+}
+
+// fold a full application of a PrimOp to literals, if it is one.
+fn fold(core: Core) -> Core {
+    let (op, args) = match prim_app(&core) {
+        Some((op, args)) if args.len() == primop_arity(&op) => (op, args),
+        _ => return core,
+    };
+    let int = |n: Option<i64>| n.map(|n| Core::Lit(Lit::LInt(n)));
+    let folded = match (&op, &args[..]) {
+        (PrimOp::Add, [Core::Lit(Lit::LInt(a)), Core::Lit(Lit::LInt(b))]) => int(a.checked_add(*b)),
+        (PrimOp::Sub, [Core::Lit(Lit::LInt(a)), Core::Lit(Lit::LInt(b))]) => int(a.checked_sub(*b)),
+        (PrimOp::Mul, [Core::Lit(Lit::LInt(a)), Core::Lit(Lit::LInt(b))]) => int(a.checked_mul(*b)),
+        (PrimOp::Eql, [Core::Lit(Lit::LInt(a)), Core::Lit(Lit::LInt(b))]) => {
+            Some(Core::Lit(Lit::LBool(a == b)))
+        }
+        (PrimOp::Null, [list]) => match prim_app(list) {
+            Some((PrimOp::Nil, _)) => Some(Core::Lit(Lit::LBool(true))),
+            Some((PrimOp::Cons, _)) if is_total(list) => Some(Core::Lit(Lit::LBool(false))),
+            _ => None,
+        },
+        (PrimOp::Fst, [pair]) | (PrimOp::Snd, [pair]) => match prim_app(pair) {
+            Some((PrimOp::Pair, parts)) if parts.len() == 2 && is_total(pair) => Some(
+                if op == PrimOp::Fst {
+                    parts[0]
+                } else {
+                    parts[1]
+                }
+                .clone(),
+            ),
+            _ => None,
+        },
+        _ => None,
+    };
+    folded.unwrap_or(core)
+}
+
+// the PrimOp at the head of an application, & its arguments.
+fn prim_app(core: &Core) -> Option<(PrimOp, Vec<&Core>)> {
+    let mut args = Vec::new();
+    let mut head = core;
+    while let Core::App(fun, arg) = head {
+        args.push(&**arg);
+        head = fun;
+    }
+    args.reverse();
+    match head {
+        Core::Prim(op) => Some((op.clone(), args)),
+        _ => None,
+    }
+}
+
+// whether the evaluation of `core` certainly terminates, without overflow:
+// values, & the PrimOps which cannot overflow applied to total arguments.
+fn is_total(core: &Core) -> bool {
+    match core {
+        Core::Local(_) | Core::Global(_) | Core::Lit(_) | Core::Prim(_) | Core::Lam(_, _) => true,
+        Core::Let(_x, e, bd) => is_total(e) && is_total(bd),
+        Core::If(tst, thn, els) => is_total(tst) && is_total(thn) && is_total(els),
+        Core::Fix(_) => false,
+        Core::App(_, _) => match prim_app(core) {
+            Some((op, args)) => {
+                let total_op = match op {
+                    PrimOp::Eql
+                    | PrimOp::Null
+                    | PrimOp::Pair
+                    | PrimOp::Fst
+                    | PrimOp::Snd
+                    | PrimOp::Cons
+                    | PrimOp::Nil => true,
+                    // a partial application is a closure.
+                    _ => args.len() < primop_arity(&op),
+                };
+                total_op && args.into_iter().all(is_total)
+            }
+            None => false,
+        },
+    }
+}
+
+fn size(core: &Core) -> usize {
+    1 + match core {
+        Core::Local(_) | Core::Global(_) | Core::Lit(_) | Core::Prim(_) => 0,
+        Core::Lam(_x, bd) => size(bd),
+        Core::Fix(e) => size(e),
+        Core::App(fun, arg) => size(fun) + size(arg),
+        Core::Let(_x, e, bd) => size(e) + size(bd),
+        Core::If(tst, thn, els) => size(tst) + size(thn) + size(els),
+    }
+}
@@ -1,16 +1,48 @@
-use combine::error::{ParseError, StreamError};
+use combine::error::{ParseError as StreamParseError, StreamError};
 use combine::parser::char::{alpha_num, char, digit, letter, spaces, string};
-use combine::stream::{Stream, StreamErrorFor};
+use combine::parser::token::satisfy;
+use combine::stream::position::{self, SourcePosition};
+use combine::stream::{easy, Stream, StreamErrorFor};
 use combine::{attempt, between, choice, many, many1, not_followed_by, optional, parser, Parser};
+use std::fmt;
 
 use super::syntax::*;
+use super::types::{type_arr, type_list, type_pair, Type, TV};
+
+/// the stream type used by the library-level parse functions. it tracks line
+/// & column so that errors can point at the offending input.
+pub type PositionStream<'a> = easy::Stream<position::Stream<&'a str, SourcePosition>>;
+
+/// errors returned by the library-level parse functions (`parse_program`,
+/// `parse_expr`, `parse_defn_or_it_expr`).
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    /// the parser could not make sense of the input.
+    Syntax(easy::Errors<char, String, SourcePosition>),
+    /// the parser succeeded, but did not consume all of the input. carries the
+    /// position at which the unconsumed region begins, and the region itself.
+    UnconsumedInput(SourcePosition, String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Syntax(errs) => write!(f, "{}", errs),
+            ParseError::UnconsumedInput(pos, rest) => write!(
+                f,
+                "unconsumed input at line: {}, column: {}: {:?}",
+                pos.line, pos.column, rest
+            ),
+        }
+    }
+}
 
 // `impl Parser` can be used to create reusable parsers with zero overhead
 pub fn expr_<Input>() -> impl Parser<Input, Output = Expr>
 where
     Input: Stream<Token = char>,
     // Necessary due to rust-lang/rust#24159
-    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+    Input::Error: StreamParseError<Input::Token, Input::Range, Input::Position>,
 {
     let l_bool = choice((
         res_str("true").map(|_| Lit::LBool(true)),
@@ -25,7 +57,7 @@ where
             None => Lit::LInt(num),
         }
     });
-    let lit = choice((l_bool, l_int)).map(|v| Expr::Lit(v));
+    let lit = choice((l_bool, l_int)).map(Expr::Lit);
 
     let prim_op = choice((
         res_str("+").map(|_| PrimOp::Add),
@@ -41,7 +73,7 @@ where
         res_str("cons").map(|_| PrimOp::Cons),
         res_str("nil").map(|_| PrimOp::Nil),
     ))
-    .map(|v| Expr::Prim(v));
+    .map(Expr::Prim);
 
     let app = (expr(), many1::<Vec<_>, _, _>(expr())).map(|t| {
         let applicator = |fun, arg: Expr| Expr::App(Box::new(fun), Box::new(arg));
@@ -130,7 +162,7 @@ pub fn defn_<Input>() -> impl Parser<Input, Output = Defn>
 where
     Input: Stream<Token = char>,
     // Necessary due to rust-lang/rust#24159
-    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+    Input::Error: StreamParseError<Input::Token, Input::Range, Input::Position>,
 {
     let defn_ = (res_str("defn"), name(), expr()).map(|t| Defn(t.1, t.2));
 
@@ -153,16 +185,137 @@ parser! {
     }
 }
 
+// types are written as they are pretty-printed: `Int`, `(List Int)`,
+// `(Int, Bool)`, `(Int -> Bool)`. lowercase names are type variables.
+pub fn type_<Input>() -> impl Parser<Input, Output = Type>
+where
+    Input: Stream<Token = char>,
+    // Necessary due to rust-lang/rust#24159
+    Input::Error: StreamParseError<Input::Token, Input::Range, Input::Position>,
+{
+    let atom = word().map(|s: String| {
+        if s.starts_with(char::is_uppercase) {
+            Type::TCon(s)
+        } else {
+            Type::TVar(TV(s))
+        }
+    });
+
+    let list = (res_str("List"), type_expr()).map(|t| type_list(t.1));
+
+    let pair_or_arr = (
+        type_expr(),
+        choice((
+            lex_char(',').with(type_expr()).map(|t| (true, t)),
+            (char('-'), lex_char('>'))
+                .with(type_expr())
+                .map(|t| (false, t)),
+        )),
+    )
+        .map(|(t1, (is_pair, t2))| {
+            if is_pair {
+                type_pair(t1, t2)
+            } else {
+                type_arr(t1, t2)
+            }
+        });
+
+    choice((
+        attempt(atom),
+        between(
+            lex_char('('),
+            lex_char(')'),
+            choice((attempt(list), pair_or_arr)),
+        ),
+    ))
+    .skip(skip_spaces())
+}
+
+parser! {
+    pub fn type_expr[Input]()(Input) -> Type
+    where [Input: Stream<Token = char>]
+    {
+        type_()
+    }
+}
+
+pub fn params_<Input>() -> impl Parser<Input, Output = Vec<Param>>
+where
+    Input: Stream<Token = char>,
+    // Necessary due to rust-lang/rust#24159
+    Input::Error: StreamParseError<Input::Token, Input::Range, Input::Position>,
+{
+    let param = (lex_char('['), name(), type_expr(), lex_char(']')).map(|t| Param(t.1, t.2));
+    let params_ = (res_str("params"), many(param)).map(|t| t.1);
+
+    between(lex_char('('), lex_char(')'), params_).skip(skip_spaces())
+}
+
+parser! {
+    pub fn params[Input]()(Input) -> Vec<Param>
+    where [Input: Stream<Token = char>]
+    {
+        params_()
+    }
+}
+
+pub fn import_<Input>() -> impl Parser<Input, Output = Import>
+where
+    Input: Stream<Token = char>,
+    // Necessary due to rust-lang/rust#24159
+    Input::Error: StreamParseError<Input::Token, Input::Range, Input::Position>,
+{
+    let path = between(char('"'), lex_char('"'), many1(satisfy(|c| c != '"'))).map(Import::Path);
+    let import_ = (res_str("import"), choice((path, name().map(Import::Named)))).map(|t| t.1);
+
+    between(lex_char('('), lex_char(')'), import_).skip(skip_spaces())
+}
+
+parser! {
+    pub fn import[Input]()(Input) -> Import
+    where [Input: Stream<Token = char>]
+    {
+        import_()
+    }
+}
+
+pub fn export_<Input>() -> impl Parser<Input, Output = Vec<Name>>
+where
+    Input: Stream<Token = char>,
+    // Necessary due to rust-lang/rust#24159
+    Input::Error: StreamParseError<Input::Token, Input::Range, Input::Position>,
+{
+    let export_ = (res_str("export"), many(name())).map(|t| t.1);
+
+    between(lex_char('('), lex_char(')'), export_).skip(skip_spaces())
+}
+
+parser! {
+    pub fn export[Input]()(Input) -> Vec<Name>
+    where [Input: Stream<Token = char>]
+    {
+        export_()
+    }
+}
+
 pub fn program_<Input>() -> impl Parser<Input, Output = Program>
 where
     Input: Stream<Token = char>,
     // Necessary due to rust-lang/rust#24159
-    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+    Input::Error: StreamParseError<Input::Token, Input::Range, Input::Position>,
 {
-    (many(attempt(defn())), expr()).map(|t| Program {
-        p_defns: t.0,
-        p_body: t.1,
-    })
+    (
+        many(attempt(import())),
+        many(attempt(defn())),
+        optional(attempt(params())),
+        expr(),
+    )
+        .map(|t| Program {
+            p_imports: t.0,
+            p_defns: t.1,
+            p_params: t.2,
+            p_body: t.3,
+        })
 }
 
 parser! {
@@ -173,6 +326,84 @@ parser! {
     }
 }
 
+pub fn module_<Input>() -> impl Parser<Input, Output = Module>
+where
+    Input: Stream<Token = char>,
+    // Necessary due to rust-lang/rust#24159
+    Input::Error: StreamParseError<Input::Token, Input::Range, Input::Position>,
+{
+    (
+        optional(attempt(export())),
+        many(attempt(import())),
+        many(defn()),
+    )
+        .map(|t| Module {
+            m_exports: t.0,
+            m_imports: t.1,
+            m_defns: t.2,
+        })
+}
+
+parser! {
+    pub fn module[Input]()(Input) -> Module
+    where [Input: Stream<Token = char>]
+    {
+        module_()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// entry points
+////////////////////////////////////////////////////////////////////////////////
+
+/// parse a whole program, failing if any input is left over.
+pub fn parse_program(input: &str) -> Result<Program, ParseError> {
+    parse_all(program(), input)
+}
+
+/// parse a module (a sequence of `defn`s, optionally preceded by an `export`
+/// list and `import`s), failing if any input is left over.
+pub fn parse_module(input: &str) -> Result<Module, ParseError> {
+    parse_all(module(), input)
+}
+
+/// parse a type, failing if any input is left over.
+pub fn parse_type(input: &str) -> Result<Type, ParseError> {
+    parse_all(type_expr(), input)
+}
+
+/// parse a single expression, failing if any input is left over.
+pub fn parse_expr(input: &str) -> Result<Expr, ParseError> {
+    parse_all(expr(), input)
+}
+
+/// parse a `defn` or a bare expression (bound to `it`), failing if any input
+/// is left over. this is what the REPL accepts.
+pub fn parse_defn_or_it_expr(input: &str) -> Result<Defn, ParseError> {
+    parse_all(defn_or_it_expr(), input)
+}
+
+// run `parser` over the whole of `input`, skipping any leading whitespace.
+fn parse_all<'a, P, O>(parser: P, input: &'a str) -> Result<O, ParseError>
+where
+    P: Parser<PositionStream<'a>, Output = O>,
+{
+    let mut parser = (skip_spaces(), parser).map(|t| t.1);
+    match parser.parse(easy::Stream(position::Stream::new(input))) {
+        Err(err) => Err(ParseError::Syntax(err.map_range(|r| r.to_string()))),
+        Ok((v, easy::Stream(rest))) => {
+            if rest.input.is_empty() {
+                Ok(v)
+            } else {
+                Err(ParseError::UnconsumedInput(
+                    rest.positioner,
+                    rest.input.to_string(),
+                ))
+            }
+        }
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // helpers
 ////////////////////////////////////////////////////////////////////////////////
@@ -181,7 +412,7 @@ parser! {
 fn lex_char<Input>(c: char) -> impl Parser<Input, Output = char>
 where
     Input: Stream<Token = char>,
-    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+    Input::Error: StreamParseError<Input::Token, Input::Range, Input::Position>,
 {
     char(c).skip(skip_spaces())
 }
@@ -192,7 +423,7 @@ where
 fn skip_spaces<Input>() -> impl Parser<Input, Output = ()>
 where
     Input: Stream<Token = char>,
-    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+    Input::Error: StreamParseError<Input::Token, Input::Range, Input::Position>,
 {
     spaces().silent()
 }
@@ -200,7 +431,7 @@ where
 fn word<Input>() -> impl Parser<Input, Output = String>
 where
     Input: Stream<Token = char>,
-    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+    Input::Error: StreamParseError<Input::Token, Input::Range, Input::Position>,
 {
     many1(letter()).skip(skip_spaces())
 }
@@ -208,7 +439,7 @@ where
 fn integer<Input>() -> impl Parser<Input, Output = String>
 where
     Input: Stream<Token = char>,
-    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+    Input::Error: StreamParseError<Input::Token, Input::Range, Input::Position>,
 {
     many1(digit()).skip(skip_spaces())
 }
@@ -216,7 +447,7 @@ where
 fn res_str<'a, Input>(x: &'static str) -> impl Parser<Input, Output = &'a str>
 where
     Input: Stream<Token = char>,
-    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+    Input::Error: StreamParseError<Input::Token, Input::Range, Input::Position>,
 {
     string(x)
         .skip(not_followed_by(alpha_num()))
@@ -226,7 +457,7 @@ where
 pub fn reserved() -> Vec<String> {
     [
         "let", "lam", "fix", "true", "false", "if", "null", "map", "foldl", "pair", "fst", "snd",
-        "cons", "defn", "list", "nil",
+        "cons", "defn", "list", "nil", "import", "export", "params",
     ]
     .iter()
     .map(|x| x.to_string())
@@ -236,7 +467,7 @@ pub fn reserved() -> Vec<String> {
 fn name<Input>() -> impl Parser<Input, Output = Name>
 where
     Input: Stream<Token = char>,
-    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+    Input::Error: StreamParseError<Input::Token, Input::Range, Input::Position>,
 {
     word().and_then(move |s: String| {
         if reserved().contains(&s) {
@@ -249,10 +480,37 @@ where
     })
 }
 
+// a name which may be qualified by a module name, as in `module.name`. only
+// variable references may be qualified - binders may not.
+fn qualified_name<Input>() -> impl Parser<Input, Output = Name>
+where
+    Input: Stream<Token = char>,
+    Input::Error: StreamParseError<Input::Token, Input::Range, Input::Position>,
+{
+    (
+        many1::<String, _, _>(letter()),
+        optional((char('.'), many1::<String, _, _>(letter()))),
+    )
+        .skip(skip_spaces())
+        .and_then(move |(s1, s2)| {
+            let res = reserved();
+            if res.contains(&s1) || s2.iter().any(|(_, s)| res.contains(s)) {
+                Err(StreamErrorFor::<Input>::unexpected_static_message(
+                    "reserved keyword",
+                ))
+            } else {
+                match s2 {
+                    None => Ok(Name(s1)),
+                    Some((_, s)) => Ok(Name(format!("{}.{}", s1, s))),
+                }
+            }
+        })
+}
+
 fn var<Input>() -> impl Parser<Input, Output = Expr>
 where
     Input: Stream<Token = char>,
-    Input::Error: ParseError<Input::Token, Input::Range, Input::Position>,
+    Input::Error: StreamParseError<Input::Token, Input::Range, Input::Position>,
 {
-    name().map(Expr::Var)
+    qualified_name().map(Expr::Var)
 }
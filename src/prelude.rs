@@ -0,0 +1,37 @@
+use std::sync::OnceLock;
+
+use super::{
+    env::Env,
+    eval::TermEnv,
+    module::{MemoryResolver, ModuleId, ModuleLoader},
+    parse::parse_module,
+};
+
+/// the source of the prelude, which is bundled into the crate.
+pub const PRELUDE_SOURCE: &str = include_str!("prelude.poly");
+
+/// the type & term environments of the prelude's exports. these seed
+/// programs, modules & the REPL unless the prelude is opted out of.
+pub struct Prelude {
+    pub type_env: Env,
+    pub term_env: TermEnv,
+}
+
+static PRELUDE: OnceLock<Prelude> = OnceLock::new();
+
+/// the prelude, which is parsed, type checked & evaluated the first time this
+/// is called.
+pub fn prelude() -> &'static Prelude {
+    PRELUDE.get_or_init(|| {
+        let module = match parse_module(PRELUDE_SOURCE) {
+            Ok(module) => module,
+            Err(err) => panic!("impossible: prelude: parse error: {}", err),
+        };
+        let resolver = MemoryResolver::new();
+        let mut loader = ModuleLoader::new(&resolver).without_prelude();
+        match loader.load_module(ModuleId("<prelude>".to_string()), &module) {
+            Ok((type_env, term_env)) => Prelude { type_env, term_env },
+            Err(err) => panic!("impossible: prelude: {:?}", err),
+        }
+    })
+}
@@ -1,13 +1,15 @@
 use pretty::RcDoc;
 use std::iter;
 
-use super::syntax::{Defn, Expr, Expr::*, Lit, Lit::*, Name, PrimOp, PrimOp::*, Program};
+use super::syntax::{
+    Defn, Expr, Expr::*, Import, Lit, Lit::*, Module, Name, Param, PrimOp, PrimOp::*, Program,
+};
 use crate::sp;
 use crate::util::pretty::parens;
 
 impl Expr {
-    pub fn ppr(&self) -> RcDoc<()> {
-        match &*self {
+    pub fn ppr(&self) -> RcDoc<'_, ()> {
+        match self {
             Var(name) => name.ppr(),
             App(fun, arg) => {
                 let fun_ = fun.ppr();
@@ -49,7 +51,7 @@ impl Expr {
 }
 
 impl Lit {
-    pub fn ppr(&self) -> RcDoc<()> {
+    pub fn ppr(&self) -> RcDoc<'_, ()> {
         match *self {
             LInt(i) => RcDoc::as_string(i),
             LBool(true) => RcDoc::text("true"),
@@ -59,7 +61,7 @@ impl Lit {
 }
 
 impl PrimOp {
-    pub fn ppr(&self) -> RcDoc<()> {
+    pub fn ppr(&self) -> RcDoc<'_, ()> {
         match *self {
             Add => RcDoc::text("+"),
             Sub => RcDoc::text("-"),
@@ -78,16 +80,16 @@ impl PrimOp {
 }
 
 impl Name {
-    pub fn ppr(&self) -> RcDoc<()> {
-        match &*self {
+    pub fn ppr(&self) -> RcDoc<'_, ()> {
+        match self {
             Name(s) => RcDoc::text(s),
         }
     }
 }
 
 impl Defn {
-    pub fn ppr(&self) -> RcDoc<()> {
-        match &*self {
+    pub fn ppr(&self) -> RcDoc<'_, ()> {
+        match self {
             Defn(nm, bd) => parens(
                 RcDoc::text("defn ")
                     .append(nm.ppr())
@@ -98,13 +100,56 @@ impl Defn {
     }
 }
 
+impl Import {
+    pub fn ppr(&self) -> RcDoc<'_, ()> {
+        let target = match self {
+            Import::Path(path) => RcDoc::text(format!("\"{}\"", path)),
+            Import::Named(nm) => nm.ppr(),
+        };
+        parens(RcDoc::text("import ").append(target))
+    }
+}
+
+impl Param {
+    pub fn ppr(&self) -> RcDoc<'_, ()> {
+        match self {
+            Param(nm, ty) => RcDoc::text("[")
+                .append(nm.ppr())
+                .append(sp!())
+                .append(ty.ppr())
+                .append(RcDoc::text("]")),
+        }
+    }
+}
+
 impl Program {
-    pub fn ppr(&self) -> RcDoc<()> {
+    pub fn ppr(&self) -> RcDoc<'_, ()> {
+        let params = self.p_params.iter().map(|ps| {
+            let header = iter::once(RcDoc::text("(params"));
+            RcDoc::intersperse(header.chain(ps.iter().map(|p| p.ppr())), sp!())
+                .append(RcDoc::text(")"))
+        });
         let docs = self
-            .p_defns
+            .p_imports
             .iter()
-            .map(|d| d.ppr())
+            .map(|i| i.ppr())
+            .chain(self.p_defns.iter().map(|d| d.ppr()))
+            .chain(params)
             .chain(iter::once(self.p_body.ppr()));
         RcDoc::intersperse(docs, "\n\n")
     }
 }
+
+impl Module {
+    pub fn ppr(&self) -> RcDoc<'_, ()> {
+        let exports = self.m_exports.iter().map(|nms| {
+            let header = iter::once(RcDoc::text("(export"));
+            RcDoc::intersperse(header.chain(nms.iter().map(|nm| nm.ppr())), sp!())
+                .append(RcDoc::text(")"))
+        });
+        let docs = exports
+            .chain(self.m_imports.iter().map(|i| i.ppr()))
+            .chain(self.m_defns.iter().map(|d| d.ppr()));
+        RcDoc::intersperse(docs, "\n\n")
+    }
+}
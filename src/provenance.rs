@@ -0,0 +1,302 @@
+//! explanations of computed values, as derivation trees.
+//!
+//! a `Derivation` is built from the `Trace` of an evaluation, by keeping only
+//! the steps which explain where a value came from: references to inputs &
+//! to (non-function) `defn`s, calls to `defn`s, the higher order primitives
+//! (`map` & `foldl`), & each application of their functions to the elements
+//! of a list. the other steps are elided, & their kept descendants lifted
+//! into place. expressions & values are pretty printed, so that derivations
+//! are always serializable (with the `serde` feature).
+
+use pretty::RcDoc;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::{
+    eval::Value,
+    syntax::{primop_arity, Expr, Name, PrimOp},
+    trace::{Step, Trace},
+    util::pretty::to_pretty,
+};
+
+/// why a step of a derivation was kept.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Kind {
+    /// the calculation as a whole.
+    Calculation,
+    /// a reference to an input.
+    Input,
+    /// a reference to, or a call of, a `defn`.
+    Defn,
+    /// an application of `map` or `foldl`.
+    Primitive,
+    /// the application of the function of a `map` or `foldl` to the element
+    /// of the list at this index.
+    Element(usize),
+}
+
+/// where a bound name comes from.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Source {
+    Input,
+    Defn,
+    Local,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Binding {
+    pub name: String,
+    pub source: Source,
+    pub value: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Derivation {
+    pub kind: Kind,
+    pub expr: String,
+    pub value: String,
+    /// the (non-function) values bound to the free variables of `expr`.
+    pub bindings: Vec<Binding>,
+    pub children: Vec<Derivation>,
+}
+
+impl Derivation {
+    /// the derivation of the value of the step at `root` of `trace`, in which
+    /// `inputs` & `defns` are the names bound to the inputs & to the `defn`s of
+    /// the calculation.
+    pub fn from_trace(trace: &Trace, root: usize, inputs: &[Name], defns: &[Name]) -> Derivation {
+        let builder = Builder {
+            steps: trace.steps(),
+            children: children(trace.steps()),
+            body: &trace.steps()[root].expr,
+            inputs,
+            defns,
+            calls: trace.breakpoints(defns),
+        };
+        builder.node(root, Kind::Calculation, true)
+    }
+
+    /// the tree, a step per line, indented by depth.
+    pub fn ppr(&self) -> RcDoc<'static, ()> {
+        let kind = match &self.kind {
+            Kind::Calculation => "calculation".to_string(),
+            Kind::Input => "input".to_string(),
+            Kind::Defn => "defn".to_string(),
+            Kind::Primitive => "primitive".to_string(),
+            Kind::Element(ix) => format!("element {}", ix),
+        };
+        let bindings = self
+            .bindings
+            .iter()
+            .map(|binding| format!("{} = {}", binding.name, binding.value))
+            .collect::<Vec<String>>();
+        let mut line = format!("[{}] {} => {}", kind, self.expr, self.value);
+        if !bindings.is_empty() {
+            line = format!("{} where {}", line, bindings.join(", "));
+        }
+        let children = self
+            .children
+            .iter()
+            .map(|child| RcDoc::hardline().append(child.ppr()));
+        RcDoc::text(line).append(RcDoc::concat(children).nest(2))
+    }
+}
+
+// the indices of the children of each step. the steps of a trace are in
+// order of entry, so that the parent of a step is the closest preceding step
+// which is one level shallower.
+fn children(steps: &[Step]) -> Vec<Vec<usize>> {
+    let mut children = vec![Vec::new(); steps.len()];
+    let mut ancestors: Vec<usize> = Vec::new();
+    for (ix, step) in steps.iter().enumerate() {
+        while let Some(&parent) = ancestors.last() {
+            if steps[parent].depth < step.depth {
+                break;
+            }
+            ancestors.pop();
+        }
+        if let Some(&parent) = ancestors.last() {
+            children[parent].push(ix);
+        }
+        ancestors.push(ix);
+    }
+    children
+}
+
+struct Builder<'a> {
+    steps: &'a [Step],
+    children: Vec<Vec<usize>>,
+    // the body of the calculation, under whose binders the inputs are in scope.
+    body: &'a Expr,
+    inputs: &'a [Name],
+    defns: &'a [Name],
+    // the outermost steps of calls to `defns`.
+    calls: Vec<usize>,
+}
+
+impl<'a> Builder<'a> {
+    // `scoped` is whether the inputs are in scope at the step.
+    fn node(&self, ix: usize, kind: Kind, scoped: bool) -> Derivation {
+        let step = &self.steps[ix];
+        let children = match higher_order(&step.expr) {
+            // after the arguments come the applications of the function, of
+            // which only those to elements (not partial applications) count.
+            Some(arity) => {
+                let (args, apps) = self.children[ix].split_at(arity.min(self.children[ix].len()));
+                let elems = apps
+                    .iter()
+                    .filter(|&&app| !self.steps[app].value.as_ref().is_some_and(is_function));
+                args.iter()
+                    .flat_map(|&arg| self.kept(arg, self.scoped(ix, arg, scoped)))
+                    .chain(elems.enumerate().map(|(elem, &app)| {
+                        self.node(app, Kind::Element(elem), self.scoped(ix, app, scoped))
+                    }))
+                    .collect()
+            }
+            None => self.children[ix]
+                .iter()
+                .flat_map(|&child| self.kept(child, self.scoped(ix, child, scoped)))
+                .collect(),
+        };
+        // a reference is explained by its value alone.
+        let bindings = match &step.expr {
+            Expr::Var(_) => Vec::new(),
+            _ => step
+                .bindings
+                .iter()
+                .filter(|(_nm, val)| !is_function(val))
+                .map(|(nm, val)| Binding {
+                    name: nm.0.clone(),
+                    source: self.source(nm, scoped),
+                    value: to_pretty(val.ppr(), 80),
+                })
+                .collect(),
+        };
+        Derivation {
+            kind,
+            expr: to_pretty(step.expr.ppr(), 80),
+            value: match &step.value {
+                Some(val) => to_pretty(val.ppr(), 80),
+                None => "<<failed>>".to_string(),
+            },
+            bindings,
+            children,
+        }
+    }
+
+    // the derivation of the step at `ix`, if it is kept, & otherwise those of
+    // its kept descendants.
+    fn kept(&self, ix: usize, scoped: bool) -> Vec<Derivation> {
+        match self.kind(ix, scoped) {
+            Some(kind) => vec![self.node(ix, kind, scoped)],
+            None => self.children[ix]
+                .iter()
+                .flat_map(|&child| self.kept(child, self.scoped(ix, child, scoped)))
+                .collect(),
+        }
+    }
+
+    fn kind(&self, ix: usize, scoped: bool) -> Option<Kind> {
+        let step = &self.steps[ix];
+        match &step.expr {
+            Expr::Var(nm) if scoped && self.inputs.contains(nm) => Some(Kind::Input),
+            Expr::Var(nm)
+                if self.defns.contains(nm) && !step.value.as_ref().is_some_and(is_function) =>
+            {
+                Some(Kind::Defn)
+            }
+            Expr::App(_, _) if self.calls.binary_search(&ix).is_ok() => Some(Kind::Defn),
+            expr if higher_order(expr).is_some() => Some(Kind::Primitive),
+            _ => None,
+        }
+    }
+
+    // whether the inputs are in scope at the `child` step of `parent`. a step
+    // which is not a part of its parent's expression evaluates the body of a
+    // function: of a lambda in the calculation, in which the inputs are in
+    // scope, or of one from elsewhere (e.g. a `defn`), in which they are not.
+    fn scoped(&self, parent: usize, child: usize, scoped: bool) -> bool {
+        let child = &self.steps[child].expr;
+        if is_part(&self.steps[parent].expr, child) {
+            scoped
+        } else {
+            contains(self.body, child)
+        }
+    }
+
+    fn source(&self, nm: &Name, scoped: bool) -> Source {
+        if scoped && self.inputs.contains(nm) {
+            Source::Input
+        } else if self.defns.contains(nm) {
+            Source::Defn
+        } else {
+            Source::Local
+        }
+    }
+}
+
+// whether `part` is an immediate sub-expression of `expr`, or (as the
+// arguments of a `PrimOp` are evaluated directly) of its application spine.
+fn is_part(expr: &Expr, part: &Expr) -> bool {
+    match expr {
+        Expr::App(fun, arg) => **fun == *part || **arg == *part || is_part(fun, part),
+        Expr::Let(_x, e, bd) => **e == *part || **bd == *part,
+        Expr::If(tst, thn, els) => **tst == *part || **thn == *part || **els == *part,
+        Expr::Fix(e) => **e == *part,
+        Expr::Var(_) | Expr::Lam(_, _) | Expr::Lit(_) | Expr::Prim(_) => false,
+    }
+}
+
+// whether `part` is a sub-expression of `expr`.
+fn contains(expr: &Expr, part: &Expr) -> bool {
+    *expr == *part
+        || match expr {
+            Expr::App(fun, arg) => contains(fun, part) || contains(arg, part),
+            Expr::Lam(_x, bd) => contains(bd, part),
+            Expr::Let(_x, e, bd) => contains(e, part) || contains(bd, part),
+            Expr::If(tst, thn, els) => {
+                contains(tst, part) || contains(thn, part) || contains(els, part)
+            }
+            Expr::Fix(e) => contains(e, part),
+            Expr::Var(_) | Expr::Lit(_) | Expr::Prim(_) => false,
+        }
+}
+
+// the arity of `map` or `foldl`, if `expr` is a full application of one.
+fn higher_order(expr: &Expr) -> Option<usize> {
+    let mut args = 0;
+    let mut head = expr;
+    while let Expr::App(fun, _arg) = head {
+        args += 1;
+        head = fun;
+    }
+    match head {
+        Expr::Prim(op @ PrimOp::Map) | Expr::Prim(op @ PrimOp::Foldl) => {
+            Some(primop_arity(op)).filter(|&arity| arity == args)
+        }
+        _ => None,
+    }
+}
+
+fn is_function(val: &Value) -> bool {
+    matches!(
+        val,
+        Value::VClosure(_, _, _)
+            | Value::VCore(_, _, _, _)
+            | Value::VNative(_, _)
+            | Value::VCode(_, _)
+    )
+}
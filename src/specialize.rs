@@ -0,0 +1,132 @@
+//! partial evaluation of expressions, some of whose variables are known.
+//!
+//! `partial_eval` evaluates the sub-expressions whose free variables are all
+//! known, & replaces them by their values, written back as expressions. only
+//! the sub-expressions which are certainly evaluated along with the whole are
+//! evaluated: not the bodies of lambdas, nor the branches of an `if` whose
+//! test is unknown. so partial evaluation fails (or diverges) only where the
+//! full evaluation of the expression would.
+//!
+//! see `toplevel::specialize_calculation` for the specialization of a program
+//! to some of its inputs.
+
+use super::{
+    eval::{eval_, EvalState, TermEnv, Value},
+    syntax::{Expr, Lit, Name, PrimOp},
+};
+use crate::app;
+
+/// `val` as an expression which evaluates to it, if it is data (& not, e.g., a
+/// closure).
+pub fn reify(val: &Value) -> Option<Expr> {
+    match val {
+        Value::VInt(n) => Some(Expr::Lit(Lit::LInt(*n))),
+        Value::VBool(b) => Some(Expr::Lit(Lit::LBool(*b))),
+        Value::VList(vals) => vals
+            .iter()
+            .rev()
+            .try_fold(Expr::Prim(PrimOp::Nil), |tl, val| {
+                reify(val).map(|hd| app!(app!(Expr::Prim(PrimOp::Cons), hd), tl))
+            }),
+        Value::VPair(a, b) => Some(app!(app!(Expr::Prim(PrimOp::Pair), reify(a)?), reify(b)?)),
+        Value::VThunk(_) | Value::VCons(_, _) => reify(&val.clone().forced().ok()?),
+        Value::VClosure(_, _, _)
+        | Value::VCore(_, _, _, _)
+        | Value::VNative(_, _)
+        | Value::VCode(_, _) => None,
+    }
+}
+
+/// evaluate what can be evaluated of `expr`, whose known variables are bound
+/// in `env`, within the limits of `es`.
+pub fn partial_eval(env: &TermEnv, es: &mut EvalState, expr: &Expr) -> Expr {
+    let mut pe = PartialEval {
+        es,
+        unknown: Vec::new(),
+    };
+    pe.eval(env, expr)
+}
+
+struct PartialEval<'a> {
+    es: &'a mut EvalState,
+    // the names bound to unknown values, which shadow those of the `env`.
+    unknown: Vec<Name>,
+}
+
+impl<'a> PartialEval<'a> {
+    fn eval(&mut self, env: &TermEnv, expr: &Expr) -> Expr {
+        if let Some(val) = self.known(env, expr) {
+            return val;
+        }
+        match expr {
+            Expr::Var(_) | Expr::Lit(_) | Expr::Prim(_) | Expr::Lam(_, _) | Expr::Fix(_) => {
+                expr.clone()
+            }
+            Expr::App(fun, arg) => app!(self.eval(env, fun), self.eval(env, arg)),
+            Expr::If(tst, thn, els) => match self.eval(env, tst) {
+                Expr::Lit(Lit::LBool(true)) => self.eval(env, thn),
+                Expr::Lit(Lit::LBool(false)) => self.eval(env, els),
+                tst => Expr::If(Box::new(tst), thn.clone(), els.clone()),
+            },
+            Expr::Let(x, e, bd) => {
+                let e = self.eval(env, e);
+                let known = if is_data(&e) {
+                    self.value(&TermEnv::new(), &e)
+                } else {
+                    None
+                };
+                let bd = match known {
+                    Some(val) => {
+                        let unknown = self.unknown.clone();
+                        self.unknown.retain(|nm| nm != x);
+                        let bd = self.eval(&env.update(x.clone(), val), bd);
+                        self.unknown = unknown;
+                        bd
+                    }
+                    None => {
+                        self.unknown.push(x.clone());
+                        let bd = self.eval(env, bd);
+                        self.unknown.pop();
+                        bd
+                    }
+                };
+                Expr::Let(x.clone(), Box::new(e), Box::new(bd))
+            }
+        }
+    }
+
+    // the value of `expr`, as an expression, if it is known & is data.
+    fn known(&mut self, env: &TermEnv, expr: &Expr) -> Option<Expr> {
+        if let Expr::Lit(_) | Expr::Lam(_, _) = expr {
+            return None;
+        }
+        let closed = expr
+            .free_vars()
+            .iter()
+            .all(|nm| env.contains_key(nm) && !self.unknown.contains(nm));
+        if !closed {
+            return None;
+        }
+        self.value(env, expr).as_ref().and_then(reify)
+    }
+
+    fn value(&mut self, env: &TermEnv, expr: &Expr) -> Option<Value> {
+        eval_(env, self.es, expr).ok()
+    }
+}
+
+// whether `expr` is data, as written by `reify`.
+fn is_data(expr: &Expr) -> bool {
+    match expr {
+        Expr::Lit(_) | Expr::Prim(PrimOp::Nil) => true,
+        Expr::App(fun, tl) => match &**fun {
+            Expr::App(op, hd) => {
+                matches!(&**op, Expr::Prim(PrimOp::Cons) | Expr::Prim(PrimOp::Pair))
+                    && is_data(hd)
+                    && is_data(tl)
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
@@ -1,7 +1,34 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use super::types::Type;
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Name(pub String);
 
+impl Name {
+    /// the `n`th of the names `base`, `basea`, `baseb`, ... `baseaa`, ...,
+    /// which are of letters alone (if `base` is), & so may be written as
+    /// variables.
+    pub(crate) fn lettered(base: &str, mut n: usize) -> Name {
+        let mut suffix = Vec::new();
+        while n > 0 {
+            n -= 1;
+            suffix.push((b'a' + (n % 26) as u8) as char);
+            n /= 26;
+        }
+        let suffix: String = suffix.into_iter().rev().collect();
+        Name(format!("{}{}", base, suffix))
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum Expr {
     Var(Name),
     App(Box<Expr>, Box<Expr>),
@@ -28,12 +55,20 @@ macro_rules! lam {
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Lit {
+    #[cfg_attr(feature = "serde", serde(rename = "int"))]
     LInt(i64),
+    #[cfg_attr(feature = "serde", serde(rename = "bool"))]
     LBool(bool),
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum PrimOp {
     Add,
     Sub,
@@ -50,16 +85,118 @@ pub enum PrimOp {
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Defn(pub Name, pub Expr);
 
+/// a reference to another module, as written in an `import` form.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum Import {
+    /// `(import "path/to/module.poly")`: a module referenced by file path.
+    Path(String),
+    /// `(import module)`: a module located by the resolver's search paths.
+    Named(Name),
+}
+
+/// a named & typed parameter of a program, as declared in a `params` form.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Param(pub Name, pub Type);
+
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Program {
+    #[cfg_attr(feature = "serde", serde(rename = "imports"))]
+    pub p_imports: Vec<Import>,
+    #[cfg_attr(feature = "serde", serde(rename = "defns"))]
     pub p_defns: Vec<Defn>,
+    /// if present, the body is abstracted over these parameters, in order.
+    #[cfg_attr(feature = "serde", serde(rename = "params"))]
+    pub p_params: Option<Vec<Param>>,
+    #[cfg_attr(feature = "serde", serde(rename = "body"))]
     pub p_body: Expr,
 }
 
+impl Program {
+    /// the body of the program, wrapped in a lambda binding each of its
+    /// declared parameters (if any).
+    pub fn body_expr(&self) -> Expr {
+        match &self.p_params {
+            None => self.p_body.clone(),
+            Some(params) => {
+                let applicator = |bd, Param(nm, _ty): &Param| Expr::Lam(nm.clone(), Box::new(bd));
+                params.iter().rev().fold(self.p_body.clone(), applicator)
+            }
+        }
+    }
+}
+
+/// a module is a program without a body. if `m_exports` is `None`, all of its
+/// `defn`s are exported.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Module {
+    #[cfg_attr(feature = "serde", serde(rename = "exports"))]
+    pub m_exports: Option<Vec<Name>>,
+    #[cfg_attr(feature = "serde", serde(rename = "imports"))]
+    pub m_imports: Vec<Import>,
+    #[cfg_attr(feature = "serde", serde(rename = "defns"))]
+    pub m_defns: Vec<Defn>,
+}
+
+impl Expr {
+    /// the free variables of the expression, in order of first appearance.
+    pub fn free_vars(&self) -> Vec<Name> {
+        let mut free = Vec::new();
+        free_vars_in(self, &mut Vec::new(), &mut free);
+        free
+    }
+}
+
 // helpers
 
+/// the free variables of `expr`, not in `bound`, in order of first appearance.
+pub fn free_vars_in(expr: &Expr, bound: &mut Vec<Name>, free: &mut Vec<Name>) {
+    match expr {
+        Expr::Var(x) => {
+            if !bound.contains(x) && !free.contains(x) {
+                free.push(x.clone());
+            }
+        }
+        Expr::Lit(_) | Expr::Prim(_) => {}
+        Expr::App(fun, arg) => {
+            free_vars_in(fun, bound, free);
+            free_vars_in(arg, bound, free);
+        }
+        Expr::Lam(x, bd) => {
+            bound.push(x.clone());
+            free_vars_in(bd, bound, free);
+            bound.pop();
+        }
+        Expr::Let(x, e, bd) => {
+            free_vars_in(e, bound, free);
+            bound.push(x.clone());
+            free_vars_in(bd, bound, free);
+            bound.pop();
+        }
+        Expr::If(tst, thn, els) => {
+            free_vars_in(tst, bound, free);
+            free_vars_in(thn, bound, free);
+            free_vars_in(els, bound, free);
+        }
+        Expr::Fix(e) => free_vars_in(e, bound, free),
+    }
+}
+
+/// build a qualified name, referring to `nm` as exported from `module`.
+pub fn qualified_name(module: &Name, nm: &Name) -> Name {
+    Name(format!("{}.{}", module.0, nm.0))
+}
+
 pub fn primop_arity(op: &PrimOp) -> usize {
     match op {
         PrimOp::Add => 2,
@@ -0,0 +1,312 @@
+//! static checking that a program always terminates.
+//!
+//! without `fix`, every well typed program terminates: `map` & `foldl` only
+//! ever walk a finite list. a `fix` is accepted if its recursion is structural
+//! on a list parameter: every recursive call passes, in the same position, a
+//! list strictly shorter than the one the function was called with. a list is
+//! known to be shorter if it is the result of a destructor (e.g. a native
+//! `tail`) applied to the parameter, in the `else` branch of an
+//! `(if (null xs) ...)` test of it.
+//!
+//! the destructors are trusted to return a strictly shorter list when given a
+//! non-empty one, & never a longer one. natives are trusted to terminate, &
+//! imported modules are checked separately (see `check_module_termination`).
+
+use super::syntax::{Defn, Expr, Module, Name, PrimOp, Program};
+
+/// a `fix` whose termination could not be shown.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recursion {
+    /// the `defn` in which the `fix` occurs, or `None` if it is in the body.
+    pub defn: Option<Name>,
+    pub fix: Expr,
+    pub reason: Reason,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Reason {
+    /// the argument of the `fix` is not a lambda, so its recursive calls cannot
+    /// be found.
+    Opaque,
+    /// the recursive function is used other than by being called, e.g. by
+    /// being passed to `map`.
+    Escapes,
+    /// no list parameter is shorter in every recursive call.
+    NotStructural,
+}
+
+/// check that each `fix` in the program's `defn`s & body is structurally
+/// recursive, where `destructors` name the functions which shorten a list. a
+/// program which passes may be reduced without limits on its evaluation.
+pub fn check_termination(prog: &Program, destructors: &[Name]) -> Result<(), Vec<Recursion>> {
+    let mut found = check_defns(&prog.p_defns, destructors);
+    let destructors = unshadowed(&prog.p_defns, destructors);
+    found.extend(check_expr(None, &prog.p_body, &destructors));
+    if found.is_empty() {
+        Ok(())
+    } else {
+        Err(found)
+    }
+}
+
+/// as `check_termination`, for the `defn`s of a module.
+pub fn check_module_termination(
+    module: &Module,
+    destructors: &[Name],
+) -> Result<(), Vec<Recursion>> {
+    let found = check_defns(&module.m_defns, destructors);
+    if found.is_empty() {
+        Ok(())
+    } else {
+        Err(found)
+    }
+}
+
+// each `defn` may shadow a destructor, for those after it.
+fn check_defns(defns: &[Defn], destructors: &[Name]) -> Vec<Recursion> {
+    let mut found = Vec::new();
+    for (ix, Defn(nm, bd)) in defns.iter().enumerate() {
+        let destructors = unshadowed(&defns[..ix], destructors);
+        found.extend(check_expr(Some(nm), bd, &destructors));
+    }
+    found
+}
+
+fn unshadowed(defns: &[Defn], destructors: &[Name]) -> Vec<Name> {
+    destructors
+        .iter()
+        .filter(|nm| !defns.iter().any(|Defn(defn, _bd)| defn == *nm))
+        .cloned()
+        .collect()
+}
+
+// check each `fix` in `expr`.
+fn check_expr(defn: Option<&Name>, expr: &Expr, destructors: &[Name]) -> Vec<Recursion> {
+    let mut found = Vec::new();
+    let mut checker = Checker {
+        defn,
+        destructors: destructors.to_vec(),
+        found: &mut found,
+    };
+    checker.walk(expr);
+    found
+}
+
+struct Checker<'a> {
+    defn: Option<&'a Name>,
+    // the destructors not shadowed at the current expression.
+    destructors: Vec<Name>,
+    found: &'a mut Vec<Recursion>,
+}
+
+impl<'a> Checker<'a> {
+    fn walk(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Var(_) | Expr::Lit(_) | Expr::Prim(_) => (),
+            Expr::App(fun, arg) => {
+                self.walk(fun);
+                self.walk(arg);
+            }
+            Expr::Lam(x, bd) | Expr::Let(x, _, bd) => {
+                if let Expr::Let(_x, e, _bd) = expr {
+                    self.walk(e);
+                }
+                let shadowed = self.destructors.iter().position(|nm| nm == x);
+                let destructor = shadowed.map(|ix| self.destructors.remove(ix));
+                self.walk(bd);
+                if let (Some(ix), Some(nm)) = (shadowed, destructor) {
+                    self.destructors.insert(ix, nm);
+                }
+            }
+            Expr::If(tst, thn, els) => {
+                self.walk(tst);
+                self.walk(thn);
+                self.walk(els);
+            }
+            Expr::Fix(e) => {
+                if let Some(reason) = self.check_fix(e) {
+                    self.found.push(Recursion {
+                        defn: self.defn.cloned(),
+                        fix: expr.clone(),
+                        reason,
+                    });
+                }
+                self.walk(e);
+            }
+        }
+    }
+
+    // the reason the `fix` of `e` may not terminate, if any.
+    fn check_fix(&self, e: &Expr) -> Option<Reason> {
+        let (fun, mut body) = match e {
+            Expr::Lam(fun, body) => (fun, &**body),
+            _ => return Some(Reason::Opaque),
+        };
+        let mut params = Vec::new();
+        while let Expr::Lam(param, bd) = body {
+            params.push(param.clone());
+            body = bd;
+        }
+        // the function & its parameters shadow any destructors of their names.
+        let mut destructors = self.destructors.clone();
+        destructors.retain(|nm| nm != fun && !params.contains(nm));
+        let mut calls = Calls {
+            fun,
+            destructors,
+            facts: Facts::default(),
+            calls: Vec::new(),
+            escapes: false,
+        };
+        calls.walk(body);
+        if calls.escapes {
+            return Some(Reason::Escapes);
+        }
+        // the calls must all shorten the same parameter.
+        let structural = (0..params.len()).any(|ix| {
+            calls.calls.iter().all(|call| match call.args.get(ix) {
+                Some(arg) => call.facts.shorter(arg, &params[ix], &call.destructors),
+                None => false,
+            })
+        });
+        if structural {
+            None
+        } else {
+            Some(Reason::NotStructural)
+        }
+    }
+}
+
+// what is known of the lists at an expression.
+#[derive(Clone, Default)]
+struct Facts {
+    // the names bound to non-empty lists.
+    non_empty: Vec<Name>,
+    // the names bound to lists shorter than the list bound to another.
+    shorter: Vec<(Name, Name)>,
+    // the names which have been rebound, & so no longer refer to parameters.
+    rebound: Vec<Name>,
+}
+
+impl Facts {
+    // whether `arg` is shorter than the list bound to `param`.
+    fn shorter(&self, arg: &Expr, param: &Name, destructors: &[Name]) -> bool {
+        if self.rebound.contains(param) {
+            return false;
+        }
+        match arg {
+            Expr::Var(x) => self.shorter.contains(&(x.clone(), param.clone())),
+            Expr::App(fun, list) => match &**fun {
+                Expr::Var(d) if destructors.contains(d) => match &**list {
+                    Expr::Var(x) if x == param => self.non_empty.contains(x),
+                    list => self.shorter(list, param, destructors),
+                },
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    // forget all that is known of `x`, as it is rebound.
+    fn rebind(&mut self, x: &Name) {
+        self.non_empty.retain(|nm| nm != x);
+        self.shorter.retain(|(nm, than)| nm != x && than != x);
+        self.rebound.push(x.clone());
+    }
+}
+
+struct Call {
+    args: Vec<Expr>,
+    facts: Facts,
+    destructors: Vec<Name>,
+}
+
+// the recursive calls to `fun`.
+struct Calls<'a> {
+    fun: &'a Name,
+    destructors: Vec<Name>,
+    facts: Facts,
+    calls: Vec<Call>,
+    escapes: bool,
+}
+
+impl<'a> Calls<'a> {
+    fn walk(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Var(x) if x == self.fun => self.escapes = true,
+            Expr::Var(_) | Expr::Lit(_) | Expr::Prim(_) => (),
+            Expr::App(_, _) => {
+                let mut args = Vec::new();
+                let mut head = expr;
+                while let Expr::App(fun, arg) = head {
+                    args.push((**arg).clone());
+                    head = fun;
+                }
+                args.reverse();
+                match head {
+                    Expr::Var(x) if x == self.fun => self.calls.push(Call {
+                        args: args.clone(),
+                        facts: self.facts.clone(),
+                        destructors: self.destructors.clone(),
+                    }),
+                    head => self.walk(head),
+                }
+                for arg in args.iter() {
+                    self.walk(arg);
+                }
+            }
+            Expr::Lam(x, bd) => self.bind(x, None, bd),
+            Expr::Let(x, e, bd) => {
+                self.walk(e);
+                self.bind(x, Some(e), bd);
+            }
+            // the `else` branch of a `null` test knows the list is non-empty.
+            Expr::If(tst, thn, els) => {
+                self.walk(tst);
+                self.walk(thn);
+                match null_test(tst) {
+                    Some(x) if !self.facts.non_empty.contains(x) => {
+                        self.facts.non_empty.push(x.clone());
+                        self.walk(els);
+                        self.facts.non_empty.pop();
+                    }
+                    _ => self.walk(els),
+                }
+            }
+            Expr::Fix(e) => self.walk(e),
+        }
+    }
+
+    // walk `bd`, under which `x` is bound to `e` (if known).
+    fn bind(&mut self, x: &Name, e: Option<&Expr>, bd: &Expr) {
+        // the recursive function may be shadowed, when it can no longer be
+        // called.
+        if x == self.fun {
+            return;
+        }
+        let saved = (self.facts.clone(), self.destructors.clone());
+        self.facts.rebind(x);
+        self.destructors.retain(|nm| nm != x);
+        if let Some(e) = e {
+            let params: Vec<Name> = saved.0.non_empty.clone();
+            for param in params.iter().chain(saved.0.shorter.iter().map(|(_, p)| p)) {
+                if saved.0.shorter(e, param, &saved.1) {
+                    self.facts.shorter.push((x.clone(), param.clone()));
+                }
+            }
+        }
+        self.walk(bd);
+        self.facts = saved.0;
+        self.destructors = saved.1;
+    }
+}
+
+// the list tested by `(null x)`.
+fn null_test(tst: &Expr) -> Option<&Name> {
+    match tst {
+        Expr::App(fun, arg) => match (&**fun, &**arg) {
+            (Expr::Prim(PrimOp::Null), Expr::Var(x)) => Some(x),
+            _ => None,
+        },
+        _ => None,
+    }
+}
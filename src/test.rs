@@ -1,5 +1,368 @@
+// the modules here are only compiled for tests, as are the generators &
+// helpers which they share.
+#![cfg(test)]
+
 #[cfg(test)]
 pub mod parse_pretty;
 
 #[cfg(test)]
 pub mod syntax;
+
+#[cfg(test)]
+pub mod module;
+
+#[cfg(test)]
+pub mod prelude;
+
+#[cfg(test)]
+pub mod toplevel;
+
+#[cfg(all(test, feature = "serde"))]
+pub mod json;
+
+#[cfg(all(test, feature = "derive"))]
+pub mod convert;
+
+#[cfg(test)]
+pub mod native;
+
+#[cfg(test)]
+pub mod engine;
+
+#[cfg(test)]
+pub mod vm;
+
+#[cfg(test)]
+pub mod cek;
+
+#[cfg(test)]
+pub mod lazy;
+
+#[cfg(test)]
+pub mod trace;
+
+#[cfg(test)]
+pub mod provenance;
+
+#[cfg(test)]
+pub mod cost;
+
+#[cfg(test)]
+pub mod termination;
+
+#[cfg(test)]
+pub mod optimize;
+
+#[cfg(test)]
+pub mod specialize;
+
+#[cfg(test)]
+pub mod normalize;
+
+#[cfg(test)]
+pub mod core;
+
+#[cfg(test)]
+pub mod lower;
+
+use std::fmt;
+
+use quickcheck::{empty_shrinker, Arbitrary, Gen};
+use rand::Rng;
+
+use crate::eval::Value::{self, VBool, VInt, VList};
+use crate::syntax::{Defn, Expr, Lit, Name, Param, PrimOp, Program};
+use crate::types::{type_bool, type_int};
+use crate::util::pretty::to_pretty;
+use crate::{app, lam};
+
+pub fn ppr(val: &Value) -> String {
+    to_pretty(val.ppr(), 80)
+}
+
+pub fn ints(xs: &[i64]) -> Value {
+    VList(xs.iter().map(|x| VInt(*x)).collect())
+}
+
+/// a closed, well typed expression, whose evaluation terminates.
+#[derive(Clone, Debug)]
+pub struct Typed(pub Expr);
+
+impl Arbitrary for Typed {
+    fn arbitrary<G: Gen>(g: &mut G) -> Typed {
+        let ty = Ty::arbitrary(g);
+        let size = std::cmp::min(g.size(), 40);
+        Typed(gen_typed(g, &mut Vec::new(), ty, size))
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Typed>> {
+        empty_shrinker()
+    }
+}
+
+/// a well typed program, with its inputs, whose evaluation terminates. its
+/// `defn`s & parameters often shadow one another, & refer to earlier ones.
+#[derive(Clone)]
+pub struct TypedProgram(pub Program, pub Vec<Value>);
+
+// `Value` is not `Debug`, so a failing case is shown pretty printed.
+impl fmt::Debug for TypedProgram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let TypedProgram(prog, inputs) = self;
+        let inputs: Vec<String> = inputs.iter().map(ppr).collect();
+        write!(
+            f,
+            "{}\ninputs: {}",
+            to_pretty(prog.ppr(), 80),
+            inputs.join(" ")
+        )
+    }
+}
+
+impl Arbitrary for TypedProgram {
+    fn arbitrary<G: Gen>(g: &mut G) -> TypedProgram {
+        let size = std::cmp::min(g.size(), 20);
+        let mut scope = Vec::new();
+        let mut defns = Vec::new();
+        for _ in 0..g.gen_range(0, 5) {
+            let nm = gen_name(g);
+            let ty = Ty::arbitrary(g);
+            defns.push(Defn(nm.clone(), gen_typed(g, &mut scope, ty, size)));
+            scope.push((nm, ty));
+        }
+        let mut params: Vec<Param> = Vec::new();
+        let mut inputs = Vec::new();
+        for _ in 0..g.gen_range(0, 3) {
+            let nm = gen_name(g);
+            if params.iter().any(|Param(prev, _ty)| *prev == nm) {
+                continue;
+            }
+            if bool::arbitrary(g) {
+                params.push(Param(nm.clone(), type_int()));
+                inputs.push(VInt(g.gen_range(-10, 10)));
+                scope.push((nm, Ty::Int));
+            } else {
+                params.push(Param(nm.clone(), type_bool()));
+                inputs.push(VBool(bool::arbitrary(g)));
+                scope.push((nm, Ty::Bool));
+            }
+        }
+        // the result is not a function, so that it may be compared.
+        let ty = [Ty::Int, Ty::Bool, Ty::List, Ty::Pair][g.gen_range(0, 4)];
+        let prog = Program {
+            p_imports: vec![],
+            p_defns: defns,
+            p_params: if params.is_empty() {
+                None
+            } else {
+                Some(params)
+            },
+            p_body: gen_typed(g, &mut scope, ty, size),
+        };
+        TypedProgram(prog, inputs)
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = TypedProgram>> {
+        empty_shrinker()
+    }
+}
+
+// the types of the generated expressions. `Pair` is `(Int, Bool)`, & `Fun`
+// is `(Int -> Int)`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Ty {
+    Int,
+    Bool,
+    List,
+    Pair,
+    Fun,
+}
+
+impl Ty {
+    fn arbitrary<G: Gen>(g: &mut G) -> Ty {
+        match g.gen_range(0, 5) {
+            0 => Ty::Int,
+            1 => Ty::Bool,
+            2 => Ty::List,
+            3 => Ty::Pair,
+            4 => Ty::Fun,
+            _ => panic!("impossible: Arbitrary: Ty: gen out of bounds"),
+        }
+    }
+}
+
+// a small pool of names, so that bindings often shadow one another.
+fn gen_name<G: Gen>(g: &mut G) -> Name {
+    const NAMES: [&str; 4] = ["a", "b", "c", "d"];
+    Name(NAMES[g.gen_range(0, NAMES.len())].to_string())
+}
+
+fn var(nm: &str) -> Expr {
+    Expr::Var(Name(nm.to_string()))
+}
+
+fn prim(op: PrimOp) -> Expr {
+    Expr::Prim(op)
+}
+
+fn int(n: i64) -> Expr {
+    Expr::Lit(Lit::LInt(n))
+}
+
+// the variables in scope of type `ty`, taking shadowing into account.
+fn vars_of(scope: &[(Name, Ty)], ty: Ty) -> Vec<Name> {
+    let mut seen: Vec<&Name> = Vec::new();
+    let mut vars = Vec::new();
+    for (nm, nm_ty) in scope.iter().rev() {
+        if !seen.contains(&nm) {
+            seen.push(nm);
+            if *nm_ty == ty {
+                vars.push(nm.clone());
+            }
+        }
+    }
+    vars
+}
+
+fn gen_typed<G: Gen>(g: &mut G, scope: &mut Vec<(Name, Ty)>, ty: Ty, size: usize) -> Expr {
+    let vars = vars_of(scope, ty);
+    if !vars.is_empty() && g.gen_range(0, 4) == 0 {
+        return Expr::Var(vars[g.gen_range(0, vars.len())].clone());
+    }
+    if size < 1 {
+        return gen_leaf(g, ty);
+    }
+    match g.gen_range(0, 6) {
+        // forms common to all types.
+        0 => {
+            let nm = gen_name(g);
+            let e_ty = Ty::arbitrary(g);
+            let e = gen_typed(g, scope, e_ty, size / 2);
+            scope.push((nm.clone(), e_ty));
+            let bd = gen_typed(g, scope, ty, size / 2);
+            scope.pop();
+            Expr::Let(nm, Box::new(e), Box::new(bd))
+        }
+        1 => {
+            let tst = gen_typed(g, scope, Ty::Bool, size / 3);
+            let thn = gen_typed(g, scope, ty, size / 3);
+            let els = gen_typed(g, scope, ty, size / 3);
+            Expr::If(Box::new(tst), Box::new(thn), Box::new(els))
+        }
+        2 => {
+            let nm = gen_name(g);
+            let arg_ty = Ty::arbitrary(g);
+            let arg = gen_typed(g, scope, arg_ty, size / 2);
+            scope.push((nm.clone(), arg_ty));
+            let bd = gen_typed(g, scope, ty, size / 2);
+            scope.pop();
+            app!(lam!(nm, bd), arg)
+        }
+        _ => gen_specific(g, scope, ty, size),
+    }
+}
+
+fn gen_leaf<G: Gen>(g: &mut G, ty: Ty) -> Expr {
+    match ty {
+        Ty::Int => int(g.gen_range(-10, 10)),
+        Ty::Bool => Expr::Lit(Lit::LBool(bool::arbitrary(g))),
+        Ty::List => prim(PrimOp::Nil),
+        Ty::Pair => {
+            let a = gen_leaf(g, Ty::Int);
+            let b = gen_leaf(g, Ty::Bool);
+            app!(app!(prim(PrimOp::Pair), a), b)
+        }
+        // a partially applied PrimOp.
+        Ty::Fun => app!(prim(PrimOp::Sub), gen_leaf(g, Ty::Int)),
+    }
+}
+
+fn gen_specific<G: Gen>(g: &mut G, scope: &mut Vec<(Name, Ty)>, ty: Ty, size: usize) -> Expr {
+    let half = size / 2;
+    match ty {
+        Ty::Int => match g.gen_range(0, 7) {
+            0 => {
+                let op = [PrimOp::Add, PrimOp::Sub][g.gen_range(0, 2)].clone();
+                let a = gen_typed(g, scope, Ty::Int, half);
+                let b = gen_typed(g, scope, Ty::Int, half);
+                app!(app!(prim(op), a), b)
+            }
+            // multiplication by a small constant keeps results in range.
+            1 => {
+                let a = gen_typed(g, scope, Ty::Int, half);
+                app!(app!(prim(PrimOp::Mul), a), int(g.gen_range(-2, 3)))
+            }
+            2 => {
+                let f = gen_typed(g, scope, Ty::Fun, half);
+                let a = gen_typed(g, scope, Ty::Int, half);
+                app!(f, a)
+            }
+            3 => app!(prim(PrimOp::Fst), gen_typed(g, scope, Ty::Pair, size)),
+            // a bare PrimOp, as an argument.
+            4 => {
+                let l = gen_typed(g, scope, Ty::List, half);
+                let z = gen_typed(g, scope, Ty::Int, half);
+                app!(app!(app!(prim(PrimOp::Foldl), prim(PrimOp::Add)), z), l)
+            }
+            // a bare PrimOp, bound to a name.
+            5 => {
+                let a = gen_typed(g, scope, Ty::Int, half);
+                let b = gen_typed(g, scope, Ty::Int, half);
+                let op = Name("op".to_string());
+                let bd = app!(app!(Expr::Var(op.clone()), a), b);
+                Expr::Let(op, Box::new(prim(PrimOp::Sub)), Box::new(bd))
+            }
+            // bounded recursion.
+            _ => {
+                let base = gen_typed(g, scope, Ty::Int, half);
+                scope.push((Name("n".to_string()), Ty::Int));
+                let step = gen_typed(g, scope, Ty::Int, half);
+                scope.pop();
+                let rec = app!(var("f"), app!(app!(prim(PrimOp::Sub), var("n")), int(1)));
+                let bd = Expr::If(
+                    Box::new(app!(app!(prim(PrimOp::Eql), var("n")), int(0))),
+                    Box::new(base),
+                    Box::new(app!(app!(prim(PrimOp::Add), step), rec)),
+                );
+                let fun = lam!(Name("f".to_string()), lam!(Name("n".to_string()), bd));
+                app!(Expr::Fix(Box::new(fun)), int(g.gen_range(0, 5)))
+            }
+        },
+        Ty::Bool => match g.gen_range(0, 3) {
+            0 => {
+                let a = gen_typed(g, scope, Ty::Int, half);
+                let b = gen_typed(g, scope, Ty::Int, half);
+                app!(app!(prim(PrimOp::Eql), a), b)
+            }
+            1 => app!(prim(PrimOp::Null), gen_typed(g, scope, Ty::List, size)),
+            _ => app!(prim(PrimOp::Snd), gen_typed(g, scope, Ty::Pair, size)),
+        },
+        Ty::List => match g.gen_range(0, 2) {
+            0 => {
+                let a = gen_typed(g, scope, Ty::Int, half);
+                let l = gen_typed(g, scope, Ty::List, half);
+                app!(app!(prim(PrimOp::Cons), a), l)
+            }
+            _ => {
+                let f = gen_typed(g, scope, Ty::Fun, half);
+                let l = gen_typed(g, scope, Ty::List, half);
+                app!(app!(prim(PrimOp::Map), f), l)
+            }
+        },
+        Ty::Pair => {
+            let a = gen_typed(g, scope, Ty::Int, half);
+            let b = gen_typed(g, scope, Ty::Bool, half);
+            app!(app!(prim(PrimOp::Pair), a), b)
+        }
+        Ty::Fun => match g.gen_range(0, 2) {
+            0 => {
+                let nm = gen_name(g);
+                scope.push((nm.clone(), Ty::Int));
+                let bd = gen_typed(g, scope, Ty::Int, size);
+                scope.pop();
+                lam!(nm, bd)
+            }
+            _ => app!(prim(PrimOp::Add), gen_typed(g, scope, Ty::Int, size)),
+        },
+    }
+}
@@ -0,0 +1,136 @@
+pub mod cek_unit {
+    use crate::{
+        cek::{eval_cek, Machine},
+        eval::{eval_, EvalError, EvalState, Limits, TermEnv, Value},
+        parse::parse_expr,
+        prelude::prelude,
+        syntax::Name,
+        test::{ppr, Typed},
+    };
+
+    const COUNT_DOWN: &str = "((fix (lam [f n] (if (== n 0) 0 (+ 1 (f (- n 1)))))) 10000)";
+
+    fn machine(src: &str) -> Machine {
+        Machine::new(prelude().term_env.clone(), parse_expr(src).unwrap())
+    }
+
+    #[test]
+    fn agrees_with_eval() {
+        let cases = [
+            "(sum (map (+ 1) (list 1 2 3)))",
+            "(foldl - 0 (list 1 2 3))",
+            "(let ([p pair]) (p 1 true))",
+            "(map fst (list (pair 1 true) (pair 2 false)))",
+            "(filter (lam [x] (not (== x 2))) (list 1 2 3))",
+            "((compose (* 2) (+ 1)) 3)",
+            "((fix (lam [f n] (if (== n 0) 0 (+ 1 (f (- n 1)))))) 50)",
+        ];
+        let env = &prelude().term_env;
+        for src in cases.iter() {
+            let expr = parse_expr(src).unwrap();
+            let expected = eval_(env, &mut EvalState::new(), &expr).ok().unwrap();
+            let actual = eval_cek(env, &mut EvalState::new(), &expr).ok().unwrap();
+            assert_eq!(ppr(&expected), ppr(&actual), "in: {}", src);
+        }
+    }
+
+    #[test]
+    fn deep_recursion_in_constant_native_stack() {
+        let val = machine(COUNT_DOWN).run().ok().unwrap();
+        assert_eq!(ppr(&val), "10000");
+        // a long fold, through a closure from the prelude.
+        let xs = Value::VList((1..=10000).map(Value::VInt).collect());
+        let env = prelude().term_env.update(Name("xs".to_string()), xs);
+        let mut m = Machine::new(env, parse_expr("(length (map (+ 1) xs))").unwrap());
+        assert_eq!(ppr(&m.run().ok().unwrap()), "10000");
+        // a function from a strictly evaluated `defn`, whose body is core, on
+        // a small native stack.
+        let count = parse_expr("(fix (lam [f n] (if (== n 0) 0 (+ 1 (f (- n 1))))))").unwrap();
+        let count = eval_(&TermEnv::new(), &mut EvalState::new(), &count)
+            .ok()
+            .unwrap();
+        assert!(matches!(count, Value::VCore(_, _, _, _)));
+        let env = TermEnv::new().update(Name("count".to_string()), count);
+        let mut m = Machine::new(env, parse_expr("(count 20000)").unwrap());
+        let val = std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(move || m.run().ok().map(|val| ppr(&val)))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(val.unwrap(), "20000");
+    }
+
+    #[test]
+    fn tail_calls_take_no_continuations() {
+        let src = "((fix (lam [f n] (if (== n 0) 0 (f (- n 1))))) 1000)";
+        let mut m = machine(src);
+        let mut max_depth = 0;
+        while !m.is_done() {
+            m.step().unwrap();
+            max_depth = std::cmp::max(max_depth, m.depth());
+        }
+        assert!(
+            max_depth < 10,
+            "expected a bounded depth, got: {}",
+            max_depth
+        );
+    }
+
+    #[test]
+    fn pause_and_resume() {
+        let mut m = machine("(sum (map (* 2) (list 1 2 3)))");
+        assert!(m.run_for(10).unwrap().is_none());
+        // a paused machine may be cloned, & each copy resumed independently.
+        let mut copy = m.clone();
+        assert!(m.run_for(5).unwrap().is_none());
+        assert_eq!(ppr(&m.run().ok().unwrap()), "12");
+        assert_eq!(ppr(&copy.run().ok().unwrap()), "12");
+        // a machine which is done stays done.
+        assert_eq!(ppr(&m.run_for(10).unwrap().unwrap()), "12");
+    }
+
+    #[test]
+    fn limits() {
+        let limits = Limits {
+            max_steps: Some(1000),
+            ..Limits::default()
+        };
+        let mut es = EvalState::with_limits(limits);
+        let expr = parse_expr("((fix (lam [f x] (f x))) 1)").unwrap();
+        match eval_cek(&TermEnv::new(), &mut es, &expr) {
+            Err(EvalError::StepLimitExceeded(1000)) => (),
+            res => panic!(
+                "expected step limit error, got: {:?}",
+                res.as_ref().map(ppr)
+            ),
+        }
+        // a machine which exceeds its limits may be resumed under raised ones.
+        let limits = Limits {
+            max_depth: Some(100),
+            ..Limits::default()
+        };
+        let mut m = Machine::with_state(
+            prelude().term_env.clone(),
+            parse_expr(COUNT_DOWN).unwrap(),
+            EvalState::with_limits(limits),
+        );
+        match m.run() {
+            Err(EvalError::DepthLimitExceeded(100)) => (),
+            res => panic!(
+                "expected depth limit error, got: {:?}",
+                res.as_ref().map(ppr)
+            ),
+        }
+        m.set_limits(Limits::default());
+        assert_eq!(ppr(&m.run().ok().unwrap()), "10000");
+    }
+
+    #[quickcheck]
+    fn cek_agrees_with_eval(Typed(e): Typed) -> bool {
+        let env = TermEnv::new();
+        let expected = eval_(&env, &mut EvalState::new(), &e).ok().unwrap();
+        let actual = eval_cek(&env, &mut EvalState::new(), &e).ok().unwrap();
+        ppr(&expected) == ppr(&actual)
+    }
+}
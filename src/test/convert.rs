@@ -0,0 +1,126 @@
+pub mod convert_unit {
+    use crate::{
+        convert::{FromValue, IntoValue, PolyType},
+        eval::Value::*,
+        parse::{parse_program, parse_type},
+        toplevel::{compile_calculation, ReputationCalculationError},
+        types::Type,
+        util::pretty::to_pretty,
+    };
+
+    #[derive(Clone, Debug, PartialEq, PolyType, IntoValue, FromValue)]
+    struct Rating {
+        score: i64,
+        verified: bool,
+        tags: Vec<i64>,
+    }
+
+    #[derive(Debug, PartialEq, PolyType, IntoValue, FromValue)]
+    struct Weight(i64);
+
+    #[derive(Debug, PartialEq, PolyType, IntoValue, FromValue)]
+    struct Tagged<T> {
+        tag: i64,
+        item: T,
+    }
+
+    fn ty(s: &str) -> Type {
+        parse_type(s).unwrap()
+    }
+
+    fn roundtrip<T: IntoValue + FromValue + Clone + PartialEq + std::fmt::Debug>(x: T) {
+        assert_eq!(T::from_value(x.clone().into_value()).unwrap(), x);
+    }
+
+    #[test]
+    fn primitives_and_containers() {
+        assert_eq!(<(i64, Vec<bool>)>::poly_type(), ty("(Int, (List Bool))"));
+        assert_eq!(<Option<i64>>::poly_type(), ty("(List Int)"));
+        assert_eq!(<(i64, bool, i64)>::poly_type(), ty("(Int, (Bool, Int))"));
+        let val = (1i64, vec![true, false], Some(3i64), None::<bool>).into_value();
+        assert_eq!(
+            to_pretty(val.ppr(), 80),
+            "(1, ((list true false), ((list 3), (list))))"
+        );
+        roundtrip(-7i64);
+        roundtrip(vec![(1i64, true), (2, false)]);
+        roundtrip((Some(1i64), None::<i64>, vec![vec![true]], 4i64, false));
+    }
+
+    #[test]
+    fn mismatches() {
+        let err = i64::from_value(VBool(true)).unwrap_err();
+        assert_eq!(err.to_string(), "expected a value of type Int, found true");
+        assert!(<Option<i64>>::from_value(VList(vec![VInt(1), VInt(2)])).is_err());
+        assert!(<(i64, i64)>::from_value(VList(vec![])).is_err());
+        assert!(<Vec<bool>>::from_value(VList(vec![VBool(true), VInt(1)])).is_err());
+    }
+
+    #[test]
+    fn derived() {
+        assert_eq!(Rating::poly_type(), ty("(Int, (Bool, (List Int)))"));
+        assert_eq!(Weight::poly_type(), ty("Int"));
+        assert_eq!(<Tagged<bool>>::poly_type(), ty("(Int, Bool)"));
+        let r = Rating {
+            score: 4,
+            verified: true,
+            tags: vec![1, 2],
+        };
+        assert_eq!(
+            to_pretty(r.clone().into_value().ppr(), 80),
+            "(4, (true, (list 1 2)))"
+        );
+        roundtrip(r);
+        assert_eq!(Weight::from_value(VInt(3)).unwrap(), Weight(3));
+        assert_eq!(
+            <Tagged<Weight>>::from_value(
+                Tagged {
+                    tag: 1,
+                    item: Weight(2)
+                }
+                .into_value()
+            )
+            .unwrap(),
+            Tagged {
+                tag: 1,
+                item: Weight(2)
+            }
+        );
+    }
+
+    #[test]
+    fn check_before_reduce() {
+        let prog = parse_program(
+            "(params [ratings (List (Int, (Bool, (List Int))))] [weight Int])
+
+(* weight (sum (map (lam [r] (fst r)) ratings)))",
+        )
+        .unwrap();
+        let compiled = compile_calculation(prog).unwrap();
+        compiled
+            .check_input_types(&[<Vec<Rating>>::poly_type(), Weight::poly_type()])
+            .unwrap();
+        match compiled.check_input_types(&[<Vec<Rating>>::poly_type(), bool::poly_type()]) {
+            Err(ReputationCalculationError::ParameterTypeError(nm, _)) => {
+                assert_eq!(nm.0, "weight")
+            }
+            res => panic!("expected parameter type error, got: {:?}", res),
+        }
+
+        let ratings = vec![
+            Rating {
+                score: 2,
+                verified: true,
+                tags: vec![],
+            },
+            Rating {
+                score: 5,
+                verified: false,
+                tags: vec![3],
+            },
+        ];
+        let inputs = vec![ratings.into_value(), Weight(3).into_value()];
+        let out = compiled.reduce(&mut inputs.into_iter()).unwrap();
+        assert_eq!(i64::from_value(out.value).unwrap(), 21);
+    }
+}
@@ -0,0 +1,210 @@
+pub mod core_unit {
+    use quickcheck_macros::quickcheck;
+
+    use crate::{
+        core::{resolve, Core},
+        eval::{apply, eval_, named_closure, EvalState, Locals, Value},
+        normalize::alpha_eq,
+        parse::parse_expr,
+        prelude::prelude,
+        syntax::{Lit, Name},
+        test::Typed,
+        util::pretty::to_pretty,
+    };
+
+    fn nm(s: &str) -> Name {
+        Name(s.to_string())
+    }
+
+    fn core(src: &str) -> Core {
+        resolve(&parse_expr(src).unwrap())
+    }
+
+    fn named(core: &Core) -> String {
+        to_pretty(core.to_expr().ppr(), 80)
+    }
+
+    #[test]
+    fn resolves_bound_variables() {
+        let lam = |x: &str, bd| Core::Lam(nm(x), Box::new(bd));
+        let app = |f, a| Core::App(Box::new(f), Box::new(a));
+        assert_eq!(
+            core("(lam [x y] (x z))"),
+            lam("x", lam("y", app(Core::Local(1), Core::Global(nm("z")))))
+        );
+        // the innermost binder of a name is the one referred to.
+        assert_eq!(core("(lam [x x] x)"), lam("x", lam("x", Core::Local(0))));
+        assert_eq!(
+            core("(let ([x 1]) (let ([y x]) x))"),
+            Core::Let(
+                nm("x"),
+                Box::new(Core::Lit(Lit::LInt(1))),
+                Box::new(Core::Let(
+                    nm("y"),
+                    Box::new(Core::Local(0)),
+                    Box::new(Core::Local(1))
+                ))
+            )
+        );
+    }
+
+    #[test]
+    fn substitution_renames_only_to_avoid_capture() {
+        // `(lam [y] x)`, with the global `y` substituted for `x`.
+        let body = match core("(lam [x] (lam [y] x))") {
+            Core::Lam(_x, bd) => bd,
+            other => panic!("expected a lambda, got: {:?}", other),
+        };
+        assert_eq!(
+            named(&body.instantiate(&Core::Global(nm("y")))),
+            "(lam [ya] y)"
+        );
+        assert_eq!(
+            named(&body.instantiate(&Core::Global(nm("z")))),
+            "(lam [y] z)"
+        );
+        // a shadowed binder keeps its name, unless the outer one is used.
+        assert_eq!(
+            named(&core("(lam [x] (lam [x] x))")),
+            "(lam [x] (lam [x] x))"
+        );
+        let shadowing = Core::Lam(
+            nm("x"),
+            Box::new(Core::Lam(nm("x"), Box::new(Core::Local(1)))),
+        );
+        assert_eq!(named(&shadowing), "(lam [x] (lam [xa] x))");
+    }
+
+    #[test]
+    fn renamed_binders_may_be_parsed() {
+        let body = match core("(lam [x] (lam [y] (lam [ya] (x ya))))") {
+            Core::Lam(_x, bd) => bd,
+            other => panic!("expected a lambda, got: {:?}", other),
+        };
+        let renamed = body.instantiate(&Core::Global(nm("y")));
+        assert_eq!(named(&renamed), "(lam [ya] (lam [ya] (y ya)))");
+        let shadowing = Core::Lam(
+            nm("x"),
+            Box::new(Core::Lam(
+                nm("x"),
+                Box::new(Core::App(
+                    Box::new(Core::Local(1)),
+                    Box::new(Core::Local(0)),
+                )),
+            )),
+        );
+        for core in [renamed, shadowing] {
+            let expr = core.to_expr();
+            let parsed = parse_expr(&to_pretty(expr.ppr(), 80)).unwrap();
+            assert!(alpha_eq(&parsed, &expr), "in: {}", named(&core));
+        }
+    }
+
+    #[test]
+    fn shifts_free_locals_only() {
+        let open = Core::Lam(
+            nm("y"),
+            Box::new(Core::App(
+                Box::new(Core::Local(0)),
+                Box::new(Core::Local(1)),
+            )),
+        );
+        assert_eq!(open.free_locals().into_iter().collect::<Vec<_>>(), vec![0]);
+        let shifted = open.shift(2);
+        assert_eq!(
+            shifted.free_locals().into_iter().collect::<Vec<_>>(),
+            vec![2]
+        );
+        assert_eq!(shifted.occurrences(2), 1);
+        assert_eq!(open.occurrences(0), 1);
+    }
+
+    #[test]
+    fn strict_evaluation_needs_no_fresh_names() {
+        let env = &prelude().term_env;
+        let mut es = EvalState::new();
+        let src = "((fix (lam [f n] (if (== n 0) (map (+ n) nil) (f (- n 1))))) 3)";
+        let val = eval_(env, &mut es, &parse_expr(src).unwrap()).ok().unwrap();
+        assert_eq!(to_pretty(val.ppr(), 80), "(list)");
+        assert_eq!(es.fresh(), nm("_1"));
+    }
+
+    #[test]
+    fn evaluation_of_core_agrees_with_traced_evaluation() {
+        let env = &prelude().term_env;
+        for src in [
+            "(sum (map (lam [x] (* x x)) (list 1 2 3)))",
+            "(let ([y 2]) ((lam [x y] (+ x y)) y 3))",
+            "(let ([p pair]) (fst (p 1 true)))",
+            "((fix (lam [f n] (if (== n 0) 0 (+ 2 (f (- n 1)))))) 4)",
+            "(foldl (lam [acc x] (if (== x 2) acc (+ acc x))) 0 (list 1 2 3))",
+        ]
+        .iter()
+        {
+            let expr = parse_expr(src).unwrap();
+            let mut es = EvalState::new();
+            let val = eval_(env, &mut es, &expr).ok().unwrap();
+            let mut traced_es = EvalState::new();
+            traced_es.start_trace();
+            let traced = eval_(env, &mut traced_es, &expr).ok().unwrap();
+            assert_eq!(
+                to_pretty(val.ppr(), 80),
+                to_pretty(traced.ppr(), 80),
+                "in: {}",
+                src
+            );
+            assert_eq!(es.steps(), traced_es.steps(), "in: {}", src);
+            assert_eq!(es.gas(), traced_es.gas(), "in: {}", src);
+        }
+    }
+
+    #[test]
+    fn core_closures_are_named_without_capture() {
+        let env = &prelude().term_env;
+        let src = "(let ([y 1]) (let ([x 2]) (lam [x] (+ x y))))";
+        let clo = eval_(env, &mut EvalState::new(), &parse_expr(src).unwrap())
+            .ok()
+            .unwrap();
+        let (nm, bd, clo_env) = match &clo {
+            Value::VCore(nm, bd, locals, globals) => named_closure(nm, bd, locals, globals),
+            _ => panic!("expected a core closure, got: {}", to_pretty(clo.ppr(), 80)),
+        };
+        assert_eq!(to_pretty(bd.ppr(), 80), format!("((+ {}) y)", nm.0));
+        let named = Value::VClosure(nm, bd, clo_env);
+        for fun in [clo, named].iter() {
+            let val = apply(&mut EvalState::new(), fun.clone(), Value::VInt(5))
+                .ok()
+                .unwrap();
+            assert_eq!(to_pretty(val.ppr(), 80), "6");
+        }
+    }
+
+    #[test]
+    fn locals_are_indexed_innermost_first() {
+        let mut locals = Locals::new();
+        let mut scopes = vec![locals.clone()];
+        for x in 0..100 {
+            locals = locals.push(nm(&x.to_string()), Value::VInt(x));
+            scopes.push(locals.clone());
+        }
+        // each scope is unchanged by the binders pushed onto it.
+        for (len, scope) in scopes.iter().enumerate() {
+            for ix in 0..len {
+                match scope.get(ix) {
+                    Some(Value::VInt(x)) => assert_eq!(*x as usize, len - 1 - ix),
+                    _ => panic!("expected local {} of {}", ix, len),
+                }
+            }
+            assert!(scope.get(len).is_none());
+            let names: Vec<String> = scope.iter().map(|(x, _val)| x.0.clone()).collect();
+            let expected: Vec<String> = (0..len).rev().map(|x| x.to_string()).collect();
+            assert_eq!(names, expected);
+        }
+    }
+
+    #[quickcheck]
+    fn resolution_round_trips(Typed(e): Typed) -> bool {
+        let round_trip = resolve(&e).to_expr();
+        alpha_eq(&round_trip, &e) && resolve(&round_trip) == resolve(&e)
+    }
+}
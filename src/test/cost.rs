@@ -0,0 +1,170 @@
+pub mod cost_unit {
+    use crate::{
+        cek::eval_cek,
+        cost::{BoundError, CostTable},
+        eval::{eval_, EvalState, Strategy, TermEnv, Value, Value::*},
+        parse::{parse_expr, parse_program},
+        prelude::prelude,
+        test::ints,
+        toplevel::{compile_calculation, compile_calculation_with, CalculationOptions},
+    };
+
+    const PROGRAMS: [&str; 5] = [
+        "(lam [x] (+ x 1))",
+        "(lam [xs] (map (lam [x] (if (== x 0) 1 (* x x))) xs))",
+        "(lam [xs] (foldl (lam [acc x] (cons x acc)) nil xs))",
+        "(lam [xs ys] (pair (foldl + 0 xs) (sum (map fst ys))))",
+        r#"
+(defn bonus 10)
+
+(defn weigh
+  (lam [w x] (* w x)))
+
+(lam [w ratings]
+  (+ bonus (foldl (lam [acc x] (+ acc (weigh w x))) 0 ratings)))
+"#,
+    ];
+
+    fn inputs(src: &str, len: i64) -> Vec<Value> {
+        let xs: Vec<i64> = (0..len).collect();
+        if src.contains("ys") {
+            let ys = (0..len)
+                .map(|x| VPair(Box::new(VInt(x)), Box::new(VBool(true))))
+                .collect();
+            vec![ints(&xs), VList(ys)]
+        } else if src.contains("ratings") {
+            vec![VInt(3), ints(&xs)]
+        } else if src.contains("xs") {
+            vec![ints(&xs)]
+        } else {
+            vec![VInt(len)]
+        }
+    }
+
+    fn gas(src: &str, len: i64) -> u64 {
+        let calc = compile_calculation(parse_program(src).unwrap()).unwrap();
+        match calc.reduce(&mut inputs(src, len).into_iter()) {
+            Ok(output) => output.gas,
+            Err(err) => panic!("expected an output, got: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn gas_is_deterministic() {
+        for src in PROGRAMS.iter() {
+            assert!(gas(src, 3) > 0, "in: {}", src);
+            assert_eq!(gas(src, 3), gas(src, 3), "in: {}", src);
+        }
+        // each element costs more gas.
+        assert!(gas(PROGRAMS[1], 4) > gas(PROGRAMS[1], 3));
+    }
+
+    #[test]
+    fn gas_follows_the_cost_table() {
+        let src = PROGRAMS[3];
+        let options = CalculationOptions {
+            costs: CostTable {
+                element: 100,
+                ..CostTable::default()
+            },
+            ..CalculationOptions::default()
+        };
+        let calc = compile_calculation_with(parse_program(src).unwrap(), &options).unwrap();
+        let output = calc.reduce(&mut inputs(src, 2).into_iter()).unwrap();
+        // `foldl`, `map` & `sum`'s `foldl` each process two elements.
+        assert_eq!(output.gas, gas(src, 2) + 6 * 99);
+    }
+
+    #[test]
+    fn gas_saturates() {
+        for strategy in [Strategy::Strict, Strategy::Lazy] {
+            for src in [PROGRAMS[1], PROGRAMS[2]] {
+                let options = CalculationOptions {
+                    costs: CostTable {
+                        map: u64::MAX,
+                        element: u64::MAX,
+                        ..CostTable::default()
+                    },
+                    strategy,
+                    ..CalculationOptions::default()
+                };
+                let calc = compile_calculation_with(parse_program(src).unwrap(), &options).unwrap();
+                let output = calc.reduce(&mut inputs(src, 3).into_iter()).unwrap();
+                assert_eq!(output.gas, u64::MAX, "in: {}", src);
+            }
+        }
+    }
+
+    #[test]
+    fn evaluators_agree_on_gas() {
+        let env = &prelude().term_env;
+        for src in [
+            "(sum (map (lam [x] (* x x)) (list 1 2 3)))",
+            "(let ([f (lam [p] (fst p))]) (f (pair 1 (cons 2 nil))))",
+            "(foldl (lam [acc x] (if (== x 2) acc (+ acc x))) 0 (list 1 2 3))",
+            "(map (+ 1) (list 1 2))",
+        ]
+        .iter()
+        {
+            let expr = parse_expr(src).unwrap();
+            let mut es = EvalState::new();
+            eval_(env, &mut es, &expr).ok().unwrap();
+            let mut cek_es = EvalState::new();
+            eval_cek(env, &mut cek_es, &expr).ok().unwrap();
+            assert_eq!(es.gas(), cek_es.gas(), "in: {}", src);
+        }
+        let mut es = EvalState::new();
+        eval_(&TermEnv::new(), &mut es, &parse_expr("1").unwrap())
+            .ok()
+            .unwrap();
+        assert_eq!(es.gas(), 0);
+    }
+
+    #[test]
+    fn bounds_bound_the_gas_used() {
+        for src in PROGRAMS.iter() {
+            let calc = compile_calculation(parse_program(src).unwrap()).unwrap();
+            let bound = match calc.gas_bound(5) {
+                Ok(bound) => bound,
+                Err(err) => panic!("expected a bound, got: {:?}", err),
+            };
+            for len in 0..=5 {
+                assert!(gas(src, len) <= bound, "in: {} at {}", src, len);
+            }
+            // without branches, the longest inputs reach the bound.
+            if !src.contains("if") {
+                assert_eq!(gas(src, 5), bound, "in: {}", src);
+            }
+        }
+    }
+
+    #[test]
+    fn bounds_of_long_lists() {
+        // the accumulator of `reverse` grows by an element at each step, so
+        // its bound is exact, & that of `concat` by a list.
+        let src = "(lam [xs] (reverse xs))";
+        let calc = compile_calculation(parse_program(src).unwrap()).unwrap();
+        assert_eq!(calc.gas_bound(1000).unwrap(), gas(src, 1000));
+        assert!(calc.gas_bound(100_000).unwrap() < u64::MAX);
+        let src = "(lam [xss] (concat xss))";
+        let calc = compile_calculation(parse_program(src).unwrap()).unwrap();
+        assert!(calc.gas_bound(200).unwrap() < u64::MAX);
+        let xss = VList((0..30).map(|_| ints(&[0; 30])).collect());
+        let used = calc.reduce(&mut vec![xss].into_iter()).unwrap().gas;
+        assert!(used <= calc.gas_bound(30).unwrap());
+        // one which doubles cannot be extrapolated, but is still bounded.
+        let src = "(lam [xs] (foldl (lam [acc x] (append acc acc)) (list 1) xs))";
+        let calc = compile_calculation(parse_program(src).unwrap()).unwrap();
+        assert_eq!(calc.gas_bound(100).unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn recursive_programs_have_no_bound() {
+        let src = "(lam [n] ((fix (lam [f n] (if (== n 0) 0 (f (- n 1))))) n))";
+        let calc = compile_calculation(parse_program(src).unwrap()).unwrap();
+        match calc.gas_bound(5) {
+            Err(BoundError::Recursive) => (),
+            res => panic!("expected a recursion error, got: {:?}", res),
+        }
+    }
+}
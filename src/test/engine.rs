@@ -0,0 +1,177 @@
+pub mod engine_unit {
+    use crate::{
+        engine::{Engine, EngineError},
+        eval::{EvalError, Limits, Value::*},
+        infer::TypeError,
+        module::MemoryResolver,
+        parse::{parse_defn_or_it_expr, parse_expr, parse_program},
+        syntax::{Defn, Name},
+        toplevel::{compile_calculation_with, CalculationOptions, ReputationCalculationError},
+        util::pretty::to_pretty,
+    };
+
+    const LOOP: &str = "((fix (lam [f x] (f x))) 1)";
+
+    const COUNT_DOWN: &str = "((fix (lam [f n] (if (== n 0) 0 (+ 1 (f (- n 1)))))) 50)";
+
+    fn define(engine: &mut Engine, src: &str) -> Result<(String, String), EngineError> {
+        let defn = parse_defn_or_it_expr(src).unwrap();
+        let (val, sc) = engine.define(&defn)?;
+        Ok((to_pretty(val.ppr(), 80), to_pretty(sc.ppr(), 80)))
+    }
+
+    fn limited(limits: Limits) -> Engine {
+        Engine::with_options(CalculationOptions {
+            limits,
+            ..CalculationOptions::default()
+        })
+    }
+
+    #[test]
+    fn define_check_eval() {
+        let mut engine = Engine::new();
+        assert_eq!(
+            define(&mut engine, "(defn double (lam [x] (* 2 x)))")
+                .unwrap()
+                .1,
+            "(Int -> Int)"
+        );
+        assert_eq!(
+            define(&mut engine, "(double (sum (list 1 2)))").unwrap(),
+            ("6".to_string(), "Int".to_string())
+        );
+        // expressions are bound to `it`.
+        let it = engine.check(&parse_expr("it").unwrap()).unwrap();
+        assert_eq!(to_pretty(it.ppr(), 80), "Int");
+        let (val, _) = engine.eval(&parse_expr("(double it)").unwrap()).unwrap();
+        assert_eq!(to_pretty(val.ppr(), 80), "12");
+    }
+
+    #[test]
+    fn failed_definitions_are_not_bound() {
+        let mut engine = Engine::new();
+        match define(&mut engine, "(defn bad (+ 1 true))") {
+            Err(EngineError::Type(TypeError::UnificationFail(_, _))) => (),
+            res => panic!("expected type error, got: {:?}", res),
+        }
+        match engine.check(&parse_expr("bad").unwrap()) {
+            Err(EngineError::Type(TypeError::UnboundVariable(Name(nm)))) => {
+                assert_eq!(nm, "bad")
+            }
+            res => panic!("expected unbound variable, got: {:?}", res),
+        }
+        assert!(!engine.term_env().contains_key(&Name("bad".to_string())));
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        let mut engine = Engine::new();
+        define(&mut engine, "(defn x 1)").unwrap();
+        let snapshot = engine.snapshot();
+        define(&mut engine, "(defn x true)").unwrap();
+        define(&mut engine, "(defn y 2)").unwrap();
+        engine.restore(snapshot.clone());
+        let (val, sc) = engine.eval(&parse_expr("x").unwrap()).unwrap();
+        assert_eq!(to_pretty(val.ppr(), 80), "1");
+        assert_eq!(to_pretty(sc.ppr(), 80), "Int");
+        assert!(engine.check(&parse_expr("y").unwrap()).is_err());
+        // a snapshot may be restored more than once.
+        define(&mut engine, "(defn y 3)").unwrap();
+        engine.restore(snapshot);
+        assert!(engine.check(&parse_expr("y").unwrap()).is_err());
+    }
+
+    #[test]
+    fn programs() {
+        let mut resolver = MemoryResolver::new();
+        resolver.insert("util", "(defn incr (lam [x] (+ x 1)))");
+        let mut engine = Engine::with_options(CalculationOptions {
+            resolver: Box::new(resolver),
+            ..CalculationOptions::default()
+        });
+        let prog =
+            parse_program("(import util)\n\n(defn two (util.incr 1))\n\n(util.incr two)").unwrap();
+        let (val, _) = engine.eval_program(&prog).unwrap();
+        assert_eq!(to_pretty(val.ppr(), 80), "3");
+        // the program's imports & defns are now in scope.
+        let (val, _) = engine
+            .eval(&parse_expr("(util.incr two)").unwrap())
+            .unwrap();
+        assert_eq!(to_pretty(val.ppr(), 80), "3");
+
+        // a failing program leaves no trace.
+        let prog = parse_program("(defn three 3)\n\n(+ three false)").unwrap();
+        assert!(engine.eval_program(&prog).is_err());
+        assert!(engine.check(&parse_expr("three").unwrap()).is_err());
+    }
+
+    #[test]
+    fn reduce_in_engine() {
+        let mut engine = Engine::new();
+        define(&mut engine, "(defn weight 3)").unwrap();
+        let prog = parse_program("(lam [xs] (* weight (sum xs)))").unwrap();
+        let out = engine
+            .reduce(prog, &mut vec![VList(vec![VInt(1), VInt(2)])].into_iter())
+            .unwrap();
+        assert_eq!(to_pretty(out.value.ppr(), 80), "9");
+    }
+
+    #[test]
+    fn step_limit() {
+        let mut engine = limited(Limits {
+            max_steps: Some(1000),
+            max_depth: None,
+        });
+        match engine.eval(&parse_expr(LOOP).unwrap()) {
+            Err(EngineError::Eval(EvalError::StepLimitExceeded(1000))) => (),
+            res => panic!("expected step limit, got: {:?}", res.err()),
+        }
+        // the limit applies to each evaluation separately.
+        for _ in 0..3 {
+            define(&mut engine, "(sum (list 1 2 3))").unwrap();
+        }
+    }
+
+    #[test]
+    fn depth_limit() {
+        let mut engine = limited(Limits {
+            max_steps: None,
+            max_depth: Some(100),
+        });
+        match engine.eval(&parse_expr(COUNT_DOWN).unwrap()) {
+            Err(EngineError::Eval(EvalError::DepthLimitExceeded(100))) => (),
+            res => panic!("expected depth limit, got: {:?}", res.err()),
+        }
+        engine.set_limits(Limits::default());
+        let (val, _) = engine.eval(&parse_expr(COUNT_DOWN).unwrap()).unwrap();
+        assert_eq!(to_pretty(val.ppr(), 80), "50");
+    }
+
+    #[test]
+    fn calculation_limits() {
+        let options = CalculationOptions {
+            limits: Limits {
+                max_steps: Some(1000),
+                max_depth: None,
+            },
+            ..CalculationOptions::default()
+        };
+        let prog = parse_program(&format!("(lam [y] (+ y {}))", LOOP)).unwrap();
+        let compiled = compile_calculation_with(prog, &options).unwrap();
+        match compiled.reduce(&mut vec![VInt(1)].into_iter()) {
+            Err(ReputationCalculationError::EvalError(EvalError::StepLimitExceeded(_))) => (),
+            res => panic!("expected step limit, got: {:?}", res.err()),
+        }
+        let prog = parse_program(&format!("(defn boom {})\n\n(lam [y] y)", LOOP)).unwrap();
+        match compile_calculation_with(prog, &options) {
+            Err(ReputationCalculationError::EvalError(EvalError::StepLimitExceeded(_))) => (),
+            res => panic!("expected step limit, got: {:?}", res.err()),
+        }
+    }
+
+    #[test]
+    fn unused_defn_name() {
+        let Defn(nm, _) = parse_defn_or_it_expr("(+ 1 2)").unwrap();
+        assert_eq!(nm, Name("it".to_string()));
+    }
+}
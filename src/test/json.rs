@@ -0,0 +1,252 @@
+pub mod json_unit {
+    use serde_json::json;
+
+    use crate::{
+        eval::{eval_program, Value::*},
+        json::{decode_arguments, decode_value, DecodeErrorKind},
+        parse::{parse_expr, parse_program, parse_type},
+        syntax::{Expr, Program},
+        toplevel::compile_calculation,
+        types::{Scheme, Type, TV},
+        util::pretty::to_pretty,
+    };
+
+    fn roundtrip_value(val: serde_json::Value) {
+        let v: crate::eval::Value = serde_json::from_value(val.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&v).unwrap(), val);
+    }
+
+    #[test]
+    fn values() {
+        let v = VPair(
+            Box::new(VList(vec![VInt(1), VInt(-2)])),
+            Box::new(VBool(true)),
+        );
+        assert_eq!(
+            serde_json::to_value(&v).unwrap(),
+            json!({"pair": [[1, -2], true]})
+        );
+        roundtrip_value(json!({"pair": [[1, -2], true]}));
+        roundtrip_value(json!([[], [{"pair": [0, false]}]]));
+        roundtrip_value(json!(9_223_372_036_854_775_807i64));
+    }
+
+    #[test]
+    fn values_rejected() {
+        let prog = parse_program("(lam [x] x)").unwrap();
+        let (closure, _) = eval_program(&prog).unwrap();
+        assert!(serde_json::to_value(&closure).is_err());
+        assert!(serde_json::to_value(VList(vec![closure])).is_err());
+        let mut natives = crate::native::Natives::new();
+        natives.register_fn1("neg", |x: i64| -x);
+        for native in natives.term_env().unwrap().iter().map(|(_, val)| val) {
+            assert!(serde_json::to_value(native).is_err());
+        }
+        for bad in &[
+            json!(1.5),
+            json!("1"),
+            json!(null),
+            json!(18_446_744_073_709_551_615u64),
+            json!({"pair": [1]}),
+            json!({"tuple": [1, 2]}),
+            json!({"pair": [1, 2], "extra": 3}),
+        ] {
+            let res: Result<crate::eval::Value, _> = serde_json::from_value(bad.clone());
+            assert!(res.is_err(), "expected error for {}", bad);
+        }
+    }
+
+    #[test]
+    fn values_evaluated() {
+        let prog = parse_program("(pair (list 1 2) (== 1 2))").unwrap();
+        let (val, _) = eval_program(&prog).unwrap();
+        let json = serde_json::to_string(&val).unwrap();
+        assert_eq!(json, r#"{"pair":[[1,2],false]}"#);
+        let back: crate::eval::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(to_pretty(back.ppr(), 80), to_pretty(val.ppr(), 80));
+    }
+
+    #[test]
+    fn types() {
+        let ty = parse_type("((List a) -> (Int, Bool))").unwrap();
+        let json = json!({"arr": [
+            {"list": {"var": "a"}},
+            {"pair": [{"con": "Int"}, {"con": "Bool"}]}
+        ]});
+        assert_eq!(serde_json::to_value(&ty).unwrap(), json);
+        assert_eq!(serde_json::from_value::<Type>(json).unwrap(), ty);
+    }
+
+    #[test]
+    fn schemes() {
+        let sc = Scheme(vec![TV("a".to_string())], parse_type("(a -> a)").unwrap());
+        let json = json!({"forall": ["a"], "type": {"arr": [{"var": "a"}, {"var": "a"}]}});
+        assert_eq!(serde_json::to_value(&sc).unwrap(), json);
+        assert_eq!(serde_json::from_value::<Scheme>(json).unwrap(), sc);
+    }
+
+    #[test]
+    fn exprs() {
+        let e = parse_expr("(let ([f (lam [x] (+ x 1))]) (if true (f 2) (fix f)))").unwrap();
+        let json = json!({"let": [
+            "f",
+            {"lam": ["x", {"app": [
+                {"app": [{"prim": "add"}, {"var": "x"}]},
+                {"lit": {"int": 1}}
+            ]}]},
+            {"if": [
+                {"lit": {"bool": true}},
+                {"app": [{"var": "f"}, {"lit": {"int": 2}}]},
+                {"fix": {"var": "f"}}
+            ]}
+        ]});
+        assert_eq!(serde_json::to_value(&e).unwrap(), json);
+        assert_eq!(serde_json::from_value::<Expr>(json).unwrap(), e);
+    }
+
+    #[test]
+    fn programs() {
+        let prog = parse_program(
+            r#"(import "lib/util.poly")
+(import other)
+
+(defn twice (lam [x] (* 2 x)))
+
+(params [n Int])
+
+(twice n)"#,
+        )
+        .unwrap();
+        let json = serde_json::to_value(&prog).unwrap();
+        assert_eq!(
+            json["imports"],
+            json!([{"path": "lib/util.poly"}, {"named": "other"}])
+        );
+        assert_eq!(json["defns"][0][0], json!("twice"));
+        assert_eq!(json["params"], json!([["n", {"con": "Int"}]]));
+        assert_eq!(
+            json["body"],
+            json!({"app": [{"var": "twice"}, {"var": "n"}]})
+        );
+        let back: Program = serde_json::from_value(json).unwrap();
+        assert_eq!(to_pretty(back.ppr(), 80), to_pretty(prog.ppr(), 80));
+    }
+
+    #[quickcheck]
+    fn expr_roundtrip(e: Expr) -> bool {
+        let json = serde_json::to_string(&e).unwrap();
+        serde_json::from_str::<Expr>(&json).is_ok_and(|back| back == e)
+    }
+
+    fn decode(ty: &str, json: serde_json::Value) -> Result<String, String> {
+        let ty = parse_type(ty).unwrap();
+        decode_value(&ty, &json)
+            .map(|v| to_pretty(v.ppr(), 80))
+            .map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn decode_typed() {
+        assert_eq!(decode("Int", json!(-3)), Ok("-3".to_string()));
+        assert_eq!(
+            decode(
+                "(List (Int, Bool))",
+                json!([[1, true], {"pair": [2, false]}])
+            ),
+            Ok("(list (1, true) (2, false))".to_string())
+        );
+        // the same JSON decodes differently, depending on the expected type.
+        assert_eq!(
+            decode("(List Int)", json!([1, 2])),
+            Ok("(list 1 2)".to_string())
+        );
+        assert_eq!(
+            decode("(Int, Int)", json!([1, 2])),
+            Ok("(1, 2)".to_string())
+        );
+        // polymorphic positions fall back to the untyped schema.
+        assert_eq!(
+            decode("(List a)", json!([[1], []])),
+            Ok("(list (list 1) (list))".to_string())
+        );
+    }
+
+    #[test]
+    fn decode_errors() {
+        assert_eq!(
+            decode("(List (Int, Bool))", json!([[1, true], [2, 3]])),
+            Err("at $[1][1]: expected Bool, found 3".to_string())
+        );
+        assert_eq!(
+            decode("(List (Int, Int))", json!([{"pair": [1, "x"]}])),
+            Err("at $[0].pair[1]: expected Int, found \"x\"".to_string())
+        );
+        assert_eq!(
+            decode("(Int, Int)", json!([1, 2, 3])),
+            Err("at $: expected (Int, Int), found [1,2,3]".to_string())
+        );
+        assert_eq!(
+            decode("Int", json!(1.5)),
+            Err("at $: expected Int, found 1.5".to_string())
+        );
+        assert!(decode("Int", json!(18_446_744_073_709_551_615u64))
+            .unwrap_err()
+            .contains("out of range"));
+        assert!(decode("(Int -> Int)", json!(1))
+            .unwrap_err()
+            .contains("function"));
+        assert!(decode("(List a)", json!([null]))
+            .unwrap_err()
+            .starts_with("at $[0]: "));
+    }
+
+    #[test]
+    fn decode_calculation_arguments() {
+        let prog = parse_program(
+            "(params [ratings (List (Int, Int))] [weight Int])
+
+(* weight (sum (map (lam [r] (fst r)) ratings)))",
+        )
+        .unwrap();
+        let compiled = compile_calculation(prog).unwrap();
+        let sig = compiled.signature();
+
+        let by_name = json!({"weight": 2, "ratings": [[1, 0], [2, 0]]});
+        let args = decode_arguments(&sig, &by_name).unwrap();
+        let out = compiled.reduce(&mut args.into_iter()).unwrap();
+        assert_eq!(to_pretty(out.value.ppr(), 80), "6");
+
+        let by_position = json!([[[3, 0]], 1]);
+        let args = decode_arguments(&sig, &by_position).unwrap();
+        let out = compiled.reduce(&mut args.into_iter()).unwrap();
+        assert_eq!(to_pretty(out.value.ppr(), 80), "3");
+
+        let err = decode_arguments(&sig, &json!({"weight": 2, "ratings": [[1, true]]}));
+        assert_eq!(
+            err.err().unwrap().to_string(),
+            "at $.ratings[0][1]: expected Int, found true"
+        );
+        let err = decode_arguments(&sig, &json!({"weight": 2}));
+        assert!(matches!(
+            err.err().unwrap().kind,
+            DecodeErrorKind::MissingParameter(_)
+        ));
+        let err = decode_arguments(&sig, &json!({"weight": 2, "ratings": [], "x": 1}));
+        assert!(matches!(
+            err.err().unwrap().kind,
+            DecodeErrorKind::UnexpectedParameter(_)
+        ));
+        let err = decode_arguments(&sig, &json!([1]));
+        assert!(matches!(
+            err.err().unwrap().kind,
+            DecodeErrorKind::ArityMismatch(2, 1)
+        ));
+
+        let unnamed = compile_calculation(parse_program("(lam [x] +)").unwrap()).unwrap();
+        let err = decode_arguments(&unnamed.signature(), &json!({"x": 1, "y": 2}));
+        assert!(matches!(
+            err.err().unwrap().kind,
+            DecodeErrorKind::UnnamedParameters
+        ));
+    }
+}
@@ -0,0 +1,176 @@
+pub mod lazy_unit {
+    use crate::{
+        eval::{eval_, EvalError, EvalState, Limits, Strategy, TermEnv, Value},
+        parse::{parse_expr, parse_program},
+        prelude::prelude,
+        test::{ppr, Typed},
+        toplevel::{compile_calculation_with, CalculationOptions},
+    };
+
+    const LOOP: &str = "((fix (lam [f x] (f x))) 1)";
+    const ONES: &str = "(fix (lam [xs] (cons 1 xs)))";
+
+    fn lazy_state(limits: Limits) -> EvalState {
+        let mut es = EvalState::with_limits(limits);
+        es.set_strategy(Strategy::Lazy);
+        es
+    }
+
+    fn eval_lazy(src: &str) -> Result<Value, EvalError> {
+        let limits = Limits {
+            max_steps: Some(10000),
+            ..Limits::default()
+        };
+        let expr = parse_expr(src).unwrap();
+        eval_(&prelude().term_env, &mut lazy_state(limits), &expr)
+    }
+
+    fn steps_lazy(src: &str) -> u64 {
+        let mut es = lazy_state(Limits::default());
+        eval_(&prelude().term_env, &mut es, &parse_expr(src).unwrap())
+            .ok()
+            .unwrap();
+        es.steps()
+    }
+
+    #[test]
+    fn agrees_with_eval() {
+        let cases = [
+            "(sum (map (+ 1) (list 1 2 3)))",
+            "(foldl - 0 (list 1 2 3))",
+            "(let ([p pair]) (p 1 true))",
+            "(map fst (list (pair 1 true) (pair 2 false)))",
+            "(filter (lam [x] (not (== x 2))) (list 1 2 3))",
+            "(reverse (cons 1 (cons 2 nil)))",
+            "((compose (* 2) (+ 1)) 3)",
+            "((fix (lam [f n] (if (== n 0) 0 (+ 1 (f (- n 1)))))) 50)",
+        ];
+        let env = &prelude().term_env;
+        for src in cases.iter() {
+            let expr = parse_expr(src).unwrap();
+            let expected = eval_(env, &mut EvalState::new(), &expr).ok().unwrap();
+            let actual = eval_lazy(src).ok().unwrap();
+            assert_eq!(ppr(&expected), ppr(&actual), "in: {}", src);
+        }
+    }
+
+    #[test]
+    fn unused_arguments_are_not_evaluated() {
+        let cases = [
+            (format!("(fst (pair 1 {}))", LOOP), "1"),
+            (format!("((lam [x] 1) {})", LOOP), "1"),
+            (format!("(let ([x {}]) 1)", LOOP), "1"),
+            (format!("(const 1 {})", LOOP), "1"),
+            (format!("(null (cons {} nil))", LOOP), "false"),
+        ];
+        for (src, expected) in cases.iter() {
+            match eval_lazy(src) {
+                Ok(val) => assert_eq!(ppr(&val), *expected, "in: {}", src),
+                Err(err) => panic!("expected a value, got: {:?} in: {}", err, src),
+            }
+        }
+        // strictly, they are.
+        let limits = Limits {
+            max_steps: Some(1000),
+            ..Limits::default()
+        };
+        let expr = parse_expr(&cases[0].0).unwrap();
+        match eval_(&TermEnv::new(), &mut EvalState::with_limits(limits), &expr) {
+            Err(EvalError::StepLimitExceeded(1000)) => (),
+            res => panic!(
+                "expected step limit error, got: {:?}",
+                res.as_ref().map(ppr)
+            ),
+        }
+    }
+
+    #[test]
+    fn infinite_lists() {
+        let cases = [
+            (format!("(null {})", ONES), "false"),
+            (format!("(null (map (+ 1) {}))", ONES), "false"),
+            (format!("(fst (pair 1 (length {})))", ONES), "1"),
+        ];
+        for (src, expected) in cases.iter() {
+            match eval_lazy(src) {
+                Ok(val) => assert_eq!(ppr(&val), *expected, "in: {}", src),
+                Err(err) => panic!("expected a value, got: {:?} in: {}", err, src),
+            }
+        }
+        // forcing the whole of an infinite list exhausts the limits.
+        match eval_lazy(&format!("(length {})", ONES)) {
+            Err(EvalError::StepLimitExceeded(10000)) => (),
+            res => panic!(
+                "expected step limit error, got: {:?}",
+                res.as_ref().map(ppr)
+            ),
+        }
+    }
+
+    #[test]
+    fn thunks_are_evaluated_once() {
+        let big = "(sum (map (* 2) (list 1 2 3 4 5 6 7 8 9 10)))";
+        let once = steps_lazy(&format!("(let ([x {}]) (+ x 1))", big));
+        let twice = steps_lazy(&format!("(let ([x {}]) (+ x x))", big));
+        assert!(
+            twice < once + 5,
+            "expected a shared thunk, got: {} & {} steps",
+            once,
+            twice
+        );
+        // including through an argument, & a pair.
+        let once = steps_lazy(&format!("((lam [p] (+ (fst p) 1)) (pair {} 0))", big));
+        let twice = steps_lazy(&format!("((lam [p] (+ (fst p) (fst p))) (pair {} 0))", big));
+        assert!(
+            twice < once + 5,
+            "expected a shared thunk, got: {} & {} steps",
+            once,
+            twice
+        );
+    }
+
+    #[test]
+    fn values_are_forced_when_printed() {
+        let cases = [
+            ("(map (+ 1) (cons 1 (cons 2 nil)))", "(list 2 3)"),
+            ("(pair (+ 1 2) (cons true nil))", "(3, (list true))"),
+            ("(cons (pair 1 2) nil)", "(list (1, 2))"),
+        ];
+        for (src, expected) in cases.iter() {
+            let val = eval_lazy(src).ok().unwrap();
+            assert_eq!(ppr(&val), *expected, "in: {}", src);
+        }
+    }
+
+    #[test]
+    fn lazy_calculations() {
+        let prog = parse_program(&format!(
+            "(defn ones {})\n(lam [x] (pair (null ones) (fst (pair x {}))))",
+            ONES, LOOP
+        ))
+        .unwrap();
+        let options = CalculationOptions {
+            strategy: Strategy::Lazy,
+            limits: Limits {
+                max_steps: Some(10000),
+                ..Limits::default()
+            },
+            ..CalculationOptions::default()
+        };
+        let calc = compile_calculation_with(prog, &options).ok().unwrap();
+        match calc.reduce(&mut vec![Value::VInt(7)].into_iter()) {
+            Ok(output) => assert_eq!(ppr(&output.value), "(false, 7)"),
+            Err(err) => panic!("expected a value, got: {:?}", err),
+        }
+    }
+
+    #[quickcheck]
+    fn lazy_agrees_with_eval(Typed(e): Typed) -> bool {
+        let env = TermEnv::new();
+        let expected = eval_(&env, &mut EvalState::new(), &e).ok().unwrap();
+        let actual = eval_(&env, &mut lazy_state(Limits::default()), &e)
+            .ok()
+            .unwrap();
+        ppr(&expected) == ppr(&actual)
+    }
+}
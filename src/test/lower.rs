@@ -0,0 +1,191 @@
+pub mod lower_unit {
+    use quickcheck_macros::quickcheck;
+
+    use crate::{
+        eval::{apply, eval_program, EvalState, Value},
+        infer::infer_program,
+        lower::{anf, anf_program, closure_convert, is_anf, lower_program},
+        parse::{parse_expr, parse_program},
+        prelude::prelude,
+        syntax::{Defn, Expr, Program},
+        test::Typed,
+        types::Scheme,
+        util::pretty::to_pretty,
+    };
+
+    fn anf_of(src: &str) -> String {
+        to_pretty(anf(&parse_expr(src).unwrap()).ppr(), 200)
+    }
+
+    fn has_lam(expr: &Expr) -> bool {
+        match expr {
+            Expr::Lam(_, _) => true,
+            Expr::Var(_) | Expr::Lit(_) | Expr::Prim(_) => false,
+            Expr::App(fun, arg) => has_lam(fun) || has_lam(arg),
+            Expr::Let(_x, e, bd) => has_lam(e) || has_lam(bd),
+            Expr::If(tst, thn, els) => has_lam(tst) || has_lam(thn) || has_lam(els),
+            Expr::Fix(e) => has_lam(e),
+        }
+    }
+
+    // the lambdas of a closure converted program are the bodies of its
+    // `defn`s, which only refer to earlier `defn`s & to the prelude.
+    fn assert_closure_converted(prog: &Program) {
+        let prelude = &prelude().term_env;
+        let mut defined = Vec::new();
+        for Defn(nm, bd) in prog.p_defns.iter() {
+            let mut inner = bd;
+            while let Expr::Lam(_x, bd) = inner {
+                inner = bd;
+            }
+            assert!(is_anf(bd), "not in a-normal form: {}", nm.0);
+            assert!(!has_lam(inner), "a nested lambda in: {}", nm.0);
+            for x in bd.free_vars() {
+                let global = defined.contains(&x) || prelude.contains_key(&x);
+                assert!(global, "{} is free in: {}", x.0, nm.0);
+            }
+            defined.push(nm.clone());
+        }
+        assert!(is_anf(&prog.p_body));
+        assert!(!has_lam(&prog.p_body));
+    }
+
+    fn value(prog: &Program) -> String {
+        match eval_program(prog) {
+            Ok((val, _env)) => to_pretty(val.ppr(), 80),
+            Err(err) => panic!("expected a value, got: {:?}", err),
+        }
+    }
+
+    fn scheme(prog: &Program) -> Scheme {
+        match infer_program(prelude().type_env.clone(), prog) {
+            Ok((sc, _env)) => sc,
+            Err(err) => panic!("expected a type, got: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn names_intermediate_results() {
+        assert_eq!(anf_of("(+ (* 2 3) 1)"), "(let ([t ((* 2) 3)]) ((+ t) 1))");
+        assert_eq!(
+            anf_of("(if (== 1 2) 3 (f (g 4)))"),
+            "(let ([t ((== 1) 2)]) (if t 3 (let ([ta (g 4)]) (f ta))))"
+        );
+        // the result of a fully applied PrimOp is bound before it is applied.
+        assert_eq!(anf_of("(fst p 3)"), "(let ([t (fst p)]) (t 3))");
+        assert_eq!(
+            anf_of("(fix (lam [f] f))"),
+            "(let ([t (lam [f] f)]) (fix t))"
+        );
+    }
+
+    #[test]
+    fn flattens_lets_without_capture() {
+        assert_eq!(
+            anf_of("(let ([x (let ([y 1]) (+ y 2))]) (* x x))"),
+            "(let ([y 1]) (let ([x ((+ y) 2)]) ((* x) x)))"
+        );
+        // the inner `x` is renamed, as it would otherwise capture the outer.
+        assert_eq!(
+            anf_of("(lam [x] (+ (let ([x 1]) x) x))"),
+            "(lam [x] (let ([xa 1]) ((+ xa) x)))"
+        );
+        // as is a binder of the name of a free variable.
+        assert_eq!(anf_of("(f (let ([f 1]) f))"), "(let ([fa 1]) (f fa))");
+    }
+
+    #[test]
+    fn lifts_lambdas_with_explicit_environments() {
+        let src = "(defn k 2)\n(params [n Int])\n(let ([m 3]) (map (lam [x] (+ (* k x) (+ m n))) (list 1 2)))";
+        let prog = parse_program(src).unwrap();
+        let lowered = lower_program(&prog);
+        assert_closure_converted(&lowered);
+        let lifted = to_pretty(lowered.p_defns[1].ppr(), 200);
+        assert_eq!(
+            lifted,
+            "(defn fun (lam [env] (lam [x] (let ([m (fst env)]) (let ([enva (snd env)]) (let ([n enva]) (let ([t ((* k) x)]) (let ([ta ((+ m) n)]) ((+ t) ta)))))))))"
+        );
+        // a lambda which captures one variable takes it as its environment, &
+        // a closed one takes none.
+        let prog = parse_program("(lam [a] (pair (lam [b] a) (lam [c] c)))").unwrap();
+        let lowered = lower_program(&prog);
+        assert_closure_converted(&lowered);
+        let defns: Vec<_> = lowered
+            .p_defns
+            .iter()
+            .map(|defn| to_pretty(defn.ppr(), 200))
+            .collect();
+        assert_eq!(
+            defns,
+            vec![
+                "(defn fun (lam [a] (lam [b] a)))",
+                "(defn funa (lam [c] c))",
+                "(defn funb (lam [a] (let ([t (fun a)]) (let ([ta funa]) ((pair t) ta)))))",
+            ]
+        );
+        assert_eq!(to_pretty(lowered.p_body.ppr(), 80), "funb");
+    }
+
+    #[test]
+    fn lowered_programs_agree() {
+        for src in [
+            "(defn double (lam [x] (* 2 x)))\n(defn xs (map double (list 1 2 3)))\n(sum (map (lam [x] (+ x (double 2))) xs))",
+            "(let ([y 2]) ((lam [x y] (+ x y)) y 3))",
+            "((fix (lam [f n] (if (== n 0) 0 (+ 2 (f (- n 1)))))) 4)",
+            "(let ([a 1]) (let ([b 2]) (foldl (lam [acc x] (+ acc (* x (+ a b)))) 0 (list 1 2 3))))",
+            "(defn x 1)\n(defn f (lam [y] (+ x y)))\n(defn x true)\n(if x (f 2) 0)",
+            "(let ([f (lam [x] (lam [y] (pair x y)))]) (fst (snd ((f 1) (f 2 3)))))",
+            "(let ([id (lam [x] x)]) ((lam [y] (if (snd (pair (id 1) (id true))) y 0)) 5))",
+            "(defn g (lam [n] (let ([id (lam [x] x)]) ((lam [y] (if (id true) (id y) 0)) n))))\n(g 3)",
+            "(let ([a 1]) (let ([b true]) (let ([k (lam [x] (pair x (pair a b)))]) (map (lam [y] (if (fst (k b)) (fst (k y)) 0)) (list (fst (snd (k a))))))))",
+        ]
+        .iter()
+        {
+            let prog = parse_program(src).unwrap();
+            let anf = anf_program(&prog);
+            let lowered = closure_convert(&anf);
+            assert_closure_converted(&lowered);
+            // each pass keeps the type of the program,
+            let expected = scheme(&prog);
+            assert_eq!(scheme(&anf), expected, "in: {}", src);
+            assert_eq!(scheme(&lowered), expected, "in: {}", src);
+            // & its value.
+            let expected = value(&prog);
+            assert_eq!(value(&anf), expected, "in: {}", src);
+            assert_eq!(value(&lowered), expected, "in: {}", src);
+            // & may be written out, & read back.
+            let printed = to_pretty(lowered.ppr(), 80);
+            let parsed = parse_program(&printed).unwrap();
+            assert_eq!(to_pretty(parsed.ppr(), 80), printed, "in: {}", src);
+        }
+    }
+
+    // functions are compared by their results.
+    fn result(prog: &Program) -> String {
+        let val = eval_program(prog).ok().unwrap().0;
+        match val {
+            Value::VClosure(_, _, _) | Value::VCore(_, _, _, _) => {
+                let res = apply(&mut EvalState::new(), val, Value::VInt(3));
+                to_pretty(res.ok().unwrap().ppr(), 80)
+            }
+            val => to_pretty(val.ppr(), 80),
+        }
+    }
+
+    #[quickcheck]
+    fn lowering_agrees_with_eval(Typed(e): Typed) -> bool {
+        let prog = Program {
+            p_imports: vec![],
+            p_defns: vec![],
+            p_params: None,
+            p_body: e,
+        };
+        let anf = anf_program(&prog);
+        let lowered = closure_convert(&anf);
+        let typed = infer_program(prelude().type_env.clone(), &lowered).is_ok();
+        is_anf(&anf.p_body)
+            && typed
+            && result(&anf) == result(&prog)
+            && result(&lowered) == result(&prog)
+    }
+}
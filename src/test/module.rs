@@ -0,0 +1,143 @@
+pub mod module_unit {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::{
+        eval::eval_program_in,
+        infer::{infer_program, TypeError},
+        module::{
+            load_program_imports, MemoryResolver, ModuleError, ModuleId, ModuleResolver,
+            ModuleSource,
+        },
+        parse::parse_program,
+        syntax::{Import, Name},
+        util::pretty::to_pretty,
+    };
+
+    const LISTUTILS: &str = r#"
+(export elem)
+
+(defn elem
+  (lam [target ls]
+    (foldl (lam [seen x] (if (== x target) true seen)) false ls)))
+
+(defn hidden 1)
+"#;
+
+    fn resolver() -> MemoryResolver {
+        let mut r = MemoryResolver::new();
+        r.insert("lib/listutils.poly", LISTUTILS);
+        r.insert("listutils", LISTUTILS);
+        r.insert("a", "(import b)\n(defn x b.y)\n");
+        r.insert("b", "(import a)\n(defn y a.x)\n");
+        r.insert("bad", "(export nope)\n(defn x 1)\n");
+        r.insert(
+            "wrapper",
+            "(import listutils)\n(defn has (listutils.elem 2))\n",
+        );
+        r
+    }
+
+    fn run(src: &str) -> Result<(String, String), ModuleError> {
+        let prog = parse_program(src).unwrap();
+        let (env, term_env) = load_program_imports(&resolver(), &prog)?;
+        let (sc, _env) = infer_program(env, &prog)
+            .map_err(|err| ModuleError::Type(crate::module::ModuleId("<root>".to_string()), err))?;
+        let (val, _term_env) = eval_program_in(term_env, &prog).unwrap();
+        Ok((to_pretty(val.ppr(), 80), to_pretty(sc.ppr(), 80)))
+    }
+
+    #[test]
+    fn path_import() {
+        let res = run("(import \"lib/listutils.poly\")\n(listutils.elem 2 (list 1 2 3))");
+        assert_eq!(res.unwrap(), ("true".to_string(), "Bool".to_string()));
+    }
+
+    #[test]
+    fn named_import() {
+        let res = run("(import listutils)\n(listutils.elem 4 (list 1 2 3))");
+        assert_eq!(res.unwrap(), ("false".to_string(), "Bool".to_string()));
+    }
+
+    #[test]
+    fn transitive_import() {
+        let res = run("(import wrapper)\n(wrapper.has (list 2))");
+        assert_eq!(res.unwrap(), ("true".to_string(), "Bool".to_string()));
+    }
+
+    #[test]
+    fn unexported_defn_is_unbound() {
+        match run("(import listutils)\nlistutils.hidden") {
+            Err(ModuleError::Type(_, TypeError::UnboundVariable(Name(nm)))) => {
+                assert_eq!(nm, "listutils.hidden")
+            }
+            res => panic!("expected unbound variable, got: {:?}", res.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn unknown_export() {
+        match run("(import bad)\n1") {
+            Err(ModuleError::UnknownExport(_, Name(nm))) => assert_eq!(nm, "nope"),
+            res => panic!("expected unknown export, got: {:?}", res.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn import_cycle() {
+        match run("(import a)\n1") {
+            Err(ModuleError::Cycle(ids)) => {
+                let ids: Vec<String> = ids.into_iter().map(|id| id.0).collect();
+                assert_eq!(ids, vec!["a", "b", "a"]);
+            }
+            res => panic!("expected import cycle, got: {:?}", res.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn duplicate_import() {
+        match run("(import listutils)\n(import \"lib/listutils.poly\")\n1") {
+            Err(ModuleError::DuplicateImport(Name(nm))) => assert_eq!(nm, "listutils"),
+            res => panic!("expected duplicate import, got: {:?}", res.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn missing_module() {
+        match run("(import nowhere)\n1") {
+            Err(ModuleError::NotFound(_)) => (),
+            res => panic!("expected missing module, got: {:?}", res.map(|_| ())),
+        }
+    }
+
+    // counts the modules read, via `resolve`.
+    struct Counting(MemoryResolver, AtomicUsize);
+
+    impl ModuleResolver for Counting {
+        fn resolve(
+            &self,
+            import: &Import,
+            from: Option<&ModuleId>,
+        ) -> Result<ModuleSource, ModuleError> {
+            self.1.fetch_add(1, Ordering::SeqCst);
+            self.0.resolve(import, from)
+        }
+
+        fn locate(
+            &self,
+            import: &Import,
+            from: Option<&ModuleId>,
+        ) -> Result<ModuleId, ModuleError> {
+            self.0.locate(import, from)
+        }
+    }
+
+    #[test]
+    fn modules_are_read_once() {
+        let counting = Counting(resolver(), AtomicUsize::new(0));
+        let src = "(import listutils)\n(import wrapper)\n(wrapper.has (list 2))";
+        let prog = parse_program(src).unwrap();
+        load_program_imports(&counting, &prog).unwrap();
+        // `wrapper` imports `listutils` again.
+        assert_eq!(counting.1.load(Ordering::SeqCst), 2);
+    }
+}
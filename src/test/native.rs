@@ -0,0 +1,228 @@
+pub mod native_unit {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use crate::{
+        convert::FromValue,
+        engine::{Engine, EngineError},
+        eval::{eval_, eval_program, EvalError, Strategy, Value::*},
+        infer::TypeError,
+        module::{MemoryResolver, ModuleError},
+        native::Natives,
+        parse::{parse_expr, parse_program, parse_type},
+        syntax::Name,
+        toplevel::{
+            compile_calculation, compile_calculation_with, CalculationOptions,
+            ReputationCalculationError,
+        },
+        types::Scheme,
+        util::pretty::to_pretty,
+    };
+
+    fn natives() -> Natives {
+        let mut natives = Natives::new();
+        natives.register_fn1("trust", |id: i64| if id == 7 { 10 } else { 1 });
+        natives.register_fn2("scale", |k: i64, x: i64| k * x);
+        natives.register(
+            "singleton",
+            Scheme(vec![], parse_type("(a -> (List a))").unwrap()),
+            |args| Ok(VList(args.to_vec())),
+        );
+        natives.register("answer", Scheme(vec![], parse_type("Int").unwrap()), |_| {
+            Ok(VInt(42))
+        });
+        natives.register(
+            "lookup",
+            Scheme(vec![], parse_type("(Int -> Int)").unwrap()),
+            |args| match &args[0] {
+                VInt(id) if *id > 0 => Ok(VInt(id * 10)),
+                arg => Err(format!("no such id: {}", to_pretty(arg.ppr(), 80))),
+            },
+        );
+        natives
+    }
+
+    fn options() -> CalculationOptions {
+        CalculationOptions {
+            natives: natives(),
+            ..CalculationOptions::default()
+        }
+    }
+
+    fn run(src: &str) -> Result<(String, String), ReputationCalculationError> {
+        let compiled = compile_calculation_with(parse_program(src).unwrap(), &options())?;
+        let out = compiled.reduce(&mut vec![].into_iter())?;
+        Ok((
+            to_pretty(out.value.ppr(), 80),
+            to_pretty(out.scheme.ppr(), 80),
+        ))
+    }
+
+    #[test]
+    fn call_natives() {
+        assert_eq!(
+            run("(sum (map trust (list 1 7 3)))").unwrap().0,
+            "12".to_string()
+        );
+        assert_eq!(run("(scale 3 answer)").unwrap().0, "126");
+    }
+
+    #[test]
+    fn partial_application() {
+        assert_eq!(
+            run("(map (scale 2) (list 1 2 3))").unwrap().0,
+            "(list 2 4 6)"
+        );
+        assert_eq!(
+            run("(let ([s scale]) (foldl (lam [acc x] (s acc x)) 1 (list 2 3)))")
+                .unwrap()
+                .0,
+            "6"
+        );
+        assert_eq!(
+            run("(pair (scale 2) 1)").unwrap().0,
+            "(<<native scale>>, 1)"
+        );
+    }
+
+    #[test]
+    fn polymorphic_natives() {
+        assert_eq!(
+            run("(pair (singleton 1) (singleton true))").unwrap(),
+            (
+                "((list 1), (list true))".to_string(),
+                "((List Int), (List Bool))".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn natives_are_type_checked() {
+        match run("(trust true)") {
+            Err(ReputationCalculationError::ProgramTypeInferenceError(
+                TypeError::UnificationFail(_, _),
+            )) => (),
+            res => panic!("expected type error, got: {:?}", res),
+        }
+        // without the natives, they are unbound.
+        match compile_calculation(parse_program("(trust 1)").unwrap()) {
+            Err(ReputationCalculationError::ProgramTypeInferenceError(
+                TypeError::UnboundVariable(_),
+            )) => (),
+            res => panic!("expected unbound variable, got: {:?}", res.err()),
+        }
+    }
+
+    #[test]
+    fn natives_in_modules() {
+        let mut resolver = MemoryResolver::new();
+        resolver.insert("weights", "(defn weigh (lam [id] (scale (trust id) 2)))");
+        let options = CalculationOptions {
+            resolver: Box::new(resolver),
+            ..options()
+        };
+        let prog = parse_program("(import weights)\n\n(weights.weigh 7)").unwrap();
+        let compiled = compile_calculation_with(prog, &options).unwrap();
+        let out = compiled.reduce(&mut vec![].into_iter()).unwrap();
+        assert_eq!(to_pretty(out.value.ppr(), 80), "20");
+    }
+
+    #[test]
+    fn natives_as_inputs() {
+        let compiled =
+            compile_calculation(parse_program("(lam [f x] (f (f x)))").unwrap()).unwrap();
+        let scale =
+            natives().term_env().unwrap()[&crate::syntax::Name("scale".to_string())].clone();
+        let applied =
+            crate::eval::apply(&mut crate::eval::EvalState::new(), scale, VInt(3)).unwrap();
+        let out = compiled
+            .reduce(&mut vec![applied, VInt(2)].into_iter())
+            .unwrap();
+        assert_eq!(to_pretty(out.value.ppr(), 80), "18");
+        let bad =
+            natives().term_env().unwrap()[&crate::syntax::Name("singleton".to_string())].clone();
+        match compiled.reduce(&mut vec![bad, VInt(2)].into_iter()) {
+            Err(ReputationCalculationError::ProgramValuesUnificationError(_)) => (),
+            res => panic!("expected unification error, got: {:?}", res.err()),
+        }
+    }
+
+    #[test]
+    fn lifted_primops_use_their_arity() {
+        let prog =
+            parse_program("(pair (map fst (list (pair 1 true))) (map null (list nil)))").unwrap();
+        let (val, _) = eval_program(&prog).unwrap();
+        assert_eq!(to_pretty(val.ppr(), 80), "((list 1), (list true))");
+    }
+
+    #[test]
+    fn failing_natives() {
+        assert_eq!(run("(lookup 2)").unwrap().0, "20");
+        match run("(+ 1 (lookup 0))") {
+            Err(ReputationCalculationError::EvalError(EvalError::Native(Name(nm), msg))) => {
+                assert_eq!((nm.as_str(), msg.as_str()), ("lookup", "no such id: 0"))
+            }
+            res => panic!("expected a native error, got: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn constants_are_called_once() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut natives = Natives::new();
+        let counted = calls.clone();
+        natives.register(
+            "answer",
+            Scheme(vec![], parse_type("Int").unwrap()),
+            move |_| {
+                counted.fetch_add(1, Ordering::SeqCst);
+                Ok(VInt(42))
+            },
+        );
+        for _ in 0..3 {
+            natives.term_env().unwrap();
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        // a failed constant is reported when the natives are loaded.
+        natives.register("broken", Scheme(vec![], parse_type("Int").unwrap()), |_| {
+            Err("unavailable".to_string())
+        });
+        let options = CalculationOptions {
+            natives,
+            ..CalculationOptions::default()
+        };
+        match compile_calculation_with(parse_program("answer").unwrap(), &options) {
+            Err(ReputationCalculationError::ModuleError(ModuleError::Native(
+                EvalError::Native(Name(nm), _),
+            ))) => assert_eq!(nm, "broken"),
+            res => panic!("expected a native error, got: {:?}", res.err()),
+        }
+    }
+
+    #[test]
+    fn lazy_failures_are_reported() {
+        let options = CalculationOptions {
+            strategy: Strategy::Lazy,
+            ..options()
+        };
+        // the failing call is in a thunk, which evaluation alone leaves be.
+        let expr = parse_expr("(pair 1 (lookup 0))").unwrap();
+        let mut es = options.eval_state();
+        let val = eval_(&options.natives.term_env().unwrap(), &mut es, &expr).unwrap();
+        assert!(to_pretty(val.ppr(), 80).contains("<<error: Native"));
+        #[cfg(feature = "serde")]
+        assert!(serde_json::to_value(&val).is_err());
+        assert!(<(i64, i64)>::from_value(val.clone())
+            .err()
+            .and_then(|err| err.error)
+            .is_some());
+        // but an engine forces the values it returns.
+        let mut engine = Engine::with_options(options);
+        match engine.eval(&expr) {
+            Err(EngineError::Eval(EvalError::Native(Name(nm), _))) => assert_eq!(nm, "lookup"),
+            res => panic!("expected a native error, got: {:?}", res.err()),
+        }
+    }
+}
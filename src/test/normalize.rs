@@ -0,0 +1,200 @@
+pub mod normalize_unit {
+    use quickcheck_macros::quickcheck;
+
+    use crate::{
+        eval::{eval, TermEnv},
+        normalize::{alpha_eq, normalize, Equivalence, NormalizeError},
+        optimize::optimize,
+        parse::{parse_expr, parse_program},
+        prelude::prelude,
+        specialize::reify,
+        syntax::{Expr, Program},
+        test::Typed,
+        toplevel::equivalent,
+        util::pretty::to_pretty,
+    };
+
+    fn alpha(a: &str, b: &str) -> bool {
+        alpha_eq(&parse_expr(a).unwrap(), &parse_expr(b).unwrap())
+    }
+
+    fn normal(src: &str) -> Result<Expr, NormalizeError> {
+        normalize(&prelude().term_env, &parse_expr(src).unwrap(), 100)
+    }
+
+    fn normalized(src: &str) -> String {
+        match normal(src) {
+            Ok(expr) => to_pretty(expr.ppr(), 80),
+            Err(err) => panic!("expected a normal form, got: {:?}", err),
+        }
+    }
+
+    fn equiv(p1: &str, p2: &str) -> Equivalence {
+        let prog = |src| -> Program { parse_program(src).unwrap() };
+        equivalent(prog(p1), prog(p2)).unwrap()
+    }
+
+    #[test]
+    fn alpha_equivalence_renames_bound_variables() {
+        assert!(alpha("(lam [x] x)", "(lam [y] y)"));
+        assert!(alpha("(let ([x 1]) (+ x z))", "(let ([y 1]) (+ y z))"));
+        assert!(!alpha("(lam [x y] x)", "(lam [x y] y)"));
+        // free variables are compared by name.
+        assert!(alpha("(lam [x] z)", "(lam [y] z)"));
+        assert!(!alpha("(lam [x] z)", "(lam [z] z)"));
+        assert!(!alpha("(lam [x] z)", "(lam [x] w)"));
+    }
+
+    #[test]
+    fn normalizes_under_binders() {
+        assert_eq!(
+            normalized("(lam [x] ((lam [y] (+ y (* 2 3))) x))"),
+            "(lam [_n0] ((+ _n0) 6))"
+        );
+        assert_eq!(normalized("(let ([xs (list 1 2 3)]) (sum xs))"), "6");
+        assert_eq!(
+            normalized("(lam [b] (if b (fst (pair 1 2)) 3))"),
+            "(lam [_n0] (if _n0 1 3))"
+        );
+        // partial applications of PrimOps are eta expanded.
+        assert!(alpha_eq(
+            &normal("+").unwrap(),
+            &normal("(lam [a b] (+ a b))").unwrap()
+        ));
+        // a free variable is neutral.
+        assert_eq!(
+            normalized("(map (lam [x] x) ys)"),
+            "((map (lam [_n0] _n0)) ys)"
+        );
+    }
+
+    #[test]
+    fn unfolds_fix_on_known_arguments() {
+        let len = "(fix (lam [len n] (if (== n 0) 0 (+ 1 (len (- n 1))))))";
+        assert_eq!(normalized(&format!("({} 3)", len)), "3");
+        // on an unknown argument, the recursion is left as it is.
+        let normal = normalized(&format!("(lam [m] ({} m))", len));
+        assert!(normal.starts_with("(lam [_n0] ((fix"), "got: {}", normal);
+        let looping = "((fix (lam [f n] (f n))) 1)";
+        assert_eq!(normal_err(looping), NormalizeError::OutOfFuel);
+        assert_eq!(
+            normal_err("(+ 9223372036854775807 1)"),
+            NormalizeError::Overflow
+        );
+    }
+
+    #[test]
+    fn keeps_computations_which_may_not_terminate() {
+        let kept = |src, expected| {
+            let normal = normal(src).unwrap();
+            let pretty = to_pretty(normal.ppr(), 80);
+            assert!(
+                alpha_eq(&normal, &parse_expr(expected).unwrap()),
+                "got: {}",
+                pretty
+            );
+        };
+        kept(
+            "(lam [g] (let ([u (g 1)]) (fst (pair 2 (g 3)))))",
+            "(lam [g] (let ([a (g 1)]) (let ([b (g 3)]) 2)))",
+        );
+        // or in the branch of an `if` which may be taken.
+        kept(
+            "(lam [g b] (let ([u (if b (g 1) 2)]) 3))",
+            "(lam [g] (lam [b] (let ([u (if b (g 1) 2)]) 3)))",
+        );
+        assert_eq!(
+            normalized("(lam [b] (let ([u (if b 1 2)]) 3))"),
+            "(lam [_n0] 3)"
+        );
+    }
+
+    fn normal_err(src: &str) -> NormalizeError {
+        match normal(src) {
+            Err(err) => err,
+            Ok(expr) => panic!("expected an error, got: {}", to_pretty(expr.ppr(), 80)),
+        }
+    }
+
+    #[test]
+    fn equivalent_programs() {
+        assert_eq!(
+            equiv("(lam [xs] (sum xs))", "(lam [ys] (foldl + 0 ys))"),
+            Equivalence::Equivalent
+        );
+        assert_eq!(
+            equiv(
+                "(defn inc (lam [x] (+ x 1)))\n(lam [y] (inc (inc y)))",
+                "(lam [y] (+ (+ y 1) 1))"
+            ),
+            Equivalence::Equivalent
+        );
+        assert_eq!(
+            equiv("(params [x Int])\n(* x 2)", "(lam [y] (* y 2))"),
+            Equivalence::Equivalent
+        );
+    }
+
+    #[test]
+    fn inequivalent_programs() {
+        assert_eq!(
+            equiv("(lam [x] (+ x 1))", "(lam [x] (+ x 2))"),
+            Equivalence::Inequivalent
+        );
+        assert_eq!(
+            equiv("(lam [xs] (length xs))", "(lam [xs] (sum xs))"),
+            Equivalence::Inequivalent
+        );
+    }
+
+    #[test]
+    fn unknown_equivalence() {
+        // the same function, with a different normal form.
+        assert_eq!(
+            equiv("(lam [x y] (+ x y))", "(lam [x y] (+ y x))"),
+            Equivalence::Unknown
+        );
+        let len = "(fix (lam [len xs] (if (null xs) 0 (+ 1 (len (tail xs))))))";
+        let src = format!("(defn tail (lam [xs] xs))\n(lam [xs] ({} xs))", len);
+        assert_eq!(equiv(&src, &src), Equivalence::Equivalent);
+        // the recursion does not terminate on a non-empty list.
+        assert_eq!(equiv(&src, "(lam [xs] (length xs))"), Equivalence::Unknown);
+        // programs of different arities, whose inputs can't be shared, may be
+        // equal up to eta.
+        assert_eq!(
+            equiv("(lam [f] f)", "(lam [f x] (f x))"),
+            Equivalence::Unknown
+        );
+        assert_eq!(equiv("(lam [x y] x)", "(lam [x] x)"), Equivalence::Unknown);
+        // a recursion whose value is not used may still not terminate.
+        assert_eq!(
+            equiv(
+                "(lam [x] (let ([u ((fix (lam [f y] (f y))) x)]) 1))",
+                "(lam [x] 1)"
+            ),
+            Equivalence::Unknown
+        );
+    }
+
+    #[quickcheck]
+    fn normalize_agrees_with_eval(Typed(e): Typed) -> bool {
+        let val = eval(&e).ok().unwrap();
+        match (reify(&val), normalize(&TermEnv::new(), &e, 100_000)) {
+            (Some(expected), Ok(normal)) => expected == normal,
+            (None, Ok(_)) => true,
+            (_, Err(_)) => false,
+        }
+    }
+
+    #[quickcheck]
+    fn optimized_exprs_are_equivalent(Typed(e): Typed) -> bool {
+        let prog = |body| Program {
+            p_imports: vec![],
+            p_defns: vec![],
+            p_params: None,
+            p_body: body,
+        };
+        let opt = optimize(&e);
+        matches!(equivalent(prog(e), prog(opt)), Ok(Equivalence::Equivalent))
+    }
+}
@@ -0,0 +1,169 @@
+pub mod optimize_unit {
+    use quickcheck_macros::quickcheck;
+
+    use crate::app;
+    use crate::{
+        eval::{eval, eval_program, Value, Value::VInt},
+        normalize::alpha_eq,
+        optimize::{optimize, optimize_program},
+        parse::{parse_expr, parse_program},
+        syntax::{Expr, Lit, Program},
+        test::{ppr, Typed, TypedProgram},
+        toplevel::compile_calculation,
+        util::pretty::to_pretty,
+    };
+
+    fn optimized(src: &str) -> String {
+        to_pretty(optimize(&parse_expr(src).unwrap()).ppr(), 80)
+    }
+
+    #[test]
+    fn folds_constants() {
+        assert_eq!(optimized("(+ 1 (* 2 3))"), "7");
+        assert_eq!(optimized("(if (== 1 1) 2 3)"), "2");
+        assert_eq!(optimized("(null (cons 1 nil))"), "false");
+        assert_eq!(optimized("(snd (pair 1 true))"), "true");
+        assert_eq!(optimized("(lam [x] (+ x (- 3 1)))"), "(lam [x] ((+ x) 2))");
+    }
+
+    #[test]
+    fn does_not_fold_overflow() {
+        let src = "(+ 9223372036854775807 1)";
+        assert_eq!(
+            optimized(src),
+            to_pretty(parse_expr(src).unwrap().ppr(), 80)
+        );
+    }
+
+    #[test]
+    fn reduces_applied_lambdas() {
+        assert_eq!(optimized("((lam [x y] (+ x y)) 1 2)"), "3");
+        assert_eq!(optimized("(let ([f (lam [x] (* x x))]) (f 3))"), "9");
+        assert_eq!(optimized("(let ([op -]) (op 5 1))"), "4");
+        // an argument which is not a value is bound, rather than duplicated.
+        assert_eq!(
+            optimized("(lam [y] ((lam [x] (+ x x)) (* y y)))"),
+            "(lam [y] (let ([x ((* y) y)]) ((+ x) x)))"
+        );
+    }
+
+    #[test]
+    fn drops_only_total_dead_lets() {
+        assert_eq!(optimized("(let ([x (pair 1 nil)]) 2)"), "2");
+        // the binding might not terminate, so it is kept.
+        let src = "(let ([x ((fix (lam [f n] (f n))) 1)]) 2)";
+        assert_eq!(
+            optimized(src),
+            to_pretty(parse_expr(src).unwrap().ppr(), 80)
+        );
+    }
+
+    #[test]
+    fn substitution_avoids_capture() {
+        let expr = parse_expr("(let ([y 1]) ((lam [x] (lam [y] x)) y))").unwrap();
+        let applied = |e: Expr| app!(e, Expr::Lit(Lit::LInt(5)));
+        let opt = optimize(&expr);
+        assert_eq!(ppr(&eval(&applied(opt)).unwrap()), "1");
+        assert_eq!(ppr(&eval(&applied(expr)).unwrap()), "1");
+    }
+
+    #[test]
+    fn inlines_small_defns() {
+        let src =
+            "(defn double (lam [x] (* 2 x)))\n(defn k (double 4))\n(lam [y] (+ k (double y)))";
+        let prog = parse_program(src).unwrap();
+        let opt = optimize_program(&prog);
+        assert_eq!(opt.p_defns.len(), 2);
+        assert_eq!(to_pretty(opt.p_defns[1].1.ppr(), 80), "8");
+        assert_eq!(
+            to_pretty(opt.p_body.ppr(), 80),
+            "(lam [y] ((+ 8) ((* 2) y)))"
+        );
+        // a parameter shadows a `defn`.
+        let src = "(defn k 1)\n(params [k Int])\n(+ k 1)";
+        let opt = optimize_program(&parse_program(src).unwrap());
+        assert_eq!(to_pretty(opt.p_body.ppr(), 80), "((+ k) 1)");
+    }
+
+    #[test]
+    fn optimized_programs_agree() {
+        let src = "(defn double (lam [x] (* 2 x)))\n(defn xs (map double (list 1 2 3)))\n(sum (map (lam [x] (+ x (double 2))) xs))";
+        let prog = parse_program(src).unwrap();
+        let (expected, _env) = eval_program(&prog).ok().unwrap();
+        let (actual, _env) = eval_program(&optimize_program(&prog)).ok().unwrap();
+        assert_eq!(ppr(&expected), ppr(&actual));
+    }
+
+    // the value of a program, both before & after it is optimized. the
+    // optimized program must print as it parses.
+    fn both(prog: &Program, inputs: &[Value]) -> (String, String) {
+        let reduce = |prog: Program| {
+            let calc = compile_calculation(prog).unwrap();
+            ppr(&calc.reduce(&mut inputs.iter().cloned()).unwrap().value)
+        };
+        let opt = optimize_program(prog);
+        let printed = to_pretty(opt.ppr(), 80);
+        let parsed = parse_program(&printed).unwrap();
+        assert!(
+            alpha_eq(&parsed.body_expr(), &opt.body_expr()),
+            "in: {}",
+            printed
+        );
+        (reduce(prog.clone()), reduce(opt))
+    }
+
+    #[test]
+    fn optimized_programs_may_be_parsed() {
+        // the binder `x` of the inlined `defn` is renamed, not to capture the
+        // `x` of the body.
+        let src = "(defn f (lam [y x] (* x y)))\n(lam [x k] (f x))";
+        let prog = parse_program(src).unwrap();
+        let printed = to_pretty(optimize_program(&prog).p_body.ppr(), 80);
+        assert_eq!(printed, "(lam [x] (lam [k] (lam [xa] ((* xa) x))))");
+        let (expected, actual) = both(&prog, &[VInt(2), VInt(0), VInt(5)]);
+        assert_eq!(expected, "10");
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn inlining_avoids_capture() {
+        // `f` refers to the first `a`, so it is not inlined after the second.
+        let src = "(defn a (foldl + 0 (cons 1 nil)))\n(defn f (lam [x] (+ x a)))\n(defn a (foldl + 0 (cons 2 nil)))\n(f 0)";
+        let (expected, actual) = both(&parse_program(src).unwrap(), &[]);
+        assert_eq!(expected, "1");
+        assert_eq!(actual, expected);
+        // nor is it inlined where a parameter binds its global.
+        let src = "(defn w (foldl + 0 (cons 1 nil)))\n(defn f (lam [x] (+ x w)))\n(params [w Int])\n(f 100)";
+        let (expected, actual) = both(&parse_program(src).unwrap(), &[VInt(5)]);
+        assert_eq!(expected, "101");
+        assert_eq!(actual, expected);
+        // a `defn` which refers to the global it shadows.
+        let src = "(defn xs (list 1))\n(defn xs (cons 2 xs))\n(defn n (length xs))\n(defn xs nil)\n(pair n xs)";
+        let (expected, actual) = both(&parse_program(src).unwrap(), &[]);
+        assert_eq!(expected, "(2, (list))");
+        assert_eq!(actual, expected);
+    }
+
+    // functions are compared by their results.
+    fn result(e: Expr) -> String {
+        match eval(&e).ok().unwrap() {
+            Value::VClosure(_, _, _) | Value::VCore(_, _, _, _) => {
+                ppr(&eval(&app!(e, Expr::Lit(Lit::LInt(3)))).ok().unwrap())
+            }
+            val => ppr(&val),
+        }
+    }
+
+    #[quickcheck]
+    fn optimize_agrees_with_eval(Typed(e): Typed) -> bool {
+        let opt = optimize(&e);
+        let parsed = parse_expr(&to_pretty(opt.ppr(), 80)).unwrap();
+        alpha_eq(&parsed, &opt) && result(e) == result(opt)
+    }
+
+    #[quickcheck]
+    fn optimized_programs_agree_with_eval(TypedProgram(prog, inputs): TypedProgram) -> bool {
+        let (expected, actual) = both(&prog, &inputs);
+        expected == actual
+    }
+}
@@ -93,6 +93,11 @@ pub mod parse_unit {
         check_parse_expr!("(let ([v x]) x)", e6());
     }
 
+    #[test]
+    fn ex_qualified() {
+        check_parse_expr!("listutils.elem", Var(Name("listutils.elem".to_string())));
+    }
+
     #[test]
     fn ex_lit_1() {
         check_parse_expr!("1", Expr::Lit(Lit::LInt(1)));
@@ -192,6 +197,60 @@ pub mod parse_unit {
         let s = to_pretty(e2.ppr(), 80);
         check_parse_expr!(&s[..], e2);
     }
+
+    #[test]
+    fn program_unconsumed_input() {
+        match parse_program("(defn x 1)\n\nx\n\n(+ x 1)\n") {
+            Err(ParseError::UnconsumedInput(pos, rest)) => {
+                assert_eq!((pos.line, pos.column), (5, 1));
+                assert_eq!(rest, "(+ x 1)\n");
+            }
+            res => panic!("expected unconsumed input, got: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn program_syntax_error() {
+        match parse_program("(defn x 1)\n\n(lam [x] x") {
+            Err(ParseError::Syntax(errs)) => assert_eq!(errs.position.line, 3),
+            res => panic!("expected syntax error, got: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn types() {
+        use crate::types::*;
+        let a = Type::TVar(TV("a".to_string()));
+        assert_eq!(parse_type("Int").unwrap(), type_int());
+        assert_eq!(parse_type("(List a)").unwrap(), type_list(a.clone()));
+        assert_eq!(
+            parse_type("(Int, (List Bool))").unwrap(),
+            type_pair(type_int(), type_list(type_bool()))
+        );
+        let ty = type_arr(a, type_arr(type_int(), type_bool()));
+        assert_eq!(parse_type(&to_pretty(ty.ppr(), 80)).unwrap(), ty);
+    }
+
+    #[test]
+    fn program_params() {
+        let src = "(defn x 1)\n\n(params [ratings (List Int)] [weight Int])\n\nx";
+        let prog = parse_program(src).unwrap();
+        let params = prog.p_params.clone().unwrap();
+        assert_eq!(params.len(), 2);
+        assert_eq!(to_pretty(prog.ppr(), 80), src);
+        // a program may declare that it has no params.
+        let src = "(params)\n\nx";
+        let prog = parse_program(src).unwrap();
+        assert_eq!(prog.p_params, Some(vec![]));
+        assert_eq!(to_pretty(prog.ppr(), 80), src);
+    }
+
+    #[test]
+    fn program_fully_consumed() {
+        let prog = parse_program("(defn x 1)\n\nx\n").unwrap();
+        assert_eq!(prog.p_defns.len(), 1);
+        assert_eq!(prog.p_body, e0());
+    }
 }
 
 pub mod roundtrip {
@@ -207,9 +266,6 @@ pub mod roundtrip {
     fn parse_pretty_roundtrip(e: Expr) -> bool {
         let s = to_pretty(e.ppr(), 80);
         let res = expr().parse(easy::Stream(&s[..]));
-        match res {
-            Ok((_, stream)) if stream == easy::Stream("") => true,
-            _ => false,
-        }
+        matches!(res, Ok((_, easy::Stream(""))))
     }
 }
@@ -0,0 +1,78 @@
+pub mod prelude_unit {
+    use crate::{
+        env::Env,
+        eval::eval_program,
+        infer::{infer_program, TypeError},
+        parse::parse_program,
+        prelude::prelude,
+        syntax::Name,
+        toplevel::{get_calculation_type_with, CalculationOptions},
+        util::pretty::to_pretty,
+    };
+
+    fn run(src: &str) -> (String, String) {
+        let prog = parse_program(src).unwrap();
+        let (sc, _env) = infer_program(prelude().type_env.clone(), &prog).unwrap();
+        let (val, _term_env) = eval_program(&prog).unwrap();
+        (to_pretty(val.ppr(), 80), to_pretty(sc.ppr(), 80))
+    }
+
+    fn check(src: &str, val: &str, ty: &str) {
+        assert_eq!(run(src), (val.to_string(), ty.to_string()));
+    }
+
+    #[test]
+    fn bundled_prelude_checks() {
+        assert!(prelude().type_env.get(&Name("sum".to_string())).is_some());
+        assert!(prelude().term_env.contains_key(&Name("filter".to_string())));
+    }
+
+    #[test]
+    fn list_functions() {
+        check("(length (list 1 2 3))", "3", "Int");
+        check("(sum (list 1 2 3))", "6", "Int");
+        check("(product (list 2 3 4))", "24", "Int");
+        check("(reverse (list 1 2 3))", "(list 3 2 1)", "(List Int)");
+        check("(append (list 1 2) (list 3))", "(list 1 2 3)", "(List Int)");
+        check(
+            "(concat (list (list 1) nil (list 2 3)))",
+            "(list 1 2 3)",
+            "(List Int)",
+        );
+        check(
+            "(filter (lam [x] (not (== x 2))) (list 1 2 3))",
+            "(list 1 3)",
+            "(List Int)",
+        );
+        check("(elem 2 (list 1 2 3))", "true", "Bool");
+        check("(all (== 1) (list 1 2))", "false", "Bool");
+        check("(count (== 1) (list 1 2 1))", "2", "Int");
+    }
+
+    #[test]
+    fn combinators() {
+        check("(compose (+ 1) (flip - 1) 5)", "5", "Int");
+        check("(const (id 1) true)", "1", "Int");
+        check("(and true (or false true))", "true", "Bool");
+    }
+
+    #[test]
+    fn defns_shadow_prelude() {
+        check("(defn sum 7)\n\nsum", "7", "Int");
+    }
+
+    #[test]
+    fn opt_out() {
+        let prog = parse_program("(sum (list 1 2 3))").unwrap();
+        match infer_program(Env::new(), &prog) {
+            Err(TypeError::UnboundVariable(Name(nm))) => assert_eq!(nm, "sum"),
+            res => panic!("expected unbound variable, got: {:?}", res),
+        }
+        let options = CalculationOptions {
+            prelude: false,
+            ..CalculationOptions::default()
+        };
+        assert!(get_calculation_type_with(prog.clone(), &options).is_err());
+        assert!(get_calculation_type_with(prog, &CalculationOptions::default()).is_ok());
+    }
+}
@@ -0,0 +1,127 @@
+pub mod provenance_unit {
+    use crate::{
+        eval::Value::{self, *},
+        parse::parse_program,
+        provenance::{Derivation, Kind, Source},
+        test::{ints, ppr},
+        toplevel::{compile_calculation, CompiledCalculation},
+        util::pretty::to_pretty,
+    };
+
+    const SCORE: &str = r#"
+(defn bonus 10)
+
+(defn weigh
+  (lam [w x] (* w x)))
+
+(lam [w ratings]
+  (+ bonus (foldl (lam [acc x] (+ acc (weigh w x))) 0 ratings)))
+"#;
+
+    fn compiled(src: &str) -> CompiledCalculation {
+        compile_calculation(parse_program(src).unwrap()).unwrap()
+    }
+
+    fn explain(src: &str, inputs: Vec<Value>) -> Derivation {
+        match compiled(src).explain(&mut inputs.into_iter()) {
+            Ok((_output, derivation)) => derivation,
+            Err(err) => panic!("expected a derivation, got: {:?}", err),
+        }
+    }
+
+    fn find<'a>(d: &'a Derivation, pred: &dyn Fn(&Derivation) -> bool) -> Option<&'a Derivation> {
+        if pred(d) {
+            return Some(d);
+        }
+        d.children.iter().find_map(|child| find(child, pred))
+    }
+
+    #[test]
+    fn explains_the_result() {
+        let d = explain(SCORE, vec![VInt(2), ints(&[1, 2, 3])]);
+        assert_eq!(d.kind, Kind::Calculation);
+        assert_eq!(d.value, "22");
+        assert_eq!(d.children.len(), 2);
+        assert_eq!(d.children[0].kind, Kind::Defn);
+        assert_eq!(
+            (&d.children[0].expr[..], &d.children[0].value[..]),
+            ("bonus", "10")
+        );
+        assert_eq!(d.children[1].kind, Kind::Primitive);
+        assert_eq!(d.children[1].value, "12");
+    }
+
+    #[test]
+    fn explains_each_element_of_a_fold() {
+        let d = explain(SCORE, vec![VInt(2), ints(&[1, 2, 3])]);
+        let fold = match find(&d, &|d| d.kind == Kind::Primitive) {
+            Some(fold) => fold,
+            None => panic!("expected a fold, got: {}", to_pretty(d.ppr(), 80)),
+        };
+        // the list is an input.
+        assert!(fold
+            .children
+            .iter()
+            .any(|child| child.kind == Kind::Input && child.expr == "ratings"));
+        let elems: Vec<&Derivation> = fold
+            .children
+            .iter()
+            .filter(|child| matches!(child.kind, Kind::Element(_)))
+            .collect();
+        assert_eq!(elems.len(), 3);
+        for (ix, elem) in elems.iter().enumerate() {
+            assert_eq!(elem.kind, Kind::Element(ix));
+            let x = elem.bindings.iter().find(|binding| binding.name == "x");
+            assert_eq!(x.map(|x| &x.value[..]), Some(&(ix + 1).to_string()[..]));
+            let w = elem.bindings.iter().find(|binding| binding.name == "w");
+            assert_eq!(w.map(|w| &w.source), Some(&Source::Input));
+            // each element calls a defn.
+            let call = match find(elem, &|d| d.kind == Kind::Defn) {
+                Some(call) => call,
+                None => panic!("expected a call, got: {}", to_pretty(elem.ppr(), 80)),
+            };
+            assert_eq!(call.value, (2 * (ix + 1)).to_string());
+            // the `w` bound by the defn itself is not the input.
+            let inputs = call.children.iter().filter(|d| d.kind == Kind::Input);
+            assert_eq!(inputs.count(), 1);
+        }
+    }
+
+    #[test]
+    fn explains_declared_parameters() {
+        let src = "(params [ratings (List Int)] [weight Int])\n(* weight (sum ratings))";
+        let d = explain(src, vec![ints(&[1, 2]), VInt(3)]);
+        assert_eq!(d.expr, "((* weight) (sum ratings))");
+        assert_eq!(d.value, "9");
+        let inputs: Vec<&str> = d
+            .children
+            .iter()
+            .filter(|child| child.kind == Kind::Input)
+            .map(|child| &child.expr[..])
+            .collect();
+        assert_eq!(inputs, vec!["weight", "ratings"]);
+        // `sum` is from the prelude: its call is elided, but its fold is kept.
+        assert!(find(&d, &|d| d.kind == Kind::Primitive && d.value == "3").is_some());
+    }
+
+    #[test]
+    fn explaining_agrees_with_reducing() {
+        let calc = compiled(SCORE);
+        let inputs = vec![VInt(3), ints(&[4, 5])];
+        let reduced = calc.reduce(&mut inputs.clone().into_iter()).unwrap();
+        let (explained, d) = calc.explain(&mut inputs.into_iter()).unwrap();
+        assert_eq!(ppr(&reduced.value), ppr(&explained.value));
+        assert_eq!(ppr(&explained.value), d.value);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn derivations_serialize() {
+        let d = explain(SCORE, vec![VInt(2), ints(&[1])]);
+        let json = serde_json::to_value(&d).unwrap();
+        assert_eq!(json["kind"], "calculation");
+        assert_eq!(json["children"][0]["kind"], "defn");
+        let round_tripped: Derivation = serde_json::from_value(json).unwrap();
+        assert_eq!(round_tripped, d);
+    }
+}
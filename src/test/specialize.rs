@@ -0,0 +1,192 @@
+pub mod specialize_unit {
+    use quickcheck_macros::quickcheck;
+    use std::collections::HashMap;
+    use std::sync::OnceLock;
+
+    use crate::{
+        eval::{eval, Value, Value::*},
+        parse::{parse_expr, parse_program},
+        specialize::reify,
+        syntax::{Name, Program},
+        test::{ints, ppr},
+        toplevel::{
+            compile_calculation, specialize_calculation, specialize_calculation_named,
+            specialize_calculation_named_with, CalculationOptions, CompiledCalculation,
+            ReputationCalculationError,
+        },
+        util::pretty::to_pretty,
+    };
+
+    const SCORE: &str = r#"
+(defn bonus 10)
+
+(defn weigh
+  (lam [w x] (* w x)))
+
+(params [w Int] [ratings (List Int)] [boost Bool])
+
+(+ (if boost bonus 0) (foldl (lam [acc x] (+ acc (weigh w x))) 0 ratings))
+"#;
+
+    fn specialized(src: &str, known: &[Option<Value>]) -> (Program, String) {
+        match specialize_calculation(parse_program(src).unwrap(), known) {
+            Ok((prog, scheme)) => (prog, to_pretty(scheme.ppr(), 80)),
+            Err(err) => panic!("expected a residual program, got: {:?}", err),
+        }
+    }
+
+    fn reduced(prog: Program, inputs: Vec<Value>) -> String {
+        let calc = compile_calculation(prog).unwrap();
+        ppr(&calc.reduce(&mut inputs.into_iter()).unwrap().value)
+    }
+
+    #[test]
+    fn reified_values_evaluate_to_themselves() {
+        let val = VList(vec![
+            VPair(Box::new(VInt(1)), Box::new(ints(&[]))),
+            VPair(Box::new(VInt(-2)), Box::new(VList(vec![VBool(true)]))),
+        ]);
+        let expr = reify(&val).unwrap();
+        assert_eq!(ppr(&eval(&expr).ok().unwrap()), ppr(&val));
+        let closure = eval(&parse_expr("(lam [x] x)").unwrap()).ok();
+        assert!(closure.and_then(|val| reify(&val)).is_none());
+    }
+
+    #[test]
+    fn residual_programs_take_the_unknown_inputs() {
+        let (prog, scheme) = specialized(SCORE, &[Some(VInt(2)), None, Some(VBool(true))]);
+        let params: Vec<&str> = prog
+            .p_params
+            .iter()
+            .flatten()
+            .map(|param| &param.0 .0[..])
+            .collect();
+        assert_eq!(params, vec!["ratings"]);
+        assert_eq!(scheme, "((List Int) -> Int)");
+        // the test of the known input is gone.
+        assert!(!to_pretty(prog.p_body.ppr(), 80).contains("if"));
+        let full = compile_calculation(parse_program(SCORE).unwrap()).unwrap();
+        let inputs = vec![VInt(2), ints(&[1, 2, 3]), VBool(true)];
+        let expected = ppr(&full.reduce(&mut inputs.into_iter()).unwrap().value);
+        assert_eq!(reduced(prog, vec![ints(&[1, 2, 3])]), expected);
+    }
+
+    #[test]
+    fn known_lists_are_evaluated() {
+        let src = "(lam [ws xs] (+ (sum ws) (sum xs)))";
+        let (prog, scheme) = specialized(src, &[Some(ints(&[1, 2, 3]))]);
+        assert_eq!(
+            to_pretty(prog.p_body.ppr(), 80),
+            "(lam [xs] ((+ 6) (sum xs)))"
+        );
+        assert_eq!(scheme, "((List Int) -> Int)");
+        // with every input known, the residual program is its value.
+        let (prog, scheme) = specialized(src, &[Some(ints(&[1])), Some(ints(&[2]))]);
+        assert_eq!(to_pretty(prog.p_body.ppr(), 80), "3");
+        assert_eq!(scheme, "Int");
+    }
+
+    #[test]
+    fn eta_expanded_programs_may_be_parsed() {
+        for (src, expected, value) in [
+            ("(lam [w] (+ w))", "(lam [x] ((+ 3) x))", "5"),
+            // the expansion's name avoids those of the program.
+            (
+                "(defn x 1)\n(lam [w] (+ (+ w x)))",
+                "(lam [xa] ((+ 4) xa))",
+                "6",
+            ),
+            (
+                "(lam [w] (let ([x w]) (+ x)))",
+                "(lam [xa] ((+ 3) xa))",
+                "5",
+            ),
+        ] {
+            let (prog, _scheme) = specialized(src, &[Some(VInt(3))]);
+            let printed = to_pretty(prog.ppr(), 80);
+            assert!(printed.ends_with(expected), "in: {}", printed);
+            let parsed = parse_program(&printed).unwrap();
+            assert_eq!(to_pretty(parsed.ppr(), 80), printed);
+            assert_eq!(reduced(parsed, vec![VInt(2)]), value);
+        }
+    }
+
+    #[test]
+    fn inputs_beyond_the_params_stay_unknown() {
+        // the body takes one more input than is declared, & another itself.
+        for (src, known, inputs, expected) in [
+            (
+                "(defn f (lam [a b] (+ a b)))\n(params [x Int])\n(f x)",
+                vec![Some(VInt(1))],
+                vec![VInt(2)],
+                "3",
+            ),
+            (
+                "(params [x Int] [y Int])\n(lam [z w] (+ (* x z) (- y w)))",
+                vec![None, Some(VInt(3)), None],
+                vec![VInt(2), VInt(4), VInt(5)],
+                "6",
+            ),
+        ] {
+            let (prog, _scheme) = specialized(src, &known);
+            let printed = to_pretty(prog.ppr(), 80);
+            assert_eq!(reduced(parse_program(&printed).unwrap(), inputs), expected);
+        }
+    }
+
+    #[test]
+    fn inputs_may_be_known_by_name() {
+        let mut known = HashMap::new();
+        known.insert(Name("ratings".to_string()), ints(&[4, 5]));
+        let prog = parse_program(SCORE).unwrap();
+        let (prog, scheme) = specialize_calculation_named(prog, &known).unwrap();
+        assert_eq!(to_pretty(scheme.ppr(), 80), "(Int -> (Bool -> Int))");
+        assert_eq!(reduced(prog, vec![VInt(3), VBool(false)]), "27");
+        known.insert(Name("weight".to_string()), VInt(1));
+        let prog = parse_program(SCORE).unwrap();
+        let options = CalculationOptions::default();
+        match specialize_calculation_named_with(prog, &known, &options) {
+            Err(ReputationCalculationError::UnexpectedParameter(Name(nm))) => {
+                assert_eq!(nm, "weight")
+            }
+            res => panic!("expected unexpected parameter, got: {:?}", res.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn bad_inputs_are_rejected() {
+        let prog = || parse_program("(lam [x] (+ x 1))").unwrap();
+        match specialize_calculation(prog(), &[Some(VInt(1)), Some(VInt(2))]) {
+            Err(ReputationCalculationError::ArityMismatch(1, 2)) => (),
+            res => panic!("expected arity mismatch, got: {:?}", res.map(|_| ())),
+        }
+        match specialize_calculation(prog(), &[Some(VBool(true))]) {
+            Err(ReputationCalculationError::ProgramValuesUnificationError(_)) => (),
+            res => panic!("expected type error, got: {:?}", res.map(|_| ())),
+        }
+        let closure = eval(&parse_expr("(lam [y] y)").unwrap()).ok().unwrap();
+        match specialize_calculation(prog(), &[Some(closure)]) {
+            Err(ReputationCalculationError::NonDataInput(Name(nm))) => assert_eq!(nm, "x"),
+            res => panic!("expected non-data input, got: {:?}", res.map(|_| ())),
+        }
+    }
+
+    // each case specializes to one of the inputs, as compiling is slow.
+    #[quickcheck]
+    fn specialized_results_agree(w: i8, ratings: Vec<i8>, boost: bool) -> bool {
+        static FULL: OnceLock<CompiledCalculation> = OnceLock::new();
+        let full = FULL.get_or_init(|| compile_calculation(parse_program(SCORE).unwrap()).unwrap());
+        let w = VInt(w as i64);
+        let ratings = VList(ratings.into_iter().map(|x| VInt(x as i64)).collect());
+        let inputs = vec![w.clone(), ratings.clone(), VBool(boost)];
+        let expected = ppr(&full.reduce(&mut inputs.into_iter()).unwrap().value);
+        let actual = if boost {
+            let (prog, _scheme) = specialized(SCORE, &[Some(w)]);
+            reduced(prog, vec![ratings, VBool(boost)])
+        } else {
+            let (prog, _scheme) = specialized(SCORE, &[None, Some(ratings), Some(VBool(boost))]);
+            reduced(prog, vec![w])
+        };
+        expected == actual
+    }
+}
@@ -1,6 +1,5 @@
 use quickcheck::{empty_shrinker, single_shrinker, Arbitrary, Gen};
 use rand::Rng;
-use std::iter;
 
 use crate::parse::reserved;
 use crate::syntax::*;
@@ -11,7 +10,7 @@ impl Arbitrary for Expr {
     }
 
     fn shrink(&self) -> Box<dyn Iterator<Item = Expr>> {
-        match &*self {
+        match self {
             Expr::App(f, x) => {
                 let pairs = (f.clone(), x.clone())
                     .shrink()
@@ -48,7 +47,7 @@ impl Arbitrary for Expr {
                 Box::new(pairs.chain(tsts).chain(thns).chain(elss))
             }
             Expr::Fix(bd) => {
-                let chain = bd.shrink().map(|bd_| Expr::Fix(bd_));
+                let chain = bd.shrink().map(Expr::Fix);
                 let bds = single_shrinker(*bd.clone()).chain(bd.shrink().map(|v| *v));
                 Box::new(chain.chain(bds))
             }
@@ -117,7 +116,7 @@ impl Arbitrary for Name {
         let len = g.gen_range(3, 8);
         let res = reserved();
         loop {
-            let s = iter::repeat(gen_alpha_char(g)).take(len).collect();
+            let s = gen_alpha_char(g).to_string().repeat(len);
             if !res.contains(&s) {
                 return Name(s);
             }
@@ -149,7 +148,7 @@ fn gen_alpha_char<G: Gen>(g: &mut G) -> char {
     const ALPHA_CHARSET: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
     const RANGE: usize = ALPHA_CHARSET.len();
     let idx = g.gen_range(0, RANGE);
-    return ALPHA_CHARSET[idx as usize] as char;
+    ALPHA_CHARSET[idx] as char
 }
 
 // tests
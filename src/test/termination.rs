@@ -0,0 +1,119 @@
+pub mod termination_unit {
+    use crate::{
+        eval::Value::*,
+        native::Natives,
+        parse::{parse_program, parse_type},
+        syntax::Name,
+        termination::{check_termination, Reason, Recursion},
+        toplevel::{reduce_calculation_with, CalculationOptions},
+        types::Scheme,
+        util::pretty::to_pretty,
+    };
+
+    fn tail() -> Name {
+        Name("tail".to_string())
+    }
+
+    fn check(src: &str) -> Result<(), Vec<Recursion>> {
+        check_termination(&parse_program(src).unwrap(), &[tail()])
+    }
+
+    fn rejected(src: &str) -> Recursion {
+        match check(src) {
+            Err(mut found) if found.len() == 1 => found.remove(0),
+            res => panic!("expected one recursion, got: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn accepts_programs_without_fix() {
+        assert_eq!(check("(lam [xs] (sum (map (lam [x] (* x x)) xs)))"), Ok(()));
+        assert_eq!(check("(defn k 2)\n(lam [x] (+ k x))"), Ok(()));
+    }
+
+    #[test]
+    fn accepts_structural_recursion() {
+        for body in [
+            "(if (null xs) 0 (+ 1 (len (tail xs))))",
+            "(if (null xs) 0 (let ([rest (tail xs)]) (+ 1 (len rest))))",
+            "(if (null xs) 0 (if (null (tail xs)) 1 (+ 2 (len (tail (tail xs))))))",
+        ]
+        .iter()
+        {
+            let src = format!("(defn len (fix (lam [len xs] {})))\n(len nil)", body);
+            assert_eq!(check(&src), Ok(()), "in: {}", body);
+        }
+        // the list may be any parameter, so long as it is the same one.
+        let src = "(fix (lam [go n xs] (if (null xs) n (go (+ n 1) (tail xs)))))";
+        assert_eq!(check(src), Ok(()));
+        let src = "(fix (lam [go xs ys] (if (null xs) 0 (if (null ys) 0 (+ (go (tail xs) ys) (go xs (tail ys)))))))";
+        assert_eq!(rejected(src).reason, Reason::NotStructural);
+    }
+
+    #[test]
+    fn rejects_other_recursion() {
+        let found = rejected("(defn loop (fix (lam [f xs] (f xs))))\n(loop nil)");
+        assert_eq!(found.defn, Some(Name("loop".to_string())));
+        assert_eq!(found.reason, Reason::NotStructural);
+        assert_eq!(
+            to_pretty(found.fix.ppr(), 80),
+            "(fix (lam [f] (lam [xs] (f xs))))"
+        );
+        // without a test, the list may already be empty.
+        let found = rejected("(fix (lam [f xs] (f (tail xs))))");
+        assert_eq!((found.defn, found.reason), (None, Reason::NotStructural));
+        // the test must be of the parameter itself.
+        let src = "(lam [ys] (fix (lam [f xs] (if (null ys) 0 (f (tail xs))))))";
+        assert_eq!(rejected(src).reason, Reason::NotStructural);
+        let src = "(fix (lam [f n] (if (== n 0) 0 (f (- n 1)))))";
+        assert_eq!(rejected(src).reason, Reason::NotStructural);
+    }
+
+    #[test]
+    fn rejects_escaping_and_opaque_recursion() {
+        let src = "(fix (lam [f xs] (if (null xs) nil (map f xs))))";
+        assert_eq!(rejected(src).reason, Reason::Escapes);
+        assert_eq!(rejected("(lam [g] (fix g))").reason, Reason::Opaque);
+    }
+
+    #[test]
+    fn shadowed_names_are_not_trusted() {
+        // a `tail` bound by the program is not the destructor.
+        let src = "(defn tail (lam [xs] xs))\n(fix (lam [f xs] (if (null xs) 0 (f (tail xs)))))";
+        assert_eq!(rejected(src).reason, Reason::NotStructural);
+        let src = "(lam [tail] (fix (lam [f xs] (if (null xs) 0 (f (tail xs))))))";
+        assert_eq!(rejected(src).reason, Reason::NotStructural);
+        // including by a parameter of the `fix`, or the function itself.
+        let src =
+            "((fix (lam [f tail xs] (if (null xs) 0 (f tail (tail xs))))) (lam [l] l) (list 1))";
+        assert_eq!(rejected(src).reason, Reason::NotStructural);
+        let src = "(fix (lam [tail xs] (if (null xs) 0 (tail (tail xs)))))";
+        assert_eq!(rejected(src).reason, Reason::NotStructural);
+        // nor is a rebound parameter the parameter.
+        let src = "(lam [ys] (fix (lam [f xs] (if (null xs) 0 (let ([xs ys]) (f (tail xs)))))))";
+        assert_eq!(rejected(src).reason, Reason::NotStructural);
+    }
+
+    #[test]
+    fn checked_programs_reduce() {
+        let mut natives = Natives::new();
+        natives.register(
+            "tail",
+            Scheme(vec![], parse_type("((List a) -> (List a))").unwrap()),
+            |args| match &args[0] {
+                VList(xs) => Ok(VList(xs.iter().skip(1).cloned().collect())),
+                _ => panic!("tail: bad types"),
+            },
+        );
+        let options = CalculationOptions {
+            natives,
+            ..CalculationOptions::default()
+        };
+        let src = "(defn len (fix (lam [len xs] (if (null xs) 0 (+ 1 (len (tail xs)))))))\n(lam [xs] (len xs))";
+        let prog = parse_program(src).unwrap();
+        assert_eq!(check_termination(&prog, &[tail()]), Ok(()));
+        let input = VList(vec![VInt(4), VInt(5), VInt(6)]);
+        let output = reduce_calculation_with(prog, &mut vec![input].into_iter(), &options).unwrap();
+        assert_eq!(to_pretty(output.value.ppr(), 80), "3");
+    }
+}
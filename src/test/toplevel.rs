@@ -0,0 +1,348 @@
+pub mod toplevel_unit {
+    use std::collections::HashMap;
+
+    use crate::{
+        eval::{
+            EvalError, Limits,
+            Value::{self, *},
+        },
+        infer::TypeError,
+        parse::parse_program,
+        syntax::Name,
+        test::ints,
+        toplevel::{
+            compile_calculation, get_calculation_signature, reduce_calculation,
+            reduce_calculation_named, reduce_calculation_named_with, CalculationOptions,
+            ReputationCalculationError,
+        },
+        types::{Type, TV},
+        util::pretty::to_pretty,
+    };
+
+    const WEIGHTED: &str = r#"
+(defn weigh
+  (lam [w x] (* w x)))
+
+(lam [w ratings]
+  (sum (map (weigh w) ratings)))
+"#;
+
+    const NAMED: &str = r#"
+(params [ratings (List Int)] [weight Int])
+
+(* weight (sum ratings))
+"#;
+
+    fn named(inputs: Vec<(&str, Value)>) -> HashMap<Name, Value> {
+        inputs
+            .into_iter()
+            .map(|(nm, val)| (Name(nm.to_string()), val))
+            .collect()
+    }
+
+    #[test]
+    fn compiled_reduces_repeatedly() {
+        let compiled = compile_calculation(parse_program(WEIGHTED).unwrap()).unwrap();
+        assert_eq!(compiled.arity, 2);
+        for (w, ratings, expected) in &[(1, vec![1, 2, 3], "6"), (3, vec![1, 2], "9")] {
+            let out = compiled
+                .reduce(&mut vec![VInt(*w), ints(ratings)].into_iter())
+                .unwrap();
+            assert_eq!(to_pretty(out.value.ppr(), 80), *expected);
+            assert_eq!(
+                to_pretty(out.scheme.ppr(), 80),
+                "(Int -> ((List Int) -> Int))"
+            );
+        }
+    }
+
+    #[test]
+    fn reduce_matches_compiled() {
+        let inputs = vec![VInt(2), ints(&[4, 5])];
+        let out = reduce_calculation(parse_program(WEIGHTED).unwrap(), &mut inputs.into_iter());
+        assert_eq!(to_pretty(out.unwrap().value.ppr(), 80), "18");
+    }
+
+    #[test]
+    fn polymorphic_inputs() {
+        let prog = parse_program("(lam [x ls] (cons x ls))").unwrap();
+        let compiled = compile_calculation(prog).unwrap();
+        let out = compiled
+            .reduce(&mut vec![VBool(true), VList(vec![])].into_iter())
+            .unwrap();
+        assert_eq!(to_pretty(out.value.ppr(), 80), "(list true)");
+        let out = compiled
+            .reduce(&mut vec![VInt(1), ints(&[2])].into_iter())
+            .unwrap();
+        assert_eq!(to_pretty(out.value.ppr(), 80), "(list 1 2)");
+    }
+
+    #[test]
+    fn arity_mismatch() {
+        let compiled = compile_calculation(parse_program(WEIGHTED).unwrap()).unwrap();
+        match compiled.reduce(&mut vec![VInt(1)].into_iter()) {
+            Err(ReputationCalculationError::ArityMismatch(2, 1)) => (),
+            res => panic!("expected arity mismatch, got: {:?}", res.err()),
+        }
+    }
+
+    #[test]
+    fn input_type_mismatch() {
+        let compiled = compile_calculation(parse_program(WEIGHTED).unwrap()).unwrap();
+        match compiled.reduce(&mut vec![VBool(true), ints(&[1])].into_iter()) {
+            Err(ReputationCalculationError::ProgramValuesUnificationError(_)) => (),
+            res => panic!("expected unification error, got: {:?}", res.err()),
+        }
+    }
+
+    #[test]
+    fn named_parameters() {
+        let prog = parse_program(NAMED).unwrap();
+        let inputs = named(vec![("weight", VInt(2)), ("ratings", ints(&[1, 2, 3]))]);
+        let out = reduce_calculation_named(prog, &inputs).unwrap();
+        assert_eq!(to_pretty(out.value.ppr(), 80), "12");
+        let options = CalculationOptions {
+            limits: Limits {
+                max_steps: Some(1),
+                max_depth: None,
+            },
+            ..CalculationOptions::default()
+        };
+        let prog = parse_program(NAMED).unwrap();
+        match reduce_calculation_named_with(prog, &inputs, &options) {
+            Err(ReputationCalculationError::EvalError(EvalError::StepLimitExceeded(_))) => (),
+            res => panic!("expected a step limit error, got: {:?}", res.err()),
+        }
+    }
+
+    #[test]
+    fn named_parameters_positional() {
+        let compiled = compile_calculation(parse_program(NAMED).unwrap()).unwrap();
+        let out = compiled
+            .reduce(&mut vec![ints(&[1, 2]), VInt(3)].into_iter())
+            .unwrap();
+        assert_eq!(to_pretty(out.value.ppr(), 80), "9");
+    }
+
+    #[test]
+    fn named_parameters_missing_and_extra() {
+        let compiled = compile_calculation(parse_program(NAMED).unwrap()).unwrap();
+        match compiled.reduce_named(&named(vec![("weight", VInt(2))])) {
+            Err(ReputationCalculationError::MissingParameter(Name(nm))) => {
+                assert_eq!(nm, "ratings")
+            }
+            res => panic!("expected missing parameter, got: {:?}", res.err()),
+        }
+        let inputs = named(vec![
+            ("weight", VInt(2)),
+            ("ratings", ints(&[1])),
+            ("bonus", VInt(1)),
+        ]);
+        match compiled.reduce_named(&inputs) {
+            Err(ReputationCalculationError::UnexpectedParameter(Name(nm))) => {
+                assert_eq!(nm, "bonus")
+            }
+            res => panic!("expected unexpected parameter, got: {:?}", res.err()),
+        }
+    }
+
+    #[test]
+    fn named_parameters_type_error() {
+        let compiled = compile_calculation(parse_program(NAMED).unwrap()).unwrap();
+        let inputs = named(vec![("weight", VBool(true)), ("ratings", ints(&[1]))]);
+        match compiled.reduce_named(&inputs) {
+            Err(ReputationCalculationError::ParameterTypeError(Name(nm), _)) => {
+                assert_eq!(nm, "weight")
+            }
+            res => panic!("expected parameter type error, got: {:?}", res.err()),
+        }
+    }
+
+    #[test]
+    fn named_parameters_undeclared() {
+        let compiled = compile_calculation(parse_program(WEIGHTED).unwrap()).unwrap();
+        match compiled.reduce_named(&named(vec![("w", VInt(1))])) {
+            Err(ReputationCalculationError::NoDeclaredParameters) => (),
+            res => panic!("expected no declared parameters, got: {:?}", res.err()),
+        }
+    }
+
+    #[test]
+    fn declared_types_are_checked() {
+        let prog = parse_program(
+            "(params [x Bool])
+
+(+ x 1)",
+        )
+        .unwrap();
+        match compile_calculation(prog) {
+            Err(ReputationCalculationError::ProgramTypeInferenceError(
+                TypeError::UnificationFail(_, _),
+            )) => (),
+            res => panic!("expected type error, got: {:?}", res.err()),
+        }
+        let prog = parse_program(
+            "(params [xs (List a)])
+
+(length xs)",
+        )
+        .unwrap();
+        let compiled = compile_calculation(prog).unwrap();
+        assert_eq!(
+            to_pretty(compiled.scheme.ppr(), 80),
+            "forall t1. ((List t1) -> Int)"
+        );
+    }
+
+    #[test]
+    fn duplicate_parameters() {
+        let prog = parse_program(
+            "(params [x Int] [x Bool])
+
+(if x 1 2)",
+        )
+        .unwrap();
+        match compile_calculation(prog) {
+            Err(ReputationCalculationError::ProgramTypeInferenceError(
+                TypeError::DuplicateParameter(Name(nm)),
+            )) => assert_eq!(nm, "x"),
+            res => panic!("expected a duplicate parameter, got: {:?}", res.err()),
+        }
+    }
+
+    fn int() -> Type {
+        Type::TCon("Int".to_string())
+    }
+
+    #[test]
+    fn signature_from_params() {
+        let sig = get_calculation_signature(parse_program(NAMED).unwrap()).unwrap();
+        let params: Vec<_> = sig
+            .parameters
+            .iter()
+            .map(|p| (p.name.clone(), p.ty.clone()))
+            .collect();
+        assert_eq!(
+            params,
+            vec![
+                (
+                    Some(Name("ratings".to_string())),
+                    Type::TList(Box::new(int()))
+                ),
+                (Some(Name("weight".to_string())), int()),
+            ]
+        );
+        assert_eq!(sig.return_type, int());
+        assert!(sig.type_variables.is_empty());
+    }
+
+    #[test]
+    fn signature_from_lambda() {
+        let compiled = compile_calculation(parse_program(WEIGHTED).unwrap()).unwrap();
+        let sig = compiled.signature();
+        let names: Vec<_> = sig.parameters.iter().map(|p| p.name.clone()).collect();
+        assert_eq!(
+            names,
+            vec![
+                Some(Name("w".to_string())),
+                Some(Name("ratings".to_string()))
+            ]
+        );
+        assert_eq!(
+            sig,
+            get_calculation_signature(parse_program(WEIGHTED).unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn signature_unnamed_and_polymorphic() {
+        let prog = parse_program(
+            "(defn swap (lam [x y] (pair y x)))
+
+swap",
+        )
+        .unwrap();
+        let sig = get_calculation_signature(prog).unwrap();
+        assert_eq!(sig.parameters.len(), 2);
+        assert!(sig.parameters.iter().all(|p| p.name.is_none()));
+        assert_eq!(sig.type_variables.len(), 2);
+        let tvs: Vec<Type> = sig.type_variables.iter().cloned().map(Type::TVar).collect();
+        assert_eq!(
+            sig.return_type,
+            Type::TPair(Box::new(tvs[1].clone()), Box::new(tvs[0].clone()))
+        );
+        assert_eq!(sig.parameters[0].ty, tvs[0]);
+        assert!(sig.type_variables.iter().all(|TV(nm)| !nm.is_empty()));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn signature_json() {
+        let sig = get_calculation_signature(parse_program(NAMED).unwrap()).unwrap();
+        let json = serde_json::to_value(&sig).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "parameters": [
+                    {"name": "ratings", "type": {"list": {"con": "Int"}}},
+                    {"name": "weight", "type": {"con": "Int"}}
+                ],
+                "return_type": {"con": "Int"},
+                "type_variables": []
+            })
+        );
+        let back: crate::toplevel::CalculationSignature = serde_json::from_value(json).unwrap();
+        assert_eq!(back, sig);
+    }
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn shareable_between_threads() {
+        assert_send_sync::<Value>();
+        assert_send_sync::<crate::eval::TermEnv>();
+        assert_send_sync::<crate::toplevel::CompiledCalculation>();
+        assert_send_sync::<crate::toplevel::CalculationOptions>();
+        assert_send_sync::<crate::engine::Engine>();
+        assert_send_sync::<crate::native::Natives>();
+
+        let compiled = compile_calculation(parse_program(WEIGHTED).unwrap()).unwrap();
+        let handles: Vec<_> = (0..4)
+            .map(|w| {
+                let compiled = compiled.clone();
+                std::thread::spawn(move || {
+                    let out = compiled
+                        .reduce(&mut vec![VInt(w), ints(&[1, 2])].into_iter())
+                        .unwrap();
+                    to_pretty(out.value.ppr(), 80)
+                })
+            })
+            .collect();
+        let results: Vec<String> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(results, vec!["0", "3", "6", "9"]);
+    }
+
+    #[test]
+    fn reduce_batch() {
+        let compiled = compile_calculation(parse_program(WEIGHTED).unwrap()).unwrap();
+        let batch: Vec<Vec<Value>> = (0..25)
+            .map(|w| vec![VInt(w), ints(&[w, 1])])
+            .chain(vec![vec![VInt(1)]])
+            .collect();
+        for threads in &[1, 3, 64] {
+            let results = compiled.reduce_batch_on(&batch, *threads);
+            assert_eq!(results.len(), 26);
+            for (w, res) in results.iter().take(25).enumerate() {
+                let w = w as i64;
+                let out = res.as_ref().ok().unwrap();
+                assert_eq!(to_pretty(out.value.ppr(), 80), (w * (w + 1)).to_string());
+            }
+            match &results[25] {
+                Err(ReputationCalculationError::ArityMismatch(2, 1)) => (),
+                res => panic!("expected arity mismatch, got: {:?}", res.as_ref().err()),
+            }
+        }
+        assert!(compiled.reduce_batch(&[]).is_empty());
+        assert_eq!(compiled.reduce_batch(&batch[..3]).len(), 3);
+    }
+}
@@ -0,0 +1,147 @@
+pub mod trace_unit {
+    use crate::{
+        engine::{Engine, EngineError},
+        eval::{eval_, EvalError, EvalState, Limits, TermEnv},
+        parse::{parse_defn_or_it_expr, parse_expr},
+        syntax::{Expr, Name},
+        test::ppr,
+        toplevel::CalculationOptions,
+        trace::{eval_traced, Trace},
+        util::pretty::to_pretty,
+    };
+
+    fn trace(src: &str) -> Trace {
+        let expr = parse_expr(src).unwrap();
+        let (res, trace) = eval_traced(&TermEnv::new(), &mut EvalState::new(), &expr);
+        assert!(res.is_ok(), "in: {}", src);
+        trace
+    }
+
+    fn traced_in(engine: &mut Engine, src: &str) -> Trace {
+        let (trace, res) = engine.eval_traced(&parse_expr(src).unwrap());
+        assert!(res.is_ok(), "in: {}", src);
+        trace
+    }
+
+    #[test]
+    fn steps_record_bindings_and_values() {
+        let trace = trace("(let ([x 2]) (+ x 1))");
+        let steps = trace.steps();
+        // the first step is the whole expression.
+        assert_eq!(steps[0].expr, parse_expr("(let ([x 2]) (+ x 1))").unwrap());
+        assert_eq!(steps[0].value.as_ref().map(ppr), Some("3".to_string()));
+        assert!(steps[0].bindings.is_empty());
+        let x = Name("x".to_string());
+        match steps.iter().find(|step| step.expr == Expr::Var(x.clone())) {
+            Some(step) => {
+                assert_eq!(step.bindings.len(), 1);
+                assert_eq!(step.bindings[0].0, x);
+                assert_eq!(ppr(&step.bindings[0].1), "2");
+                assert_eq!(step.value.as_ref().map(ppr), Some("2".to_string()));
+                assert!(step.depth > steps[0].depth);
+            }
+            None => panic!("expected a step evaluating x, got: {}", trace.len()),
+        }
+    }
+
+    #[test]
+    fn steps_are_in_order_of_evaluation() {
+        let trace = trace("(if (== 1 2) 3 (+ 4 5))");
+        let exprs: Vec<String> = trace
+            .steps()
+            .iter()
+            .map(|step| to_pretty(step.expr.ppr(), 80))
+            .collect();
+        assert_eq!(
+            exprs,
+            vec![
+                "(if ((== 1) 2) 3 ((+ 4) 5))",
+                "((== 1) 2)",
+                "1",
+                "2",
+                "((+ 4) 5)",
+                "4",
+                "5"
+            ]
+        );
+        let depths: Vec<usize> = trace.steps().iter().map(|step| step.depth).collect();
+        assert_eq!(depths, vec![0, 1, 2, 2, 1, 2, 2]);
+        let lines: Vec<String> = to_pretty(trace.ppr(), 80)
+            .lines()
+            .map(|line| line.to_string())
+            .collect();
+        assert_eq!(lines[0], "(if ((== 1) 2) 3 ((+ 4) 5)) => 9");
+        assert_eq!(lines[2], "    1 => 1");
+        assert_eq!(lines.len(), 7);
+    }
+
+    #[test]
+    fn failed_steps_have_no_value() {
+        let limits = Limits {
+            max_steps: Some(100),
+            ..Limits::default()
+        };
+        let expr = parse_expr("(+ 1 ((fix (lam [f x] (f x))) 1))").unwrap();
+        let (res, trace) = eval_traced(&TermEnv::new(), &mut EvalState::with_limits(limits), &expr);
+        match res {
+            Err(EvalError::StepLimitExceeded(100)) => (),
+            res => panic!(
+                "expected step limit error, got: {:?}",
+                res.map(|val| ppr(&val))
+            ),
+        }
+        assert_eq!(trace.len(), 100);
+        assert!(trace.steps()[0].value.is_none());
+        assert_eq!(
+            trace.steps()[1].value.as_ref().map(ppr),
+            Some("1".to_string())
+        );
+    }
+
+    #[test]
+    fn tracing_is_off_by_default() {
+        let mut es = EvalState::new();
+        eval_(&TermEnv::new(), &mut es, &parse_expr("(+ 1 2)").unwrap())
+            .ok()
+            .unwrap();
+        assert!(es.take_trace().is_none());
+    }
+
+    #[test]
+    fn breakpoints_stop_once_per_call() {
+        let mut engine = Engine::with_options(CalculationOptions::default());
+        for src in [
+            "(defn double (lam [x] (* 2 x)))",
+            "(defn add (lam [x y] (+ x y)))",
+        ]
+        .iter()
+        {
+            let defn = parse_defn_or_it_expr(src).unwrap();
+            engine.define(&defn).ok().unwrap();
+        }
+        let double = || Name("double".to_string());
+        let add = || Name("add".to_string());
+        let trace = traced_in(&mut engine, "(add (double 1) (add (double 2) 3))");
+        let hits = |names: &[Name]| trace.breakpoints(names).len();
+        assert_eq!(hits(&[double()]), 2);
+        assert_eq!(hits(&[add()]), 2);
+        assert_eq!(hits(&[double(), add()]), 4);
+        // the steps of a breakpoint are calls to the named defn.
+        for ix in trace.breakpoints(&[double()]) {
+            assert_eq!(trace.steps()[ix].head(), Some(&double()));
+        }
+    }
+
+    #[test]
+    fn engine_traces_fail_without_type_errors() {
+        let mut engine = Engine::new();
+        let (trace, res) = engine.eval_traced(&parse_expr("(+ 1 true)").unwrap());
+        match res {
+            Err(EngineError::Type(_)) => assert!(trace.is_empty()),
+            res => panic!(
+                "expected type error, got: {:?}",
+                res.map(|(val, _)| ppr(&val))
+            ),
+        }
+    }
+}
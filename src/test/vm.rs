@@ -0,0 +1,121 @@
+pub mod vm_unit {
+    use crate::{
+        env::Env,
+        eval::{eval_, EvalError, EvalState, Limits, TermEnv, Value},
+        infer::infer_expr,
+        parse::parse_expr,
+        prelude::prelude,
+        syntax::{Name, PrimOp},
+        test::{ppr, Typed},
+        vm::{compile, eval_compiled, run, CompileError, Instr},
+    };
+
+    // evaluate `src` with the prelude in scope, both by `eval_` & by the vm.
+    fn both(src: &str) -> (String, String) {
+        let expr = parse_expr(src).unwrap();
+        let env = &prelude().term_env;
+        let expected = eval_(env, &mut EvalState::new(), &expr).ok().unwrap();
+        let actual = eval_compiled(env, &mut EvalState::new(), &expr)
+            .ok()
+            .unwrap();
+        (ppr(&expected), ppr(&actual))
+    }
+
+    fn vm_limited(src: &str, limits: Limits) -> Result<Value, EvalError> {
+        let expr = parse_expr(src).unwrap();
+        eval_compiled(&TermEnv::new(), &mut EvalState::with_limits(limits), &expr)
+    }
+
+    #[test]
+    fn agrees_with_eval() {
+        let cases = [
+            "(sum (map (+ 1) (list 1 2 3)))",
+            "(foldl - 0 (list 1 2 3))",
+            "(let ([p pair]) (p 1 true))",
+            "(let ([x 1]) (let ([f (lam [y] (+ x y))]) (let ([x 10]) (f x))))",
+            "(map fst (list (pair 1 true) (pair 2 false)))",
+            "(filter (lam [x] (not (== x 2))) (list 1 2 3))",
+            "(elem 3 (reverse (list 1 2 3)))",
+            "((compose (* 2) (+ 1)) 3)",
+            "((fix (lam [f n] (if (== n 0) 0 (+ 1 (f (- n 1)))))) 50)",
+            "(lam [x] x)",
+        ];
+        for src in cases.iter() {
+            let (expected, actual) = both(src);
+            assert_eq!(expected, actual, "in: {}", src);
+        }
+    }
+
+    #[test]
+    fn primop_applications_are_compiled() {
+        let expr = parse_expr("(map (+ 1) (list 1 2))").unwrap();
+        let chunk = compile(&TermEnv::new(), &expr).unwrap();
+        // `(+ 1)` is eta-expanded once, into a lambda applying `+` directly.
+        match chunk.protos() {
+            [proto] => assert!(proto.chunk.code().contains(&Instr::Prim(PrimOp::Add))),
+            protos => panic!("expected a single proto, got: {}", protos.len()),
+        }
+        assert!(chunk.code().contains(&Instr::Prim(PrimOp::Map)));
+        let val = run(&mut EvalState::new(), &chunk).ok().unwrap();
+        assert_eq!(ppr(&val), "(list 2 3)");
+    }
+
+    #[test]
+    fn recursion_does_not_use_the_native_stack() {
+        let src = "((fix (lam [f n] (if (== n 0) 0 (+ 1 (f (- n 1)))))) 100000)";
+        let val = vm_limited(src, Limits::default()).ok().unwrap();
+        assert_eq!(ppr(&val), "100000");
+    }
+
+    #[test]
+    fn limits() {
+        let max_steps = Limits {
+            max_steps: Some(1000),
+            ..Limits::default()
+        };
+        match vm_limited("((fix (lam [f x] (f x))) 1)", max_steps) {
+            Err(EvalError::StepLimitExceeded(1000)) => (),
+            res => panic!(
+                "expected step limit error, got: {:?}",
+                res.as_ref().map(ppr)
+            ),
+        }
+        let max_depth = Limits {
+            max_depth: Some(100),
+            ..Limits::default()
+        };
+        let src = "((fix (lam [f n] (if (== n 0) 0 (+ 1 (f (- n 1)))))) 1000)";
+        match vm_limited(src, max_depth) {
+            Err(EvalError::DepthLimitExceeded(100)) => (),
+            res => panic!(
+                "expected depth limit error, got: {:?}",
+                res.as_ref().map(ppr)
+            ),
+        }
+        // tail calls take no depth.
+        let src = "((fix (lam [f n] (if (== n 0) 0 (f (- n 1))))) 1000)";
+        assert!(vm_limited(src, max_depth).is_ok());
+    }
+
+    #[test]
+    fn unbound_variables() {
+        let expr = parse_expr("(lam [x] (+ x y))").unwrap();
+        match compile(&TermEnv::new(), &expr) {
+            Err(CompileError::UnboundVariable(Name(nm))) => assert_eq!(nm, "y"),
+            Ok(_) => panic!("expected unbound variable, got a chunk"),
+        }
+    }
+
+    #[quickcheck]
+    fn typed_exprs_are_well_typed(Typed(e): Typed) -> bool {
+        infer_expr(&Env::new(), &e).is_ok()
+    }
+
+    #[quickcheck]
+    fn vm_agrees_with_eval(Typed(e): Typed) -> bool {
+        let env = TermEnv::new();
+        let expected = eval_(&env, &mut EvalState::new(), &e).ok().unwrap();
+        let actual = eval_compiled(&env, &mut EvalState::new(), &e).ok().unwrap();
+        ppr(&expected) == ppr(&actual)
+    }
+}
@@ -1,14 +1,21 @@
-use combine::parser::Parser;
-use combine::stream::easy;
-use std::collections::HashMap;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{cmp, collections::HashMap, thread};
 
 use super::{
+    cost::{self, BoundError, CostTable},
     env::Env,
     eval,
-    infer::{infer_program, infer_program_with_is, unify_many, TypeError},
-    parse::program,
-    syntax,
-    syntax::{Expr, Name},
+    infer::{infer_program, infer_program_with_is, unify_many, InferState, Subst, TypeError},
+    lazy, lower,
+    module::{MemoryResolver, ModuleError, ModuleLoader, ModuleResolver},
+    native::Natives,
+    normalize::{self, Equivalence},
+    optimize,
+    parse::{parse_program, ParseError},
+    provenance::Derivation,
+    specialize, syntax,
+    syntax::{Expr, Name, Param},
     types, types_values,
     types_values::ValueInferenceError,
 };
@@ -17,13 +24,69 @@ use crate::app;
 // META TODO:
 // uses of `Result<_, String>` are unprincipled. it's better to return a specific error type.
 
-/// throws an error if the document doesn’t pass type checking
-pub fn parse_calculation(dsl_document: String) -> Result<syntax::Program, String> {
-    match program().parse(easy::Stream(&dsl_document[..])) {
-        // TODO this is janky - perhaps we can just return the error?
-        // https://docs.rs/combine/4.5.2/combine/trait.StreamOnce.html#associatedtype.Error
-        Err(err) => Err(format!("parse error: {}", err)),
-        Ok((prog, _extra_input)) => Ok(prog),
+/// throws an error if the document doesn’t parse, or if it leaves input
+/// unconsumed (e.g. a stray second body expression).
+pub fn parse_calculation(dsl_document: String) -> Result<syntax::Program, ParseError> {
+    parse_program(&dsl_document)
+}
+
+/// options controlling the environment in which calculations are checked &
+/// reduced.
+pub struct CalculationOptions {
+    /// resolves the program's imports. by default, no imports can be resolved.
+    pub resolver: Box<dyn ModuleResolver>,
+    /// whether the prelude is in scope. on by default.
+    pub prelude: bool,
+    /// host functions in scope, in the program & in its imports. none by default.
+    pub natives: Natives,
+    /// bounds on the evaluation of the program's `defn`s, and of each
+    /// reduction. unbounded by default.
+    pub limits: eval::Limits,
+    /// the order of evaluation of the program. strict by default.
+    pub strategy: eval::Strategy,
+    /// the cost of each operation, in gas (see `cost`).
+    pub costs: CostTable,
+}
+
+impl Default for CalculationOptions {
+    fn default() -> Self {
+        CalculationOptions {
+            resolver: Box::new(MemoryResolver::new()),
+            prelude: true,
+            natives: Natives::new(),
+            limits: eval::Limits::default(),
+            strategy: eval::Strategy::default(),
+            costs: CostTable::default(),
+        }
+    }
+}
+
+impl CalculationOptions {
+    /// a module loader which resolves imports, & provides the prelude &
+    /// natives, according to these options.
+    pub fn loader(&self) -> ModuleLoader<'_> {
+        let loader = ModuleLoader::new(self.resolver.as_ref()).with_natives(&self.natives);
+        if self.prelude {
+            loader
+        } else {
+            loader.without_prelude()
+        }
+    }
+
+    /// a fresh evaluation state, with these limits & strategy.
+    pub fn eval_state(&self) -> eval::EvalState {
+        let mut es = eval::EvalState::with_limits(self.limits);
+        es.set_strategy(self.strategy);
+        es.set_costs(self.costs.clone());
+        es
+    }
+
+    // load the prelude, the natives & the program's imports.
+    fn load_program_env(
+        &self,
+        prog: &syntax::Program,
+    ) -> Result<(Env, eval::TermEnv), ModuleError> {
+        self.loader().load_program(prog)
     }
 }
 
@@ -31,18 +94,108 @@ pub fn parse_calculation(dsl_document: String) -> Result<syntax::Program, String
 /// the `Type` contained within can be fed to `type_arguments` & `type_return`.
 // types::Type::TRelated(op, Type, Type) needs to be implemented to support calculated / derived units
 pub fn get_calculation_type(program: syntax::Program) -> Result<types::Scheme, String> {
-    match infer_program(Env::new(), &program) {
+    get_calculation_type_with(program, &CalculationOptions::default())
+}
+
+pub fn get_calculation_type_with(
+    program: syntax::Program,
+    options: &CalculationOptions,
+) -> Result<types::Scheme, String> {
+    let envs = options.load_program_env(&program);
+    let (env, _term_env) = match envs {
+        Ok(envs) => envs,
+        Err(err) => return Err(format!("module error: {:?}", err)),
+    };
+    match infer_program(env, &program) {
         Ok((sc, _env)) => Ok(sc),
         Err(err) => Err(format!("type error: {:?}", err)),
     }
 }
 
+/// a parameter of a calculation.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ParameterSignature {
+    /// the parameter's name, if known: either declared in a `params` form, or
+    /// bound by a `lam` which is the body of the calculation.
+    pub name: Option<Name>,
+    #[cfg_attr(feature = "serde", serde(rename = "type"))]
+    pub ty: types::Type,
+}
+
+/// the signature of a calculation: what its inputs are, and what it returns.
+/// this is the structured counterpart of the `Scheme` returned by
+/// `get_calculation_type`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CalculationSignature {
+    pub parameters: Vec<ParameterSignature>,
+    pub return_type: types::Type,
+    /// the type variables quantified over by the calculation's scheme, in
+    /// order of first appearance.
+    pub type_variables: Vec<types::TV>,
+}
+
+impl CalculationSignature {
+    /// build the signature of a calculation from its scheme & its body
+    /// (abstracted over any declared parameters, as by `Program::body_expr`).
+    pub fn new(scheme: &types::Scheme, body: &Expr) -> CalculationSignature {
+        let types::Scheme(tvs, ty) = scheme;
+        let mut names = Vec::new();
+        let mut bd = body;
+        while let Expr::Lam(nm, inner) = bd {
+            names.push(nm.clone());
+            bd = inner;
+        }
+        let mut names = names.into_iter();
+        let parameters = types::type_arguments(ty)
+            .into_iter()
+            .map(|ty| ParameterSignature {
+                name: names.next(),
+                ty,
+            })
+            .collect();
+        let type_variables = types::type_variables(ty)
+            .into_iter()
+            .filter(|tv| tvs.contains(tv))
+            .collect();
+        CalculationSignature {
+            parameters,
+            return_type: types::type_return(ty),
+            type_variables,
+        }
+    }
+}
+
+/// return the signature of a program, with the default `CalculationOptions`.
+pub fn get_calculation_signature(
+    program: syntax::Program,
+) -> Result<CalculationSignature, ReputationCalculationError> {
+    get_calculation_signature_with(program, &CalculationOptions::default())
+}
+
+pub fn get_calculation_signature_with(
+    program: syntax::Program,
+    options: &CalculationOptions,
+) -> Result<CalculationSignature, ReputationCalculationError> {
+    let (env, _term_env) = options
+        .load_program_env(&program)
+        .map_err(ReputationCalculationError::ModuleError)?;
+    let (sc, _env) = infer_program(env, &program)
+        .map_err(ReputationCalculationError::ProgramTypeInferenceError)?;
+    Ok(CalculationSignature::new(&sc, &program.body_expr()))
+}
+
 pub struct ReputationCalculationOutput {
     pub rcr_calculation: syntax::Expr,
     pub scheme: types::Scheme,
     pub value: eval::Value,
+    /// the gas used by the reduction, not counting the evaluation of the
+    /// program's `defn`s.
+    pub gas: u64,
 }
 
+#[derive(Debug)]
 pub enum ReputationCalculationError {
     // format!("arity mismatch: program body: {}; value iterator: {}", body_arity, values_arity)
     ArityMismatch(usize, usize),
@@ -50,81 +203,674 @@ pub enum ReputationCalculationError {
     ProgramValuesUnificationError(TypeError),
     ValuesIterTypeError(TypeError),
     ValuesIterPassedClosure(Name, Box<Expr>),
+    ModuleError(ModuleError),
+    /// inputs were passed by name, but the program does not declare `params`.
+    NoDeclaredParameters,
+    MissingParameter(Name),
+    UnexpectedParameter(Name),
+    /// the input passed for the named parameter does not agree with its type.
+    ParameterTypeError(Name, TypeError),
+    /// evaluation exceeded the `CalculationOptions::limits`.
+    EvalError(eval::EvalError),
+    /// the known input for the named parameter is not data (e.g. a closure),
+    /// & so cannot be specialized to.
+    NonDataInput(Name),
 }
 
+/// reduce a program with the default `CalculationOptions`: the prelude is in
+/// scope, and the program may not have imports.
 pub fn reduce_calculation(
     prog: syntax::Program,
     input_data: &mut dyn Iterator<Item = eval::Value>,
 ) -> Result<ReputationCalculationOutput, ReputationCalculationError> {
-    // infer type of program
-    let (prog_scheme, _prog_env, ref mut is) = infer_program_with_is(Env::new(), &prog)
-        .map_err(|x| ReputationCalculationError::ProgramTypeInferenceError(x))?;
+    reduce_calculation_with(prog, input_data, &CalculationOptions::default())
+}
 
-    // conjure up fresh names for the provided `Values` (from the Iterator) using
-    // `EvalState::fresh`, if there are any.
-    let mut es = eval::EvalState::new();
-    let paired_name_vals: Vec<(syntax::Name, eval::Value)> =
-        input_data.map(|val| (es.fresh(), val)).collect();
+pub fn reduce_calculation_with(
+    prog: syntax::Program,
+    input_data: &mut dyn Iterator<Item = eval::Value>,
+    options: &CalculationOptions,
+) -> Result<ReputationCalculationOutput, ReputationCalculationError> {
+    compile_calculation_with(prog, options)?.reduce(input_data)
+}
 
-    // match the arity of the program body with the # of `Value`s. if mismatch, throw error.
-    let types::Scheme(_tvars, ty) = &prog_scheme;
-    let body_type_arguments = types::type_arguments(&ty);
-    let _ = {
-        let body_arity = body_type_arguments.len();
-        let values_arity = paired_name_vals.len();
-        if values_arity == body_arity {
-            Ok(())
-        } else {
-            Err(ReputationCalculationError::ArityMismatch(
-                body_arity,
-                values_arity,
-            ))
+/// reduce a program which declares `params`, passing its inputs by name, with
+/// the default `CalculationOptions`.
+pub fn reduce_calculation_named(
+    prog: syntax::Program,
+    inputs: &HashMap<Name, eval::Value>,
+) -> Result<ReputationCalculationOutput, ReputationCalculationError> {
+    reduce_calculation_named_with(prog, inputs, &CalculationOptions::default())
+}
+
+pub fn reduce_calculation_named_with(
+    prog: syntax::Program,
+    inputs: &HashMap<Name, eval::Value>,
+    options: &CalculationOptions,
+) -> Result<ReputationCalculationOutput, ReputationCalculationError> {
+    compile_calculation_with(prog, options)?.reduce_named(inputs)
+}
+
+/// specialize a program to some of its inputs, with the default
+/// `CalculationOptions` (see `specialize_calculation_with`).
+pub fn specialize_calculation(
+    prog: syntax::Program,
+    known: &[Option<eval::Value>],
+) -> Result<(syntax::Program, types::Scheme), ReputationCalculationError> {
+    specialize_calculation_with(prog, known, &CalculationOptions::default())
+}
+
+/// specialize a program to the inputs which are known: `known[i]` is the
+/// value of the `i`th input, if it is known, & missing inputs are unknown.
+/// the residual program takes only the unknown inputs, in order, & gives the
+/// same results as the program given all of them. its scheme is re-inferred.
+pub fn specialize_calculation_with(
+    prog: syntax::Program,
+    known: &[Option<eval::Value>],
+    options: &CalculationOptions,
+) -> Result<(syntax::Program, types::Scheme), ReputationCalculationError> {
+    let (prog_env, prog_term_env) = options
+        .load_program_env(&prog)
+        .map_err(ReputationCalculationError::ModuleError)?;
+    let calc = compile_calculation_in(
+        prog.clone(),
+        prog_env.clone(),
+        prog_term_env,
+        options.eval_state(),
+    )?;
+    if known.len() > calc.arity {
+        return Err(ReputationCalculationError::ArityMismatch(
+            calc.arity,
+            known.len(),
+        ));
+    }
+    let mut es = calc.es.clone();
+    es.set_strategy(eval::Strategy::Strict);
+    es.reset_steps();
+
+    // the names of the inputs, eta expanding the body if it binds too few.
+    // the names of the expansion may be written, & capture no other.
+    let (names, inner) = calc.inputs();
+    let mut names = names;
+    let mut inner = inner.clone();
+    let mut fresh = lower::Fresh::new(&prog);
+    fresh.avoid(calc.eval_env.iter().map(|(nm, _val)| nm.clone()));
+    while names.len() < calc.arity {
+        let nm = fresh.var("x");
+        inner = app!(inner, Expr::Var(nm.clone()));
+        names.push(nm);
+    }
+
+    // the known inputs must be data, of the types of their parameters, before
+    // anything is evaluated.
+    let mut bindings = Vec::new();
+    let mut values_types = Vec::new();
+    let mut param_types = Vec::new();
+    let is = &mut calc.is.clone();
+    let types::Scheme(_tvars, ty) = &calc.scheme;
+    for ((nm, val), param_ty) in names
+        .iter()
+        .zip(known.iter())
+        .zip(types::type_arguments(ty))
+    {
+        if let Some(val) = val {
+            let expr = specialize::reify(val)
+                .ok_or_else(|| ReputationCalculationError::NonDataInput(nm.clone()))?;
+            let val_ty = types_values::infer_value(is, val).map_err(|err| match err {
+                ValueInferenceError::TyErr(te) => {
+                    ReputationCalculationError::ValuesIterTypeError(te)
+                }
+                ValueInferenceError::ClosureError(nm, bd) => {
+                    ReputationCalculationError::ValuesIterPassedClosure(nm, bd)
+                }
+                ValueInferenceError::Eval(err) => ReputationCalculationError::EvalError(err),
+            })?;
+            bindings.push((nm.clone(), expr));
+            values_types.push(val_ty);
+            param_types.push(param_ty);
         }
-    }?;
+    }
+    unify_many(values_types, param_types)
+        .map_err(ReputationCalculationError::ProgramValuesUnificationError)?;
+
+    // bind the known inputs, & evaluate what can be evaluated.
+    let body = bindings.into_iter().rev().fold(inner, |body, (nm, expr)| {
+        Expr::Let(nm, Box::new(expr), Box::new(body))
+    });
+    let body = specialize::partial_eval(&calc.eval_env, &mut es, &body);
+    let is_unknown = |ix: usize| known.get(ix).is_none_or(|val| val.is_none());
+    // the unknown inputs from the `from`th on are bound by lambdas, the rest
+    // by the `params` of the residual program.
+    let abstracted = |body, from: usize| {
+        names
+            .iter()
+            .enumerate()
+            .skip(from)
+            .rev()
+            .filter(|(ix, _nm)| is_unknown(*ix))
+            .fold(body, |body, (_ix, nm)| {
+                Expr::Lam(nm.clone(), Box::new(body))
+            })
+    };
+    let residual = match &prog.p_params {
+        Some(params) => syntax::Program {
+            p_params: Some(
+                params
+                    .iter()
+                    .enumerate()
+                    .filter(|(ix, _param)| is_unknown(*ix))
+                    .map(|(_ix, param)| param.clone())
+                    .collect(),
+            ),
+            p_body: abstracted(body, params.len()),
+            ..prog
+        },
+        None => syntax::Program {
+            p_params: None,
+            p_body: abstracted(body, 0),
+            ..prog
+        },
+    };
+    let residual = optimize::optimize_program(&residual);
+    let (scheme, _env) = infer_program(prog_env, &residual)
+        .map_err(ReputationCalculationError::ProgramTypeInferenceError)?;
+    Ok((residual, scheme))
+}
 
-    // if arity matches, then check that the types unify.
-    let values_types_result: Result<Vec<types::Type>, ValueInferenceError> = paired_name_vals
+/// specialize a program which declares `params` to the inputs which are
+/// known, by name, with the default `CalculationOptions` (see
+/// `specialize_calculation_named_with`).
+pub fn specialize_calculation_named(
+    prog: syntax::Program,
+    known: &HashMap<Name, eval::Value>,
+) -> Result<(syntax::Program, types::Scheme), ReputationCalculationError> {
+    specialize_calculation_named_with(prog, known, &CalculationOptions::default())
+}
+
+/// specialize a program which declares `params` to the inputs which are
+/// known, by name.
+pub fn specialize_calculation_named_with(
+    prog: syntax::Program,
+    known: &HashMap<Name, eval::Value>,
+    options: &CalculationOptions,
+) -> Result<(syntax::Program, types::Scheme), ReputationCalculationError> {
+    let params = match &prog.p_params {
+        None => return Err(ReputationCalculationError::NoDeclaredParameters),
+        Some(params) => params,
+    };
+    if let Some(nm) = known
+        .keys()
+        .find(|nm| !params.iter().any(|Param(p, _)| p == *nm))
+    {
+        return Err(ReputationCalculationError::UnexpectedParameter(nm.clone()));
+    }
+    let known: Vec<Option<eval::Value>> = params
         .iter()
-        .map(|(_nm, val)| types_values::infer_value(is, &val))
+        .map(|Param(nm, _ty)| known.get(nm).cloned())
         .collect();
-    let values_types = values_types_result.map_err(|x| match x {
-        ValueInferenceError::TyErr(te) => ReputationCalculationError::ValuesIterTypeError(te),
-        ValueInferenceError::ClosureError(nm, bd) => {
-            ReputationCalculationError::ValuesIterPassedClosure(nm, bd)
-        }
-    })?;
-    let subst = unify_many(values_types, body_type_arguments)
-        .map_err(|x| ReputationCalculationError::ProgramValuesUnificationError(x))?;
-
-    // wrap the body expr in a (potentially series of) applications which apply
-    // it to the successive fresh names.
-    let new_prog_body = {
-        let mut new_body = prog.p_body.clone();
-        for (name, _val) in paired_name_vals.iter() {
-            new_body = app!(new_body, Expr::Var(name.clone()));
-        }
-        new_body
+    specialize_calculation_with(prog, &known, options)
+}
+
+/// the fuel given to the normalization of each program by `equivalent`.
+pub const EQUIVALENCE_FUEL: u64 = 100_000;
+
+// the number of sets of inputs on which `equivalent` runs both programs, &
+// the limits on each run, if the options give none.
+const EQUIVALENCE_SAMPLES: usize = 16;
+const EQUIVALENCE_LIMITS: eval::Limits = eval::Limits {
+    max_steps: Some(100_000),
+    max_depth: Some(1_000),
+};
+
+/// decide whether two programs are equivalent, with the default
+/// `CalculationOptions` (see `equivalent_with`).
+pub fn equivalent(
+    p1: syntax::Program,
+    p2: syntax::Program,
+) -> Result<Equivalence, ReputationCalculationError> {
+    equivalent_with(p1, p2, &CalculationOptions::default())
+}
+
+/// decide whether two programs are equivalent. they are if their bodies have
+/// the same normal form (up to the renaming of bound variables), with their
+/// `defn`s & the prelude unfolded. they are not if they give different
+/// results for one of a few sets of inputs, of the types of the first
+/// program's parameters. otherwise the result is unknown, as when the normal
+/// forms differ only by, e.g., the order of the arguments of `+`, or when the
+/// programs take different numbers of inputs.
+pub fn equivalent_with(
+    p1: syntax::Program,
+    p2: syntax::Program,
+    options: &CalculationOptions,
+) -> Result<Equivalence, ReputationCalculationError> {
+    let compile = |prog: syntax::Program| {
+        let (prog_env, prog_term_env) = options
+            .load_program_env(&prog)
+            .map_err(ReputationCalculationError::ModuleError)?;
+        let mut es = options.eval_state();
+        es.set_strategy(eval::Strategy::Strict);
+        if es.limits() == eval::Limits::default() {
+            es.set_limits(EQUIVALENCE_LIMITS);
+        }
+        compile_calculation_in(prog, prog_env, prog_term_env, es)
     };
+    let c1 = compile(p1)?;
+    let c2 = compile(p2)?;
+    // normalization recurses on the native stack, as does the evaluator.
+    thread::scope(|scope| {
+        let handle = thread::Builder::new()
+            .stack_size(BATCH_STACK_SIZE)
+            .spawn_scoped(scope, || Ok(compare_calculations(&c1, &c2)))
+            .expect("failed to spawn equivalence thread");
+        match handle.join() {
+            Ok(equivalence) => equivalence,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    })
+}
+
+fn compare_calculations(c1: &CompiledCalculation, c2: &CompiledCalculation) -> Equivalence {
+    let n1 = normalize::normalize(&c1.eval_env, &c1.body, EQUIVALENCE_FUEL);
+    let n2 = normalize::normalize(&c2.eval_env, &c2.body, EQUIVALENCE_FUEL);
+    if let (Ok(n1), Ok(n2)) = (&n1, &n2) {
+        if normalize::alpha_eq(n1, n2) {
+            return Equivalence::Equivalent;
+        }
+    }
+
+    // the inputs of one program can't be given to another of a different
+    // arity, though the two may be equal (up to eta).
+    if c1.arity != c2.arity {
+        return Equivalence::Unknown;
+    }
+
+    // look for inputs on which the results differ. the inputs may not agree
+    // with the types of the second program, when its reduction fails.
+    let types::Scheme(_tvars, ty) = &c1.scheme;
+    let arg_tys: Vec<types::Type> = types::type_arguments(ty)
+        .into_iter()
+        .take(c1.arity)
+        .collect();
+    for seed in 0..EQUIVALENCE_SAMPLES {
+        let inputs: Option<Vec<eval::Value>> = arg_tys
+            .iter()
+            .enumerate()
+            .map(|(ix, ty)| sample_value(ty, seed * arg_tys.len() + ix))
+            .collect();
+        let inputs = match inputs {
+            Some(inputs) => inputs,
+            None => break,
+        };
+        let r1 = c1.reduce(&mut inputs.clone().into_iter());
+        let r2 = c2.reduce(&mut inputs.into_iter());
+        if let (Ok(r1), Ok(r2)) = (r1, r2) {
+            if let (Some(v1), Some(v2)) =
+                (specialize::reify(&r1.value), specialize::reify(&r2.value))
+            {
+                if v1 != v2 {
+                    return Equivalence::Inequivalent;
+                }
+            }
+        }
+    }
+    Equivalence::Unknown
+}
+
+// an input of type `ty`, which varies with `seed`, or `None` if `ty` is a
+// function type. type variables are instantiated to `Int`.
+fn sample_value(ty: &types::Type, seed: usize) -> Option<eval::Value> {
+    const INTS: [i64; 7] = [0, 1, -1, 2, 3, -7, 10];
+    match ty {
+        types::Type::TCon(con) if con == "Bool" => Some(eval::Value::VBool(seed % 2 == 1)),
+        types::Type::TCon(_) | types::Type::TVar(_) => {
+            Some(eval::Value::VInt(INTS[seed % INTS.len()]))
+        }
+        types::Type::TList(elem) => (0..seed % 4)
+            .map(|ix| sample_value(elem, seed / 4 + ix))
+            .collect::<Option<Vec<eval::Value>>>()
+            .map(eval::Value::VList),
+        types::Type::TPair(a, b) => Some(eval::Value::VPair(
+            Box::new(sample_value(a, seed)?),
+            Box::new(sample_value(b, seed / 2 + 1)?),
+        )),
+        types::Type::TArr(_, _) => None,
+    }
+}
+
+// the evaluator recurses on the native stack, so reductions get as much stack
+// as the main thread usually has.
+const BATCH_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+/// a calculation which has been type checked, and whose `defn`s have been
+/// evaluated, so that it can be cheaply reduced against many sets of inputs.
+/// it is `Send + Sync`, and cheap to clone, so that it may be reduced from
+/// many threads at once (see `reduce_batch`).
+#[derive(Clone)]
+pub struct CompiledCalculation {
+    pub rcr_calculation: syntax::Expr,
+    pub scheme: types::Scheme,
+    /// the number of inputs the calculation takes.
+    pub arity: usize,
+    /// the parameters declared by the program, if any.
+    pub params: Option<Vec<Param>>,
+    // the body, abstracted over `params`.
+    body: syntax::Expr,
+    // binds the prelude, the program's imports & its evaluated `defn`s.
+    eval_env: eval::TermEnv,
+    // the names of the program's `defn`s.
+    defns: Vec<Name>,
+    // the states after inference & evaluation of the `defn`s. these are cloned
+    // for each reduction, so that the type variables & names conjured up for
+    // the inputs do not clash with those already in use.
+    is: InferState,
+    es: eval::EvalState,
+}
+
+/// compile a program with the default `CalculationOptions`.
+pub fn compile_calculation(
+    prog: syntax::Program,
+) -> Result<CompiledCalculation, ReputationCalculationError> {
+    compile_calculation_with(prog, &CalculationOptions::default())
+}
+
+pub fn compile_calculation_with(
+    prog: syntax::Program,
+    options: &CalculationOptions,
+) -> Result<CompiledCalculation, ReputationCalculationError> {
+    // load the prelude & the program's imports, which bind qualified names.
+    let (prog_env, prog_term_env) = options
+        .load_program_env(&prog)
+        .map_err(ReputationCalculationError::ModuleError)?;
+    compile_calculation_in(prog, prog_env, prog_term_env, options.eval_state())
+}
+
+/// compile a program in the given environments, which must already bind the
+/// qualified names of the program's imports.
+pub(crate) fn compile_calculation_in(
+    prog: syntax::Program,
+    prog_env: Env,
+    prog_term_env: eval::TermEnv,
+    mut es: eval::EvalState,
+) -> Result<CompiledCalculation, ReputationCalculationError> {
+    // infer type of program
+    let (prog_scheme, _prog_env, is) = infer_program_with_is(prog_env, &prog)
+        .map_err(ReputationCalculationError::ProgramTypeInferenceError)?;
 
     // evaluate the program defns
-    let mut eval_env = HashMap::new();
+    let mut eval_env = prog_term_env;
     for syntax::Defn(nm, bd) in prog.p_defns.iter() {
-        let val = eval::eval_(&eval_env, &mut es, bd);
+        let val =
+            eval::eval_(&eval_env, &mut es, bd).map_err(ReputationCalculationError::EvalError)?;
         eval_env.insert(nm.clone(), val);
     }
 
-    // bind the freshnames to the values in the TermEnv.
-    for (name, val) in paired_name_vals.iter() {
-        eval_env.insert(name.clone(), val.clone());
+    let types::Scheme(_tvars, ty) = &prog_scheme;
+    let arity = types::type_arity(ty.clone());
+    Ok(CompiledCalculation {
+        body: prog.body_expr(),
+        rcr_calculation: prog.p_body,
+        scheme: prog_scheme,
+        arity,
+        params: prog.p_params,
+        eval_env,
+        defns: prog
+            .p_defns
+            .iter()
+            .map(|syntax::Defn(nm, _)| nm.clone())
+            .collect(),
+        is,
+        es,
+    })
+}
+
+impl CompiledCalculation {
+    pub fn signature(&self) -> CalculationSignature {
+        CalculationSignature::new(&self.scheme, &self.body)
+    }
+
+    /// apply the calculation to the provided `Value`s. only the types of the
+    /// inputs are checked, and only the body is evaluated.
+    pub fn reduce(
+        &self,
+        input_data: &mut dyn Iterator<Item = eval::Value>,
+    ) -> Result<ReputationCalculationOutput, ReputationCalculationError> {
+        self.reduce_in(input_data, &mut self.es.clone())
     }
 
-    // evaluate the program body with the set-up TermEnv and EvalState.
-    let body_val = eval::eval_(&eval_env, &mut es, &new_prog_body);
+    /// as `reduce`, along with a derivation of the value, which explains how
+    /// it was computed from the inputs & the `defn`s.
+    pub fn explain(
+        &self,
+        input_data: &mut dyn Iterator<Item = eval::Value>,
+    ) -> Result<(ReputationCalculationOutput, Derivation), ReputationCalculationError> {
+        let mut es = self.es.clone();
+        es.start_trace();
+        let output = self.reduce_in(input_data, &mut es)?;
+        let trace = es.take_trace().unwrap_or_default();
+        let (inputs, body) = self.inputs();
+        // the body is evaluated once it has been applied to the inputs.
+        let root = trace
+            .steps()
+            .iter()
+            .position(|step| step.expr == *body)
+            .unwrap_or(0);
+        let derivation = Derivation::from_trace(&trace, root, &inputs, &self.defns);
+        Ok((output, derivation))
+    }
 
-    // package up the result
-    Ok(ReputationCalculationOutput {
-        rcr_calculation: prog.p_body,
-        scheme: prog_scheme.apply(&subst),
-        value: body_val,
-    })
+    /// an upper bound on the gas used by `reduce` under the strict strategy,
+    /// for any inputs in which no list is longer than `max_len` (see
+    /// `cost::gas_bound`).
+    pub fn gas_bound(&self, max_len: u64) -> Result<u64, BoundError> {
+        let mut es = self.es.clone();
+        let types::Scheme(_tvars, ty) = &self.scheme;
+        let inputs: Vec<(Name, types::Type)> = types::type_arguments(ty)
+            .into_iter()
+            .take(self.arity)
+            .map(|ty| (es.fresh(), ty))
+            .collect();
+        let body = inputs.iter().fold(self.body.clone(), |body, (nm, _ty)| {
+            app!(body, Expr::Var(nm.clone()))
+        });
+        cost::gas_bound(es.costs(), &self.eval_env, &body, &inputs, max_len)
+    }
+
+    // the names to which the body binds its inputs, & the expression under
+    // those binders.
+    fn inputs(&self) -> (Vec<Name>, &Expr) {
+        let mut names = Vec::new();
+        let mut body = &self.body;
+        while let Expr::Lam(nm, bd) = body {
+            if names.len() == self.arity {
+                break;
+            }
+            names.push(nm.clone());
+            body = bd;
+        }
+        (names, body)
+    }
+
+    fn reduce_in(
+        &self,
+        input_data: &mut dyn Iterator<Item = eval::Value>,
+        es: &mut eval::EvalState,
+    ) -> Result<ReputationCalculationOutput, ReputationCalculationError> {
+        let is = &mut self.is.clone();
+        es.reset_steps();
+
+        // conjure up fresh names for the provided `Values` (from the Iterator) using
+        // `EvalState::fresh`, if there are any.
+        let paired_name_vals: Vec<(syntax::Name, eval::Value)> =
+            input_data.map(|val| (es.fresh(), val)).collect();
+
+        // infer the types of the `Value`s, and check that they unify with the
+        // program's parameters.
+        let values_types_result: Result<Vec<types::Type>, ValueInferenceError> = paired_name_vals
+            .iter()
+            .map(|(_nm, val)| types_values::infer_value(is, val))
+            .collect();
+        let values_types = values_types_result.map_err(|x| match x {
+            ValueInferenceError::TyErr(te) => ReputationCalculationError::ValuesIterTypeError(te),
+            ValueInferenceError::ClosureError(nm, bd) => {
+                ReputationCalculationError::ValuesIterPassedClosure(nm, bd)
+            }
+            ValueInferenceError::Eval(err) => ReputationCalculationError::EvalError(err),
+        })?;
+        let subst = self.unify_inputs(values_types)?;
+
+        // wrap the body expr in a (potentially series of) applications which apply
+        // it to the successive fresh names.
+        let new_prog_body = {
+            let mut new_body = self.body.clone();
+            for (name, _val) in paired_name_vals.iter() {
+                new_body = app!(new_body, Expr::Var(name.clone()));
+            }
+            new_body
+        };
+
+        // bind the freshnames to the values in the TermEnv.
+        let mut eval_env = self.eval_env.clone();
+        for (name, val) in paired_name_vals.into_iter() {
+            eval_env.insert(name, val);
+        }
+
+        // evaluate the program body with the set-up TermEnv and EvalState.
+        // under the lazy strategy, the result is forced in full, within the
+        // limits of the reduction.
+        let body_val = eval::eval_(&eval_env, es, &new_prog_body)
+            .and_then(|val| match es.strategy() {
+                eval::Strategy::Strict => Ok(val),
+                eval::Strategy::Lazy => lazy::force_deep(es, val),
+            })
+            .map_err(ReputationCalculationError::EvalError)?;
+
+        // package up the result
+        Ok(ReputationCalculationOutput {
+            rcr_calculation: self.rcr_calculation.clone(),
+            scheme: self.scheme.clone().apply(&subst),
+            value: body_val,
+            gas: es.gas(),
+        })
+    }
+
+    /// reduce the calculation against each set of inputs in `batch`,
+    /// concurrently, on as many threads as are available. the results are in
+    /// the same order as `batch`.
+    pub fn reduce_batch(
+        &self,
+        batch: &[Vec<eval::Value>],
+    ) -> Vec<Result<ReputationCalculationOutput, ReputationCalculationError>> {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        self.reduce_batch_on(batch, threads)
+    }
+
+    /// like `reduce_batch`, on at most `threads` threads.
+    pub fn reduce_batch_on(
+        &self,
+        batch: &[Vec<eval::Value>],
+        threads: usize,
+    ) -> Vec<Result<ReputationCalculationOutput, ReputationCalculationError>> {
+        if batch.is_empty() {
+            return Vec::new();
+        }
+        let chunk_size = cmp::max(1, (batch.len() + threads - 1) / cmp::max(1, threads));
+        let reduce_chunk = |chunk: &[Vec<eval::Value>]| {
+            chunk
+                .iter()
+                .map(|inputs| self.reduce(&mut inputs.iter().cloned()))
+                .collect::<Vec<_>>()
+        };
+        thread::scope(|scope| {
+            let handles: Vec<_> = batch
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    thread::Builder::new()
+                        .stack_size(BATCH_STACK_SIZE)
+                        .spawn_scoped(scope, move || reduce_chunk(chunk))
+                        .expect("failed to spawn reduction thread")
+                })
+                .collect();
+            handles
+                .into_iter()
+                .flat_map(|handle| match handle.join() {
+                    Ok(results) => results,
+                    Err(panic) => std::panic::resume_unwind(panic),
+                })
+                .collect()
+        })
+    }
+
+    /// apply the calculation to inputs passed by name. every declared parameter
+    /// must be provided, and no others.
+    pub fn reduce_named(
+        &self,
+        inputs: &HashMap<Name, eval::Value>,
+    ) -> Result<ReputationCalculationOutput, ReputationCalculationError> {
+        let params = match &self.params {
+            None => return Err(ReputationCalculationError::NoDeclaredParameters),
+            Some(params) => params,
+        };
+        let mut values = Vec::new();
+        for Param(nm, _ty) in params {
+            match inputs.get(nm) {
+                None => return Err(ReputationCalculationError::MissingParameter(nm.clone())),
+                Some(val) => values.push(val.clone()),
+            }
+        }
+        if let Some(nm) = inputs
+            .keys()
+            .find(|nm| !params.iter().any(|Param(p, _)| p == *nm))
+        {
+            return Err(ReputationCalculationError::UnexpectedParameter(nm.clone()));
+        }
+        self.reduce(&mut values.into_iter())
+    }
+
+    /// check that inputs of the given types (e.g. the `poly_type()`s of Rust
+    /// values, to be converted via `IntoValue`) could be passed to the
+    /// calculation, without converting or evaluating anything.
+    pub fn check_input_types(
+        &self,
+        input_types: &[types::Type],
+    ) -> Result<(), ReputationCalculationError> {
+        self.unify_inputs(input_types.to_vec()).map(|_subst| ())
+    }
+
+    // match the arity of the program body with the # of inputs, and unify the
+    // types of the inputs with those of the program's parameters.
+    fn unify_inputs(
+        &self,
+        values_types: Vec<types::Type>,
+    ) -> Result<Subst, ReputationCalculationError> {
+        if values_types.len() != self.arity {
+            return Err(ReputationCalculationError::ArityMismatch(
+                self.arity,
+                values_types.len(),
+            ));
+        }
+        let types::Scheme(_tvars, ty) = &self.scheme;
+        let body_type_arguments = types::type_arguments(ty);
+        unify_many(values_types.clone(), body_type_arguments.clone())
+            .map_err(|err| self.parameter_type_error(values_types, body_type_arguments, err))
+    }
+
+    // if the program declares its parameters, attribute a failure to unify the
+    // inputs' types to the first parameter at which unification fails.
+    fn parameter_type_error(
+        &self,
+        values_types: Vec<types::Type>,
+        body_type_arguments: Vec<types::Type>,
+        err: TypeError,
+    ) -> ReputationCalculationError {
+        if let Some(params) = &self.params {
+            for (i, Param(nm, _ty)) in params.iter().enumerate() {
+                let prefix_values = values_types[..=i].to_vec();
+                let prefix_args = body_type_arguments[..=i].to_vec();
+                if let Err(param_err) = unify_many(prefix_values, prefix_args) {
+                    return ReputationCalculationError::ParameterTypeError(nm.clone(), param_err);
+                }
+            }
+        }
+        ReputationCalculationError::ProgramValuesUnificationError(err)
+    }
 }
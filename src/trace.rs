@@ -0,0 +1,161 @@
+//! tracing of evaluation by `eval_`, for debugging.
+//!
+//! while an `EvalState` is tracing, each expression `eval_` evaluates is
+//! recorded as a `Step`: the expression, the values of its free variables,
+//! & the value it evaluated to. steps are recorded in the order in which
+//! their evaluation begins, so that a trace may be walked through as a
+//! debugger would step into each sub-expression.
+
+use pretty::RcDoc;
+use std::iter;
+
+use super::{
+    eval::{eval_, EvalError, EvalState, TermEnv, Value},
+    syntax::{Expr, Name},
+};
+use crate::sp;
+
+/// a single reduction.
+#[derive(Clone)]
+pub struct Step {
+    /// the nesting depth of the evaluation.
+    pub depth: usize,
+    pub expr: Expr,
+    /// the values of the free variables of `expr`.
+    pub bindings: Vec<(Name, Value)>,
+    /// the value of `expr`, or `None` if its evaluation failed.
+    pub value: Option<Value>,
+}
+
+impl Step {
+    /// the variable at the head of the step's expression, if it is a variable,
+    /// or the application of one.
+    pub fn head(&self) -> Option<&Name> {
+        let mut expr = &self.expr;
+        loop {
+            match expr {
+                Expr::App(fun, _arg) => expr = fun,
+                Expr::Var(nm) => return Some(nm),
+                _ => return None,
+            }
+        }
+    }
+
+    pub fn ppr(&self) -> RcDoc<'static, ()> {
+        let expr = RcDoc::text(self.expr.ppr().pretty(80).to_string());
+        let value = match &self.value {
+            Some(val) => val.ppr(),
+            None => RcDoc::text("<<failed>>"),
+        };
+        let step = expr
+            .append(sp!())
+            .append(RcDoc::text("=>"))
+            .append(sp!())
+            .append(value);
+        if self.bindings.is_empty() {
+            return step.group();
+        }
+        let bindings = self.bindings.iter().map(|(nm, val)| {
+            RcDoc::text(nm.0.clone())
+                .append(RcDoc::text(" = "))
+                .append(val.ppr())
+        });
+        let header = iter::once(RcDoc::text("where"));
+        step.append(
+            RcDoc::line()
+                .append(RcDoc::intersperse(header.chain(bindings), sp!()))
+                .nest(2),
+        )
+        .group()
+    }
+}
+
+/// the steps of an evaluation.
+#[derive(Clone, Default)]
+pub struct Trace {
+    steps: Vec<Step>,
+}
+
+impl Trace {
+    pub fn new() -> Trace {
+        Trace { steps: Vec::new() }
+    }
+
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// the indices of the steps which begin the evaluation of a call to one of
+    /// `names` (or of a reference to one, if it is not applied).
+    pub fn breakpoints(&self, names: &[Name]) -> Vec<usize> {
+        let hit = |step: &Step| step.head().is_some_and(|nm| names.contains(nm));
+        // the function of an application is evaluated first, & so follows it
+        // at one level deeper: only the outermost application of a call hits.
+        (0..self.steps.len())
+            .filter(|&ix| hit(&self.steps[ix]))
+            .filter(
+                |&ix| match ix.checked_sub(1).map(|prev| &self.steps[prev]) {
+                    Some(prev) => {
+                        !(prev.depth + 1 == self.steps[ix].depth
+                            && prev.head() == self.steps[ix].head())
+                    }
+                    None => true,
+                },
+            )
+            .collect()
+    }
+
+    /// the whole trace, a step per line, indented by depth.
+    pub fn ppr(&self) -> RcDoc<'static, ()> {
+        let min_depth = self.steps.iter().map(|step| step.depth).min().unwrap_or(0);
+        RcDoc::intersperse(
+            self.steps.iter().map(|step| {
+                let indent = 2 * (step.depth - min_depth);
+                RcDoc::text(" ".repeat(indent)).append(step.ppr().nest(indent as isize + 2))
+            }),
+            RcDoc::hardline(),
+        )
+    }
+
+    // record the beginning of a step, returning its index.
+    pub(crate) fn enter(&mut self, depth: usize, env: &TermEnv, expr: &Expr) -> usize {
+        let bindings = expr
+            .free_vars()
+            .into_iter()
+            .filter_map(|nm| env.get(&nm).cloned().map(|val| (nm, val)))
+            .collect();
+        self.steps.push(Step {
+            depth,
+            expr: expr.clone(),
+            bindings,
+            value: None,
+        });
+        self.steps.len() - 1
+    }
+
+    // record the end of the step at `ix`.
+    pub(crate) fn exit(&mut self, ix: usize, res: &Result<Value, EvalError>) {
+        if let Ok(val) = res {
+            self.steps[ix].value = Some(val.clone());
+        }
+    }
+}
+
+/// evaluate `expr` under `env`, as `eval_`, tracing each step.
+pub fn eval_traced(
+    env: &TermEnv,
+    es: &mut EvalState,
+    expr: &Expr,
+) -> (Result<Value, EvalError>, Trace) {
+    es.start_trace();
+    let res = eval_(env, es, expr);
+    (res, es.take_trace().unwrap_or_default())
+}
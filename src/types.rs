@@ -1,23 +1,60 @@
 use pretty::RcDoc;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 use crate::sp;
 use crate::util::pretty::parens;
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TV(pub String);
 
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Type {
+    #[cfg_attr(feature = "serde", serde(rename = "var"))]
     TVar(TV),
+    #[cfg_attr(feature = "serde", serde(rename = "con"))]
     TCon(String),
+    #[cfg_attr(feature = "serde", serde(rename = "arr"))]
     TArr(Box<Type>, Box<Type>),
+    #[cfg_attr(feature = "serde", serde(rename = "list"))]
     TList(Box<Type>),
+    #[cfg_attr(feature = "serde", serde(rename = "pair"))]
     TPair(Box<Type>, Box<Type>),
 }
 
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(from = "SchemeRepr", into = "SchemeRepr")
+)]
 pub struct Scheme(pub Vec<TV>, pub Type);
 
+// the serialized form of a `Scheme`: `{"forall": [...], "type": ...}`.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct SchemeRepr {
+    forall: Vec<TV>,
+    #[serde(rename = "type")]
+    ty: Type,
+}
+
+#[cfg(feature = "serde")]
+impl From<SchemeRepr> for Scheme {
+    fn from(SchemeRepr { forall, ty }: SchemeRepr) -> Scheme {
+        Scheme(forall, ty)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl From<Scheme> for SchemeRepr {
+    fn from(Scheme(forall, ty): Scheme) -> SchemeRepr {
+        SchemeRepr { forall, ty }
+    }
+}
+
 // type constructors
 
 pub fn type_int() -> Type {
@@ -46,7 +83,7 @@ pub fn type_arr_multi(args: Vec<Type>, ret: Type) -> Type {
 }
 
 impl Scheme {
-    pub fn ppr(&self) -> RcDoc<()> {
+    pub fn ppr(&self) -> RcDoc<'_, ()> {
         match self {
             Scheme(tvs, ty) => {
                 let quantifier = if tvs.is_empty() {
@@ -65,7 +102,7 @@ impl Scheme {
 }
 
 impl TV {
-    pub fn ppr(&self) -> RcDoc<()> {
+    pub fn ppr(&self) -> RcDoc<'_, ()> {
         match self {
             TV(s) => RcDoc::text(s),
         }
@@ -73,7 +110,7 @@ impl TV {
 }
 
 impl Type {
-    pub fn ppr(&self) -> RcDoc<()> {
+    pub fn ppr(&self) -> RcDoc<'_, ()> {
         match self {
             Type::TVar(tv) => tv.ppr(),
             Type::TCon(s) => RcDoc::text(s),
@@ -101,7 +138,7 @@ pub fn type_arguments(ty: &Type) -> Vec<Type> {
     let mut vec = Vec::new();
     let mut ty_ref = Some(ty);
 
-    while ty_ref != None {
+    while ty_ref.is_some() {
         match ty_ref {
             Some(Type::TArr(arg, ret)) => {
                 vec.push(*arg.clone());
@@ -124,3 +161,26 @@ pub fn type_return(ty: &Type) -> Type {
         _ => ty.clone(),
     }
 }
+
+/// return the type variables occurring in a type, in order of first
+/// appearance.
+pub fn type_variables(ty: &Type) -> Vec<TV> {
+    fn go(ty: &Type, acc: &mut Vec<TV>) {
+        match ty {
+            Type::TVar(tv) => {
+                if !acc.contains(tv) {
+                    acc.push(tv.clone())
+                }
+            }
+            Type::TCon(_) => (),
+            Type::TArr(a, b) | Type::TPair(a, b) => {
+                go(a, acc);
+                go(b, acc);
+            }
+            Type::TList(a) => go(a, acc),
+        }
+    }
+    let mut acc = Vec::new();
+    go(ty, &mut acc);
+    acc
+}
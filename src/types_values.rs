@@ -1,6 +1,6 @@
 use super::{
-    eval::Value,
-    infer::{run_solve, Constraint, InferState, TypeError},
+    eval::{named_closure, EvalError, Value},
+    infer::{instantiate, run_solve, Constraint, InferState, TypeError},
     syntax::{Expr, Name},
     types,
 };
@@ -8,12 +8,14 @@ use super::{
 pub enum ValueInferenceError {
     TyErr(TypeError),
     ClosureError(Name, Box<Expr>),
+    /// forcing a thunk of the lazy strategy failed.
+    Eval(EvalError),
 }
 
 pub fn infer_value(is: &mut InferState, value: &Value) -> Result<types::Type, ValueInferenceError> {
     let (ty, csts) = infer_value_internal(is, value)?;
 
-    let subst = run_solve(csts).map_err(|x| ValueInferenceError::TyErr(x))?;
+    let subst = run_solve(csts).map_err(ValueInferenceError::TyErr)?;
     Ok(ty.apply(&subst))
 }
 
@@ -28,8 +30,37 @@ fn infer_value_internal(
             name.clone(),
             expr.clone(),
         )),
+        Value::VCore(nm, bd, locals, globals) => {
+            let (nm, bd, _env) = named_closure(nm, bd, locals, globals);
+            Err(ValueInferenceError::ClosureError(nm, bd))
+        }
+        Value::VCode(proto, _captures) => Err(ValueInferenceError::ClosureError(
+            proto.param.clone(),
+            proto.body.clone(),
+        )),
+        Value::VThunk(_) | Value::VCons(_, _) => {
+            let value = value.clone().forced().map_err(ValueInferenceError::Eval)?;
+            infer_value_internal(is, &value)
+        }
+        // a native's type is known from its scheme. the arguments it has
+        // already been applied to peel off the front of its arrow type.
+        Value::VNative(native, args) => {
+            let mut ty = instantiate(is, &native.scheme).map_err(ValueInferenceError::TyErr)?;
+            let mut csts = Vec::new();
+            for arg in args {
+                let (arg_ty, mut arg_csts) = infer_value_internal(is, arg)?;
+                csts.append(&mut arg_csts);
+                let ret_ty = is.fresh();
+                csts.push(Constraint(
+                    ty,
+                    types::Type::TArr(Box::new(arg_ty), Box::new(ret_ty.clone())),
+                ));
+                ty = ret_ty;
+            }
+            Ok((ty, csts))
+        }
         // VClosure(Name, Box<Expr>, TermEnv),
-        // type TermEnv = HashMap<Name, Value>;
+        // type TermEnv = im::HashMap<Name, Value>;
         //
         // fn infer(
         //     env: &Env,
@@ -37,7 +68,7 @@ fn infer_value_internal(
         //     expr: &Expr,
         // ) -> Result<(Type, Vec<Constraint>), TypeError> {
         //
-        // pub struct Env(HashMap<Name, Scheme>);
+        // pub struct Env(im::HashMap<Name, Scheme>);
         //
         // if we can map `infer_value` over the `TermEnv`, we can marshall that into the `env`
         // we provide to `infer`.
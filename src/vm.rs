@@ -0,0 +1,498 @@
+//! a compiler from `Expr`s to bytecode, and a stack-based vm which runs it.
+//!
+//! free variables are resolved when compiling, against the environment the
+//! expression is compiled under. `PrimOp` applications are found once, at
+//! compile time: full applications become a single `Prim` instruction, while
+//! partial (& bare) applications are eta-expanded into closures. lambdas are
+//! compiled to `Proto`s, which are instantiated as closures (`VCode` values)
+//! capturing only their free variables.
+//!
+//! the results agree with those of `eval_`. calls between compiled closures,
+//! including recursive calls through `fix`, do not use the native stack.
+
+use std::{collections::HashMap, mem, sync::Arc, sync::OnceLock};
+
+use super::{
+    eval::{apply, apply_primop, EvalError, EvalState, TermEnv, Value},
+    syntax::{free_vars_in, primop_arity, Expr, Lit, Name, PrimOp},
+};
+use crate::{app, lam};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Instr {
+    Int(i64),
+    Bool(bool),
+    Nil,
+    /// push the local in the given slot of the current frame.
+    Local(usize),
+    /// push a value captured by the current closure.
+    Capture(usize),
+    /// push a global, as bound when compiling.
+    Global(usize),
+    /// push a closure over a proto of the current chunk, capturing the values
+    /// from the given sources.
+    Closure(usize, Vec<Source>),
+    /// pop as many arguments as the arity of the `PrimOp`, & push its result.
+    Prim(PrimOp),
+    /// pop an argument & a function, & push the function applied to the
+    /// argument.
+    Call,
+    /// as `Call`, but the application replaces the current frame.
+    TailCall,
+    /// pop the result of the current frame, & return it to the caller.
+    Return,
+    Jump(usize),
+    /// pop a `Bool`, & jump if it is false.
+    JumpIfFalse(usize),
+    /// remove the value beneath the top of the stack (a `let`-bound local,
+    /// once its body has been evaluated).
+    Slide,
+    /// push the unfolding of `(fix f)` for the function `f` on top of the
+    /// stack, i.e. `(lam [x] ((f (fix f)) x))`.
+    Fix,
+}
+
+/// where a closure finds a value it captures, in the frame creating it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Source {
+    Local(usize),
+    Capture(usize),
+}
+
+/// compiled code, along with the lambdas it creates closures over & the
+/// values of the globals it refers to.
+pub struct Chunk {
+    code: Vec<Instr>,
+    protos: Vec<Arc<Proto>>,
+    globals: Arc<Vec<Value>>,
+}
+
+impl Chunk {
+    pub fn code(&self) -> &[Instr] {
+        &self.code
+    }
+
+    pub fn protos(&self) -> &[Arc<Proto>] {
+        &self.protos
+    }
+}
+
+/// a compiled lambda. its parameter is the local in slot 0.
+pub struct Proto {
+    pub param: Name,
+    /// the body, as written.
+    pub body: Box<Expr>,
+    pub chunk: Arc<Chunk>,
+}
+
+#[derive(Debug)]
+pub enum CompileError {
+    UnboundVariable(Name),
+}
+
+/// compile `expr`, whose free variables are bound in `env`.
+pub fn compile(env: &TermEnv, expr: &Expr) -> Result<Arc<Chunk>, CompileError> {
+    let mut globals = Vec::new();
+    let mut index = HashMap::new();
+    for nm in expr.free_vars() {
+        match env.get(&nm) {
+            None => return Err(CompileError::UnboundVariable(nm)),
+            Some(val) => {
+                index.insert(nm, globals.len());
+                globals.push(val.clone());
+            }
+        }
+    }
+    let mut compiler = Compiler {
+        index,
+        globals: Arc::new(globals),
+        fresh: 0,
+    };
+    Ok(compiler.chunk(Scope::new(Vec::new(), Vec::new()), expr))
+}
+
+/// compile & run `expr` under `env`. as for `eval_`, `expr` is assumed to be
+/// well typed.
+pub fn eval_compiled(env: &TermEnv, es: &mut EvalState, expr: &Expr) -> Result<Value, EvalError> {
+    match compile(env, expr) {
+        Ok(chunk) => run(es, &chunk),
+        Err(CompileError::UnboundVariable(x)) => panic!("impossible: free variable: {:?}", x),
+    }
+}
+
+/// run a compiled chunk. each instruction counts as a step, & each call frame
+/// as a level of depth, towards the limits of `es`.
+pub fn run(es: &mut EvalState, chunk: &Arc<Chunk>) -> Result<Value, EvalError> {
+    let frame = Frame {
+        chunk: chunk.clone(),
+        pc: 0,
+        base: 0,
+        captures: Arc::new([]),
+    };
+    exec(es, Vec::new(), frame)
+}
+
+/// apply a compiled closure to an argument.
+pub fn call(
+    es: &mut EvalState,
+    proto: &Proto,
+    captures: Arc<[Value]>,
+    arg: Value,
+) -> Result<Value, EvalError> {
+    let frame = Frame {
+        chunk: proto.chunk.clone(),
+        pc: 0,
+        base: 0,
+        captures,
+    };
+    exec(es, vec![arg], frame)
+}
+
+struct Frame {
+    chunk: Arc<Chunk>,
+    pc: usize,
+    // the position on the stack of the frame's slot 0.
+    base: usize,
+    captures: Arc<[Value]>,
+}
+
+fn exec(es: &mut EvalState, mut stack: Vec<Value>, mut frame: Frame) -> Result<Value, EvalError> {
+    let mut frames: Vec<Frame> = Vec::new();
+    loop {
+        es.tick(frames.len() + 1)?;
+        let chunk = frame.chunk.clone();
+        let instr = &chunk.code[frame.pc];
+        frame.pc += 1;
+        match instr {
+            Instr::Int(n) => stack.push(Value::VInt(*n)),
+            Instr::Bool(b) => stack.push(Value::VBool(*b)),
+            Instr::Nil => stack.push(Value::VList(Vec::new())),
+            Instr::Local(i) => stack.push(stack[frame.base + i].clone()),
+            Instr::Capture(i) => stack.push(frame.captures[*i].clone()),
+            Instr::Global(i) => stack.push(chunk.globals[*i].clone()),
+            Instr::Closure(i, sources) => {
+                let captures = sources
+                    .iter()
+                    .map(|src| match src {
+                        Source::Local(j) => stack[frame.base + j].clone(),
+                        Source::Capture(j) => frame.captures[*j].clone(),
+                    })
+                    .collect();
+                es.charge(es.costs().allocation);
+                stack.push(Value::VCode(chunk.protos[*i].clone(), captures));
+            }
+            Instr::Prim(op) => {
+                let args = stack.split_off(stack.len() - primop_arity(op));
+                stack.push(apply_primop(es, op.clone(), args)?);
+            }
+            Instr::Call => {
+                let arg = pop(&mut stack);
+                match pop(&mut stack) {
+                    Value::VCode(proto, captures) => {
+                        es.charge(es.costs().application);
+                        let callee = Frame {
+                            chunk: proto.chunk.clone(),
+                            pc: 0,
+                            base: stack.len(),
+                            captures,
+                        };
+                        frames.push(mem::replace(&mut frame, callee));
+                        stack.push(arg);
+                    }
+                    fun => stack.push(apply(es, fun, arg)?),
+                }
+            }
+            Instr::TailCall => {
+                let arg = pop(&mut stack);
+                match pop(&mut stack) {
+                    Value::VCode(proto, captures) => {
+                        es.charge(es.costs().application);
+                        stack.truncate(frame.base);
+                        frame = Frame {
+                            chunk: proto.chunk.clone(),
+                            pc: 0,
+                            base: frame.base,
+                            captures,
+                        };
+                        stack.push(arg);
+                    }
+                    fun => {
+                        let val = apply(es, fun, arg)?;
+                        stack.truncate(frame.base);
+                        match frames.pop() {
+                            None => return Ok(val),
+                            Some(caller) => frame = caller,
+                        }
+                        stack.push(val);
+                    }
+                }
+            }
+            Instr::Return => {
+                let val = pop(&mut stack);
+                stack.truncate(frame.base);
+                match frames.pop() {
+                    None => return Ok(val),
+                    Some(caller) => frame = caller,
+                }
+                stack.push(val);
+            }
+            Instr::Jump(pc) => frame.pc = *pc,
+            Instr::JumpIfFalse(pc) => match pop(&mut stack) {
+                Value::VBool(true) => {}
+                Value::VBool(false) => frame.pc = *pc,
+                _ => panic!("impossible: non-bool in test position of if"),
+            },
+            Instr::Slide => {
+                let val = pop(&mut stack);
+                pop(&mut stack);
+                stack.push(val);
+            }
+            Instr::Fix => {
+                let fun = stack[stack.len() - 1].clone();
+                es.charge(es.costs().allocation);
+                stack.push(Value::VCode(fix_proto().clone(), Arc::new([fun])));
+            }
+        }
+    }
+}
+
+fn pop(stack: &mut Vec<Value>) -> Value {
+    match stack.pop() {
+        Some(val) => val,
+        None => panic!("impossible: vm: pop from an empty stack"),
+    }
+}
+
+// the body of the unfolding of `(fix f)`, capturing `f`: the unfolding is
+// rebuilt, rather than referring to itself.
+fn fix_proto() -> &'static Arc<Proto> {
+    static FIX: OnceLock<Arc<Proto>> = OnceLock::new();
+    FIX.get_or_init(|| {
+        let f = Name("_f".to_string());
+        let x = Name("_x".to_string());
+        let code = vec![
+            Instr::Capture(0),
+            Instr::Fix,
+            Instr::Call,
+            Instr::Local(0),
+            Instr::TailCall,
+        ];
+        Arc::new(Proto {
+            param: x.clone(),
+            body: Box::new(app!(Expr::Fix(Box::new(Expr::Var(f))), Expr::Var(x))),
+            chunk: Arc::new(Chunk {
+                code,
+                protos: Vec::new(),
+                globals: Arc::new(Vec::new()),
+            }),
+        })
+    })
+}
+
+// compilation
+
+struct Compiler {
+    index: HashMap<Name, usize>,
+    globals: Arc<Vec<Value>>,
+    fresh: u64,
+}
+
+// the names in scope within the chunk being compiled.
+struct Scope {
+    // names & their slots. later entries shadow earlier ones.
+    locals: Vec<(Name, usize)>,
+    captures: Vec<Name>,
+    // the number of values on the stack in the current frame.
+    depth: usize,
+    code: Vec<Instr>,
+    protos: Vec<Arc<Proto>>,
+}
+
+impl Scope {
+    fn new(locals: Vec<Name>, captures: Vec<Name>) -> Scope {
+        Scope {
+            depth: locals.len(),
+            locals: locals
+                .into_iter()
+                .enumerate()
+                .map(|(i, nm)| (nm, i))
+                .collect(),
+            captures,
+            code: Vec::new(),
+            protos: Vec::new(),
+        }
+    }
+
+    fn lookup(&self, nm: &Name) -> Option<Source> {
+        match self.locals.iter().rev().find(|(x, _)| x == nm) {
+            Some((_, slot)) => Some(Source::Local(*slot)),
+            None => self
+                .captures
+                .iter()
+                .position(|x| x == nm)
+                .map(Source::Capture),
+        }
+    }
+
+    fn emit(&mut self, instr: Instr) -> usize {
+        self.code.push(instr);
+        self.code.len() - 1
+    }
+}
+
+impl Compiler {
+    fn chunk(&mut self, mut sc: Scope, expr: &Expr) -> Arc<Chunk> {
+        self.expr(&mut sc, expr, true);
+        sc.emit(Instr::Return);
+        Arc::new(Chunk {
+            code: sc.code,
+            protos: sc.protos,
+            globals: self.globals.clone(),
+        })
+    }
+
+    // compile code which pushes the value of `expr`. in tail position, it
+    // may instead return from the frame.
+    fn expr(&mut self, sc: &mut Scope, expr: &Expr, tail: bool) {
+        let depth = sc.depth;
+        match expr {
+            Expr::Lit(Lit::LInt(n)) => {
+                sc.emit(Instr::Int(*n));
+            }
+            Expr::Lit(Lit::LBool(b)) => {
+                sc.emit(Instr::Bool(*b));
+            }
+            Expr::Var(x) => {
+                let instr = match sc.lookup(x) {
+                    Some(Source::Local(slot)) => Instr::Local(slot),
+                    Some(Source::Capture(i)) => Instr::Capture(i),
+                    None => match self.index.get(x) {
+                        Some(i) => Instr::Global(*i),
+                        None => panic!("impossible: vm: unresolved variable: {:?}", x),
+                    },
+                };
+                sc.emit(instr);
+            }
+            Expr::Lam(nm, bd) => self.lam(sc, nm, bd),
+            Expr::Let(x, e, bd) => {
+                self.expr(sc, e, false);
+                sc.locals.push((x.clone(), depth));
+                self.expr(sc, bd, tail);
+                sc.locals.pop();
+                // in tail position, returning discards the local.
+                if !tail {
+                    sc.emit(Instr::Slide);
+                }
+            }
+            Expr::If(tst, thn, els) => {
+                self.expr(sc, tst, false);
+                let jump_els = sc.emit(Instr::JumpIfFalse(0));
+                sc.depth = depth;
+                self.expr(sc, thn, tail);
+                let jump_end = sc.emit(Instr::Jump(0));
+                sc.code[jump_els] = Instr::JumpIfFalse(sc.code.len());
+                sc.depth = depth;
+                self.expr(sc, els, tail);
+                sc.code[jump_end] = Instr::Jump(sc.code.len());
+            }
+            Expr::Fix(e) => {
+                self.expr(sc, e, false);
+                sc.emit(Instr::Fix);
+                sc.emit(call_instr(tail));
+            }
+            Expr::App(_, _) | Expr::Prim(_) => self.app(sc, expr, tail),
+        }
+        sc.depth = depth + 1;
+    }
+
+    fn app(&mut self, sc: &mut Scope, expr: &Expr, tail: bool) {
+        let depth = sc.depth;
+        let (head, args) = spine(expr);
+        let rest = match head {
+            Expr::Prim(op) => {
+                let arity = primop_arity(op);
+                if args.len() >= arity {
+                    for arg in &args[..arity] {
+                        self.expr(sc, arg, false);
+                    }
+                    match op {
+                        PrimOp::Nil => sc.emit(Instr::Nil),
+                        _ => sc.emit(Instr::Prim(op.clone())),
+                    };
+                    sc.depth = depth + 1;
+                    &args[arity..]
+                } else {
+                    let lam = self.eta_expand(op, &args);
+                    self.expr(sc, &lam, false);
+                    &[]
+                }
+            }
+            _ => {
+                self.expr(sc, head, false);
+                &args[..]
+            }
+        };
+        for (i, arg) in rest.iter().enumerate() {
+            self.expr(sc, arg, false);
+            sc.emit(call_instr(tail && i + 1 == rest.len()));
+            sc.depth = depth + 1;
+        }
+    }
+
+    // `(op a)`, for `op` of arity 3, becomes `(lam [_1] (lam [_2] (op a _1 _2)))`.
+    fn eta_expand(&mut self, op: &PrimOp, args: &[&Expr]) -> Expr {
+        let names: Vec<Name> = (args.len()..primop_arity(op))
+            .map(|_| self.fresh())
+            .collect();
+        let app_f = |f, arg: Expr| app!(f, arg);
+        let full = args
+            .iter()
+            .map(|arg| (*arg).clone())
+            .chain(names.iter().cloned().map(Expr::Var))
+            .fold(Expr::Prim(op.clone()), app_f);
+        names.into_iter().rev().fold(full, |bd, nm| lam!(nm, bd))
+    }
+
+    fn lam(&mut self, sc: &mut Scope, nm: &Name, bd: &Expr) {
+        let mut free = Vec::new();
+        free_vars_in(bd, &mut vec![nm.clone()], &mut free);
+        // free variables which are not bound in the enclosing scope are
+        // globals, which need not be captured.
+        let (captures, sources): (Vec<Name>, Vec<Source>) = free
+            .into_iter()
+            .filter_map(|x| sc.lookup(&x).map(|src| (x, src)))
+            .unzip();
+        let chunk = self.chunk(Scope::new(vec![nm.clone()], captures), bd);
+        sc.protos.push(Arc::new(Proto {
+            param: nm.clone(),
+            body: Box::new(bd.clone()),
+            chunk,
+        }));
+        sc.emit(Instr::Closure(sc.protos.len() - 1, sources));
+    }
+
+    fn fresh(&mut self) -> Name {
+        self.fresh += 1;
+        Name(format!("_{}", self.fresh))
+    }
+}
+
+fn call_instr(tail: bool) -> Instr {
+    if tail {
+        Instr::TailCall
+    } else {
+        Instr::Call
+    }
+}
+
+// split an application into its head & arguments.
+fn spine(expr: &Expr) -> (&Expr, Vec<&Expr>) {
+    match expr {
+        Expr::App(fun, arg) => {
+            let (head, mut args) = spine(fun);
+            args.push(arg);
+            (head, args)
+        }
+        _ => (expr, Vec::new()),
+    }
+}